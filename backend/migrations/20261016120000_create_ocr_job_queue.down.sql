@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS ocr_job_images;
+DROP TABLE IF EXISTS ocr_jobs;
@@ -0,0 +1,22 @@
+CREATE TABLE ocr_jobs (
+    id TEXT PRIMARY KEY,
+    status TEXT NOT NULL DEFAULT 'queued'
+        CHECK (status IN ('queued', 'processing', 'completed', 'failed', 'cancelled')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    locked_at TIMESTAMPTZ
+);
+
+CREATE INDEX ocr_jobs_claim_idx ON ocr_jobs (status, created_at);
+
+CREATE TABLE ocr_job_images (
+    job_id TEXT NOT NULL REFERENCES ocr_jobs (id) ON DELETE CASCADE,
+    file_index INTEGER NOT NULL,
+    file_name TEXT,
+    image_data BYTEA NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'succeeded', 'failed')),
+    PRIMARY KEY (job_id, file_index)
+);
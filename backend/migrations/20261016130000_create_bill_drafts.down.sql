@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS bill_drafts;
+ALTER TABLE ocr_jobs DROP COLUMN IF EXISTS draft_mode;
@@ -0,0 +1,23 @@
+ALTER TABLE ocr_jobs ADD COLUMN draft_mode BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE bill_drafts (
+    id SERIAL PRIMARY KEY,
+    session_id TEXT NOT NULL REFERENCES ocr_jobs (id) ON DELETE CASCADE,
+    file_index INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    form_no TEXT,
+    serial_no TEXT,
+    invoice_no TEXT,
+    issued_date DATE,
+    seller_name TEXT,
+    seller_tax_code TEXT,
+    item_name TEXT,
+    unit TEXT,
+    quantity NUMERIC(18,2),
+    unit_price NUMERIC(18,2),
+    total_amount NUMERIC(18,2),
+    vat_rate NUMERIC(5,2),
+    vat_amount NUMERIC(18,2)
+);
+
+CREATE INDEX bill_drafts_session_idx ON bill_drafts (session_id);
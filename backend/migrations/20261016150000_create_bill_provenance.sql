@@ -0,0 +1,10 @@
+CREATE TABLE bill_provenance (
+    bill_id INTEGER PRIMARY KEY REFERENCES bills (id) ON DELETE CASCADE,
+    source_type TEXT NOT NULL CHECK (source_type IN ('email')),
+    email_subject TEXT,
+    email_sender TEXT,
+    email_date TIMESTAMPTZ,
+    email_message_id TEXT,
+    attachment_name TEXT,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+ALTER TABLE ocr_job_images DROP COLUMN IF EXISTS document;
+ALTER TABLE ocr_jobs DROP COLUMN IF EXISTS group_pages;
@@ -0,0 +1,3 @@
+ALTER TABLE ocr_jobs ADD COLUMN group_pages BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE ocr_job_images ADD COLUMN document TEXT;
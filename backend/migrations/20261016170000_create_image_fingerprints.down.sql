@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS image_fingerprints;
+
+ALTER TABLE ocr_jobs DROP COLUMN IF EXISTS force_duplicates;
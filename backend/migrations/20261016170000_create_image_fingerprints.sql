@@ -0,0 +1,16 @@
+ALTER TABLE ocr_jobs ADD COLUMN force_duplicates BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- One row per bill, or per draft until it is committed, extracted from an image
+CREATE TABLE image_fingerprints (
+    id SERIAL PRIMARY KEY,
+    sha256 TEXT NOT NULL,
+    perceptual_hash BIGINT NOT NULL,
+    bill_id INTEGER REFERENCES bills (id) ON DELETE CASCADE,
+    draft_id INTEGER REFERENCES bill_drafts (id) ON DELETE CASCADE,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (bill_id IS NOT NULL OR draft_id IS NOT NULL)
+);
+
+CREATE INDEX image_fingerprints_sha256_idx ON image_fingerprints (sha256);
+CREATE INDEX image_fingerprints_bill_idx ON image_fingerprints (bill_id);
+CREATE INDEX image_fingerprints_draft_idx ON image_fingerprints (draft_id);
@@ -0,0 +1,2 @@
+ALTER TABLE bill_drafts DROP COLUMN IF EXISTS source_images;
+ALTER TABLE bills DROP COLUMN IF EXISTS source_images;
@@ -0,0 +1,4 @@
+-- Keys of the original images in the image store, one per page
+ALTER TABLE bills ADD COLUMN source_images TEXT[] NOT NULL DEFAULT '{}';
+
+ALTER TABLE bill_drafts ADD COLUMN source_images TEXT[] NOT NULL DEFAULT '{}';
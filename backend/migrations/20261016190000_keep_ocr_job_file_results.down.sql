@@ -0,0 +1,6 @@
+DELETE FROM ocr_job_images WHERE image_data IS NULL;
+ALTER TABLE ocr_job_images DROP COLUMN IF EXISTS error_message;
+ALTER TABLE ocr_job_images DROP COLUMN IF EXISTS draft_ids;
+ALTER TABLE ocr_job_images DROP COLUMN IF EXISTS bill_ids;
+ALTER TABLE ocr_job_images DROP COLUMN IF EXISTS size_bytes;
+ALTER TABLE ocr_job_images ALTER COLUMN image_data SET NOT NULL;
@@ -0,0 +1,9 @@
+-- What processing made of each file, kept after the job finishes and its
+-- stored images are dropped
+ALTER TABLE ocr_job_images ALTER COLUMN image_data DROP NOT NULL;
+ALTER TABLE ocr_job_images ADD COLUMN size_bytes INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE ocr_job_images ADD COLUMN bill_ids INTEGER[] NOT NULL DEFAULT '{}';
+ALTER TABLE ocr_job_images ADD COLUMN draft_ids INTEGER[] NOT NULL DEFAULT '{}';
+ALTER TABLE ocr_job_images ADD COLUMN error_message TEXT;
+
+UPDATE ocr_job_images SET size_bytes = octet_length(image_data);
@@ -0,0 +1,13 @@
+ALTER TABLE webhook_deliveries ADD COLUMN response_status INTEGER;
+ALTER TABLE webhook_deliveries ADD COLUMN last_error TEXT;
+
+UPDATE webhook_deliveries AS d
+SET response_status = a.response_status, last_error = a.error
+FROM (
+    SELECT DISTINCT ON (delivery_id) delivery_id, response_status, error
+    FROM webhook_delivery_attempts
+    ORDER BY delivery_id, attempt DESC
+) AS a
+WHERE a.delivery_id = d.id;
+
+DROP TABLE IF EXISTS webhook_delivery_attempts;
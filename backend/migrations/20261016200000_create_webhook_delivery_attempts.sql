@@ -0,0 +1,23 @@
+-- One row per posting of a webhook delivery, instead of keeping only the
+-- outcome of the latest attempt on the delivery
+CREATE TABLE webhook_delivery_attempts (
+    id SERIAL PRIMARY KEY,
+    delivery_id INTEGER NOT NULL REFERENCES webhook_deliveries (id) ON DELETE CASCADE,
+    attempt INTEGER NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('delivered', 'failed')),
+    response_status INTEGER,
+    error TEXT,
+    attempted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX webhook_delivery_attempts_delivery_idx ON webhook_delivery_attempts (delivery_id, attempt);
+
+INSERT INTO webhook_delivery_attempts (delivery_id, attempt, status, response_status, error, attempted_at)
+SELECT id, attempts,
+       CASE WHEN status = 'delivered' THEN 'delivered' ELSE 'failed' END,
+       response_status, last_error, COALESCE(delivered_at, next_attempt_at)
+FROM webhook_deliveries
+WHERE attempts > 0 AND (response_status IS NOT NULL OR last_error IS NOT NULL);
+
+ALTER TABLE webhook_deliveries DROP COLUMN response_status;
+ALTER TABLE webhook_deliveries DROP COLUMN last_error;
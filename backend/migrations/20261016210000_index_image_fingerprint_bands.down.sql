@@ -0,0 +1,5 @@
+ALTER TABLE image_fingerprints
+    DROP COLUMN IF EXISTS hash_band_0,
+    DROP COLUMN IF EXISTS hash_band_1,
+    DROP COLUMN IF EXISTS hash_band_2,
+    DROP COLUMN IF EXISTS hash_band_3;
@@ -0,0 +1,13 @@
+-- The perceptual hash split into four 16-bit bands, each indexed. Two hashes
+-- within d bits have a band within d / 4 bits of each other, so near
+-- duplicates are looked up by band instead of comparing every stored hash.
+ALTER TABLE image_fingerprints
+    ADD COLUMN hash_band_0 INTEGER GENERATED ALWAYS AS ((perceptual_hash & 65535)::INTEGER) STORED,
+    ADD COLUMN hash_band_1 INTEGER GENERATED ALWAYS AS (((perceptual_hash >> 16) & 65535)::INTEGER) STORED,
+    ADD COLUMN hash_band_2 INTEGER GENERATED ALWAYS AS (((perceptual_hash >> 32) & 65535)::INTEGER) STORED,
+    ADD COLUMN hash_band_3 INTEGER GENERATED ALWAYS AS (((perceptual_hash >> 48) & 65535)::INTEGER) STORED;
+
+CREATE INDEX image_fingerprints_band_0_idx ON image_fingerprints (hash_band_0);
+CREATE INDEX image_fingerprints_band_1_idx ON image_fingerprints (hash_band_1);
+CREATE INDEX image_fingerprints_band_2_idx ON image_fingerprints (hash_band_2);
+CREATE INDEX image_fingerprints_band_3_idx ON image_fingerprints (hash_band_3);
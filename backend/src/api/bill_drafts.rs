@@ -0,0 +1,96 @@
+//! Bill draft API endpoints
+//!
+//! Uploads processed in draft mode (`?draft=true`) stage their extracted
+//! bills in a per-session staging area instead of saving them. These
+//! endpoints let a reviewer list the drafts of a session, commit the
+//! selected (optionally edited) ones as bills, or discard them.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use tracing::info;
+
+use crate::{
+    api::{ApiError, ApiResponse},
+    config::ConnectionPool,
+    models::{CommitDraftsRequest, DiscardDraftsRequest, DiscardDraftsResponse},
+    services::bill_draft_service::BillDraftService,
+};
+
+/// GET /api/ocr/jobs/{id}/drafts endpoint handler
+///
+/// Returns the drafts staged by a session that have not been committed or
+/// discarded yet.
+///
+/// # Returns
+/// - 200 OK with the list of drafts (empty when there are none)
+/// - 500 Internal Server Error on database error
+pub async fn get_bill_drafts(
+    State(pool): State<ConnectionPool>,
+    Path(session_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let draft_service = BillDraftService::new(pool.pool().clone());
+    let drafts = draft_service.get_drafts(&session_id).await?;
+
+    Ok(Json(ApiResponse::success(drafts)))
+}
+
+/// POST /api/ocr/jobs/{id}/drafts/commit endpoint handler
+///
+/// Saves the selected drafts as bills. A selection may carry an edited
+/// `bill` that replaces the extracted values. The commit is all-or-nothing.
+///
+/// # Returns
+/// - 201 Created with the saved bills
+/// - 400 Bad Request if nothing was selected or a bill fails validation
+/// - 404 Not Found if a selected draft does not belong to the session
+/// - 500 Internal Server Error on database error
+pub async fn commit_bill_drafts(
+    State(pool): State<ConnectionPool>,
+    Path(session_id): Path<String>,
+    Json(request): Json<CommitDraftsRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    if request.drafts.is_empty() {
+        return Err(ApiError::BadRequest("No drafts selected".to_string()));
+    }
+
+    let draft_service = BillDraftService::new(pool.pool().clone());
+    let bills = draft_service
+        .commit_drafts(&session_id, &request.drafts)
+        .await?;
+    info!(
+        "Committed {} draft(s) of session {}",
+        bills.len(),
+        session_id
+    );
+
+    Ok((StatusCode::CREATED, Json(ApiResponse::success(bills))))
+}
+
+/// POST /api/ocr/jobs/{id}/drafts/discard endpoint handler
+///
+/// Discards the drafts listed in `ids`, or every draft of the session when
+/// `ids` or the whole JSON body is omitted.
+///
+/// # Returns
+/// - 200 OK with the number of discarded drafts
+/// - 500 Internal Server Error on database error
+pub async fn discard_bill_drafts(
+    State(pool): State<ConnectionPool>,
+    Path(session_id): Path<String>,
+    request: Option<Json<DiscardDraftsRequest>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Json(request) = request.unwrap_or_default();
+    let draft_service = BillDraftService::new(pool.pool().clone());
+    let discarded = draft_service
+        .discard_drafts(&session_id, request.ids.as_deref())
+        .await?;
+    info!("Discarded {} draft(s) of session {}", discarded, session_id);
+
+    Ok(Json(ApiResponse::success(DiscardDraftsResponse {
+        discarded,
+    })))
+}
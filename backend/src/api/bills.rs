@@ -7,7 +7,7 @@
 use axum::{
     Json,
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{StatusCode, header},
     response::IntoResponse,
 };
 use serde::Deserialize;
@@ -16,7 +16,10 @@ use crate::{
     api::{ApiError, ApiResponse},
     config::ConnectionPool,
     models::{Bill, CreateBill},
-    services::bill_service::BillService,
+    services::{
+        bill_service::BillService,
+        image_store::{ImageStore, ImageStoreError},
+    },
 };
 
 /// GET /api/bills endpoint handler
@@ -380,3 +383,116 @@ pub async fn get_bills_count(State(pool): State<ConnectionPool>) -> impl IntoRes
         }
     }
 }
+
+/// Query parameters for the bill image endpoints
+#[derive(Debug, Deserialize)]
+pub struct BillImageParams {
+    /// Page of a multi-page invoice (starts from 1, default: 1)
+    pub page: Option<usize>,
+}
+
+/// GET /api/bills/{id}/image endpoint handler
+///
+/// Returns the original image the bill was extracted from, as uploaded.
+///
+/// # Query Parameters
+/// - `page`: Page of a multi-page invoice (default: 1)
+///
+/// # Returns
+/// - 200 OK with the image
+/// - 404 Not Found if the bill or its image doesn't exist
+/// - 500 Internal Server Error on database or storage error
+pub async fn get_bill_image(
+    State(pool): State<ConnectionPool>,
+    State(image_store): State<ImageStore>,
+    Path(id): Path<i32>,
+    Query(params): Query<BillImageParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let key = source_image_keys(&pool, id, params.page)
+        .await?
+        .swap_remove(0);
+    let image = image_store
+        .get(&key)
+        .await
+        .map_err(|e| ApiError::InternalServerError(e.to_string()))?
+        .ok_or_else(|| ApiError::NotFound(format!("Image {key} of bill {id} is not stored")))?;
+
+    let content_type = infer::get(&image)
+        .map(|kind| kind.mime_type())
+        .unwrap_or("application/octet-stream");
+    Ok(image_response(content_type, image))
+}
+
+/// GET /api/bills/{id}/image/thumbnail endpoint handler
+///
+/// Returns a small JPEG of the image the bill was extracted from. For a bill
+/// extracted from a PDF, whose first source is the PDF itself, it is made from
+/// the page's embedded image, if the page was scanned.
+///
+/// # Query Parameters
+/// - `page`: Page of a multi-page invoice (default: 1)
+///
+/// # Returns
+/// - 200 OK with the thumbnail
+/// - 404 Not Found if the bill or its image doesn't exist
+/// - 500 Internal Server Error on database or storage error
+pub async fn get_bill_image_thumbnail(
+    State(pool): State<ConnectionPool>,
+    State(image_store): State<ImageStore>,
+    Path(id): Path<i32>,
+    Query(params): Query<BillImageParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    for key in source_image_keys(&pool, id, params.page).await? {
+        match image_store.thumbnail(&key).await {
+            Ok(Some(thumbnail)) => return Ok(image_response("image/jpeg", thumbnail)),
+            Ok(None) => {
+                return Err(ApiError::NotFound(format!(
+                    "Image {key} of bill {id} is not stored"
+                )));
+            }
+            Err(ImageStoreError::NotAnImage(_)) => continue,
+            Err(e) => return Err(ApiError::InternalServerError(e.to_string())),
+        }
+    }
+
+    Err(ApiError::NotFound(format!(
+        "Bill {id} has no image to make a thumbnail of"
+    )))
+}
+
+/// Keys of the stored sources of a bill from a page on; never empty
+async fn source_image_keys(
+    pool: &ConnectionPool,
+    id: i32,
+    page: Option<usize>,
+) -> Result<Vec<String>, ApiError> {
+    let bill_service = BillService::new(pool.pool().clone());
+    let bill = bill_service
+        .get_bill_by_id(id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Bill with ID {id} not found")))?;
+
+    let page = page.unwrap_or(1);
+    if page == 0 {
+        return Err(ApiError::BadRequest("page starts from 1".to_string()));
+    }
+    let mut keys = bill.source_images;
+    if keys.len() < page {
+        return Err(ApiError::NotFound(format!(
+            "Bill {id} has no source image for page {page}"
+        )));
+    }
+    Ok(keys.split_off(page - 1))
+}
+
+/// Stored images never change, so clients may cache them for good
+fn image_response(content_type: &'static str, image: Vec<u8>) -> impl IntoResponse {
+    let headers = [
+        (header::CONTENT_TYPE, content_type),
+        (
+            header::CACHE_CONTROL,
+            "private, max-age=31536000, immutable",
+        ),
+    ];
+    (StatusCode::OK, headers, image)
+}
@@ -0,0 +1,142 @@
+//! E-invoice XML import
+//!
+//! `POST /api/bills/import/xml` saves the lines of official e-invoice XML
+//! files as bills directly, without a processing session or a Gemini
+//! request. The same files are also accepted by the upload endpoints, where
+//! they are imported as part of the session.
+
+use axum::{
+    Json,
+    extract::{Multipart, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use tracing::{info, warn};
+
+use crate::{
+    api::ApiResponse,
+    errors::UploadError,
+    models::EInvoiceImportResult,
+    services::{bill_service::BillService, einvoice_xml::parse_einvoice},
+    state::AppState,
+};
+
+/// POST /api/bills/import/xml endpoint handler
+///
+/// Accepts one or more `files` fields, each an e-invoice XML file, and saves
+/// one bill per goods or service line. Files that are not e-invoices are
+/// reported in their result without failing the others.
+///
+/// # Returns
+/// - 200 OK with the result of every file: saved bill ids and the schema,
+///   signature and totals issues found
+/// - 400 Bad Request if no files were provided
+/// - 413 Payload Too Large if the files exceed the total upload size limit
+/// - 422 Unprocessable Entity if there are more files than the image count limit
+/// - 500 Internal Server Error if a bill cannot be saved
+pub async fn import_einvoice_xml(
+    State(app_state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, UploadError> {
+    let config = app_state.upload_config.clone();
+    let mut files = Vec::new();
+    let mut received_bytes = 0;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| UploadError::MultipartError(e.to_string()))?
+    {
+        if field.name() != Some("files") {
+            continue;
+        }
+
+        if files.len() >= config.max_image_count {
+            return Err(UploadError::ImageCountExceeded {
+                count: files.len() + 1,
+                limit: config.max_image_count,
+            });
+        }
+
+        let file_name = field.file_name().map(str::to_string);
+        let data = field
+            .bytes()
+            .await
+            .map_err(|e| UploadError::MultipartError(e.to_string()))?;
+
+        received_bytes += data.len();
+        if received_bytes > config.max_total_upload_bytes {
+            return Err(UploadError::TotalSizeExceeded {
+                size: received_bytes,
+                limit: config.max_total_upload_bytes,
+            });
+        }
+
+        files.push((file_name, data));
+    }
+
+    if files.is_empty() {
+        return Err(UploadError::MultipartError(
+            "No XML files provided".to_string(),
+        ));
+    }
+
+    let bill_service = BillService::new(app_state.pool.pool().clone());
+    let mut results = Vec::with_capacity(files.len());
+
+    for (file_name, data) in files {
+        let invoice = match parse_einvoice(&data) {
+            Ok(invoice) => invoice,
+            Err(e) => {
+                warn!("Rejecting e-invoice file {:?}: {}", file_name, e);
+                results.push(EInvoiceImportResult {
+                    file_name,
+                    invoice_no: None,
+                    serial_no: None,
+                    seller_tax_code: None,
+                    buyer_name: None,
+                    currency: None,
+                    total_before_tax: None,
+                    total_tax: None,
+                    bill_ids: Vec::new(),
+                    issues: Vec::new(),
+                    error_message: Some(e.to_string()),
+                });
+                continue;
+            }
+        };
+
+        let mut bill_ids = Vec::new();
+        for bill_data in invoice.to_bills() {
+            let bill = bill_service
+                .create_bill(bill_data)
+                .await
+                .map_err(|e| UploadError::StorageError(format!("{e:?}")))?;
+            bill_ids.push(bill.id);
+        }
+        info!(
+            "Imported e-invoice {:?}/{:?} from {:?} as bills {:?} with {} issue(s)",
+            invoice.serial_no,
+            invoice.invoice_no,
+            file_name,
+            bill_ids,
+            invoice.issues.len()
+        );
+
+        results.push(EInvoiceImportResult {
+            file_name,
+            invoice_no: invoice.invoice_no,
+            serial_no: invoice.serial_no,
+            seller_tax_code: invoice.seller.tax_code,
+            buyer_name: invoice.buyer.name,
+            currency: invoice.currency,
+            total_before_tax: invoice.total_before_tax,
+            total_tax: invoice.total_tax,
+            bill_ids,
+            issues: invoice.issues,
+            error_message: None,
+        });
+    }
+
+    Ok((StatusCode::OK, Json(ApiResponse::success(results))))
+}
@@ -0,0 +1,351 @@
+//! Email export ingestion
+//!
+//! `POST /api/ocr/email` and the `ingest-email` command accept `.eml` files
+//! or mbox archives, pull out their image and PDF attachments and process
+//! them like an upload to `POST /api/ocr`. The subject, sender and date of
+//! the email each attachment came from are recorded as the provenance of the
+//! bills extracted from it, available at `GET /api/bills/{id}/provenance`.
+
+use std::path::PathBuf;
+
+use axum::{
+    Json,
+    extract::{Multipart, Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    response::sse::{KeepAlive, Sse},
+};
+use tracing::info;
+
+use crate::{
+    api::{
+        ApiError, ApiResponse,
+        ocr::{
+            UploadBudget, process_files_with_events, receive_export_field, send_session_error,
+            session_event_stream,
+        },
+        webhooks::notify_session_finished,
+    },
+    config::ConnectionPool,
+    errors::UploadError,
+    models::OcrJob,
+    services::{
+        bill_provenance_service::BillProvenanceService,
+        email_ingestion::{EmailAttachment, extract_attachments},
+        ocr_job_queue::{JobFiles, ProcessingOptions, QueuedFile},
+        session_registry::{SessionHandle, SessionSubscription},
+    },
+    state::AppState,
+};
+
+/// Outcome of one attachment of an ingested email export
+#[derive(Debug, Clone)]
+pub struct AttachmentOutcome {
+    pub file_name: String,
+    pub bill_ids: Vec<i32>,
+    pub error_message: Option<String>,
+}
+
+/// POST /api/ocr/email endpoint handler
+///
+/// Accepts one or more `files` fields, each an `.eml` message or an mbox
+/// archive. The attachments are processed as one session whose events are
+/// streamed back like those of `POST /api/ocr`.
+///
+/// # Returns
+/// - 200 OK with the SSE event stream
+/// - 400 Bad Request if a file is not an email export or has no image or PDF attachments
+/// - 413 Payload Too Large if the files exceed the archive size limit, or
+///   their attachments the uncompressed size limit
+/// - 422 Unprocessable Entity if there are more attachments than the archive entry limit
+pub async fn upload_email_sse(
+    State(app_state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, UploadError> {
+    let config = app_state.upload_config.clone();
+    let mut budget = UploadBudget::new(&config);
+    let mut attachments = Vec::new();
+    let mut export_count = 0;
+
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| UploadError::MultipartError(e.to_string()))?
+    {
+        if field.name() != Some("files") {
+            continue;
+        }
+
+        let file_name = field.file_name().unwrap_or("email").to_string();
+        let data = receive_export_field(&mut field, &mut budget).await?;
+        attachments.extend(export_attachments(&file_name, &data, &mut budget)?);
+        export_count += 1;
+    }
+
+    if export_count == 0 {
+        return Err(UploadError::MultipartError(
+            "No email files provided".to_string(),
+        ));
+    }
+    require_attachments(&attachments)?;
+
+    let (session, receiver) = app_state.sessions.create_session(true);
+    info!(
+        "Ingesting {} email attachment(s) in session {}",
+        attachments.len(),
+        session.session_id()
+    );
+    let stream = session_event_stream(
+        app_state.sessions.clone(),
+        session.session_id().to_string(),
+        SessionSubscription::live(receiver),
+    );
+    tokio::spawn(async move {
+        ingest_attachments(attachments, session, &app_state).await;
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// GET /api/bills/{id}/provenance endpoint handler
+///
+/// Returns where a bill came from when it was ingested from an email.
+///
+/// # Returns
+/// - 200 OK with the provenance
+/// - 404 Not Found if the bill has no recorded provenance
+/// - 500 Internal Server Error on database error
+pub async fn get_bill_provenance(
+    State(pool): State<ConnectionPool>,
+    Path(bill_id): Path<i32>,
+) -> Result<impl IntoResponse, ApiError> {
+    let provenance_service = BillProvenanceService::new(pool.pool().clone());
+
+    match provenance_service.get_provenance(bill_id).await? {
+        Some(provenance) => Ok((StatusCode::OK, Json(ApiResponse::success(provenance)))),
+        None => Err(ApiError::NotFound(format!(
+            "No provenance recorded for bill {bill_id}"
+        ))),
+    }
+}
+
+/// Run the `ingest-email` command on the given `.eml` and mbox files
+///
+/// Prints the outcome of every attachment and returns whether all of them
+/// produced at least one bill.
+pub async fn run_ingest_email_command(paths: &[PathBuf], app_state: AppState) -> bool {
+    let mut exports = Vec::with_capacity(paths.len());
+    for path in paths {
+        match tokio::fs::read(path).await {
+            Ok(data) => exports.push((path.display().to_string(), data)),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                return false;
+            }
+        }
+    }
+
+    let mut budget = UploadBudget::new(&app_state.upload_config);
+    let mut attachments = Vec::new();
+    for (file_name, data) in &exports {
+        match export_attachments(file_name, data, &mut budget) {
+            Ok(extracted) => attachments.extend(extracted),
+            Err(e) => {
+                eprintln!("{e}");
+                return false;
+            }
+        }
+    }
+    if let Err(e) = require_attachments(&attachments) {
+        eprintln!("{e}");
+        return false;
+    }
+
+    let (session, _receiver) = app_state.sessions.create_session(false);
+    println!(
+        "Processing {} attachment(s) in session {}",
+        attachments.len(),
+        session.session_id()
+    );
+    let outcomes = ingest_attachments(attachments, session, &app_state).await;
+
+    for outcome in &outcomes {
+        match &outcome.error_message {
+            None => println!("{}: saved bills {:?}", outcome.file_name, outcome.bill_ids),
+            Some(error_message) => println!("{}: failed: {}", outcome.file_name, error_message),
+        }
+    }
+    outcomes
+        .iter()
+        .all(|outcome| outcome.error_message.is_none())
+}
+
+/// Extract the attachments of an email export within the upload's budget
+///
+/// Like the files of a ZIP archive, every attachment counts toward the
+/// archive entry and uncompressed size limits.
+fn export_attachments(
+    file_name: &str,
+    data: &[u8],
+    budget: &mut UploadBudget<'_>,
+) -> Result<Vec<EmailAttachment>, UploadError> {
+    let attachments = extract_attachments(data)
+        .map_err(|e| UploadError::InvalidEmail(format!("{file_name}: {e}")))?;
+    for attachment in &attachments {
+        budget.add_extracted_file(attachment.data.len())?;
+    }
+    Ok(attachments)
+}
+
+fn require_attachments(attachments: &[EmailAttachment]) -> Result<(), UploadError> {
+    if attachments.is_empty() {
+        return Err(UploadError::InvalidEmail(
+            "No image or PDF attachments found".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Process email attachments as one session
+///
+/// Each attachment carries the email it came from, so its bills are saved
+/// together with their provenance.
+async fn ingest_attachments(
+    attachments: Vec<EmailAttachment>,
+    session: SessionHandle,
+    app_state: &AppState,
+) -> Vec<AttachmentOutcome> {
+    let files = JobFiles {
+        pending: attachments
+            .iter()
+            .enumerate()
+            .map(|(file_index, attachment)| QueuedFile {
+                file_index,
+                file_name: Some(attachment.file_name.clone()),
+                document: None,
+                data: attachment.data.clone().into(),
+                email: Some(attachment.source.clone()),
+            })
+            .collect(),
+        total_files: attachments.len(),
+        processed_files: 0,
+        successful_files: 0,
+    };
+    if let Err(e) = process_files_with_events(
+        files,
+        ProcessingOptions::default(),
+        session.clone(),
+        app_state.clone(),
+    )
+    .await
+    {
+        send_session_error(&session, &e);
+    }
+    notify_session_finished(app_state, &session).await;
+
+    let job = app_state
+        .sessions
+        .get_session_with_events(session.session_id())
+        .map(|(state, events)| OcrJob::from_events(&state, &events));
+    let mut outcomes = Vec::with_capacity(attachments.len());
+
+    for (file_index, attachment) in attachments.iter().enumerate() {
+        let result = job
+            .as_ref()
+            .and_then(|job| job.files.iter().find(|file| file.file_index == file_index));
+        let bill_ids = result.map(|file| file.bill_ids.clone()).unwrap_or_default();
+
+        let error_message = if bill_ids.is_empty() {
+            Some(
+                result
+                    .and_then(|file| file.error_message.clone())
+                    .or_else(|| job.as_ref().and_then(|job| job.error_message.clone()))
+                    .unwrap_or_else(|| "No bill data was saved".to_string()),
+            )
+        } else {
+            None
+        };
+        outcomes.push(AttachmentOutcome {
+            file_name: attachment.file_name.clone(),
+            bill_ids,
+            error_message,
+        });
+    }
+
+    outcomes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::UploadConfig, utils::image_utils::ResizeConfig};
+    use base64::Engine;
+    use std::collections::HashMap;
+
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    /// Message with one PNG attachment per name
+    fn eml(attachment_names: &[&str]) -> String {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(PNG_SIGNATURE);
+        let mut message = "From: billing@supplier.vn\r\n\
+                           Subject: Invoices\r\n\
+                           MIME-Version: 1.0\r\n\
+                           Content-Type: multipart/mixed; boundary=\"b1\"\r\n\r\n"
+            .to_string();
+        for name in attachment_names {
+            message.push_str(&format!(
+                "--b1\r\n\
+                 Content-Type: image/png; name=\"{name}\"\r\n\
+                 Content-Disposition: attachment; filename=\"{name}\"\r\n\
+                 Content-Transfer-Encoding: base64\r\n\r\n\
+                 {encoded}\r\n"
+            ));
+        }
+        message.push_str("--b1--\r\n");
+        message
+    }
+
+    fn config() -> UploadConfig {
+        UploadConfig {
+            max_file_size_bytes: 2048,
+            max_image_count: 1,
+            max_total_upload_bytes: 16,
+            max_concurrent_images: 1,
+            max_pdf_pages: 1,
+            max_archive_entries: 3,
+            max_archive_uncompressed_bytes: 4096,
+            max_archive_bytes: 8192,
+            duplicate_max_hash_distance: 0,
+            image_pipeline: ResizeConfig::default(),
+            provider_resize_targets: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_attachments_are_budgeted_like_archive_entries() {
+        let config = config();
+        let mut budget = UploadBudget::new(&config);
+
+        // More attachments than the image count limit, within the entry limit
+        let first = eml(&["1.png", "2.png"]);
+        let attachments = export_attachments("march.eml", first.as_bytes(), &mut budget).unwrap();
+        assert_eq!(attachments.len(), 2);
+
+        // The entry limit covers every export of the upload
+        let second = eml(&["3.png", "4.png"]);
+        assert!(matches!(
+            export_attachments("april.eml", second.as_bytes(), &mut budget),
+            Err(UploadError::ArchiveEntryCountExceeded { count: 4, limit: 3 })
+        ));
+
+        let config = UploadConfig {
+            max_archive_uncompressed_bytes: PNG_SIGNATURE.len() * 3 / 2,
+            ..config
+        };
+        let mut budget = UploadBudget::new(&config);
+        assert!(matches!(
+            export_attachments("march.eml", first.as_bytes(), &mut budget),
+            Err(UploadError::ArchiveSizeExceeded { .. })
+        ));
+    }
+}
@@ -13,20 +13,37 @@ use serde_json::json;
 use tracing::{error, warn};
 
 // Public API modules
+pub mod bill_drafts;
 pub mod bills;
+pub mod einvoice_import;
+pub mod email_ingestion;
 pub mod export;
 pub mod health;
 pub mod ocr;
+pub mod ocr_jobs;
 pub mod response;
+pub mod watch_folder;
+pub mod webhooks;
 
 // Re-export endpoint handlers for router setup
+pub use bill_drafts::{commit_bill_drafts, discard_bill_drafts, get_bill_drafts};
 pub use bills::{
-    create_bill, delete_bill, get_all_bills, get_bill_by_id, get_bills_count, search_bills,
-    update_bill,
+    create_bill, delete_bill, get_all_bills, get_bill_by_id, get_bill_image,
+    get_bill_image_thumbnail, get_bills_count, search_bills, update_bill,
 };
+pub use einvoice_import::import_einvoice_xml;
+pub use email_ingestion::{get_bill_provenance, run_ingest_email_command, upload_email_sse};
 pub use export::export_bills;
 pub use health::{get_health, get_health_detail};
 pub use ocr::{upload_images, upload_images_sse};
+pub use ocr_jobs::{
+    cancel_ocr_job, create_ocr_job, get_ocr_job, get_ocr_job_events, spawn_job_workers,
+};
+pub use watch_folder::spawn_folder_watcher;
+pub use webhooks::{
+    create_webhook, delete_webhook, get_webhook_deliveries, get_webhooks,
+    spawn_webhook_dispatcher,
+};
 
 // Re-export response utilities
 pub use response::ApiResponse;
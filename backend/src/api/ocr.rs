@@ -1,116 +1,747 @@
 use axum::{
-    extract::{Multipart, State},
+    body::Bytes,
+    extract::{Multipart, Query, State, multipart::Field},
     response::IntoResponse,
     response::sse::{Event, KeepAlive, Sse},
 };
 use chrono::Utc;
-use futures_util::stream::Stream;
-use std::{convert::Infallible, pin::Pin, sync::Arc, time::Instant};
-use tokio::sync::broadcast;
+use futures_util::{
+    future,
+    stream::{self, Stream, StreamExt},
+};
+use serde::Deserialize;
+use std::{
+    convert::Infallible,
+    io::{Read, Seek, Write},
+    ops::ControlFlow,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tempfile::{NamedTempFile, SpooledTempFile};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
 use tracing::{debug, error, info, instrument, warn};
-use uuid::Uuid;
 
 use crate::{
-    config::UploadConfig,
+    api::ApiError,
+    api::ocr_jobs::run_with_lease,
+    config::{ConnectionPool, UploadConfig},
     errors::UploadError,
     models::{
-        ImageFileInfo, ProcessingErrorType, ProcessingEvent, ValidationErrorCode, ValidationStatus,
+        Bill, BillDraft, CreateBill, DuplicateMatch, GeminiResponse, ImageFileInfo,
+        ImageFingerprint, ProcessingErrorType, ProcessingEvent, SSEEventEnvelope,
+        ValidationErrorCode, ValidationStatus,
     },
     services::{
+        bill_draft_service::BillDraftService,
         bill_extractor::BillDataExtractor,
+        bill_provenance_service::BillProvenanceService,
         bill_service::BillService,
-        gemini_service::{GeminiError, GeminiService},
-        image_validation::{validate_file_size, validate_image_format},
+        document_grouping::{InvoiceKey, group_by_invoice_key, unify_invoice_header},
+        einvoice_xml::{XML_CONTENT_TYPE, parse_einvoice},
+        email_ingestion::EmailSource,
+        extraction_provider::{ExtractionError, ExtractionImage, ExtractionProvider},
+        image_fingerprint_service::{ImageFingerprintService, fingerprint_image},
+        image_store::ImageStore,
+        image_validation::{
+            PDF_CONTENT_TYPE, validate_file_size, validate_image_format, validate_upload_format,
+        },
+        invoice_qr::{InvoiceQrData, read_invoice_qr, reconcile},
+        ocr_job_queue::{
+            FileData, FileResult, JobFiles, ProcessingOptions, QueuedFile, QueuedFileStatus,
+        },
+        pdf_extraction::{PageContent, extract_pages},
+        session_registry::{SessionHandle, SessionRegistry, SessionSubscription},
+        zip_archive::{ArchiveEntry, ArchiveError, ArchiveLimits, ArchiveReader, is_zip_upload},
     },
     state::AppState,
-    utils::image_utils::resize_image_default,
+    utils::{
+        image_hash::sha256_hex,
+        image_utils::{ResizeConfig, preprocess_image},
+    },
 };
 
+/// Multipart fields larger than this are spooled to a temporary file while they arrive
+const FIELD_SPOOL_THRESHOLD_BYTES: usize = 512 * 1024;
+
+/// Query parameters of the upload endpoints
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct UploadParams {
+    /// Stage extracted bills for review instead of saving them
+    #[serde(default)]
+    pub draft: bool,
+    /// Save images of the same invoice, as named by their QR codes or extracted
+    /// serial and number, as one document
+    #[serde(default)]
+    pub group_pages: bool,
+    /// Extract images that duplicate an earlier upload instead of skipping them
+    #[serde(default)]
+    pub force: bool,
+}
+
+impl UploadParams {
+    pub(crate) fn processing_options(self) -> ProcessingOptions {
+        ProcessingOptions {
+            draft_mode: self.draft,
+            group_pages: self.group_pages,
+            force_duplicates: self.force,
+        }
+    }
+}
+
 pub async fn upload_images_sse(
     State(app_state): State<AppState>,
+    Query(params): Query<UploadParams>,
     multipart: Multipart,
 ) -> Result<impl IntoResponse, UploadError> {
-    let session_id = Uuid::new_v4().to_string();
-    let broadcaster = app_state.event_broadcaster.clone();
+    // Each upload gets its own session channel; subscribe before processing starts
+    let (session, receiver) = app_state.sessions.create_session(true);
+    let session_id = session.session_id().to_string();
     let app_state_clone = app_state.clone();
 
-    // Start background processing
+    // Start processing while the images are still arriving
     tokio::spawn(async move {
         if let Err(e) = process_upload_with_events(
             multipart,
-            broadcaster.clone(),
-            session_id.clone(),
+            params.processing_options(),
+            session.clone(),
             app_state_clone,
         )
         .await
         {
-            let _ = broadcaster.send(ProcessingEvent::ProcessingError {
-                session_id,
-                error_message: e.to_string(),
-                error_type: ProcessingErrorType::InternalServerError,
-                timestamp: Utc::now(),
-            });
+            send_session_error(&session, &e);
         }
     });
 
     // Return SSE stream
-    let mut receiver = app_state.event_broadcaster.subscribe();
-    let stream: Pin<Box<dyn Stream<Item = Result<Event, Infallible>> + Send>> = Box::pin(
-        async_stream::stream! {
-            while let Ok(event) = receiver.recv().await {
-                let event_type = match &event {
-                    ProcessingEvent::UploadStarted { .. } => "upload_started",
-                    ProcessingEvent::ImageReceived { .. } => "image_received",
-                    ProcessingEvent::ImageValidationStart { .. } => "image_validation_start",
-                    ProcessingEvent::ImageValidationSuccess { .. } => "image_validation_success",
-                    ProcessingEvent::ImageValidationError { .. } => "image_validation_error",
-                    ProcessingEvent::AllImagesValidated { .. } => "all_images_validated",
-                    ProcessingEvent::ProcessingComplete { .. } => "processing_complete",
-                    ProcessingEvent::ProcessingError { .. } => "processing_error",
-                    ProcessingEvent::GeminiProcessingStart { .. } => "gemini_processing_start",
-                    ProcessingEvent::GeminiProcessingSuccess { .. } => "gemini_processing_success",
-                    ProcessingEvent::GeminiProcessingError { .. } => "gemini_processing_error",
-                    ProcessingEvent::BillDataSaved { .. } => "bill_data_saved",
-                };
+    Ok(Sse::new(session_event_stream(
+        app_state.sessions.clone(),
+        session_id,
+        SessionSubscription::live(receiver),
+    ))
+    .keep_alive(KeepAlive::default()))
+}
+
+/// Emit the terminal error event for a session whose processing failed
+pub(crate) fn send_session_error(session: &SessionHandle, error: &UploadError) {
+    let error_type = match error {
+        UploadError::MultipartError(_)
+        | UploadError::InvalidArchive(_)
+        | UploadError::InvalidEmail(_) => ProcessingErrorType::MultipartParsingError,
+        UploadError::ImageCountExceeded { .. }
+        | UploadError::TotalSizeExceeded { .. }
+        | UploadError::ArchiveEntryCountExceeded { .. }
+        | UploadError::ArchiveSizeExceeded { .. }
+        | UploadError::CompressedArchiveSizeExceeded { .. } => {
+            ProcessingErrorType::UploadLimitExceeded
+        }
+        _ => ProcessingErrorType::InternalServerError,
+    };
+    send_session_error_message(session, error.to_string(), error_type);
+}
+
+/// Emit a terminal `ProcessingError` event with the given message
+pub(crate) fn send_session_error_message(
+    session: &SessionHandle,
+    error_message: String,
+    error_type: ProcessingErrorType,
+) {
+    session.send(ProcessingEvent::ProcessingError {
+        session_id: session.session_id().to_string(),
+        error_message,
+        error_type,
+        timestamp: Utc::now(),
+    });
+}
+
+/// Turn replayed and live session events into an SSE stream that ends with the session
+///
+/// Each SSE event carries the envelope's `id` and `retry` fields so that
+/// clients can reconnect with `Last-Event-ID`. A client that falls behind the
+/// live channel is caught up from the session's event log, so the stream
+/// only ends with the session or when the session is gone.
+pub(crate) fn session_event_stream(
+    sessions: SessionRegistry,
+    session_id: String,
+    subscription: SessionSubscription,
+) -> Pin<Box<dyn Stream<Item = Result<Event, Infallible>> + Send>> {
+    Box::pin(async_stream::stream! {
+        let mut last_sequence = None;
+
+        for envelope in subscription.replay {
+            last_sequence = envelope.sequence();
+            let is_terminal = envelope.data.is_terminal();
+            yield Ok(to_sse_event(&envelope));
+
+            if is_terminal {
+                return;
+            }
+        }
+
+        let Some(mut receiver) = subscription.receiver else {
+            return;
+        };
 
-                let data = serde_json::to_string(&event).unwrap_or_default();
-                yield Ok(Event::default().event(event_type).data(data));
+        loop {
+            let envelopes = match receiver.recv().await {
+                Ok(envelope) => vec![envelope],
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(
+                        "Event stream of session {} fell {} event(s) behind, replaying them",
+                        session_id, skipped
+                    );
+                    match sessions.events_after(&session_id, last_sequence) {
+                        Some(envelopes) => envelopes,
+                        None => break,
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+
+            for envelope in envelopes {
+                // Skip anything already delivered as part of a replay
+                if envelope.sequence() <= last_sequence {
+                    continue;
+                }
+                last_sequence = envelope.sequence();
+                let is_terminal = envelope.data.is_terminal();
+                yield Ok(to_sse_event(&envelope));
 
                 // Close stream on completion
-                if matches!(event, ProcessingEvent::ProcessingComplete { .. } | ProcessingEvent::ProcessingError { .. }) {
-                    break;
+                if is_terminal {
+                    return;
                 }
             }
-        },
-    );
+        }
+    })
+}
+
+fn to_sse_event(envelope: &SSEEventEnvelope) -> Event {
+    let data = serde_json::to_string(&envelope.data).unwrap_or_default();
+    let mut event = Event::default().event(&envelope.event_type).data(data);
+
+    if let Some(event_id) = &envelope.event_id {
+        event = event.id(event_id);
+    }
+    if let Some(retry) = envelope.retry {
+        event = event.retry(Duration::from_millis(retry as u64));
+    }
 
-    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+    event
 }
 
+/// Process a streamed upload while its `images` fields are still arriving
+///
+/// The upload is registered in the job queue as a job claimed by this
+/// instance, and every image is stored there before it is processed, so a
+/// restart resumes the images received so far.
 async fn process_upload_with_events(
-    mut multipart: Multipart,
-    broadcaster: broadcast::Sender<ProcessingEvent>,
-    session_id: String,
+    multipart: Multipart,
+    options: ProcessingOptions,
+    session: SessionHandle,
     app_state: AppState,
 ) -> Result<(), UploadError> {
-    let config = app_state.upload_config.clone();
-    let start_time = Instant::now();
+    app_state
+        .job_queue
+        .begin(session.session_id(), options)
+        .await
+        .map_err(|e| UploadError::StorageError(e.to_string()))?;
+
+    let files = receive_image_fields(multipart, session.clone(), app_state.clone());
+    run_with_lease(
+        session.clone(),
+        &app_state,
+        process_file_stream(files, None, 0, 0, options, session, app_state.clone()),
+    )
+    .await;
+
+    Ok(())
+}
+
+/// Stream the `images` fields of a multipart upload as each one finishes arriving
+///
+/// Every image is stored in the job queue before it is yielded. The next
+/// field is only read once the pipeline has room for another file.
+///
+/// The limits of the [`UploadBudget`] are enforced while reading: the field
+/// that crosses a limit is reported with an `ImageValidationError` event and
+/// the stream ends with the limit error.
+///
+/// A field holding a ZIP archive is expanded and each file in it becomes an
+/// image of the upload, named by its path in the archive.
+///
+/// A `document` text field labels the `images` field that follows it (every
+/// file of it, for an archive); images with the same label are the pages of
+/// one multi-page invoice.
+fn receive_image_fields(
+    mut multipart: Multipart,
+    session: SessionHandle,
+    app_state: AppState,
+) -> impl Stream<Item = Result<QueuedFile, UploadError>> {
+    async_stream::try_stream! {
+        let config = app_state.upload_config.clone();
+        let job_id = session.session_id().to_string();
+        let mut budget = UploadBudget::new(&config);
+        let mut file_index = 0;
+        let mut document = None;
+
+        while let Some(mut field) = multipart
+            .next_field()
+            .await
+            .map_err(|e| UploadError::MultipartError(e.to_string()))?
+        {
+            if field.name() == Some("document") {
+                document = read_document_label(field).await?;
+                continue;
+            }
+            if field.name() != Some("images") {
+                continue;
+            }
+
+            let file_name = field.file_name().map(|s| s.to_string());
+            let document = document.take();
+
+            if is_zip_upload(field.content_type(), file_name.as_deref()) {
+                let mut entries = match open_archive_field(&mut field, file_name.clone(), &mut budget).await {
+                    Ok(entries) => entries,
+                    Err(error) => {
+                        reject_field(&session, file_index, file_name.clone(), &error);
+                        Err(error)?
+                    }
+                };
+
+                while let Some(entry) = entries.next().await {
+                    let entry = match entry {
+                        Ok(entry) => entry,
+                        Err(error) => {
+                            let error = UploadError::from(error);
+                            reject_field(&session, file_index, file_name.clone(), &error);
+                            Err(error)?
+                        }
+                    };
+                    budget.add_archive_entry(entry.data.len());
+
+                    let file = QueuedFile {
+                        file_index,
+                        file_name: Some(entry.name),
+                        document: document.clone(),
+                        data: entry.data.into(),
+                        email: None,
+                    };
+                    app_state
+                        .job_queue
+                        .add_file(&job_id, &file)
+                        .await
+                        .map_err(|e| UploadError::StorageError(e.to_string()))?;
+
+                    yield file;
+                    file_index += 1;
+                }
+                continue;
+            }
+
+            let data = match receive_image_field(&mut field, &mut budget).await {
+                Ok(data) => data,
+                Err(error @ (UploadError::MultipartError(_) | UploadError::StorageError(_))) => {
+                    Err(error)?
+                }
+                Err(error) => {
+                    reject_field(&session, file_index, file_name.clone(), &error);
+                    Err(error)?
+                }
+            };
+
+            let file = QueuedFile {
+                file_index,
+                file_name,
+                document,
+                data,
+                email: None,
+            };
+            app_state
+                .job_queue
+                .add_file(&job_id, &file)
+                .await
+                .map_err(|e| UploadError::StorageError(e.to_string()))?;
+
+            yield file;
+            file_index += 1;
+        }
+
+        if file_index == 0 {
+            Err(UploadError::MultipartError(
+                "No images provided".to_string(),
+            ))?;
+        }
+    }
+}
+
+/// Read the label of a `document` field; a blank label groups nothing
+async fn read_document_label(field: Field<'_>) -> Result<Option<String>, UploadError> {
+    let label = field
+        .text()
+        .await
+        .map_err(|e| UploadError::MultipartError(e.to_string()))?;
+    let label = label.trim();
+    Ok((!label.is_empty()).then(|| label.to_string()))
+}
+
+/// Report an `images` field that was rejected before it was processed
+fn reject_field(
+    session: &SessionHandle,
+    file_index: usize,
+    file_name: Option<String>,
+    error: &UploadError,
+) {
+    warn!("Rejecting file index {}: {}", file_index, error);
+    session.send(ProcessingEvent::ImageValidationError {
+        file_index,
+        file_name,
+        error_message: error.to_string(),
+        error_code: map_error_to_code(error),
+        timestamp: Utc::now(),
+    });
+}
+
+/// Files and bytes one upload request has received, checked against its limits
+///
+/// Plain `images` fields are held to the image count and total size limits.
+/// Every file, whether sent as a field or found in a ZIP archive, also counts
+/// toward the upload's file count and uncompressed size limits, and the
+/// archives as sent toward the archive size limit. One budget covers the
+/// whole request, however many archives it has. Email exports are budgeted
+/// like archives.
+pub(crate) struct UploadBudget<'a> {
+    config: &'a UploadConfig,
+    image_count: usize,
+    image_bytes: usize,
+    file_count: usize,
+    file_bytes: usize,
+    archive_bytes: usize,
+}
+
+impl<'a> UploadBudget<'a> {
+    pub(crate) fn new(config: &'a UploadConfig) -> Self {
+        Self {
+            config,
+            image_count: 0,
+            image_bytes: 0,
+            file_count: 0,
+            file_bytes: 0,
+            archive_bytes: 0,
+        }
+    }
+
+    /// Check that the upload may have another plain image, before it is read
+    fn check_image_count(&self) -> Result<(), UploadError> {
+        if self.image_count >= self.config.max_image_count {
+            return Err(UploadError::ImageCountExceeded {
+                count: self.image_count + 1,
+                limit: self.config.max_image_count,
+            });
+        }
+        if self.file_count >= self.config.max_archive_entries {
+            return Err(UploadError::ArchiveEntryCountExceeded {
+                count: self.file_count + 1,
+                limit: self.config.max_archive_entries,
+            });
+        }
+        Ok(())
+    }
+
+    /// Count a received plain image
+    fn add_image(&mut self, size: usize) -> Result<(), UploadError> {
+        let file_bytes = self.file_bytes + size;
+        if file_bytes > self.config.max_archive_uncompressed_bytes {
+            return Err(UploadError::ArchiveSizeExceeded {
+                size: file_bytes,
+                limit: self.config.max_archive_uncompressed_bytes,
+            });
+        }
+        self.image_count += 1;
+        self.image_bytes += size;
+        self.file_count += 1;
+        self.file_bytes = file_bytes;
+        Ok(())
+    }
+
+    /// Count a file extracted from an archive; the archive reader enforces
+    /// the limits of [`Self::archive_limits`] itself
+    fn add_archive_entry(&mut self, size: usize) {
+        self.file_count += 1;
+        self.file_bytes += size;
+    }
+
+    /// Count a file taken out of a container the archive reader does not
+    /// handle, such as an email attachment
+    pub(crate) fn add_extracted_file(&mut self, size: usize) -> Result<(), UploadError> {
+        if self.file_count >= self.config.max_archive_entries {
+            return Err(UploadError::ArchiveEntryCountExceeded {
+                count: self.file_count + 1,
+                limit: self.config.max_archive_entries,
+            });
+        }
+        let file_bytes = self.file_bytes + size;
+        if file_bytes > self.config.max_archive_uncompressed_bytes {
+            return Err(UploadError::ArchiveSizeExceeded {
+                size: file_bytes,
+                limit: self.config.max_archive_uncompressed_bytes,
+            });
+        }
+        self.file_count += 1;
+        self.file_bytes = file_bytes;
+        Ok(())
+    }
+
+    /// Limits of the next archive: what is left of the upload's file budget
+    fn archive_limits(&self) -> ArchiveLimits {
+        ArchiveLimits {
+            max_entries: self.config.max_archive_entries,
+            max_uncompressed_bytes: self.config.max_archive_uncompressed_bytes,
+            used_entries: self.file_count,
+            used_bytes: self.file_bytes,
+        }
+    }
+}
+
+/// Read a plain image field within the upload's budget
+async fn receive_image_field(
+    field: &mut Field<'_>,
+    budget: &mut UploadBudget<'_>,
+) -> Result<FileData, UploadError> {
+    budget.check_image_count()?;
+    let data = spool_field(
+        field,
+        budget.image_bytes,
+        budget.config.max_total_upload_bytes,
+    )
+    .await?;
+    budget.add_image(data.len())?;
+    Ok(data)
+}
+
+/// Read a multipart field chunk by chunk, spilling a large body to a temporary file
+///
+/// A spilled body is left on disk and only read when the file is processed,
+/// so the files of a batch do not all sit in memory. Stops reading as soon as
+/// the field would take the upload past `max_total_bytes`, given the
+/// `received_bytes` of earlier fields.
+async fn spool_field(
+    field: &mut Field<'_>,
+    received_bytes: usize,
+    max_total_bytes: usize,
+) -> Result<FileData, UploadError> {
+    let spool_error = |e: std::io::Error| UploadError::StorageError(e.to_string());
+    let mut buffer = Vec::new();
+    let mut spill: Option<NamedTempFile> = None;
+    let mut field_bytes = 0;
+
+    while let Some(chunk) = field
+        .chunk()
+        .await
+        .map_err(|e| UploadError::MultipartError(e.to_string()))?
+    {
+        field_bytes += chunk.len();
+        if received_bytes + field_bytes > max_total_bytes {
+            return Err(UploadError::TotalSizeExceeded {
+                size: received_bytes + field_bytes,
+                limit: max_total_bytes,
+            });
+        }
+
+        match spill.as_mut() {
+            Some(file) => file.write_all(&chunk).map_err(spool_error)?,
+            None if field_bytes > FIELD_SPOOL_THRESHOLD_BYTES => {
+                let mut file = NamedTempFile::new().map_err(spool_error)?;
+                file.write_all(&buffer).map_err(spool_error)?;
+                file.write_all(&chunk).map_err(spool_error)?;
+                buffer = Vec::new();
+                spill = Some(file);
+            }
+            None => buffer.extend_from_slice(&chunk),
+        }
+    }
+
+    match spill {
+        Some(mut file) => {
+            file.flush().map_err(spool_error)?;
+            Ok(FileData::Spooled {
+                file: Arc::new(file),
+                size: field_bytes,
+            })
+        }
+        None => Ok(FileData::Memory(Bytes::from(buffer))),
+    }
+}
+
+/// Read a rewound spool of `size` bytes into memory
+fn read_spool(mut spool: SpooledTempFile, size: usize) -> Result<Bytes, UploadError> {
+    let mut data = Vec::with_capacity(size);
+    spool
+        .read_to_end(&mut data)
+        .map_err(|e| UploadError::StorageError(e.to_string()))?;
+
+    Ok(Bytes::from(data))
+}
+
+/// Spool a multipart field to a temporary file, rewound to its start, and return its size
+async fn spool_field_to_file(
+    field: &mut Field<'_>,
+    received_bytes: usize,
+    max_total_bytes: usize,
+) -> Result<(SpooledTempFile, usize), UploadError> {
+    let mut spool = tempfile::spooled_tempfile(FIELD_SPOOL_THRESHOLD_BYTES);
+    let spool_error = |e: std::io::Error| UploadError::StorageError(e.to_string());
+    let mut field_bytes = 0;
+
+    while let Some(chunk) = field
+        .chunk()
+        .await
+        .map_err(|e| UploadError::MultipartError(e.to_string()))?
+    {
+        field_bytes += chunk.len();
+        if received_bytes + field_bytes > max_total_bytes {
+            return Err(UploadError::TotalSizeExceeded {
+                size: received_bytes + field_bytes,
+                limit: max_total_bytes,
+            });
+        }
+        spool.write_all(&chunk).map_err(spool_error)?;
+    }
+
+    spool.rewind().map_err(spool_error)?;
+    Ok((spool, field_bytes))
+}
+
+/// Spool an archive field and start extracting it within the upload's budget
+async fn open_archive_field(
+    field: &mut Field<'_>,
+    archive_name: Option<String>,
+    budget: &mut UploadBudget<'_>,
+) -> Result<ReceiverStream<Result<ArchiveEntry, ArchiveError>>, UploadError> {
+    let (spool, _) = spool_archive_field(field, budget).await?;
+    Ok(expand_archive(spool, budget.archive_limits(), archive_name))
+}
+
+/// Read an email export field within the upload's archive size limit
+///
+/// The attachments taken out of it are then counted with
+/// [`UploadBudget::add_extracted_file`].
+pub(crate) async fn receive_export_field(
+    field: &mut Field<'_>,
+    budget: &mut UploadBudget<'_>,
+) -> Result<Bytes, UploadError> {
+    let (spool, size) = spool_archive_field(field, budget).await?;
+    read_spool(spool, size)
+}
+
+/// Spool a field holding an archive or an email export
+///
+/// The archives and exports of an upload, as sent, may not exceed the
+/// archive size limit together.
+async fn spool_archive_field(
+    field: &mut Field<'_>,
+    budget: &mut UploadBudget<'_>,
+) -> Result<(SpooledTempFile, usize), UploadError> {
+    let (spool, size) =
+        spool_field_to_file(field, budget.archive_bytes, budget.config.max_archive_bytes)
+            .await
+            .map_err(|e| match e {
+                UploadError::TotalSizeExceeded { size, limit } => {
+                    UploadError::CompressedArchiveSizeExceeded { size, limit }
+                }
+                e => e,
+            })?;
+    budget.archive_bytes += size;
+    Ok((spool, size))
+}
+
+/// Open a spooled archive and extract its files on a blocking thread
+///
+/// Files are handed over one at a time, so at most one extracted file waits
+/// while the previous one is stored. Extraction stops when the receiver is
+/// dropped.
+fn expand_archive(
+    spool: SpooledTempFile,
+    limits: ArchiveLimits,
+    archive_name: Option<String>,
+) -> ReceiverStream<Result<ArchiveEntry, ArchiveError>> {
+    let (sender, receiver) = mpsc::channel(1);
+
+    tokio::task::spawn_blocking(move || {
+        let mut archive = match ArchiveReader::open(spool, limits) {
+            Ok(archive) => archive,
+            Err(error) => {
+                let _ = sender.blocking_send(Err(error));
+                return;
+            }
+        };
+        info!(
+            "Expanding archive {:?} with {} file(s)",
+            archive_name,
+            archive.entry_count()
+        );
+
+        while let Some(entry) = archive.next_entry().transpose() {
+            let failed = entry.is_err();
+            if sender.blocking_send(entry).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    ReceiverStream::new(receiver)
+}
+
+/// Read every `images` field of a multipart upload into memory
+///
+/// Fails as soon as the upload exceeds a limit of its [`UploadBudget`]. ZIP
+/// archives are expanded into their files and `document` labels apply, as
+/// for `POST /api/ocr`.
+pub(crate) async fn collect_image_fields(
+    mut multipart: Multipart,
+    config: &UploadConfig,
+) -> Result<Vec<QueuedFile>, UploadError> {
+    let mut budget = UploadBudget::new(config);
     let mut files = Vec::new();
+    let mut document = None;
 
-    // Collect all files first
-    while let Some(field) = multipart
+    while let Some(mut field) = multipart
         .next_field()
         .await
         .map_err(|e| UploadError::MultipartError(e.to_string()))?
     {
-        if field.name() == Some("images") {
+        if field.name() == Some("document") {
+            document = read_document_label(field).await?;
+        } else if field.name() == Some("images") {
             let file_name = field.file_name().map(|s| s.to_string());
-            let data = field
-                .bytes()
-                .await
-                .map_err(|e| UploadError::MultipartError(e.to_string()))?;
+            let document = document.take();
+
+            if is_zip_upload(field.content_type(), file_name.as_deref()) {
+                let mut entries = open_archive_field(&mut field, file_name, &mut budget).await?;
+                while let Some(entry) = entries.next().await {
+                    let entry = entry?;
+                    budget.add_archive_entry(entry.data.len());
+                    files.push(QueuedFile {
+                        file_index: files.len(),
+                        file_name: Some(entry.name),
+                        document: document.clone(),
+                        data: entry.data.into(),
+                        email: None,
+                    });
+                }
+                continue;
+            }
 
-            files.push((file_name, data));
+            let data = receive_image_field(&mut field, &mut budget).await?;
+            files.push(QueuedFile {
+                file_index: files.len(),
+                file_name,
+                document,
+                data,
+                email: None,
+            });
         }
     }
 
@@ -120,113 +751,1350 @@ async fn process_upload_with_events(
         ));
     }
 
-    // Send upload started event
-    let _ = broadcaster.send(ProcessingEvent::UploadStarted {
-        total_files: files.len(),
-        session_id: session_id.clone(),
-        timestamp: Utc::now(),
-    });
+    Ok(files)
+}
 
-    let mut successful_files = 0;
+/// Validate, resize and extract the pending files of a queued job, emitting its events
+///
+/// The totals cover the whole job, including files finished by an earlier,
+/// interrupted attempt.
+pub(crate) async fn process_files_with_events(
+    files: JobFiles,
+    options: ProcessingOptions,
+    session: SessionHandle,
+    app_state: AppState,
+) -> Result<(), UploadError> {
+    process_file_stream(
+        stream::iter(files.pending.into_iter().map(Ok)),
+        Some(files.total_files),
+        files.processed_files,
+        files.successful_files,
+        options,
+        session,
+        app_state,
+    )
+    .await
+}
 
-    for (file_index, (file_name, data)) in files.iter().enumerate() {
-        // Send image received event
-        let _ = broadcaster.send(ProcessingEvent::ImageReceived {
-            file_index,
-            file_name: file_name.clone(),
-            size_bytes: data.len(),
-            timestamp: Utc::now(),
-        });
+/// Process files as the stream yields them, emitting the session's events
+///
+/// `total_files` is `None` when the number of files is only known once the
+/// stream ends. Each finished file is recorded in the job queue so that a
+/// resumed run only processes what is left.
+///
+/// Pages of multi-page documents are held back until the stream ends, since
+/// another page may still arrive; see [`group_documents`].
+async fn process_file_stream<S>(
+    files: S,
+    total_files: Option<usize>,
+    processed_before: usize,
+    successful_before: usize,
+    options: ProcessingOptions,
+    session: SessionHandle,
+    app_state: AppState,
+) -> Result<(), UploadError>
+where
+    S: Stream<Item = Result<QueuedFile, UploadError>>,
+{
+    let config = app_state.upload_config.clone();
+    let start_time = Instant::now();
 
-        // Send validation start event
-        let _ = broadcaster.send(ProcessingEvent::ImageValidationStart {
-            file_index,
-            file_name: file_name.clone(),
-            timestamp: Utc::now(),
-        });
+    // Send upload started event
+    session.send(ProcessingEvent::UploadStarted {
+        total_files,
+        session_id: session.session_id().to_string(),
+        timestamp: Utc::now(),
+    });
+    session.mark_files_processed(processed_before);
 
-        // Validate file
-        match validate_file(data, config.as_ref(), file_index).await {
-            Ok(file_info) => {
-                let _ = broadcaster.send(ProcessingEvent::ImageValidationSuccess {
-                    file_index,
-                    file_info,
-                    timestamp: Utc::now(),
-                });
+    let concurrency = config.max_concurrent_images.max(1);
+    let mut stopped_early = false;
 
-                // Resize image pixel dimensions before processing with Gemini
-                info!("Resizing image pixel dimensions to 40% for file index {}", file_index);
-                let resized_data = match resize_image_default(data) {
-                    Ok(resized) => {
-                        let size_reduction = ((data.len() - resized.len()) as f32 / data.len() as f32) * 100.0;
-                        info!(
-                            "Image pixel dimensions resized: {} bytes -> {} bytes (file size reduced by {:.1}%)",
-                            data.len(),
-                            resized.len(),
-                            size_reduction
-                        );
-                        resized
+    // Up to `concurrency` files are in flight at once; their events interleave
+    // but each one is tagged with its file_index
+    //
+    // A stream error (a broken upload or a limit violation) ends the stream;
+    // files already in flight still finish before the error is returned.
+    let results: Vec<Result<Vec<FileOutcome>, UploadError>> = group_documents(files, options)
+        .take_while(|_| {
+            // Stop pulling new files once the session was cancelled or its client went away
+            stopped_early = session.cancellation_reason().is_some();
+            future::ready(!stopped_early)
+        })
+        .map(|unit| {
+            let session = &session;
+            let app_state = &app_state;
+            async move {
+                let unit = unit?;
+                if session.cancellation_reason().is_some() {
+                    return Ok(vec![FileOutcome::Skipped; unit.file_count()]);
+                }
+                let outcomes = match unit {
+                    UploadUnit::File(file) => {
+                        let file_index = file.file_index;
+                        let outcome = process_single_file(file, options, session, app_state).await;
+                        vec![(file_index, outcome)]
+                    }
+                    UploadUnit::Document { document, pages } => {
+                        process_document(document, pages, options, session, app_state).await
                     }
-                    Err(e) => {
-                        warn!("Failed to resize image pixel dimensions for file index {}: {}. Using original image.", file_index, e);
-                        data.to_vec()
+                    UploadUnit::Pages(pages) => {
+                        process_grouped_pages(pages, options, session, app_state).await
                     }
                 };
 
-                // Process with Gemini after successful validation and resizing
-                match process_with_gemini(
-                    &resized_data,
-                    file_index,
-                    file_name.clone(),
-                    broadcaster.clone(),
-                    &app_state.pool,
-                )
-                .await
-                {
-                    Ok(()) => {
-                        successful_files += 1;
-                    }
-                    Err(e) => {
-                        // Log Gemini error but don't fail the entire upload
-                        let _ = broadcaster.send(ProcessingEvent::GeminiProcessingError {
-                            file_index,
-                            error_message: format!("Gemini processing failed: {}", e),
-                            timestamp: Utc::now(),
-                        });
-                        successful_files += 1; // Still count as successful since image validation passed
+                for (file_index, outcome) in &outcomes {
+                    if *outcome == FileOutcome::Skipped {
+                        continue;
                     }
+                    session.mark_file_processed();
+                    record_file_outcome(session, app_state, *file_index, *outcome).await;
                 }
+                Ok(outcomes.into_iter().map(|(_, outcome)| outcome).collect())
             }
-            Err(error) => {
-                let _ = broadcaster.send(ProcessingEvent::ImageValidationError {
-                    file_index,
-                    file_name: file_name.clone(),
-                    error_message: error.to_string(),
-                    error_code: map_error_to_code(&error),
-                    timestamp: Utc::now(),
-                });
-            }
+        })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+
+    let mut outcomes = Vec::with_capacity(results.len());
+    for result in results {
+        outcomes.extend(result?);
+    }
+
+    let total_files = total_files.unwrap_or(processed_before + outcomes.len());
+    let processed_files = processed_before
+        + outcomes
+            .iter()
+            .filter(|outcome| **outcome != FileOutcome::Skipped)
+            .count();
+    if (stopped_early || processed_files < total_files)
+        && stop_if_cancelled(&session, total_files, processed_files)
+    {
+        return Ok(());
+    }
+
+    let successful_files = successful_before
+        + outcomes
+            .iter()
+            .filter(|outcome| **outcome == FileOutcome::Succeeded)
+            .count();
+
+    // Send all images validated event
+    session.send(ProcessingEvent::AllImagesValidated {
+        total_processed: total_files,
+        successful_count: successful_files,
+        failed_count: total_files - successful_files,
+        timestamp: Utc::now(),
+    });
+
+    // Send completion event
+    session.send(ProcessingEvent::ProcessingComplete {
+        session_id: session.session_id().to_string(),
+        total_files,
+        successful_files,
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        timestamp: Utc::now(),
+    });
+
+    Ok(())
+}
+
+/// Record a processed file and its result in the job queue, so a resumed
+/// run skips it and the job status outlives the session
+async fn record_file_outcome(
+    session: &SessionHandle,
+    app_state: &AppState,
+    file_index: usize,
+    outcome: FileOutcome,
+) {
+    let status = if outcome == FileOutcome::Succeeded {
+        QueuedFileStatus::Succeeded
+    } else {
+        QueuedFileStatus::Failed
+    };
+    let file = session.file_result(file_index);
+    let result = FileResult {
+        status,
+        bill_ids: file
+            .as_ref()
+            .map(|file| file.bill_ids.clone())
+            .unwrap_or_default(),
+        draft_ids: file
+            .as_ref()
+            .map(|file| file.draft_ids.clone())
+            .unwrap_or_default(),
+        error_message: file.and_then(|file| file.error_message),
+    };
+    if let Err(e) = app_state
+        .job_queue
+        .mark_file(session.session_id(), file_index, &result)
+        .await
+    {
+        warn!(
+            "Failed to record file {} of job {}: {}",
+            file_index,
+            session.session_id(),
+            e
+        );
+    }
+}
+
+/// Files processed together: a single file, the pages of one invoice or the
+/// images of a `group_pages` upload
+enum UploadUnit {
+    File(QueuedFile),
+    Document {
+        /// Label sent with the pages, `None` when grouped by invoice key
+        document: Option<String>,
+        pages: Vec<QueuedFile>,
+    },
+    /// Images extracted one by one and saved as one document per invoice
+    Pages(Vec<QueuedFile>),
+}
+
+impl UploadUnit {
+    /// A document of a single page is processed as a plain file
+    fn document(document: Option<String>, mut pages: Vec<QueuedFile>) -> Self {
+        if pages.len() == 1 {
+            return UploadUnit::File(pages.remove(0));
+        }
+        UploadUnit::Document { document, pages }
+    }
+
+    fn file_count(&self) -> usize {
+        match self {
+            UploadUnit::File(_) => 1,
+            UploadUnit::Document { pages, .. } | UploadUnit::Pages(pages) => pages.len(),
+        }
+    }
+}
+
+/// Turn a stream of files into the units they are processed as
+///
+/// Files without a `document` label are passed on as they arrive. Labelled
+/// files are held until the stream ends and then yielded as one document per
+/// label, pages in upload order. With `group_pages`, unlabelled files are held
+/// as well and yielded together, to be grouped by invoice once extracted; see
+/// [`process_grouped_pages`].
+fn group_documents<S>(
+    files: S,
+    options: ProcessingOptions,
+) -> impl Stream<Item = Result<UploadUnit, UploadError>>
+where
+    S: Stream<Item = Result<QueuedFile, UploadError>>,
+{
+    async_stream::try_stream! {
+        let mut labelled: Vec<(String, Vec<QueuedFile>)> = Vec::new();
+        let mut ungrouped: Vec<QueuedFile> = Vec::new();
+
+        futures_util::pin_mut!(files);
+        while let Some(file) = files.next().await {
+            let file = file?;
+            if let Some(label) = file.document.clone() {
+                match labelled.iter_mut().find(|(document, _)| *document == label) {
+                    Some((_, pages)) => pages.push(file),
+                    None => labelled.push((label, vec![file])),
+                }
+                continue;
+            }
+
+            if options.group_pages {
+                ungrouped.push(file);
+            } else {
+                yield UploadUnit::File(file);
+            }
+        }
+
+        for (label, pages) in labelled {
+            yield UploadUnit::document(Some(label), pages);
+        }
+
+        match ungrouped.len() {
+            0 => {}
+            1 => yield UploadUnit::File(ungrouped.remove(0)),
+            _ => yield UploadUnit::Pages(ungrouped),
+        }
+    }
+}
+
+/// Outcome of one file within an upload session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileOutcome {
+    /// Passed validation (a Gemini failure is reported but still counts as success)
+    Succeeded,
+    /// Rejected by validation
+    Failed,
+    /// Not started because the session was cancelled
+    Skipped,
+}
+
+/// Validate, resize and extract a single file, emitting its events
+async fn process_single_file(
+    file: QueuedFile,
+    options: ProcessingOptions,
+    session: &SessionHandle,
+    app_state: &AppState,
+) -> FileOutcome {
+    let Some(file) = load_file(file, session).await else {
+        return FileOutcome::Failed;
+    };
+    let Some(content_type) = receive_file(
+        file.file_index,
+        file.file_name.clone(),
+        &file.data,
+        session,
+        app_state,
+    )
+    .await
+    else {
+        return FileOutcome::Failed;
+    };
+
+    process_validated_file(file, &content_type, options, session, app_state).await
+}
+
+/// A queued file read into memory to be processed
+struct LoadedFile {
+    file_index: usize,
+    file_name: Option<String>,
+    data: Bytes,
+    email: Option<EmailSource>,
+}
+
+/// Read a queued file into memory once it is its turn to be processed
+///
+/// Returns `None` when its spool file could not be read; the error has been
+/// reported.
+async fn load_file(file: QueuedFile, session: &SessionHandle) -> Option<LoadedFile> {
+    match file.data.load().await {
+        Ok(data) => Some(LoadedFile {
+            file_index: file.file_index,
+            file_name: file.file_name,
+            data,
+            email: file.email,
+        }),
+        Err(e) => {
+            let error = UploadError::StorageError(e.to_string());
+            reject_field(session, file.file_index, file.file_name, &error);
+            None
+        }
+    }
+}
+
+/// Report a file as received and validate it, returning its content type
+///
+/// Returns `None` when validation failed; the error has been reported.
+async fn receive_file(
+    file_index: usize,
+    file_name: Option<String>,
+    data: &[u8],
+    session: &SessionHandle,
+    app_state: &AppState,
+) -> Option<String> {
+    // Send image received event
+    session.send(ProcessingEvent::ImageReceived {
+        file_index,
+        file_name: file_name.clone(),
+        size_bytes: data.len(),
+        timestamp: Utc::now(),
+    });
+
+    // Send validation start event
+    session.send(ProcessingEvent::ImageValidationStart {
+        file_index,
+        file_name: file_name.clone(),
+        timestamp: Utc::now(),
+    });
+
+    // Validate file
+    let file_info = match validate_file(data, app_state.upload_config.as_ref(), file_index).await {
+        Ok(file_info) => file_info,
+        Err(error) => {
+            session.send(ProcessingEvent::ImageValidationError {
+                file_index,
+                file_name,
+                error_message: error.to_string(),
+                error_code: map_error_to_code(&error),
+                timestamp: Utc::now(),
+            });
+            return None;
+        }
+    };
+
+    let content_type = file_info.content_type.clone();
+    session.send(ProcessingEvent::ImageValidationSuccess {
+        file_index,
+        file_info,
+        timestamp: Utc::now(),
+    });
+
+    Some(content_type)
+}
+
+/// Extract a file that passed validation, according to its content type
+async fn process_validated_file(
+    file: LoadedFile,
+    content_type: &str,
+    options: ProcessingOptions,
+    session: &SessionHandle,
+    app_state: &AppState,
+) -> FileOutcome {
+    let LoadedFile {
+        file_index,
+        file_name,
+        data,
+        email,
+    } = file;
+    if content_type == XML_CONTENT_TYPE {
+        return import_einvoice(
+            file_index,
+            file_name,
+            &data,
+            email.as_ref(),
+            options,
+            session,
+            &app_state.pool,
+        )
+        .await;
+    }
+    if content_type == PDF_CONTENT_TYPE {
+        process_pdf_pages(
+            file_index,
+            file_name,
+            data,
+            email.as_ref(),
+            options,
+            session,
+            app_state,
+        )
+        .await;
+        return FileOutcome::Succeeded;
+    }
+
+    let (fingerprint, duplicate) = find_duplicate(data.clone(), file_index, app_state).await;
+    let ControlFlow::Continue(similar) = screen_duplicate(file_index, duplicate, options, session)
+    else {
+        return FileOutcome::Succeeded;
+    };
+
+    let image = prepare_image(
+        data,
+        file_index,
+        None,
+        fingerprint,
+        similar,
+        session,
+        app_state,
+    )
+    .await;
+
+    // Process with Gemini after successful validation and resizing
+    let origin = BillOrigin {
+        file_index,
+        file_name: file_name.as_deref(),
+        page_number: None,
+        email: email.as_ref(),
+        document_key: None,
+    };
+    if let Err(e) = process_with_provider(
+        ExtractionInput::Image(Box::new(image)),
+        origin,
+        options,
+        session,
+        app_state,
+    )
+    .await
+    {
+        // Log Gemini error but don't fail the entire upload
+        session.send(ProcessingEvent::GeminiProcessingError {
+            file_index,
+            page_number: None,
+            error_message: format!("Gemini processing failed: {}", e),
+            timestamp: Utc::now(),
+        });
+    }
+
+    // Still count as successful since image validation passed
+    FileOutcome::Succeeded
+}
+
+/// Validate the pages of a multi-page invoice and extract them as one document
+///
+/// The images are sent to Gemini in a single request and the resulting bills
+/// share one invoice header; they are reported under the `file_index` of the
+/// first page. Pages that fail validation are left out, PDFs and XML files
+/// are processed on their own, and a document left with a single image is
+/// processed like any other image. Returns the outcome of every page.
+async fn process_document(
+    document: Option<String>,
+    pages: Vec<QueuedFile>,
+    options: ProcessingOptions,
+    session: &SessionHandle,
+    app_state: &AppState,
+) -> Vec<(usize, FileOutcome)> {
+    let mut outcomes = Vec::with_capacity(pages.len());
+    let mut images = Vec::with_capacity(pages.len());
+
+    for page in pages {
+        let file_index = page.file_index;
+        let Some(page) = load_file(page, session).await else {
+            outcomes.push((file_index, FileOutcome::Failed));
+            continue;
+        };
+        let Some(content_type) = receive_file(
+            page.file_index,
+            page.file_name.clone(),
+            &page.data,
+            session,
+            app_state,
+        )
+        .await
+        else {
+            outcomes.push((page.file_index, FileOutcome::Failed));
+            continue;
+        };
+        if content_type == XML_CONTENT_TYPE || content_type == PDF_CONTENT_TYPE {
+            let file_index = page.file_index;
+            let outcome =
+                process_validated_file(page, &content_type, options, session, app_state).await;
+            outcomes.push((file_index, outcome));
+        } else {
+            images.push((page, content_type));
+        }
+    }
+
+    if session.cancellation_reason().is_some() {
+        outcomes.extend(
+            images
+                .iter()
+                .map(|(page, _)| (page.file_index, FileOutcome::Skipped)),
+        );
+        return outcomes;
+    }
+
+    if images.len() == 1 {
+        let (page, content_type) = images.remove(0);
+        let file_index = page.file_index;
+        let outcome =
+            process_validated_file(page, &content_type, options, session, app_state).await;
+        outcomes.push((file_index, outcome));
+        return outcomes;
+    }
+    let Some((first_page, _)) = images.first() else {
+        return outcomes;
+    };
+
+    let file_index = first_page.file_index;
+    let origin = BillOrigin {
+        file_index,
+        file_name: first_page.file_name.as_deref(),
+        page_number: None,
+        email: first_page.email.as_ref(),
+        document_key: None,
+    };
+    let page_file_indexes: Vec<usize> = images.iter().map(|(page, _)| page.file_index).collect();
+
+    // The document is skipped when every page was uploaded before with the
+    // same bytes; when every page only looks like a saved one, it is extracted
+    // and compared by invoice key
+    let checks = future::join_all(
+        images
+            .iter()
+            .map(|(page, _)| find_duplicate(page.data.clone(), page.file_index, app_state)),
+    )
+    .await;
+    let all_duplicates = checks.iter().all(|(_, duplicate)| duplicate.is_some());
+    let all_exact = checks
+        .iter()
+        .all(|(_, duplicate)| duplicate.as_ref().is_some_and(|duplicate| duplicate.exact));
+    let skip = all_exact && !options.force_duplicates;
+    let compare_keys = all_duplicates && !all_exact && !options.force_duplicates;
+    let mut checked_pages = Vec::with_capacity(checks.len());
+    for ((fingerprint, duplicate), page_file_index) in checks.into_iter().zip(&page_file_indexes) {
+        let similar = match duplicate {
+            Some(duplicate) if compare_keys => Some(duplicate),
+            Some(duplicate) => {
+                report_duplicate(*page_file_index, duplicate, !skip, session);
+                None
+            }
+            None => None,
+        };
+        checked_pages.push((fingerprint, similar));
+    }
+    if skip {
+        outcomes.extend(
+            page_file_indexes
+                .into_iter()
+                .map(|file_index| (file_index, FileOutcome::Succeeded)),
+        );
+        return outcomes;
+    }
+    info!(
+        "Extracting file indexes {:?} as one {}-page document {:?}",
+        page_file_indexes,
+        page_file_indexes.len(),
+        document
+    );
+    session.send(ProcessingEvent::DocumentPagesGrouped {
+        file_index,
+        document,
+        page_file_indexes: page_file_indexes.clone(),
+        timestamp: Utc::now(),
+    });
+
+    let prepared_pages = future::join_all(images.iter().zip(checked_pages).map(
+        |((page, _), (fingerprint, similar))| {
+            prepare_image(
+                page.data.clone(),
+                page.file_index,
+                None,
+                fingerprint,
+                similar,
+                session,
+                app_state,
+            )
+        },
+    ))
+    .await;
+
+    if let Err(e) = process_with_provider(
+        ExtractionInput::Document(prepared_pages),
+        origin,
+        options,
+        session,
+        app_state,
+    )
+    .await
+    {
+        session.send(ProcessingEvent::GeminiProcessingError {
+            file_index,
+            page_number: None,
+            error_message: format!("Gemini processing failed: {}", e),
+            timestamp: Utc::now(),
+        });
+    }
+
+    outcomes.extend(
+        page_file_indexes
+            .into_iter()
+            .map(|file_index| (file_index, FileOutcome::Succeeded)),
+    );
+    outcomes
+}
+
+/// An image of a `group_pages` upload, extracted on its own and waiting to be
+/// saved with the other pages of its invoice
+struct ExtractedPage {
+    file_index: usize,
+    file_name: Option<String>,
+    email: Option<EmailSource>,
+    input: ExtractionInput,
+    responses: Vec<GeminiResponse>,
+}
+
+impl ExtractedPage {
+    /// Invoice the page belongs to, as named by its QR code or else by the
+    /// bills extracted from it
+    fn invoice_key(&self) -> Option<InvoiceKey> {
+        self.input
+            .invoice_qr()
+            .and_then(InvoiceKey::from_qr)
+            .or_else(|| self.responses.iter().find_map(InvoiceKey::from_response))
+    }
+}
+
+/// Extract the images of a `group_pages` upload and save them as one document
+/// per invoice
+///
+/// Every image is validated, checked for duplicates and extracted on its own,
+/// up to `max_concurrent_images` at a time, so that pages without a QR code
+/// are grouped by the serial and number read from them. The pages of each
+/// invoice are then saved together under the `file_index` of the first one;
+/// PDFs and XML files are processed on their own. Returns the outcome of
+/// every file.
+async fn process_grouped_pages(
+    pages: Vec<QueuedFile>,
+    options: ProcessingOptions,
+    session: &SessionHandle,
+    app_state: &AppState,
+) -> Vec<(usize, FileOutcome)> {
+    let concurrency = app_state.upload_config.max_concurrent_images.max(1);
+    let results: Vec<(usize, FileOutcome, Option<ExtractedPage>)> = stream::iter(pages)
+        .map(|page| extract_page(page, options, session, app_state))
+        .buffered(concurrency)
+        .collect()
+        .await;
+
+    let mut outcomes = Vec::with_capacity(results.len());
+    let mut extracted = Vec::new();
+    for (file_index, outcome, page) in results {
+        outcomes.push((file_index, outcome));
+        extracted.extend(page);
+    }
+
+    for group in group_extracted_pages(extracted) {
+        save_page_group(group, options, session, app_state).await;
+    }
+    outcomes
+}
+
+/// Validate and extract one image of a `group_pages` upload
+///
+/// Returns the page when it has bills left to save.
+async fn extract_page(
+    page: QueuedFile,
+    options: ProcessingOptions,
+    session: &SessionHandle,
+    app_state: &AppState,
+) -> (usize, FileOutcome, Option<ExtractedPage>) {
+    let file_index = page.file_index;
+    if session.cancellation_reason().is_some() {
+        return (file_index, FileOutcome::Skipped, None);
+    }
+    let Some(page) = load_file(page, session).await else {
+        return (file_index, FileOutcome::Failed, None);
+    };
+    let Some(content_type) = receive_file(
+        file_index,
+        page.file_name.clone(),
+        &page.data,
+        session,
+        app_state,
+    )
+    .await
+    else {
+        return (file_index, FileOutcome::Failed, None);
+    };
+    if content_type == XML_CONTENT_TYPE || content_type == PDF_CONTENT_TYPE {
+        let outcome =
+            process_validated_file(page, &content_type, options, session, app_state).await;
+        return (file_index, outcome, None);
+    }
+
+    let (fingerprint, duplicate) = find_duplicate(page.data.clone(), file_index, app_state).await;
+    let ControlFlow::Continue(similar) = screen_duplicate(file_index, duplicate, options, session)
+    else {
+        return (file_index, FileOutcome::Succeeded, None);
+    };
+    let image = prepare_image(
+        page.data,
+        file_index,
+        None,
+        fingerprint,
+        similar,
+        session,
+        app_state,
+    )
+    .await;
+
+    let input = ExtractionInput::Image(Box::new(image));
+    let origin = BillOrigin {
+        file_index,
+        file_name: page.file_name.as_deref(),
+        page_number: None,
+        email: page.email.as_ref(),
+        document_key: None,
+    };
+    match extract_with_provider(&input, origin, session, app_state).await {
+        Ok(Some(responses)) => (
+            file_index,
+            FileOutcome::Succeeded,
+            Some(ExtractedPage {
+                file_index,
+                file_name: page.file_name,
+                email: page.email,
+                input,
+                responses,
+            }),
+        ),
+        Ok(None) => (file_index, FileOutcome::Succeeded, None),
+        Err(e) => {
+            session.send(ProcessingEvent::GeminiProcessingError {
+                file_index,
+                page_number: None,
+                error_message: format!("Gemini processing failed: {}", e),
+                timestamp: Utc::now(),
+            });
+            (file_index, FileOutcome::Succeeded, None)
+        }
+    }
+}
+
+/// Group extracted pages by the invoice they belong to, in upload order
+fn group_extracted_pages(pages: Vec<ExtractedPage>) -> Vec<Vec<ExtractedPage>> {
+    let keys: Vec<Option<InvoiceKey>> = pages.iter().map(ExtractedPage::invoice_key).collect();
+    let mut pages: Vec<Option<ExtractedPage>> = pages.into_iter().map(Some).collect();
+    group_by_invoice_key(&keys)
+        .into_iter()
+        .map(|group| {
+            group
+                .into_iter()
+                .filter_map(|position| pages[position].take())
+                .collect()
+        })
+        .collect()
+}
+
+/// Save the pages of one invoice, as a document when there are several
+async fn save_page_group(
+    mut group: Vec<ExtractedPage>,
+    options: ProcessingOptions,
+    session: &SessionHandle,
+    app_state: &AppState,
+) {
+    let file_index = group[0].file_index;
+    let file_name = group[0].file_name.clone();
+    let email = group[0].email.clone();
+    let (input, responses) = if group.len() == 1 {
+        let page = group.remove(0);
+        (page.input, page.responses)
+    } else {
+        let page_file_indexes: Vec<usize> = group.iter().map(|page| page.file_index).collect();
+        info!(
+            "Saving file indexes {:?} as one {}-page document",
+            page_file_indexes,
+            page_file_indexes.len()
+        );
+        session.send(ProcessingEvent::DocumentPagesGrouped {
+            file_index,
+            document: None,
+            page_file_indexes,
+            timestamp: Utc::now(),
+        });
+
+        let mut images = Vec::with_capacity(group.len());
+        let mut responses = Vec::new();
+        for page in group {
+            images.extend(page.input.into_images());
+            responses.extend(page.responses);
+        }
+        unify_invoice_header(&mut responses);
+        (ExtractionInput::Document(images), responses)
+    };
+
+    let origin = BillOrigin {
+        file_index,
+        file_name: file_name.as_deref(),
+        page_number: None,
+        email: email.as_ref(),
+        document_key: None,
+    };
+    if let Err(e) = save_extraction(&input, responses, origin, options, session, app_state).await {
+        session.send(ProcessingEvent::GeminiProcessingError {
+            file_index,
+            page_number: None,
+            error_message: format!("Gemini processing failed: {}", e),
+            timestamp: Utc::now(),
+        });
+    }
+}
+
+/// Extract every page of a PDF, reporting each one as a sub-item of the file
+///
+/// Pages with a text layer are extracted from their text, scanned pages from
+/// their embedded image, and pages with neither are skipped. A page whose
+/// extraction fails does not stop the remaining pages.
+///
+/// The PDF itself is kept in the image store, so the bills of every page
+/// list it as their first source image, followed by the page's embedded
+/// image for a scanned page.
+async fn process_pdf_pages(
+    file_index: usize,
+    file_name: Option<String>,
+    data: Bytes,
+    email: Option<&EmailSource>,
+    options: ProcessingOptions,
+    session: &SessionHandle,
+    app_state: &AppState,
+) {
+    let document_key = store_original(&data, file_index, &app_state.image_store).await;
+    let pages = match tokio::task::spawn_blocking(move || extract_pages(&data)).await {
+        Ok(Ok(pages)) => pages,
+        Ok(Err(e)) => {
+            session.send(ProcessingEvent::GeminiProcessingError {
+                file_index,
+                page_number: None,
+                error_message: format!("PDF extraction failed: {}", e),
+                timestamp: Utc::now(),
+            });
+            return;
+        }
+        Err(e) => {
+            session.send(ProcessingEvent::GeminiProcessingError {
+                file_index,
+                page_number: None,
+                error_message: format!("PDF extraction task failed: {}", e),
+                timestamp: Utc::now(),
+            });
+            return;
+        }
+    };
+
+    let total_pages = pages.len();
+    info!(
+        "Extracted {} page(s) from PDF file index {}",
+        total_pages, file_index
+    );
+
+    for page in pages {
+        if session.cancellation_reason().is_some() {
+            break;
+        }
+
+        session.send(ProcessingEvent::PdfPageExtracted {
+            file_index,
+            page_number: page.page_number,
+            total_pages,
+            content: page.content.kind(),
+            timestamp: Utc::now(),
+        });
+
+        let input = match page.content {
+            PageContent::Text(text) => ExtractionInput::Text(text),
+            PageContent::Image(image) => {
+                let image = prepare_image(
+                    Bytes::from(image),
+                    file_index,
+                    Some(page.page_number),
+                    None,
+                    None,
+                    session,
+                    app_state,
+                )
+                .await;
+                ExtractionInput::Image(Box::new(image))
+            }
+            PageContent::Empty => continue,
+        };
+
+        let origin = BillOrigin {
+            file_index,
+            file_name: file_name.as_deref(),
+            page_number: Some(page.page_number),
+            email,
+            document_key: document_key.as_deref(),
+        };
+        if let Err(e) = process_with_provider(input, origin, options, session, app_state).await {
+            session.send(ProcessingEvent::GeminiProcessingError {
+                file_index,
+                page_number: Some(page.page_number),
+                error_message: format!("Gemini processing failed: {}", e),
+                timestamp: Utc::now(),
+            });
+        }
+    }
+}
+
+/// Save the lines of an e-invoice XML file as bills, without a Gemini request
+///
+/// The `EInvoiceImported` event lists the issues found in the file; in
+/// draft mode it also carries the staged drafts, otherwise a `BillDataSaved`
+/// event follows for every saved bill.
+async fn import_einvoice(
+    file_index: usize,
+    file_name: Option<String>,
+    data: &[u8],
+    email: Option<&EmailSource>,
+    options: ProcessingOptions,
+    session: &SessionHandle,
+    connection_pool: &ConnectionPool,
+) -> FileOutcome {
+    let invoice = match parse_einvoice(data) {
+        Ok(invoice) => invoice,
+        Err(e) => {
+            let error = UploadError::InvalidEInvoice(e.to_string());
+            reject_field(session, file_index, file_name, &error);
+            return FileOutcome::Failed;
+        }
+    };
+    let bills = invoice.to_bills();
+    info!(
+        "Importing e-invoice {:?}/{:?} from file index {}: {} line(s), {} issue(s)",
+        invoice.serial_no,
+        invoice.invoice_no,
+        file_index,
+        bills.len(),
+        invoice.issues.len()
+    );
+
+    let (drafts, error_message) = if options.draft_mode {
+        match stage_bills(&bills, file_index, &[], session, connection_pool).await {
+            Ok(drafts) => (drafts, None),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        }
+    } else {
+        (Vec::new(), None)
+    };
+    let failed = error_message.is_some();
+    session.send(ProcessingEvent::EInvoiceImported {
+        file_index,
+        file_name: file_name.clone(),
+        serial_no: invoice.serial_no,
+        invoice_no: invoice.invoice_no,
+        line_count: bills.len(),
+        issues: invoice.issues,
+        drafts,
+        error_message,
+        timestamp: Utc::now(),
+    });
+    if failed {
+        return FileOutcome::Failed;
+    }
+    if options.draft_mode {
+        return FileOutcome::Succeeded;
+    }
+
+    let origin = BillOrigin {
+        file_index,
+        file_name: file_name.as_deref(),
+        page_number: None,
+        email,
+        document_key: None,
+    };
+    for bill_data in bills {
+        match save_bill(bill_data, &[], origin, connection_pool).await {
+            Ok(bill) => {
+                session.send(ProcessingEvent::BillDataSaved {
+                    file_index,
+                    page_number: None,
+                    bill_id: bill.id,
+                    timestamp: Utc::now(),
+                });
+            }
+            Err(e) => {
+                error!(
+                    "Failed to save e-invoice line of file index {} to database: {:?}",
+                    file_index, e
+                );
+            }
+        }
+    }
+
+    FileOutcome::Succeeded
+}
+
+/// Fingerprint an uploaded image and look for bills extracted from the same image
+///
+/// A failure to hash or look up the image is logged and does not stop its
+/// extraction; the fingerprint is then missing or no duplicate is reported.
+async fn find_duplicate(
+    data: Bytes,
+    file_index: usize,
+    app_state: &AppState,
+) -> (Option<ImageFingerprint>, Option<DuplicateMatch>) {
+    let fingerprint = match tokio::task::spawn_blocking(move || fingerprint_image(&data)).await {
+        Ok(Ok(fingerprint)) => fingerprint,
+        Ok(Err(e)) => {
+            warn!(
+                "Failed to fingerprint image of file index {}: {}",
+                file_index, e
+            );
+            return (None, None);
+        }
+        Err(e) => {
+            warn!(
+                "Fingerprint task failed for file index {}: {}",
+                file_index, e
+            );
+            return (None, None);
+        }
+    };
+
+    let fingerprint_service = ImageFingerprintService::new(app_state.pool.pool().clone());
+    let duplicate = fingerprint_service
+        .find_duplicates(
+            &fingerprint,
+            app_state.upload_config.duplicate_max_hash_distance,
+        )
+        .await
+        .unwrap_or_else(|e| {
+            warn!(
+                "Failed to look up duplicates of file index {}: {:?}",
+                file_index, e
+            );
+            None
+        });
+    (Some(fingerprint), duplicate)
+}
+
+/// Decide whether an image that matches saved ones is extracted
+///
+/// An exact copy is reported and skipped unless the upload forces
+/// processing. An image that only looks like a saved one may be a new
+/// invoice printed on the same layout: its match is returned, to be reported
+/// once its invoice key is known.
+fn screen_duplicate(
+    file_index: usize,
+    duplicate: Option<DuplicateMatch>,
+    options: ProcessingOptions,
+    session: &SessionHandle,
+) -> ControlFlow<(), Option<DuplicateMatch>> {
+    match duplicate {
+        Some(duplicate) if duplicate.exact || options.force_duplicates => {
+            report_duplicate(file_index, duplicate, options.force_duplicates, session);
+            if options.force_duplicates {
+                ControlFlow::Continue(None)
+            } else {
+                ControlFlow::Break(())
+            }
+        }
+        similar => ControlFlow::Continue(similar),
+    }
+}
+
+/// Send `DuplicateDetected` for an image that was uploaded before
+///
+/// `forced` tells whether the image is extracted anyway.
+fn report_duplicate(
+    file_index: usize,
+    duplicate: DuplicateMatch,
+    forced: bool,
+    session: &SessionHandle,
+) {
+    info!(
+        "File index {} duplicates the image of bill(s) {:?} and draft(s) {:?} (exact: {}){}",
+        file_index,
+        duplicate.bill_ids,
+        duplicate.draft_ids,
+        duplicate.exact,
+        if forced {
+            ", extracting it anyway"
+        } else {
+            ", skipping it"
+        }
+    );
+    session.send(ProcessingEvent::DuplicateDetected {
+        file_index,
+        exact: duplicate.exact,
+        bill_ids: duplicate.bill_ids,
+        draft_ids: duplicate.draft_ids,
+        forced,
+        timestamp: Utc::now(),
+    });
+}
+
+/// Report the images of an extraction that look like saved ones, once it is
+/// known whether they are of a saved invoice
+///
+/// Returns `true` when they are and the extraction must not be saved. An
+/// image is taken for a saved invoice when the invoice key of its QR code or
+/// of the extracted bills matches one of the bills it looks like; without
+/// such a key it is extracted.
+async fn report_similar_images(
+    input: &ExtractionInput,
+    keys: &[InvoiceKey],
+    session: &SessionHandle,
+    app_state: &AppState,
+) -> bool {
+    let similar: Vec<(usize, &DuplicateMatch)> = input
+        .images()
+        .iter()
+        .filter_map(|image| Some((image.file_index, image.similar.as_ref()?)))
+        .collect();
+    if similar.is_empty() {
+        return false;
+    }
+
+    let mut bill_ids: Vec<i32> = similar
+        .iter()
+        .flat_map(|(_, duplicate)| duplicate.bill_ids.iter().copied())
+        .collect();
+    bill_ids.sort_unstable();
+    bill_ids.dedup();
+    let mut draft_ids: Vec<i32> = similar
+        .iter()
+        .flat_map(|(_, duplicate)| duplicate.draft_ids.iter().copied())
+        .collect();
+    draft_ids.sort_unstable();
+    draft_ids.dedup();
+    let fingerprint_service = ImageFingerprintService::new(app_state.pool.pool().clone());
+    let saved_keys = fingerprint_service
+        .invoice_keys(&bill_ids, &draft_ids)
+        .await
+        .unwrap_or_else(|e| {
+            warn!(
+                "Failed to look up invoice keys of bill(s) {:?} and draft(s) {:?}: {:?}",
+                bill_ids, draft_ids, e
+            );
+            Vec::new()
+        });
+
+    let saved = is_saved_invoice(keys, &saved_keys);
+    for (file_index, duplicate) in similar {
+        report_duplicate(file_index, duplicate.clone(), !saved, session);
+    }
+    saved
+}
+
+/// Whether any of the invoice keys names one of the saved invoices
+fn is_saved_invoice(keys: &[InvoiceKey], saved_keys: &[InvoiceKey]) -> bool {
+    keys.iter()
+        .any(|key| saved_keys.iter().any(|saved| key.same_invoice(saved)))
+}
+
+/// An uploaded image ready for extraction
+struct PreparedImage {
+    /// Preprocessed image bytes sent to Gemini
+    data: Vec<u8>,
+    /// Invoice QR code read from the original
+    invoice_qr: Option<InvoiceQrData>,
+    /// Key of the original in the image store
+    stored_key: Option<String>,
+    /// Upload position of the original
+    file_index: usize,
+    /// Fingerprint of the original, recorded with the extracted bills
+    fingerprint: Option<ImageFingerprint>,
+    /// Saved bills whose images only look like the original
+    similar: Option<DuplicateMatch>,
+    /// SHA-256 of the original, which keys Gemini cassettes
+    source_sha256: String,
+}
+
+impl PreparedImage {
+    fn extraction_image(&self) -> ExtractionImage<'_> {
+        ExtractionImage {
+            data: &self.data,
+            source_sha256: Some(&self.source_sha256),
+        }
+    }
+}
+
+/// Preprocess an image for Gemini, read its invoice QR code from the original
+/// and keep the original in the image store
+///
+/// The QR code is decoded at full resolution, alongside the preprocessing.
+async fn prepare_image(
+    data: Bytes,
+    file_index: usize,
+    page_number: Option<usize>,
+    fingerprint: Option<ImageFingerprint>,
+    similar: Option<DuplicateMatch>,
+    session: &SessionHandle,
+    app_state: &AppState,
+) -> PreparedImage {
+    let source_sha256 = fingerprint
+        .as_ref()
+        .map(|fingerprint| fingerprint.sha256.clone())
+        .unwrap_or_else(|| sha256_hex(&data));
+    let (preprocessed, invoice_qr, stored_key) = tokio::join!(
+        preprocess_for_extraction(
+            data.clone(),
+            file_index,
+            page_number,
+            session,
+            app_state
+                .upload_config
+                .image_pipeline_for(app_state.extraction.kind())
+        ),
+        read_invoice_qr(data.clone(), file_index),
+        store_original(&data, file_index, &app_state.image_store)
+    );
+    PreparedImage {
+        data: preprocessed,
+        invoice_qr,
+        stored_key,
+        file_index,
+        fingerprint,
+        similar,
+        source_sha256,
+    }
+}
+
+/// Keep an uploaded image in the image store; a failure is logged and the
+/// bills are saved without a source image
+async fn store_original(
+    data: &[u8],
+    file_index: usize,
+    image_store: &ImageStore,
+) -> Option<String> {
+    image_store
+        .put(data)
+        .await
+        .map_err(|e| {
+            warn!(
+                "Failed to store original image of file index {}: {}",
+                file_index, e
+            )
+        })
+        .ok()
+}
+
+/// Run the preprocessing pipeline on an image before it is sent to Gemini,
+/// falling back to the original on failure
+///
+/// Reports what each step did with an `ImagePreprocessed` event.
+async fn preprocess_for_extraction(
+    data: Bytes,
+    file_index: usize,
+    page_number: Option<usize>,
+    session: &SessionHandle,
+    pipeline: ResizeConfig,
+) -> Vec<u8> {
+    // Decoding, the pipeline steps and encoding are CPU-bound, so keep them
+    // off the async workers
+    info!("Preprocessing image for file index {}", file_index);
+    let input = data.clone();
+    let result = tokio::task::spawn_blocking(move || preprocess_image(&input, &pipeline)).await;
+    match result {
+        Ok(Ok(image)) => {
+            session.send(ProcessingEvent::ImagePreprocessed {
+                file_index,
+                page_number,
+                original_width: image.original_dimensions.0,
+                original_height: image.original_dimensions.1,
+                width: image.dimensions.0,
+                height: image.dimensions.1,
+                steps: image.steps,
+                timestamp: Utc::now(),
+            });
+            image.data
+        }
+        Ok(Err(e)) => {
+            warn!(
+                "Failed to preprocess image for file index {}: {}. Using original image.",
+                file_index, e
+            );
+            data.to_vec()
+        }
+        Err(e) => {
+            warn!(
+                "Preprocessing task failed for file index {}: {}. Using original image.",
+                file_index, e
+            );
+            data.to_vec()
         }
     }
+}
 
-    // Send all images validated event
-    let _ = broadcaster.send(ProcessingEvent::AllImagesValidated {
-        total_processed: files.len(),
-        successful_count: successful_files,
-        failed_count: files.len() - successful_files,
-        timestamp: Utc::now(),
-    });
+/// End the session with `ProcessingCancelled` if it should stop processing
+///
+/// Returns `true` when the caller must not process any further files.
+fn stop_if_cancelled(session: &SessionHandle, total_files: usize, processed_files: usize) -> bool {
+    let Some(reason) = session.cancellation_reason() else {
+        return false;
+    };
 
-    // Send completion event
-    let _ = broadcaster.send(ProcessingEvent::ProcessingComplete {
-        session_id,
-        total_files: files.len(),
-        successful_files,
-        duration_ms: start_time.elapsed().as_millis() as u64,
+    let saved_bill_ids = session.saved_bill_ids();
+    info!(
+        "Stopping session {} after {}/{} file(s): {:?} ({} bill(s) already saved)",
+        session.session_id(),
+        processed_files,
+        total_files,
+        reason,
+        saved_bill_ids.len()
+    );
+
+    session.send(ProcessingEvent::ProcessingCancelled {
+        session_id: session.session_id().to_string(),
+        reason,
+        total_files,
+        processed_files,
+        saved_bill_ids,
         timestamp: Utc::now(),
     });
 
-    Ok(())
+    true
 }
 
 async fn validate_file(
@@ -237,7 +2105,7 @@ async fn validate_file(
     let validation_start = Instant::now();
 
     validate_file_size(data.len(), config.max_file_size_bytes)?;
-    let content_type = validate_image_format(data).await?;
+    let content_type = validate_upload_format(data, config.max_pdf_pages).await?;
 
     let processing_duration = validation_start.elapsed().as_millis() as u64;
 
@@ -257,96 +2125,278 @@ async fn validate_file(
     })
 }
 
-/// Process image with Gemini AI and save extracted bill data
+/// The file, or PDF page, that bills are extracted from
+#[derive(Debug, Clone, Copy)]
+struct BillOrigin<'a> {
+    file_index: usize,
+    file_name: Option<&'a str>,
+    /// Set when the bills come from a page of a PDF
+    page_number: Option<usize>,
+    /// Email the file was attached to, recorded as the provenance of its bills
+    email: Option<&'a EmailSource>,
+    /// Image store key of the PDF a page was taken from, recorded on the
+    /// bills ahead of the page's own image
+    document_key: Option<&'a str>,
+}
+
+/// Content sent to Gemini for one extraction
+enum ExtractionInput {
+    /// A single image
+    Image(Box<PreparedImage>),
+    /// The images of the pages of one invoice
+    Document(Vec<PreparedImage>),
+    /// Invoice text, such as a PDF page's text layer
+    Text(String),
+}
+
+impl ExtractionInput {
+    /// The uploaded images, in page order
+    fn images(&self) -> &[PreparedImage] {
+        match self {
+            ExtractionInput::Image(image) => std::slice::from_ref(image.as_ref()),
+            ExtractionInput::Document(pages) => pages,
+            ExtractionInput::Text(_) => &[],
+        }
+    }
+
+    /// The uploaded images, in page order
+    fn into_images(self) -> Vec<PreparedImage> {
+        match self {
+            ExtractionInput::Image(image) => vec![*image],
+            ExtractionInput::Document(pages) => pages,
+            ExtractionInput::Text(_) => Vec::new(),
+        }
+    }
+
+    /// Invoice QR code found on any of the images
+    fn invoice_qr(&self) -> Option<&InvoiceQrData> {
+        self.images()
+            .iter()
+            .find_map(|image| image.invoice_qr.as_ref())
+    }
+
+    /// Fingerprints of the uploaded images, recorded with the extracted bills
+    fn fingerprints(&self) -> impl Iterator<Item = &ImageFingerprint> {
+        self.images()
+            .iter()
+            .filter_map(|image| image.fingerprint.as_ref())
+    }
+
+    /// Image store keys of the originals, recorded on the extracted bills
+    fn source_images(&self) -> Vec<String> {
+        self.images()
+            .iter()
+            .filter_map(|image| image.stored_key.clone())
+            .collect()
+    }
+}
+
+/// Process an image, a multi-page document or invoice text with the
+/// configured extraction provider and save extracted bill data
 #[instrument(
-    skip(image_data, broadcaster, connection_pool),
-    fields(file_index, file_name)
+    skip_all,
+    fields(file_index = origin.file_index, file_name = origin.file_name)
 )]
-async fn process_with_gemini(
-    image_data: &[u8],
-    file_index: usize,
-    file_name: Option<String>,
-    broadcaster: broadcast::Sender<ProcessingEvent>,
-    connection_pool: &crate::config::ConnectionPool,
+async fn process_with_provider(
+    input: ExtractionInput,
+    origin: BillOrigin<'_>,
+    options: ProcessingOptions,
+    session: &SessionHandle,
+    app_state: &AppState,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(gemini_responses) = extract_with_provider(&input, origin, session, app_state).await?
+    else {
+        return Ok(());
+    };
+    save_extraction(
+        &input,
+        gemini_responses,
+        origin,
+        options,
+        session,
+        app_state,
+    )
+    .await
+}
+
+/// Extract the bills of an image, a multi-page document or invoice text with
+/// the configured extraction provider
+///
+/// The bills of a document share one invoice header and the invoice QR code
+/// overrides what was read. Returns `None` when the input turned out to be an
+/// invoice that was saved before.
+async fn extract_with_provider(
+    input: &ExtractionInput,
+    origin: BillOrigin<'_>,
+    session: &SessionHandle,
+    app_state: &AppState,
+) -> Result<Option<Vec<GeminiResponse>>, Box<dyn std::error::Error + Send + Sync>> {
+    let BillOrigin {
+        file_index,
+        file_name,
+        page_number,
+        ..
+    } = origin;
+    let provider = &app_state.extraction;
     info!(
-        "Starting Gemini processing for file {} (index: {})",
-        file_name.as_deref().unwrap_or("unknown"),
+        "Starting {} processing for file {} (index: {})",
+        provider.kind(),
+        file_name.unwrap_or("unknown"),
         file_index
     );
 
-    // Send Gemini processing start event
-    let _ = broadcaster.send(ProcessingEvent::GeminiProcessingStart {
+    // A QR code settles whether a look-alike image is a saved invoice before
+    // anything is sent to Gemini
+    let qr_keys: Vec<InvoiceKey> = input
+        .invoice_qr()
+        .and_then(InvoiceKey::from_qr)
+        .into_iter()
+        .collect();
+    let compare_extracted_keys = qr_keys.is_empty();
+    if !compare_extracted_keys && report_similar_images(input, &qr_keys, session, app_state).await {
+        return Ok(None);
+    }
+
+    // Send processing start event
+    session.send(ProcessingEvent::GeminiProcessingStart {
         file_index,
-        file_name: file_name.clone(),
+        file_name: file_name.map(str::to_string),
+        page_number,
         timestamp: Utc::now(),
     });
 
-    // Initialize Gemini service
-    debug!("Initializing Gemini service");
-    let gemini_service = GeminiService::with_default_config().map_err(|e| {
-        error!("Failed to initialize Gemini service: {}", e);
-        format!("Failed to initialize Gemini service: {}", e)
-    })?;
-
-    // Extract bill data from image
-    let gemini_responses = match gemini_service.extract_bill_data(image_data).await {
+    // Extract bill data from the image or text
+    let extraction = match input {
+        ExtractionInput::Image(image) => provider.extract_image(image.extraction_image()).await,
+        ExtractionInput::Document(pages) => {
+            let pages: Vec<ExtractionImage> =
+                pages.iter().map(PreparedImage::extraction_image).collect();
+            provider.extract_pages(&pages).await
+        }
+        ExtractionInput::Text(text) => provider.extract_text(text).await,
+    };
+    let mut gemini_responses = match extraction {
         Ok(response) => response,
-        Err(GeminiError::RateLimitExceeded { retry_after }) => {
-            let error_msg = format!(
-                "Gemini API rate limit exceeded. Retry after: {:?} seconds",
-                retry_after
-            );
-            let _ = broadcaster.send(ProcessingEvent::GeminiProcessingError {
+        Err(e) => {
+            let error_msg = extraction_error_message(provider.as_ref(), &e);
+            session.send(ProcessingEvent::GeminiProcessingError {
                 file_index,
+                page_number,
                 error_message: error_msg.clone(),
                 timestamp: Utc::now(),
             });
             return Err(UploadError::MultipartError(error_msg).into());
         }
-        Err(GeminiError::AuthenticationFailed) => {
-            let error_msg =
-                "Gemini API authentication failed. Please check your API key.".to_string();
-            let _ = broadcaster.send(ProcessingEvent::GeminiProcessingError {
+    };
+
+    if let ExtractionInput::Document(..) = input {
+        unify_invoice_header(&mut gemini_responses);
+    }
+
+    if let Some(invoice_qr) = input.invoice_qr() {
+        let discrepancies = reconcile(&mut gemini_responses, invoice_qr);
+        if !discrepancies.is_empty() {
+            warn!(
+                "Invoice QR code of file index {} disagrees with Gemini on {} field(s); keeping the QR values",
                 file_index,
-                error_message: error_msg.clone(),
-                timestamp: Utc::now(),
-            });
-            return Err(UploadError::MultipartError(error_msg).into());
-        }
-        Err(GeminiError::Timeout { seconds }) => {
-            let error_msg = format!("Gemini API request timeout after {} seconds", seconds);
-            let _ = broadcaster.send(ProcessingEvent::GeminiProcessingError {
+                discrepancies.len()
+            );
+            session.send(ProcessingEvent::QrDiscrepancyDetected {
                 file_index,
-                error_message: error_msg.clone(),
+                page_number,
+                discrepancies,
                 timestamp: Utc::now(),
             });
-            return Err(UploadError::MultipartError(error_msg).into());
         }
-        Err(GeminiError::ApiError { status, message }) => {
-            let error_msg = format!("Gemini API error {}: {}", status, message);
-            let _ = broadcaster.send(ProcessingEvent::GeminiProcessingError {
+    }
+
+    if compare_extracted_keys {
+        let keys: Vec<InvoiceKey> = gemini_responses
+            .iter()
+            .filter_map(InvoiceKey::from_response)
+            .collect();
+        if report_similar_images(input, &keys, session, app_state).await {
+            info!(
+                "File index {} is an invoice that was saved before; not saving it again",
+                file_index
+            );
+            session.send(ProcessingEvent::GeminiProcessingSuccess {
                 file_index,
-                error_message: error_msg.clone(),
+                page_number,
+                extracted_data: gemini_responses,
+                drafts: Vec::new(),
                 timestamp: Utc::now(),
             });
-            return Err(UploadError::MultipartError(error_msg).into());
+            return Ok(None);
         }
-        Err(e) => {
-            let error_msg = format!("Gemini processing failed: {}", e);
-            let _ = broadcaster.send(ProcessingEvent::GeminiProcessingError {
-                file_index,
-                error_message: error_msg.clone(),
-                timestamp: Utc::now(),
-            });
-            return Err(UploadError::MultipartError(error_msg).into());
+    }
+
+    Ok(Some(gemini_responses))
+}
+
+/// Save the bills extracted from an input, or stage them as drafts in draft mode
+///
+/// The bills are recorded with the fingerprints and stored originals of the
+/// input's images, and with the email the file came from.
+async fn save_extraction(
+    input: &ExtractionInput,
+    gemini_responses: Vec<GeminiResponse>,
+    origin: BillOrigin<'_>,
+    options: ProcessingOptions,
+    session: &SessionHandle,
+    app_state: &AppState,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let BillOrigin {
+        file_index,
+        page_number,
+        ..
+    } = origin;
+    let connection_pool = &app_state.pool;
+    let fingerprint_service = ImageFingerprintService::new(connection_pool.pool().clone());
+    let source_images: Vec<String> = origin
+        .document_key
+        .map(str::to_string)
+        .into_iter()
+        .chain(input.source_images())
+        .collect();
+    if options.draft_mode {
+        let drafts = stage_drafts(
+            &gemini_responses,
+            file_index,
+            &source_images,
+            session,
+            connection_pool,
+        )
+        .await?;
+        for draft in &drafts {
+            for fingerprint in input.fingerprints() {
+                if let Err(e) = fingerprint_service
+                    .record_for_draft(fingerprint, draft.id)
+                    .await
+                {
+                    warn!(
+                        "Failed to record image fingerprint of draft {}: {:?}",
+                        draft.id, e
+                    );
+                }
+            }
         }
-    };
+        session.send(ProcessingEvent::GeminiProcessingSuccess {
+            file_index,
+            page_number,
+            extracted_data: gemini_responses,
+            drafts,
+            timestamp: Utc::now(),
+        });
+        return Ok(());
+    }
 
     // Send Gemini processing success event
-    let _ = broadcaster.send(ProcessingEvent::GeminiProcessingSuccess {
+    session.send(ProcessingEvent::GeminiProcessingSuccess {
         file_index,
+        page_number,
         extracted_data: gemini_responses.clone(),
+        drafts: Vec::new(),
         timestamp: Utc::now(),
     });
 
@@ -355,7 +2405,6 @@ async fn process_with_gemini(
         gemini_responses.len()
     );
     let extractor = BillDataExtractor::new();
-    let bill_service = BillService::new(connection_pool.pool().clone());
 
     for (candidate_idx, response) in gemini_responses.iter().enumerate() {
         debug!(
@@ -376,14 +2425,26 @@ async fn process_with_gemini(
             candidate_idx, bill_data.form_no, bill_data.invoice_no
         );
 
-        match bill_service.create_bill(bill_data).await {
+        match save_bill(bill_data, &source_images, origin, connection_pool).await {
             Ok(bill) => {
                 info!(
                     "Successfully saved bill data (candidate {}) to database with ID: {}",
                     candidate_idx, bill.id
                 );
-                let _ = broadcaster.send(ProcessingEvent::BillDataSaved {
+                for fingerprint in input.fingerprints() {
+                    if let Err(e) = fingerprint_service
+                        .record_for_bill(fingerprint, bill.id)
+                        .await
+                    {
+                        warn!(
+                            "Failed to record image fingerprint of bill {}: {:?}",
+                            bill.id, e
+                        );
+                    }
+                }
+                session.send(ProcessingEvent::BillDataSaved {
                     file_index,
+                    page_number,
                     bill_id: bill.id,
                     timestamp: Utc::now(),
                 });
@@ -400,6 +2461,120 @@ async fn process_with_gemini(
     Ok(())
 }
 
+/// Save a bill, with its email provenance in the same transaction when the
+/// file came from an email
+async fn save_bill(
+    bill_data: CreateBill,
+    source_images: &[String],
+    origin: BillOrigin<'_>,
+    connection_pool: &ConnectionPool,
+) -> Result<Bill, ApiError> {
+    let pool = connection_pool.pool().clone();
+    match origin.email {
+        Some(email) => {
+            BillProvenanceService::new(pool)
+                .create_bill_from_email(&bill_data, source_images, email, origin.file_name)
+                .await
+        }
+        None => {
+            BillService::new(pool)
+                .create_bill_with_sources(bill_data, source_images)
+                .await
+        }
+    }
+}
+
+/// Message of the `GeminiProcessingError` event sent for a failed extraction
+fn extraction_error_message(provider: &dyn ExtractionProvider, error: &ExtractionError) -> String {
+    let label = provider.kind().label();
+    match error {
+        ExtractionError::RateLimited { retry_after } => format!(
+            "{} rate limit exceeded. Retry after: {:?} seconds",
+            label, retry_after
+        ),
+        ExtractionError::AuthenticationFailed => {
+            format!(
+                "{} authentication failed. Please check your API key.",
+                label
+            )
+        }
+        ExtractionError::Timeout { seconds } => {
+            format!("{} request timeout after {} seconds", label, seconds)
+        }
+        ExtractionError::Api { status, message } => {
+            format!("{} error {}: {}", label, status, message)
+        }
+        e => format!("{} processing failed: {}", label, e),
+    }
+}
+
+/// Stage the bills extracted from a file as drafts of the session
+///
+/// Every candidate is validated before any draft is written, so a file whose
+/// extraction fails leaves no partial drafts behind.
+async fn stage_drafts(
+    gemini_responses: &[GeminiResponse],
+    file_index: usize,
+    source_images: &[String],
+    session: &SessionHandle,
+    connection_pool: &crate::config::ConnectionPool,
+) -> Result<Vec<BillDraft>, Box<dyn std::error::Error + Send + Sync>> {
+    let extractor = BillDataExtractor::new();
+    let bills = gemini_responses
+        .iter()
+        .map(|response| extractor.extract_and_validate(response))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            error!("Data extraction error for file index {}: {}", file_index, e);
+            UploadError::MultipartError(format!("Data extraction error: {}", e))
+        })?;
+
+    stage_bills(&bills, file_index, source_images, session, connection_pool).await
+}
+
+/// Stage the bills of a file as drafts of the session
+async fn stage_bills(
+    bills: &[CreateBill],
+    file_index: usize,
+    source_images: &[String],
+    session: &SessionHandle,
+    connection_pool: &crate::config::ConnectionPool,
+) -> Result<Vec<BillDraft>, Box<dyn std::error::Error + Send + Sync>> {
+    let draft_service = BillDraftService::new(connection_pool.pool().clone());
+    let mut drafts = Vec::with_capacity(bills.len());
+    for bill in bills {
+        let draft = draft_service
+            .stage_draft(session.session_id(), file_index, bill, source_images)
+            .await
+            .map_err(|e| format!("Failed to stage draft: {:?}", e))?;
+        drafts.push(draft);
+    }
+
+    info!(
+        "Staged {} draft(s) for file index {} in session {}",
+        drafts.len(),
+        file_index,
+        session.session_id()
+    );
+    Ok(drafts)
+}
+
+impl From<ArchiveError> for UploadError {
+    fn from(err: ArchiveError) -> Self {
+        match err {
+            ArchiveError::TooManyEntries { count, limit } => {
+                UploadError::ArchiveEntryCountExceeded { count, limit }
+            }
+            ArchiveError::TooLarge { size, limit } => {
+                UploadError::ArchiveSizeExceeded { size, limit }
+            }
+            ArchiveError::Invalid(_) | ArchiveError::UnsafePath(_) => {
+                UploadError::InvalidArchive(err.to_string())
+            }
+        }
+    }
+}
+
 fn map_error_to_code(error: &UploadError) -> ValidationErrorCode {
     match error {
         UploadError::FileSizeExceeded { size, limit } => ValidationErrorCode::FileSizeExceeded {
@@ -409,13 +2584,28 @@ fn map_error_to_code(error: &UploadError) -> ValidationErrorCode {
         UploadError::InvalidImageFormat(format) => ValidationErrorCode::UnsupportedFormat {
             detected: format.clone(),
         },
-        UploadError::ImageCountExceeded { count, limit } => {
+        UploadError::ImageCountExceeded { count, limit }
+        | UploadError::ArchiveEntryCountExceeded { count, limit }
+        | UploadError::PdfPageCountExceeded { count, limit } => {
             ValidationErrorCode::CountLimitExceeded {
                 count: *count,
                 limit: *limit,
             }
         }
-        UploadError::MultipartError(_) => ValidationErrorCode::CorruptedFile,
+        UploadError::TotalSizeExceeded { size, limit }
+        | UploadError::ArchiveSizeExceeded { size, limit }
+        | UploadError::CompressedArchiveSizeExceeded { size, limit } => {
+            ValidationErrorCode::TotalSizeExceeded {
+                actual: *size,
+                limit: *limit,
+            }
+        }
+        UploadError::InvalidPdf(_)
+        | UploadError::InvalidEInvoice(_)
+        | UploadError::InvalidArchive(_)
+        | UploadError::InvalidEmail(_)
+        | UploadError::MultipartError(_)
+        | UploadError::StorageError(_) => ValidationErrorCode::CorruptedFile,
     }
 }
 
@@ -427,7 +2617,7 @@ pub async fn upload_images(
     let start_time = Instant::now();
     let mut image_count = 0;
     let mut accepted_images = Vec::new();
-    let mut _total_size = 0;
+    let mut total_size = 0;
 
     while let Some(field) = multipart
         .next_field()
@@ -453,7 +2643,13 @@ pub async fn upload_images(
             validate_file_size(data.len(), config.max_file_size_bytes)?;
             let content_type = validate_image_format(&data).await?;
 
-            _total_size += data.len();
+            total_size += data.len();
+            if total_size > config.max_total_upload_bytes {
+                return Err(UploadError::TotalSizeExceeded {
+                    size: total_size,
+                    limit: config.max_total_upload_bytes,
+                });
+            }
 
             let image_info = ImageFileInfo {
                 file_name,
@@ -485,3 +2681,547 @@ pub async fn upload_images(
 
     Ok(axum::Json(result))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::FromRequest, http::Request};
+    use std::collections::HashMap;
+    use zip::write::SimpleFileOptions;
+
+    const BOUNDARY: &str = "upload-boundary";
+
+    fn config() -> UploadConfig {
+        UploadConfig {
+            max_file_size_bytes: 2048,
+            max_image_count: 2,
+            max_total_upload_bytes: 4096,
+            max_concurrent_images: 1,
+            max_pdf_pages: 1,
+            max_archive_entries: 3,
+            max_archive_uncompressed_bytes: 1500,
+            max_archive_bytes: 8192,
+            duplicate_max_hash_distance: 0,
+            image_pipeline: ResizeConfig::default(),
+            provider_resize_targets: HashMap::new(),
+        }
+    }
+
+    fn zip_with(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, data) in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// Multipart upload with one `images` field per (file name, content type, data)
+    async fn multipart(fields: &[(&str, &str, Vec<u8>)]) -> Multipart {
+        let mut body = Vec::new();
+        for (file_name, content_type, data) in fields {
+            write!(
+                body,
+                "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"images\"; \
+                 filename=\"{file_name}\"\r\nContent-Type: {content_type}\r\n\r\n"
+            )
+            .unwrap();
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\r\n");
+        }
+        write!(body, "--{BOUNDARY}--\r\n").unwrap();
+
+        let request = Request::post("/api/ocr/jobs")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+        Multipart::from_request(request, &()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_large_fields_stay_spooled_until_processed() {
+        let large = vec![7u8; FIELD_SPOOL_THRESHOLD_BYTES + 1];
+        let config = UploadConfig {
+            max_total_upload_bytes: 2 * FIELD_SPOOL_THRESHOLD_BYTES,
+            max_archive_uncompressed_bytes: 2 * FIELD_SPOOL_THRESHOLD_BYTES,
+            ..config()
+        };
+
+        let files = collect_image_fields(
+            multipart(&[
+                ("small.jpg", "image/jpeg", vec![1u8; 100]),
+                ("large.jpg", "image/jpeg", large.clone()),
+            ])
+            .await,
+            &config,
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(files[0].data, FileData::Memory(_)));
+        let FileData::Spooled { file, size } = &files[1].data else {
+            panic!("large field was read into memory");
+        };
+        assert_eq!(*size, large.len());
+        assert_eq!(
+            std::fs::metadata(file.path()).unwrap().len(),
+            large.len() as u64
+        );
+        assert_eq!(files[1].data.load().await.unwrap(), Bytes::from(large));
+    }
+
+    #[tokio::test]
+    async fn test_archives_share_one_uncompressed_budget() {
+        let first = zip_with(&[("october/1.jpg", &[1u8; 1000])]);
+        let second = zip_with(&[("november/1.jpg", &[2u8; 1000])]);
+
+        // Each archive fits the limit on its own
+        let files = collect_image_fields(
+            multipart(&[("october.zip", "application/zip", first.clone())]).await,
+            &config(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(files.len(), 1);
+
+        let result = collect_image_fields(
+            multipart(&[
+                ("october.zip", "application/zip", first),
+                ("november.zip", "application/zip", second),
+            ])
+            .await,
+            &config(),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(UploadError::ArchiveSizeExceeded {
+                size: 2000,
+                limit: 1500
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_archives_and_images_share_one_file_count() {
+        let archive = zip_with(&[("1.jpg", b"one"), ("2.jpg", b"two")]);
+
+        let result = collect_image_fields(
+            multipart(&[
+                ("scan.jpg", "image/jpeg", b"scan".to_vec()),
+                ("first.zip", "application/zip", archive.clone()),
+                ("second.zip", "application/zip", archive),
+            ])
+            .await,
+            &config(),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(UploadError::ArchiveEntryCountExceeded { count: 4, limit: 3 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_archives_as_sent_share_one_size_limit() {
+        let archive = zip_with(&[("1.jpg", b"one")]);
+        let config = UploadConfig {
+            max_archive_bytes: archive.len() * 3 / 2,
+            ..config()
+        };
+
+        let result = collect_image_fields(
+            multipart(&[
+                ("first.zip", "application/zip", archive.clone()),
+                ("second.zip", "application/zip", archive),
+            ])
+            .await,
+            &config,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(UploadError::CompressedArchiveSizeExceeded { .. })
+        ));
+    }
+
+    /// PNG of an invoice printed on a fixed layout, differing only in the
+    /// digits of its number
+    fn invoice_on_layout(number: u32) -> Vec<u8> {
+        let mut image = image::GrayImage::from_pixel(360, 240, image::Luma([255]));
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let table_line = y % 40 == 0 || x % 90 == 0;
+            let header = y < 30 && x < 200;
+            if table_line || header {
+                *pixel = image::Luma([40]);
+            }
+        }
+        for digit in 0..6 {
+            if (number >> digit) & 1 == 1 {
+                let x = 300 + digit * 8;
+                for y in 10..16 {
+                    image.put_pixel(x, y, image::Luma([0]));
+                }
+            }
+        }
+        let mut png = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        png.into_inner()
+    }
+
+    fn extracted_invoice(invoice_no: &str) -> GeminiResponse {
+        GeminiResponse {
+            serial_no: Some("C25TAA".to_string()),
+            invoice_no: Some(invoice_no.to_string()),
+            seller_tax_code: Some("0312345678".to_string()),
+            ..GeminiResponse::new()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lagging_event_stream_catches_up_from_the_event_log() {
+        let sessions = SessionRegistry::new();
+        let (session, receiver) = sessions.create_session(true);
+        let stream = session_event_stream(
+            sessions.clone(),
+            session.session_id().to_string(),
+            SessionSubscription::live(receiver),
+        );
+
+        // Overflow the live channel before the client reads anything
+        for bill_id in 0..300 {
+            session.send(ProcessingEvent::BillDataSaved {
+                file_index: 0,
+                page_number: None,
+                bill_id,
+                timestamp: Utc::now(),
+            });
+        }
+        session.send(ProcessingEvent::ProcessingComplete {
+            session_id: session.session_id().to_string(),
+            total_files: 1,
+            successful_files: 1,
+            duration_ms: 1,
+            timestamp: Utc::now(),
+        });
+
+        let events: Vec<_> = stream.collect().await;
+        assert_eq!(events.len(), 301);
+    }
+
+    #[test]
+    fn test_new_invoice_on_a_saved_layout_is_not_a_duplicate() {
+        let saved = fingerprint_image(&invoice_on_layout(0b010110)).unwrap();
+        let new = fingerprint_image(&invoice_on_layout(0b101001)).unwrap();
+        assert_ne!(saved.sha256, new.sha256);
+        assert!((saved.perceptual_hash ^ new.perceptual_hash).count_ones() <= 6);
+
+        let saved_keys: Vec<InvoiceKey> = InvoiceKey::from_response(&extracted_invoice("0000022"))
+            .into_iter()
+            .collect();
+        let new_keys: Vec<InvoiceKey> = InvoiceKey::from_response(&extracted_invoice("0000041"))
+            .into_iter()
+            .collect();
+        assert!(!is_saved_invoice(&new_keys, &saved_keys));
+
+        let reupload_keys: Vec<InvoiceKey> = InvoiceKey::from_response(&extracted_invoice("22"))
+            .into_iter()
+            .collect();
+        assert!(is_saved_invoice(&reupload_keys, &saved_keys));
+        assert!(!is_saved_invoice(&[], &saved_keys));
+    }
+
+    fn extracted_page(
+        file_index: usize,
+        invoice_qr: Option<InvoiceQrData>,
+        responses: Vec<GeminiResponse>,
+    ) -> ExtractedPage {
+        ExtractedPage {
+            file_index,
+            file_name: None,
+            email: None,
+            input: ExtractionInput::Image(Box::new(PreparedImage {
+                data: Vec::new(),
+                invoice_qr,
+                stored_key: None,
+                file_index,
+                fingerprint: None,
+                similar: None,
+                source_sha256: String::new(),
+            })),
+            responses,
+        }
+    }
+
+    #[test]
+    fn test_pages_without_qr_code_are_grouped_by_extracted_key() {
+        let qr = InvoiceQrData {
+            seller_tax_code: Some("0312345678".to_string()),
+            serial_no: Some("C25TAA".to_string()),
+            invoice_no: Some("22".to_string()),
+            ..InvoiceQrData::default()
+        };
+        let second_page = GeminiResponse {
+            seller_tax_code: None,
+            ..extracted_invoice("0000022")
+        };
+        let pages = vec![
+            extracted_page(0, Some(qr), vec![GeminiResponse::new()]),
+            extracted_page(1, None, vec![extracted_invoice("41")]),
+            extracted_page(2, None, vec![second_page]),
+            extracted_page(3, None, vec![GeminiResponse::new()]),
+        ];
+
+        let groups: Vec<Vec<usize>> = group_extracted_pages(pages)
+            .iter()
+            .map(|group| group.iter().map(|page| page.file_index).collect())
+            .collect();
+        assert_eq!(groups, vec![vec![0, 2], vec![1], vec![3]]);
+    }
+
+    /// Application state over a test database, with the default image pipeline
+    /// and Gemini answering from the committed cassettes
+    async fn replay_app_state(pool: sqlx::PgPool, image_dir: &std::path::Path) -> AppState {
+        use crate::config::{GeminiConfig, ImageStoreConfig, JobQueueConfig, WebhookConfig};
+        use crate::services::{
+            gemini_cassette::{CassetteMode, GeminiCassette},
+            gemini_service::GeminiService,
+            ocr_job_queue::OcrJobQueue,
+            session_registry::SessionRegistry,
+            webhook_service::WebhookService,
+        };
+
+        // Nothing listens on the base URL: every answer comes from the cassettes
+        let gemini = GeminiService::new(GeminiConfig {
+            base_url: "http://127.0.0.1:9".to_string(),
+            ..GeminiConfig::default()
+        })
+        .unwrap()
+        .with_cassette(GeminiCassette::new(
+            CassetteMode::Replay,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/cassettes"),
+        ));
+
+        AppState {
+            pool: ConnectionPool::from_pool(pool.clone()),
+            upload_config: Arc::new(UploadConfig {
+                max_file_size_bytes: 2 * 1024 * 1024,
+                max_image_count: 10,
+                max_total_upload_bytes: 10 * 1024 * 1024,
+                max_concurrent_images: 3,
+                max_pdf_pages: 1,
+                max_archive_entries: 10,
+                max_archive_uncompressed_bytes: 10 * 1024 * 1024,
+                max_archive_bytes: 10 * 1024 * 1024,
+                duplicate_max_hash_distance: 6,
+                image_pipeline: ResizeConfig::default(),
+                provider_resize_targets: HashMap::new(),
+            }),
+            sessions: SessionRegistry::new(),
+            job_queue: OcrJobQueue::new(pool.clone(), JobQueueConfig::default()),
+            webhooks: WebhookService::new(pool, WebhookConfig::default()).unwrap(),
+            image_store: ImageStore::open(&ImageStoreConfig {
+                dir: image_dir.to_path_buf(),
+                thumbnail_edge: 256,
+            })
+            .await
+            .unwrap(),
+            extraction: Arc::new(gemini),
+        }
+    }
+
+    fn test_image() -> Bytes {
+        std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../test_images/bill_1.jpg"
+        ))
+        .unwrap()
+        .into()
+    }
+
+    /// The cassette for `bill_1.jpg` is synthetic, written by hand from the
+    /// image, so this checks the upload pipeline around the extraction, not
+    /// how real Gemini output is parsed
+    #[sqlx::test]
+    async fn test_upload_is_extracted_from_synthetic_cassette(pool: sqlx::PgPool) {
+        use crate::models::{OcrJob, ocr_job::FileProcessingStatus};
+
+        let image_dir = tempfile::tempdir().unwrap();
+        let app_state = replay_app_state(pool, image_dir.path()).await;
+
+        let (session, _receiver) = app_state.sessions.create_session(false);
+        process_upload_with_events(
+            multipart(&[("bill_1.jpg", "image/jpeg", test_image().to_vec())]).await,
+            UploadParams::default().processing_options(),
+            session.clone(),
+            app_state.clone(),
+        )
+        .await
+        .unwrap();
+
+        let (snapshot, events) = app_state
+            .sessions
+            .get_session_with_events(session.session_id())
+            .unwrap();
+        let event_types: Vec<&str> = events
+            .iter()
+            .map(|envelope| envelope.event_type.as_str())
+            .collect();
+        assert_eq!(
+            event_types,
+            [
+                "upload_started",
+                "image_received",
+                "image_validation_start",
+                "image_validation_success",
+                "image_preprocessed",
+                "gemini_processing_start",
+                "gemini_processing_success",
+                "bill_data_saved",
+                "bill_data_saved",
+                "bill_data_saved",
+                "bill_data_saved",
+                "all_images_validated",
+                "processing_complete",
+            ]
+        );
+
+        let job = OcrJob::from_events(&snapshot, &events);
+        let file = &job.files[0];
+        assert_eq!(file.status, FileProcessingStatus::Completed);
+        assert_eq!(file.bill_ids.len(), 4);
+        assert!(file.extracted_data.iter().all(|bill| {
+            bill.serial_no.as_deref() == Some("C23TYY")
+                && bill.invoice_no.as_deref() == Some("00000706")
+                && bill.seller_tax_code.as_deref() == Some("0108704354")
+        }));
+        let total: f64 = file
+            .extracted_data
+            .iter()
+            .filter_map(|bill| bill.total_amount)
+            .sum();
+        assert_eq!(total, 1_311_000.0);
+    }
+
+    #[sqlx::test]
+    async fn test_pdf_is_kept_as_the_source_of_its_bills(pool: sqlx::PgPool) {
+        use crate::{
+            config::TesseractConfig,
+            services::{pdf_extraction::pdf_with, tesseract_service::TesseractService},
+        };
+
+        let image_dir = tempfile::tempdir().unwrap();
+        // Text layers are parsed by the Tesseract provider without running OCR
+        let app_state = AppState {
+            extraction: Arc::new(TesseractService::new(TesseractConfig {
+                command: "/nonexistent/tesseract".to_string(),
+                language: "vie".to_string(),
+                page_segmentation_mode: 6,
+                timeout_seconds: 5,
+            })),
+            ..replay_app_state(pool.clone(), image_dir.path()).await
+        };
+        let pdf = pdf_with(
+            Some("So: 0000123 Ngay 15 thang 03 nam 2024 Ma so thue: 0312345678 Tong cong: 650.000"),
+            None,
+        );
+        let files = JobFiles {
+            pending: vec![QueuedFile {
+                file_index: 0,
+                file_name: Some("invoice.pdf".to_string()),
+                document: None,
+                data: Bytes::from(pdf.clone()).into(),
+                email: None,
+            }],
+            total_files: 1,
+            processed_files: 0,
+            successful_files: 0,
+        };
+
+        let (session, _receiver) = app_state.sessions.create_session(false);
+        process_files_with_events(
+            files,
+            ProcessingOptions::default(),
+            session.clone(),
+            app_state.clone(),
+        )
+        .await
+        .unwrap();
+
+        let bill_ids = session.saved_bill_ids();
+        assert_eq!(bill_ids.len(), 1);
+        let bill = BillService::new(pool)
+            .get_bill_by_id(bill_ids[0])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(bill.source_images, vec![sha256_hex(&pdf)]);
+        assert_eq!(
+            app_state
+                .image_store
+                .get(&bill.source_images[0])
+                .await
+                .unwrap(),
+            Some(pdf)
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_email_provenance_is_saved_with_each_bill(pool: sqlx::PgPool) {
+        let image_dir = tempfile::tempdir().unwrap();
+        let app_state = replay_app_state(pool.clone(), image_dir.path()).await;
+        let email = EmailSource {
+            subject: Some("Hóa đơn tháng 3".to_string()),
+            sender: Some("ke-toan@example.com".to_string()),
+            sent_at: None,
+            message_id: Some("<invoice-706@example.com>".to_string()),
+        };
+        let files = JobFiles {
+            pending: vec![QueuedFile {
+                file_index: 0,
+                file_name: Some("bill_1.jpg".to_string()),
+                document: None,
+                data: test_image().into(),
+                email: Some(email.clone()),
+            }],
+            total_files: 1,
+            processed_files: 0,
+            successful_files: 0,
+        };
+
+        // Check every bill as soon as it is announced, before the session ends
+        let (session, mut receiver) = app_state.sessions.create_session(false);
+        let process = tokio::spawn(process_files_with_events(
+            files,
+            ProcessingOptions::default(),
+            session,
+            app_state.clone(),
+        ));
+        let provenance_service = BillProvenanceService::new(pool);
+        let mut saved_bills = 0;
+        while let Ok(envelope) = receiver.recv().await {
+            match envelope.data {
+                ProcessingEvent::BillDataSaved { bill_id, .. } => {
+                    let provenance = provenance_service
+                        .get_provenance(bill_id)
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    assert_eq!(provenance.email_subject, email.subject);
+                    assert_eq!(provenance.email_message_id, email.message_id);
+                    assert_eq!(provenance.attachment_name.as_deref(), Some("bill_1.jpg"));
+                    saved_bills += 1;
+                }
+                ProcessingEvent::ProcessingComplete { .. } => break,
+                _ => {}
+            }
+        }
+        process.await.unwrap().unwrap();
+        assert_eq!(saved_bills, 4);
+    }
+}
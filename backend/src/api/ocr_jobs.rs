@@ -0,0 +1,471 @@
+//! Asynchronous OCR job API endpoints
+//!
+//! This module exposes OCR processing as jobs: the upload returns a job id
+//! right away, the job status can be polled, and the job's event stream can
+//! be (re)attached at any time with `Last-Event-ID` replay. A job is backed
+//! by the same processing session as the streaming `POST /api/ocr` upload.
+//!
+//! Both kinds of upload are stored in the durable job queue and processed by
+//! the worker loops started with [`spawn_job_workers`], so an upload that was
+//! interrupted by a restart is resumed instead of lost.
+
+use axum::{
+    Json,
+    extract::{Multipart, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    response::sse::{KeepAlive, Sse},
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    api::{
+        ApiError, ApiResponse,
+        ocr::{
+            UploadParams, collect_image_fields, process_files_with_events, send_session_error,
+            send_session_error_message, session_event_stream,
+        },
+        webhooks::notify_session_finished,
+    },
+    errors::UploadError,
+    models::{
+        OcrJob, OcrJobCreated, ProcessingErrorType, SessionStatus,
+        ocr_job::{FileProcessingStatus, OcrJobFileResult},
+    },
+    services::{
+        ocr_job_queue::{ClaimedJob, OcrJobQueue, QueuedFileStatus, QueuedJobStatus, StoredJob},
+        session_registry::{SessionError, SessionHandle, SessionRegistry},
+    },
+    state::AppState,
+};
+
+/// POST /api/ocr/jobs endpoint handler
+///
+/// Accepts the same multipart `images` fields as `POST /api/ocr`, stores them
+/// in the job queue and returns the job id immediately. With `?draft=true`
+/// the extracted bills are staged as drafts instead of being saved, and with
+/// `?group_pages=true` images of the same invoice are saved as one document.
+/// Images uploaded before are skipped unless `?force=true` is given.
+///
+/// # Returns
+/// - 202 Accepted with the job id and its status/events URLs
+/// - 400 Bad Request when no images were provided or multipart parsing failed
+/// - 500 Internal Server Error if the upload could not be stored
+pub async fn create_ocr_job(
+    State(app_state): State<AppState>,
+    Query(params): Query<UploadParams>,
+    multipart: Multipart,
+) -> Result<impl IntoResponse, UploadError> {
+    let files = collect_image_fields(multipart, &app_state.upload_config).await?;
+
+    let (session, _receiver) = app_state.sessions.create_session(false);
+    let job = OcrJobCreated::new(session.session_id().to_string(), files.len());
+    info!(
+        "Created OCR job {} with {} file(s)",
+        job.job_id, job.total_files
+    );
+
+    if let Err(e) = app_state
+        .job_queue
+        .enqueue(&job.job_id, &files, params.processing_options())
+        .await
+    {
+        error!("Failed to enqueue OCR job {}: {}", job.job_id, e);
+        let error = UploadError::StorageError(e.to_string());
+        send_session_error(&session, &error);
+        notify_session_finished(&app_state, &session).await;
+        return Err(error);
+    }
+
+    Ok((StatusCode::ACCEPTED, Json(ApiResponse::success(job))))
+}
+
+/// GET /api/ocr/jobs/{id} endpoint handler
+///
+/// Returns the job status and per-file results derived from its events.
+/// Once the session has been pruned, or after a restart, the status is built
+/// from the job queue instead: the job's status and error plus the saved
+/// bills, drafts and error of each file, without extracted data or events.
+///
+/// # Returns
+/// - 200 OK with the job status
+/// - 404 Not Found if the job is unknown
+/// - 500 Internal Server Error on database error
+pub async fn get_ocr_job(
+    State(sessions): State<SessionRegistry>,
+    State(job_queue): State<OcrJobQueue>,
+    Path(job_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    if let Some((session, events)) = sessions.get_session_with_events(&job_id) {
+        return Ok(Json(ApiResponse::success(OcrJob::from_events(
+            &session, &events,
+        ))));
+    }
+
+    let job = job_queue
+        .load_job(&job_id)
+        .await
+        .map_err(|e| ApiError::InternalServerError(e.to_string()))?
+        .ok_or_else(|| ApiError::NotFound(format!("OCR job {job_id} not found")))?;
+
+    Ok(Json(ApiResponse::success(job_from_queue(job))))
+}
+
+/// Job view of a job whose session is no longer in memory
+fn job_from_queue(job: StoredJob) -> OcrJob {
+    let status = match job.status {
+        // Interrupted by a restart and waiting to be resumed
+        QueuedJobStatus::Queued | QueuedJobStatus::Processing => SessionStatus::Processing,
+        QueuedJobStatus::Completed => SessionStatus::Completed,
+        QueuedJobStatus::Failed => SessionStatus::Failed,
+        QueuedJobStatus::Cancelled => SessionStatus::Cancelled,
+    };
+    let files: Vec<OcrJobFileResult> = job
+        .files
+        .into_iter()
+        .map(|file| OcrJobFileResult {
+            file_name: file.file_name,
+            size_bytes: file.size_bytes,
+            status: match file.result.status {
+                QueuedFileStatus::Pending => FileProcessingStatus::Received,
+                QueuedFileStatus::Succeeded => FileProcessingStatus::Completed,
+                QueuedFileStatus::Failed => FileProcessingStatus::Failed,
+            },
+            bill_ids: file.result.bill_ids,
+            draft_ids: file.result.draft_ids,
+            error_message: file.result.error_message,
+            ..OcrJobFileResult::new(file.file_index)
+        })
+        .collect();
+
+    OcrJob {
+        job_id: job.job_id,
+        status,
+        start_time: job.created_at,
+        total_files: files.len(),
+        processed_files: files
+            .iter()
+            .filter(|file| file.status != FileProcessingStatus::Received)
+            .count(),
+        last_event_id: None,
+        error_message: job.last_error,
+        files,
+    }
+}
+
+/// POST /api/ocr/jobs/{id}/cancel endpoint handler
+///
+/// Requests cancellation of a running job or streaming upload session. The
+/// processing task stops before its next file and ends the session with a
+/// `processing_cancelled` event listing the bills that were already saved.
+///
+/// # Returns
+/// - 202 Accepted with the current job status
+/// - 400 Bad Request if the job has already finished
+/// - 404 Not Found if the job is unknown or has expired
+pub async fn cancel_ocr_job(
+    State(sessions): State<SessionRegistry>,
+    Path(job_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    sessions.cancel(&job_id).map_err(|e| match e {
+        SessionError::NotFound(_) => ApiError::NotFound(e.to_string()),
+        SessionError::AlreadyFinished(_) => ApiError::BadRequest(e.to_string()),
+    })?;
+    info!("Cancellation requested for OCR job {}", job_id);
+
+    let (session, events) = sessions
+        .get_session_with_events(&job_id)
+        .ok_or_else(|| ApiError::NotFound(format!("OCR job {job_id} not found")))?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(ApiResponse::success(OcrJob::from_events(&session, &events))),
+    ))
+}
+
+/// GET /api/ocr/jobs/{id}/events endpoint handler
+///
+/// Streams the job's events as SSE. Events after the `Last-Event-ID` header
+/// are replayed first (all events when the header is absent or names an
+/// event from before a restart), then live events follow until the job
+/// finishes.
+///
+/// # Returns
+/// - 200 OK with an SSE stream
+/// - 400 Bad Request if `Last-Event-ID` is not a valid event id
+/// - 404 Not Found if the job is unknown or has expired
+pub async fn get_ocr_job_events(
+    State(sessions): State<SessionRegistry>,
+    Path(job_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    let last_event_id = match headers.get("last-event-id") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .ok_or_else(|| {
+                    warn!("Invalid Last-Event-ID header for OCR job {}", job_id);
+                    ApiError::BadRequest("Last-Event-ID must be a numeric event id".to_string())
+                })?,
+        ),
+        None => None,
+    };
+
+    let subscription = sessions
+        .subscribe_from(&job_id, last_event_id)
+        .ok_or_else(|| ApiError::NotFound(format!("OCR job {job_id} not found")))?;
+
+    info!(
+        "Attaching to OCR job {} events after {:?} ({} replayed)",
+        job_id,
+        last_event_id,
+        subscription.replay.len()
+    );
+
+    Ok(
+        Sse::new(session_event_stream(sessions, job_id, subscription))
+            .keep_alive(KeepAlive::default()),
+    )
+}
+
+/// Start the worker loops that process the durable OCR job queue
+pub fn spawn_job_workers(app_state: AppState) {
+    let workers = app_state.job_queue.config().workers;
+    for worker_id in 0..workers {
+        tokio::spawn(run_job_worker(worker_id, app_state.clone()));
+    }
+    info!("Started {} OCR job worker(s)", workers);
+}
+
+async fn run_job_worker(worker_id: usize, app_state: AppState) {
+    loop {
+        match app_state.job_queue.claim_next().await {
+            Ok(Some(job)) => {
+                info!(
+                    "OCR job worker {} claimed job {} (attempt {})",
+                    worker_id, job.job_id, job.attempts
+                );
+                run_claimed_job(job, &app_state).await;
+            }
+            Ok(None) => app_state.job_queue.wait_for_job().await,
+            Err(e) => {
+                error!("OCR job worker {} failed to claim a job: {}", worker_id, e);
+                app_state.job_queue.wait_for_job().await;
+            }
+        }
+    }
+}
+
+/// Process a claimed job and record its outcome in the queue
+///
+/// The job's lease is renewed while it runs. Database errors before
+/// processing starts leave the job claimed, so it is retried once its lease
+/// expires, up to the configured number of attempts.
+async fn run_claimed_job(job: ClaimedJob, app_state: &AppState) {
+    let queue = &app_state.job_queue;
+    let session = app_state
+        .sessions
+        .resume_session(&job.job_id, u32::try_from(job.attempts).unwrap_or_default());
+
+    if job.attempts > queue.config().max_attempts {
+        let message = format!(
+            "OCR job abandoned after {} interrupted attempt(s)",
+            job.attempts - 1
+        );
+        warn!("{}: {}", job.job_id, message);
+        send_session_error_message(
+            &session,
+            message.clone(),
+            ProcessingErrorType::InternalServerError,
+        );
+        if let Err(e) = queue
+            .finish(&job.job_id, QueuedJobStatus::Failed, Some(&message))
+            .await
+        {
+            error!("Failed to record outcome of OCR job {}: {}", job.job_id, e);
+        }
+        notify_session_finished(app_state, &session).await;
+        return;
+    }
+
+    if job.is_resumed() {
+        info!(
+            "Resuming interrupted OCR job {} (attempt {})",
+            job.job_id, job.attempts
+        );
+    }
+
+    let files = match queue.load_files(&job.job_id).await {
+        Ok(files) => files,
+        Err(e) => {
+            error!("Failed to load files of OCR job {}: {}", job.job_id, e);
+            if let Err(e) = queue.record_error(&job.job_id, &e.to_string()).await {
+                error!("Failed to record error of OCR job {}: {}", job.job_id, e);
+            }
+            return;
+        }
+    };
+
+    run_with_lease(
+        session.clone(),
+        app_state,
+        process_files_with_events(files, job.options, session, app_state.clone()),
+    )
+    .await;
+}
+
+/// Run the processing of a claimed job while renewing its lease, then record its outcome
+///
+/// A processing error also ends the session with a `ProcessingError` event.
+/// Webhooks are notified once the outcome is recorded.
+pub(crate) async fn run_with_lease<F>(session: SessionHandle, app_state: &AppState, processing: F)
+where
+    F: Future<Output = Result<(), UploadError>>,
+{
+    let queue = &app_state.job_queue;
+    let job_id = session.session_id().to_string();
+
+    let heartbeat = {
+        let queue = queue.clone();
+        let job_id = job_id.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(queue.config().heartbeat_interval());
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = queue.heartbeat(&job_id).await {
+                    warn!("Failed to renew lease of OCR job {}: {}", job_id, e);
+                }
+            }
+        })
+    };
+
+    let result = processing.await;
+    heartbeat.abort();
+
+    let (status, last_error) = match result {
+        Err(e) => {
+            send_session_error(&session, &e);
+            (QueuedJobStatus::Failed, Some(e.to_string()))
+        }
+        Ok(()) => match session.status() {
+            Some(SessionStatus::Cancelled) => (QueuedJobStatus::Cancelled, None),
+            Some(SessionStatus::Failed) => (QueuedJobStatus::Failed, None),
+            _ => (QueuedJobStatus::Completed, None),
+        },
+    };
+
+    if let Err(e) = queue.finish(&job_id, status, last_error.as_deref()).await {
+        error!("Failed to record outcome of OCR job {}: {}", job_id, e);
+    }
+
+    notify_session_finished(app_state, &session).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::JobQueueConfig,
+        services::ocr_job_queue::{FileResult, ProcessingOptions, QueuedFile},
+    };
+    use axum::body::Bytes;
+    use std::time::Duration;
+
+    fn queued_file(file_index: usize, file_name: &str) -> QueuedFile {
+        QueuedFile {
+            file_index,
+            file_name: Some(file_name.to_string()),
+            document: None,
+            data: Bytes::from_static(b"image").into(),
+            email: None,
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_job_status_outlives_its_session(pool: sqlx::PgPool) {
+        let job_queue = OcrJobQueue::new(
+            pool,
+            JobQueueConfig {
+                workers: 1,
+                poll_interval: Duration::from_secs(1),
+                lease: Duration::from_secs(60),
+                max_attempts: 3,
+            },
+        );
+        let files = [queued_file(0, "a.jpg"), queued_file(1, "b.jpg")];
+        job_queue
+            .enqueue("job-1", &files, ProcessingOptions::default())
+            .await
+            .unwrap();
+        job_queue
+            .mark_file(
+                "job-1",
+                0,
+                &FileResult {
+                    status: QueuedFileStatus::Succeeded,
+                    bill_ids: vec![7, 8],
+                    draft_ids: Vec::new(),
+                    error_message: None,
+                },
+            )
+            .await
+            .unwrap();
+        job_queue
+            .mark_file(
+                "job-1",
+                1,
+                &FileResult {
+                    status: QueuedFileStatus::Failed,
+                    bill_ids: Vec::new(),
+                    draft_ids: Vec::new(),
+                    error_message: Some("Invalid image format".to_string()),
+                },
+            )
+            .await
+            .unwrap();
+        job_queue
+            .finish("job-1", QueuedJobStatus::Completed, None)
+            .await
+            .unwrap();
+
+        // A fresh registry stands for a restart or a pruned session
+        let response = get_ocr_job(
+            State(SessionRegistry::new()),
+            State(job_queue.clone()),
+            Path("job-1".to_string()),
+        )
+        .await
+        .unwrap()
+        .into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let job = serde_json::from_slice::<ApiResponse<OcrJob>>(&body)
+            .unwrap()
+            .data
+            .unwrap();
+
+        assert!(matches!(job.status, SessionStatus::Completed));
+        assert_eq!(job.total_files, 2);
+        assert_eq!(job.processed_files, 2);
+        assert_eq!(job.files[0].status, FileProcessingStatus::Completed);
+        assert_eq!(job.files[0].bill_ids, vec![7, 8]);
+        assert_eq!(job.files[0].size_bytes, 5);
+        assert_eq!(job.files[1].status, FileProcessingStatus::Failed);
+        assert_eq!(
+            job.files[1].error_message.as_deref(),
+            Some("Invalid image format")
+        );
+
+        let unknown = get_ocr_job(
+            State(SessionRegistry::new()),
+            State(job_queue),
+            Path("job-2".to_string()),
+        )
+        .await;
+        assert!(matches!(unknown, Err(ApiError::NotFound(_))));
+    }
+}
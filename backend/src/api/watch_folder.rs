@@ -0,0 +1,423 @@
+//! Watched-folder ingestion
+//!
+//! When `WATCH_FOLDER_DIR` is set, the directory is scanned for new images
+//! (for example from an office scanner). Each image is validated and then
+//! processed like an upload to `POST /api/ocr`: resized, extracted by the
+//! configured extraction provider and saved as bills, as a processing session
+//! of its own whose events can be followed at `/api/ocr/jobs/{id}/events`.
+//!
+//! Afterwards the file is moved to `processed/` when at least one bill was
+//! saved or it duplicates an image bills were saved from, or to `failed/`
+//! together with a `<file>.error.json` report. The directory itself is the
+//! durable queue: a file is only moved once it has been processed, so a file
+//! interrupted by a restart is picked up again. A file that cannot be moved is
+//! not processed again until the backend restarts.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+use crate::{
+    api::{
+        ocr::{process_files_with_events, send_session_error},
+        webhooks::notify_session_finished,
+    },
+    config::WatchFolderConfig,
+    errors::UploadError,
+    models::{OcrJob, ProcessingEvent, ProcessingSession, SSEEventEnvelope},
+    services::{
+        image_validation::{validate_file_size, validate_upload_format},
+        ocr_job_queue::{JobFiles, ProcessingOptions, QueuedFile},
+    },
+    state::AppState,
+};
+
+/// Sidecar report written next to a file moved to `failed/`
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureReport {
+    pub file_name: String,
+    /// Processing session of the file; absent when it was rejected before processing
+    pub session_id: Option<String>,
+    pub size_bytes: u64,
+    pub error_message: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+/// Start scanning the watched directory for new images
+pub fn spawn_folder_watcher(config: WatchFolderConfig, app_state: AppState) {
+    info!(
+        "Starting watched-folder ingestion ({})",
+        config.display_config()
+    );
+
+    tokio::spawn(async move {
+        for dir in [config.processed_dir(), config.failed_dir()] {
+            if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+                error!(
+                    "Watched-folder ingestion disabled: cannot create {}: {}",
+                    dir.display(),
+                    e
+                );
+                return;
+            }
+        }
+
+        // Size and modification time of each file at the previous scan; a
+        // file is only picked up once they stop changing, so files the
+        // scanner is still writing are left alone
+        let mut last_seen: HashMap<PathBuf, (u64, SystemTime)> = HashMap::new();
+        // Files processed by this run; one that could not be moved away stays
+        // here, so it is not processed again on every scan
+        let mut handled: HashSet<PathBuf> = HashSet::new();
+
+        loop {
+            match scan_directory(&config.dir).await {
+                Ok(files) => {
+                    handled.retain(|path| files.iter().any(|(file, _)| file == path));
+                    let mut seen = HashMap::with_capacity(files.len());
+                    for (path, signature) in files {
+                        if handled.contains(&path) {
+                            continue;
+                        }
+                        if last_seen.get(&path) == Some(&signature) {
+                            process_watched_file(&path, &config, &app_state).await;
+                            handled.insert(path);
+                        } else {
+                            seen.insert(path, signature);
+                        }
+                    }
+                    last_seen = seen;
+                }
+                Err(e) => warn!(
+                    "Failed to scan watched folder {}: {}",
+                    config.dir.display(),
+                    e
+                ),
+            }
+
+            tokio::time::sleep(config.poll_interval).await;
+        }
+    });
+}
+
+/// List the regular, non-hidden files of a directory with their size and modification time
+async fn scan_directory(dir: &Path) -> std::io::Result<Vec<(PathBuf, (u64, SystemTime))>> {
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    let mut files = Vec::new();
+
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let metadata = match entry.metadata().await {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => continue,
+        };
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        files.push((entry.path(), (metadata.len(), modified)));
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Validate and process one file, then move it out of the watched directory
+async fn process_watched_file(path: &Path, config: &WatchFolderConfig, app_state: &AppState) {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let data = match tokio::fs::read(path).await {
+        Ok(data) => data,
+        Err(e) => {
+            warn!("Failed to read watched file {}: {}", path.display(), e);
+            return;
+        }
+    };
+    let size_bytes = data.len() as u64;
+
+    if let Err(e) = validate_watched_file(&data, app_state).await {
+        info!("Rejected watched file {}: {}", file_name, e);
+        let report = FailureReport {
+            file_name,
+            session_id: None,
+            size_bytes,
+            error_message: e.to_string(),
+            failed_at: Utc::now(),
+        };
+        move_to_failed(path, &config.failed_dir(), &report).await;
+        return;
+    }
+
+    let (session, _receiver) = app_state.sessions.create_session(false);
+    info!(
+        "Processing watched file {} in session {}",
+        file_name,
+        session.session_id()
+    );
+
+    let files = JobFiles {
+        pending: vec![QueuedFile {
+            file_index: 0,
+            file_name: Some(file_name.clone()),
+            document: None,
+            data: Bytes::from(data).into(),
+            email: None,
+        }],
+        total_files: 1,
+        processed_files: 0,
+        successful_files: 0,
+    };
+    if let Err(e) = process_files_with_events(
+        files,
+        ProcessingOptions::default(),
+        session.clone(),
+        app_state.clone(),
+    )
+    .await
+    {
+        send_session_error(&session, &e);
+    }
+    notify_session_finished(app_state, &session).await;
+
+    let error_message = match app_state
+        .sessions
+        .get_session_with_events(session.session_id())
+    {
+        Some((state, events)) => failure_message(&state, &events),
+        None => Some("Processing session expired".to_string()),
+    };
+
+    match error_message {
+        None => {
+            match move_to(path, &config.processed_dir()).await {
+                Ok(destination) => info!(
+                    "Processed watched file {} -> {}",
+                    file_name,
+                    destination.display()
+                ),
+                Err(e) => error!("Failed to move processed file {}: {}", path.display(), e),
+            };
+        }
+        Some(error_message) => {
+            let report = FailureReport {
+                file_name,
+                session_id: Some(session.session_id().to_string()),
+                size_bytes,
+                error_message,
+                failed_at: Utc::now(),
+            };
+            move_to_failed(path, &config.failed_dir(), &report).await;
+        }
+    }
+}
+
+/// Why a processed file goes to `failed/`, or `None` when bills were saved
+/// from it or it was skipped as a duplicate of an image bills were saved from
+fn failure_message(state: &ProcessingSession, events: &[SSEEventEnvelope]) -> Option<String> {
+    let skipped_as_duplicate = events.iter().any(|envelope| {
+        matches!(
+            envelope.data,
+            ProcessingEvent::DuplicateDetected { forced: false, .. }
+        )
+    });
+    if skipped_as_duplicate {
+        return None;
+    }
+
+    let job = OcrJob::from_events(state, events);
+    match job.files.first() {
+        Some(file) if !file.bill_ids.is_empty() => None,
+        Some(file) => Some(
+            file.error_message
+                .clone()
+                .or(job.error_message)
+                .unwrap_or_else(|| "No bill data was saved".to_string()),
+        ),
+        None => Some(
+            job.error_message
+                .unwrap_or_else(|| "File was not processed".to_string()),
+        ),
+    }
+}
+
+/// Check a file with the same limits as an uploaded image or PDF
+async fn validate_watched_file(data: &[u8], app_state: &AppState) -> Result<(), UploadError> {
+    validate_file_size(data.len(), app_state.upload_config.max_file_size_bytes)?;
+    validate_upload_format(data, app_state.upload_config.max_pdf_pages).await?;
+    Ok(())
+}
+
+/// Move a file to `failed/` and write its error report next to it
+async fn move_to_failed(path: &Path, failed_dir: &Path, report: &FailureReport) {
+    let destination = match move_to(path, failed_dir).await {
+        Ok(destination) => destination,
+        Err(e) => {
+            error!("Failed to move rejected file {}: {}", path.display(), e);
+            return;
+        }
+    };
+
+    let mut report_path = destination.clone().into_os_string();
+    report_path.push(".error.json");
+    let written = match serde_json::to_vec_pretty(report) {
+        Ok(json) => tokio::fs::write(&report_path, json).await,
+        Err(e) => Err(std::io::Error::other(e)),
+    };
+
+    match written {
+        Ok(()) => info!(
+            "Moved failed file {} -> {}: {}",
+            report.file_name,
+            destination.display(),
+            report.error_message
+        ),
+        Err(e) => error!(
+            "Failed to write error report {}: {}",
+            PathBuf::from(report_path).display(),
+            e
+        ),
+    }
+}
+
+/// Move a file into `dir`, renaming it if a file of that name is already there
+async fn move_to(path: &Path, dir: &Path) -> std::io::Result<PathBuf> {
+    let destination = unique_destination(dir, path);
+    tokio::fs::rename(path, &destination).await?;
+    Ok(destination)
+}
+
+/// Destination of `path` in `dir` that does not overwrite an existing file
+///
+/// Clashing names get a counter appended to their stem: `scan.jpg`,
+/// `scan-1.jpg`, `scan-2.jpg`, ...
+fn unique_destination(dir: &Path, path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default();
+    let candidate = dir.join(file_name);
+    if !candidate.exists() {
+        return candidate;
+    }
+
+    let stem = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..)
+        .map(|n| dir.join(format!("{stem}-{n}{extension}")))
+        .find(|candidate| !candidate.exists())
+        .expect("an unused file name exists")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unique_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = Path::new("/scans/invoice.jpg");
+
+        assert_eq!(
+            unique_destination(dir.path(), source),
+            dir.path().join("invoice.jpg")
+        );
+
+        std::fs::write(dir.path().join("invoice.jpg"), b"a").unwrap();
+        std::fs::write(dir.path().join("invoice-1.jpg"), b"b").unwrap();
+        assert_eq!(
+            unique_destination(dir.path(), source),
+            dir.path().join("invoice-2.jpg")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_move_to_failed_writes_report() {
+        let watched = tempfile::tempdir().unwrap();
+        let failed_dir = watched.path().join("failed");
+        std::fs::create_dir(&failed_dir).unwrap();
+        let source = watched.path().join("notes.txt");
+        std::fs::write(&source, b"not an image").unwrap();
+
+        let report = FailureReport {
+            file_name: "notes.txt".to_string(),
+            session_id: None,
+            size_bytes: 12,
+            error_message: "Invalid image format".to_string(),
+            failed_at: Utc::now(),
+        };
+        move_to_failed(&source, &failed_dir, &report).await;
+
+        assert!(!source.exists());
+        assert!(failed_dir.join("notes.txt").exists());
+        let written: serde_json::Value = serde_json::from_slice(
+            &std::fs::read(failed_dir.join("notes.txt.error.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(written["error_message"], "Invalid image format");
+        assert_eq!(written["size_bytes"], 12);
+    }
+
+    #[test]
+    fn test_skipped_duplicate_is_not_a_failure() {
+        let session = ProcessingSession::new("watch-1".to_string());
+        let now = Utc::now();
+        let received = ProcessingEvent::ImageReceived {
+            file_index: 0,
+            file_name: Some("scan.jpg".to_string()),
+            size_bytes: 10,
+            timestamp: now,
+        };
+        let duplicate = |forced| ProcessingEvent::DuplicateDetected {
+            file_index: 0,
+            exact: true,
+            bill_ids: vec![7],
+            draft_ids: Vec::new(),
+            forced,
+            timestamp: now,
+        };
+        let envelopes = |events: Vec<ProcessingEvent>| -> Vec<SSEEventEnvelope> {
+            events
+                .into_iter()
+                .enumerate()
+                .map(|(i, event)| SSEEventEnvelope::new(i as u64 + 1, event, None))
+                .collect()
+        };
+
+        let skipped = envelopes(vec![received.clone(), duplicate(false)]);
+        assert_eq!(failure_message(&session, &skipped), None);
+
+        // A look-alike extracted anyway still has to produce bills
+        let extracted = envelopes(vec![received, duplicate(true)]);
+        assert_eq!(
+            failure_message(&session, &extracted).as_deref(),
+            Some("No bill data was saved")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scan_directory_skips_hidden_files_and_folders() {
+        let watched = tempfile::tempdir().unwrap();
+        std::fs::write(watched.path().join("scan.jpg"), b"abc").unwrap();
+        std::fs::write(watched.path().join(".partial.jpg"), b"abc").unwrap();
+        std::fs::create_dir(watched.path().join("processed")).unwrap();
+
+        let files = scan_directory(watched.path()).await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, watched.path().join("scan.jpg"));
+        assert_eq!(files[0].1.0, 3);
+    }
+}
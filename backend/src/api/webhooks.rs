@@ -0,0 +1,207 @@
+//! Webhook API endpoints
+//!
+//! Downstream systems register webhook URLs here to be notified when an OCR
+//! session reaches `ProcessingComplete`, `ProcessingError` or
+//! `ProcessingCancelled`, instead of polling for new bills. Deliveries are posted by the dispatcher started
+//! with [`spawn_webhook_dispatcher`].
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use tracing::{error, info};
+
+use crate::{
+    api::{ApiError, ApiResponse},
+    models::{CreateWebhook, DeliveryStatus, WebhookPayload},
+    services::{
+        session_registry::SessionHandle,
+        webhook_service::{WebhookError, WebhookService},
+    },
+    state::AppState,
+};
+
+impl From<WebhookError> for ApiError {
+    fn from(err: WebhookError) -> Self {
+        match err {
+            WebhookError::InvalidUrl(_) => ApiError::BadRequest(err.to_string()),
+            _ => ApiError::InternalServerError(err.to_string()),
+        }
+    }
+}
+
+/// POST /api/webhooks endpoint handler
+///
+/// Registers a webhook. The response is the only place the signing secret
+/// is returned.
+///
+/// # Returns
+/// - 201 Created with the webhook and its secret
+/// - 400 Bad Request if the URL is not a valid http(s) URL
+/// - 500 Internal Server Error on database error
+pub async fn create_webhook(
+    State(webhooks): State<WebhookService>,
+    Json(request): Json<CreateWebhook>,
+) -> Result<impl IntoResponse, ApiError> {
+    let webhook = webhooks.create_webhook(request).await?;
+
+    Ok((StatusCode::CREATED, Json(ApiResponse::success(webhook))))
+}
+
+/// GET /api/webhooks endpoint handler
+///
+/// # Returns
+/// - 200 OK with the registered webhooks (without secrets)
+/// - 500 Internal Server Error on database error
+pub async fn get_webhooks(
+    State(webhooks): State<WebhookService>,
+) -> Result<impl IntoResponse, ApiError> {
+    let webhooks = webhooks.list_webhooks().await?;
+
+    Ok(Json(ApiResponse::success(webhooks)))
+}
+
+/// DELETE /api/webhooks/{id} endpoint handler
+///
+/// Removes the webhook and its delivery log; pending deliveries are dropped.
+///
+/// # Returns
+/// - 204 No Content if the webhook was removed
+/// - 404 Not Found if the webhook does not exist
+/// - 500 Internal Server Error on database error
+pub async fn delete_webhook(
+    State(webhooks): State<WebhookService>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !webhooks.delete_webhook(id).await? {
+        return Err(ApiError::NotFound(format!("Webhook {id} not found")));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Query parameters for the delivery log
+#[derive(Debug, Deserialize)]
+pub struct DeliveryLogParams {
+    pub limit: Option<i64>,
+}
+
+/// GET /api/webhooks/{id}/deliveries endpoint handler
+///
+/// Returns the delivery log of a webhook, newest first.
+///
+/// # Query Parameters
+/// - `limit`: Number of deliveries to return (default: 50, max: 500)
+///
+/// # Returns
+/// - 200 OK with the deliveries and the outcome of each of their attempts
+/// - 400 Bad Request on an invalid limit
+/// - 500 Internal Server Error on database error
+pub async fn get_webhook_deliveries(
+    State(webhooks): State<WebhookService>,
+    Path(id): Path<i32>,
+    Query(params): Query<DeliveryLogParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let limit = params.limit.unwrap_or(50);
+    if !(1..=500).contains(&limit) {
+        return Err(ApiError::BadRequest(
+            "Limit must be between 1 and 500".to_string(),
+        ));
+    }
+
+    let deliveries = webhooks.list_deliveries(id, limit).await?;
+
+    Ok(Json(ApiResponse::success(deliveries)))
+}
+
+/// Queue webhook deliveries for a session that has just finished
+///
+/// The saved bills are read from the file results in the job queue, so a job
+/// resumed after a restart reports the bills of its earlier attempts too.
+/// Sessions that are not queued, such as watch folder and email imports,
+/// report the bills saved in memory.
+pub(crate) async fn notify_session_finished(app_state: &AppState, session: &SessionHandle) {
+    let Some((state, events)) = app_state
+        .sessions
+        .get_session_with_events(session.session_id())
+    else {
+        return;
+    };
+    let saved_bill_ids = match app_state.job_queue.load_job(session.session_id()).await {
+        Ok(Some(job)) => job
+            .files
+            .into_iter()
+            .flat_map(|file| file.result.bill_ids)
+            .collect(),
+        Ok(None) => session.saved_bill_ids(),
+        Err(e) => {
+            error!(
+                "Failed to load saved bills of session {}: {}",
+                session.session_id(),
+                e
+            );
+            session.saved_bill_ids()
+        }
+    };
+    let Some(payload) = events.last().and_then(|envelope| {
+        WebhookPayload::from_terminal_event(&state, &envelope.data, saved_bill_ids)
+    }) else {
+        return;
+    };
+
+    if let Err(e) = app_state.webhooks.enqueue(&payload).await {
+        error!(
+            "Failed to queue webhook deliveries for session {}: {}",
+            payload.session_id, e
+        );
+    }
+}
+
+/// Start the loop that posts due webhook deliveries
+pub fn spawn_webhook_dispatcher(webhooks: WebhookService) {
+    info!(
+        "Starting webhook dispatcher ({})",
+        webhooks.config().display_config()
+    );
+
+    tokio::spawn(async move {
+        loop {
+            let delivery = match webhooks.claim_due().await {
+                Ok(Some(delivery)) => delivery,
+                Ok(None) => {
+                    webhooks.wait_for_delivery().await;
+                    continue;
+                }
+                Err(e) => {
+                    error!("Failed to claim webhook delivery: {}", e);
+                    webhooks.wait_for_delivery().await;
+                    continue;
+                }
+            };
+
+            let attempt = webhooks.send(&delivery).await;
+            match webhooks.record_attempt(&delivery, &attempt).await {
+                Ok(DeliveryStatus::Delivered) => {
+                    info!("Delivered webhook delivery {}", delivery.id)
+                }
+                Ok(DeliveryStatus::Failed) => error!(
+                    "Giving up webhook delivery {} after {} attempt(s)",
+                    delivery.id, delivery.attempts
+                ),
+                Ok(DeliveryStatus::Pending) => info!(
+                    "Webhook delivery {} failed (attempt {}), retrying in {}s",
+                    delivery.id,
+                    delivery.attempts,
+                    webhooks.config().backoff(delivery.attempts).as_secs()
+                ),
+                Err(e) => error!(
+                    "Failed to record attempt of webhook delivery {}: {}",
+                    delivery.id, e
+                ),
+            }
+        }
+    });
+}
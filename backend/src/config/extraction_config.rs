@@ -0,0 +1,250 @@
+use std::env;
+
+use crate::config::GeminiConfig;
+use crate::services::{
+    extraction_provider::ExtractionProviderKind,
+    gemini_cassette::{CassetteMode, GeminiCassette},
+};
+
+/// Selection of the backend that extracts bills from invoices
+#[derive(Debug, Clone)]
+pub struct ExtractionConfig {
+    /// Provider every image, document and invoice text is sent to
+    pub provider: ExtractionProviderKind,
+    /// Settings of the Gemini API, when it is the provider
+    pub gemini: Option<GeminiConfig>,
+    /// Settings of the OpenAI-compatible endpoint, when it is the provider
+    pub openai: Option<OpenAiConfig>,
+    /// Settings of the local Tesseract OCR engine, when it is the provider
+    pub tesseract: Option<TesseractConfig>,
+    /// Cassette Gemini responses are recorded to or replayed from
+    pub gemini_cassette: Option<GeminiCassette>,
+}
+
+/// Settings of an OpenAI-compatible chat completions endpoint, such as a
+/// llama.cpp server, vLLM or Ollama
+#[derive(Debug, Clone)]
+pub struct OpenAiConfig {
+    /// API base URL, up to and including `/v1`
+    pub base_url: String,
+    /// Vision model that reads the invoices
+    pub model: String,
+    /// Bearer token; self-hosted servers usually need none
+    pub api_key: Option<String>,
+    /// Request timeout in seconds
+    pub timeout_seconds: u64,
+}
+
+/// Settings of the local Tesseract OCR engine
+#[derive(Debug, Clone)]
+pub struct TesseractConfig {
+    /// Path or name of the `tesseract` executable
+    pub command: String,
+    /// Tesseract language(s), e.g. `vie` or `vie+eng`
+    pub language: String,
+    /// Page segmentation mode (`--psm`)
+    pub page_segmentation_mode: u8,
+    /// Time one image may take to recognise, in seconds
+    pub timeout_seconds: u64,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExtractionConfigError {
+    #[error("Parse error: {0}")]
+    Parse(String),
+
+    #[error("Invalid value: {0}")]
+    Invalid(String),
+}
+
+/// Read an environment variable, treating a blank value as unset
+fn non_empty_var(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+impl ExtractionConfig {
+    /// Create ExtractionConfig from environment variables
+    pub fn from_env() -> Result<Self, ExtractionConfigError> {
+        let provider = non_empty_var("EXTRACTION_PROVIDER")
+            .map(|provider| provider.parse())
+            .transpose()
+            .map_err(|e| ExtractionConfigError::Parse(format!("Invalid EXTRACTION_PROVIDER: {e}")))?
+            .unwrap_or(ExtractionProviderKind::Gemini);
+        let gemini_cassette = non_empty_var("GEMINI_CASSETTE_MODE")
+            .filter(|mode| !mode.eq_ignore_ascii_case("off"))
+            .map(|mode| mode.parse::<CassetteMode>())
+            .transpose()
+            .map_err(|e| {
+                ExtractionConfigError::Parse(format!("Invalid GEMINI_CASSETTE_MODE: {e}"))
+            })?
+            .map(|mode| {
+                let dir =
+                    non_empty_var("GEMINI_CASSETTE_DIR").unwrap_or_else(|| "cassettes".to_string());
+                GeminiCassette::new(mode, dir)
+            });
+
+        let gemini = match provider {
+            // Replayed responses need no API key
+            ExtractionProviderKind::Gemini
+                if gemini_cassette
+                    .as_ref()
+                    .is_some_and(|cassette| cassette.mode == CassetteMode::Replay) =>
+            {
+                Some(GeminiConfig::from_env_for_replay())
+            }
+            ExtractionProviderKind::Gemini => Some(GeminiConfig::from_env()),
+            _ => None,
+        }
+        .transpose()
+        .map_err(|e| ExtractionConfigError::Invalid(format!("Gemini: {e}")))?;
+        let openai = match provider {
+            ExtractionProviderKind::OpenAi => Some(OpenAiConfig::from_env()?),
+            _ => None,
+        };
+        let tesseract = match provider {
+            ExtractionProviderKind::Tesseract => Some(TesseractConfig::from_env()?),
+            _ => None,
+        };
+
+        Ok(Self {
+            provider,
+            gemini,
+            openai,
+            tesseract,
+            gemini_cassette,
+        })
+    }
+
+    /// Display config info (safe for logging)
+    pub fn display_config(&self) -> String {
+        let provider_config = match (&self.gemini, &self.openai, &self.tesseract) {
+            (Some(gemini), _, _) => Some(gemini.display_config()),
+            (_, Some(openai), _) => Some(openai.display_config()),
+            (_, _, Some(tesseract)) => Some(tesseract.display_config()),
+            (None, None, None) => None,
+        };
+        let mut display = match provider_config {
+            Some(provider_config) => format!("provider={}, {}", self.provider, provider_config),
+            None => format!("provider={}", self.provider),
+        };
+        if let Some(cassette) = &self.gemini_cassette {
+            display.push_str(&format!(
+                ", gemini_cassette={} ({})",
+                cassette.mode,
+                cassette.dir.display()
+            ));
+        }
+        display
+    }
+}
+
+impl OpenAiConfig {
+    /// Create OpenAiConfig from environment variables
+    pub fn from_env() -> Result<Self, ExtractionConfigError> {
+        let base_url = non_empty_var("OPENAI_BASE_URL")
+            .unwrap_or_else(|| "http://localhost:8080/v1".to_string());
+        let model = non_empty_var("OPENAI_MODEL").ok_or_else(|| {
+            ExtractionConfigError::Invalid(
+                "OPENAI_MODEL is required when EXTRACTION_PROVIDER=openai".to_string(),
+            )
+        })?;
+        let timeout_seconds = non_empty_var("OPENAI_TIMEOUT_SECONDS")
+            .unwrap_or_else(|| "120".to_string())
+            .parse()
+            .map_err(|e| {
+                ExtractionConfigError::Parse(format!("Invalid OPENAI_TIMEOUT_SECONDS: {e}"))
+            })?;
+
+        let config = Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model,
+            api_key: non_empty_var("OPENAI_API_KEY"),
+            timeout_seconds,
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Validate configuration values
+    pub fn validate(&self) -> Result<(), ExtractionConfigError> {
+        if !self.base_url.starts_with("http://") && !self.base_url.starts_with("https://") {
+            return Err(ExtractionConfigError::Invalid(
+                "OPENAI_BASE_URL must start with http:// or https://".to_string(),
+            ));
+        }
+        if self.timeout_seconds == 0 {
+            return Err(ExtractionConfigError::Invalid(
+                "OPENAI_TIMEOUT_SECONDS must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Display config info (safe for logging)
+    pub fn display_config(&self) -> String {
+        format!(
+            "base_url={}, model={}, api_key={}, timeout={}s",
+            self.base_url,
+            self.model,
+            if self.api_key.is_some() {
+                "set"
+            } else {
+                "none"
+            },
+            self.timeout_seconds
+        )
+    }
+}
+
+impl TesseractConfig {
+    /// Create TesseractConfig from environment variables
+    pub fn from_env() -> Result<Self, ExtractionConfigError> {
+        let page_segmentation_mode = non_empty_var("TESSERACT_PSM")
+            .unwrap_or_else(|| "6".to_string())
+            .parse()
+            .map_err(|e| ExtractionConfigError::Parse(format!("Invalid TESSERACT_PSM: {e}")))?;
+        let timeout_seconds = non_empty_var("TESSERACT_TIMEOUT_SECONDS")
+            .unwrap_or_else(|| "60".to_string())
+            .parse()
+            .map_err(|e| {
+                ExtractionConfigError::Parse(format!("Invalid TESSERACT_TIMEOUT_SECONDS: {e}"))
+            })?;
+
+        let config = Self {
+            command: non_empty_var("TESSERACT_CMD").unwrap_or_else(|| "tesseract".to_string()),
+            language: non_empty_var("TESSERACT_LANG").unwrap_or_else(|| "vie".to_string()),
+            page_segmentation_mode,
+            timeout_seconds,
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Validate configuration values
+    pub fn validate(&self) -> Result<(), ExtractionConfigError> {
+        if self.page_segmentation_mode > 13 {
+            return Err(ExtractionConfigError::Invalid(
+                "TESSERACT_PSM must be between 0 and 13".to_string(),
+            ));
+        }
+        if self.timeout_seconds == 0 {
+            return Err(ExtractionConfigError::Invalid(
+                "TESSERACT_TIMEOUT_SECONDS must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Display config info (safe for logging)
+    pub fn display_config(&self) -> String {
+        format!(
+            "command={}, language={}, psm={}, timeout={}s",
+            self.command, self.language, self.page_segmentation_mode, self.timeout_seconds
+        )
+    }
+}
@@ -1,85 +1,120 @@
-use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+const DEFAULT_MODEL: &str = "gemini-2.5-flash";
+const DEFAULT_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+
 /// Configuration structure for Gemini AI API integration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct GeminiConfig {
     /// Google AI Studio API key for Gemini service
     pub api_key: String,
-    /// Gemini model to use (e.g., "gemini-pro-vision")
+    /// Gemini model to use; it must support response schemas (e.g. "gemini-2.5-flash")
     pub model: String,
     /// API request timeout in seconds
     pub timeout_seconds: u64,
+    /// Maximum retry attempts for rate limiting
+    pub max_retries: u32,
+    /// Base delay between retries in milliseconds
+    pub retry_delay_ms: u64,
     /// Maximum image size allowed for processing (in MB)
     pub max_image_size_mb: u64,
     /// Gemini API base URL endpoint
     pub base_url: String,
 }
 
+impl Default for GeminiConfig {
+    fn default() -> Self {
+        Self::new(String::new())
+    }
+}
+
 impl GeminiConfig {
     /// Create a new GeminiConfig with default values
     pub fn new(api_key: String) -> Self {
         Self {
             api_key,
-            model: "gemini-pro-vision".to_string(),
+            model: DEFAULT_MODEL.to_string(),
             timeout_seconds: 45,
+            max_retries: 3,
+            retry_delay_ms: 1000,
             max_image_size_mb: 20,
-            base_url: "https://generativelanguage.googleapis.com/v1beta".to_string(),
+            base_url: DEFAULT_BASE_URL.to_string(),
         }
     }
 
     /// Create GeminiConfig from environment variables
     pub fn from_env() -> Result<Self, GeminiConfigError> {
-        dotenvy::dotenv().ok(); // Load .env file if present
-
-        let api_key = std::env::var("GEMINI_API_KEY").map_err(|_| {
-            GeminiConfigError::Configuration(
+        let config = Self::read_env()?;
+        if config.api_key.is_empty() {
+            return Err(GeminiConfigError::Configuration(
                 "GEMINI_API_KEY environment variable is required".to_string(),
-            )
-        })?;
-
-        let model =
-            std::env::var("GEMINI_MODEL").unwrap_or_else(|_| "gemini-pro-vision".to_string());
-
-        let timeout_seconds = std::env::var("GEMINI_TIMEOUT_SECONDS")
-            .unwrap_or_else(|_| "45".to_string())
-            .parse()
-            .map_err(|_| {
-                GeminiConfigError::Configuration(
-                    "GEMINI_TIMEOUT_SECONDS must be a valid number".to_string(),
-                )
-            })?;
-
-        let max_image_size_mb = std::env::var("GEMINI_MAX_IMAGE_SIZE_MB")
-            .unwrap_or_else(|_| "20".to_string())
-            .parse()
-            .map_err(|_| {
-                GeminiConfigError::Configuration(
-                    "GEMINI_MAX_IMAGE_SIZE_MB must be a valid number".to_string(),
-                )
-            })?;
-
-        let base_url = std::env::var("GEMINI_BASE_URL")
-            .unwrap_or_else(|_| "https://generativelanguage.googleapis.com/v1beta".to_string());
-
-        let config = Self {
-            api_key,
-            model,
-            timeout_seconds,
-            max_image_size_mb,
-            base_url,
-        };
+            ));
+        }
+        config.validate()?;
 
-        // Validate configuration
-        config.validate().map_err(|e|
-            GeminiConfigError::Configuration(format!(
-                "Environment configuration validation failed: {e}. Check your .env file or environment variables."
-            ))
-        )?;
+        Ok(config)
+    }
+
+    /// Create GeminiConfig from environment variables for replaying
+    /// recorded responses, which needs no API key
+    pub fn from_env_for_replay() -> Result<Self, GeminiConfigError> {
+        let config = Self::read_env()?;
+        config.validate()?;
 
         Ok(config)
     }
 
+    fn read_env() -> Result<Self, GeminiConfigError> {
+        dotenvy::dotenv().ok(); // Load .env file if present
+
+        let defaults = Self::default();
+        let var = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        fn parse<T: std::str::FromStr>(
+            name: &str,
+            value: Option<String>,
+            default: T,
+        ) -> Result<T, GeminiConfigError> {
+            value.map_or(Ok(default), |value| {
+                value.parse().map_err(|_| {
+                    GeminiConfigError::Configuration(format!("{name} must be a valid number"))
+                })
+            })
+        }
+
+        Ok(Self {
+            api_key: var("GEMINI_API_KEY").unwrap_or_default(),
+            model: var("GEMINI_MODEL").unwrap_or(defaults.model),
+            timeout_seconds: parse(
+                "GEMINI_TIMEOUT_SECONDS",
+                var("GEMINI_TIMEOUT_SECONDS"),
+                defaults.timeout_seconds,
+            )?,
+            max_retries: parse(
+                "GEMINI_MAX_RETRIES",
+                var("GEMINI_MAX_RETRIES"),
+                defaults.max_retries,
+            )?,
+            retry_delay_ms: parse(
+                "GEMINI_RETRY_DELAY_MS",
+                var("GEMINI_RETRY_DELAY_MS"),
+                defaults.retry_delay_ms,
+            )?,
+            max_image_size_mb: parse(
+                "GEMINI_MAX_IMAGE_SIZE_MB",
+                var("GEMINI_MAX_IMAGE_SIZE_MB"),
+                defaults.max_image_size_mb,
+            )?,
+            base_url: var("GEMINI_BASE_URL")
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or(defaults.base_url),
+        })
+    }
+
     /// Display configuration details for debugging (excludes sensitive API key)
     pub fn display_config(&self) -> String {
         // Mask the API key for security
@@ -94,44 +129,79 @@ impl GeminiConfig {
         };
 
         format!(
-            "GeminiConfig {{ api_key: {}, model: {}, timeout: {}s, max_image_size: {}MB, base_url: {} }}",
-            masked_api_key, self.model, self.timeout_seconds, self.max_image_size_mb, self.base_url
+            "GeminiConfig {{ api_key: {}, model: {}, timeout: {}s, max_retries: {}, retry_delay: {}ms, max_image_size: {}MB, base_url: {} }}",
+            masked_api_key,
+            self.model,
+            self.timeout_seconds,
+            self.max_retries,
+            self.retry_delay_ms,
+            self.max_image_size_mb,
+            self.base_url
         )
     }
 
     /// Validate configuration parameters
+    ///
+    /// An empty API key is accepted here, for replaying recorded responses;
+    /// [`GeminiConfig::from_env`] requires one.
     pub fn validate(&self) -> Result<(), GeminiConfigError> {
-        // Validate API key is not empty
-        if self.api_key.trim().is_empty() {
-            return Err(GeminiConfigError::Configuration(
-                "api_key cannot be empty".to_string(),
+        // Validate API key has no whitespace, which a copy-paste error leaves behind
+        if self.api_key.chars().any(char::is_whitespace) {
+            return Err(GeminiConfigError::Validation(
+                "api_key cannot contain whitespace".to_string(),
             ));
         }
 
-        // Validate model name is not empty
+        // Validate model name is usable in the `models/{model}:generateContent` path
         if self.model.trim().is_empty() {
-            return Err(GeminiConfigError::Configuration(
+            return Err(GeminiConfigError::Validation(
                 "model name cannot be empty".to_string(),
             ));
         }
+        if !self
+            .model
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'))
+        {
+            return Err(GeminiConfigError::Validation(format!(
+                "model name '{}' may only contain letters, digits, '-', '.' and '_'",
+                self.model
+            )));
+        }
 
         // Validate timeout is reasonable (between 1 and 600 seconds)
         if self.timeout_seconds == 0 || self.timeout_seconds > 600 {
-            return Err(GeminiConfigError::Configuration(
+            return Err(GeminiConfigError::Validation(
                 "timeout_seconds must be between 1 and 600".to_string(),
             ));
         }
 
+        // Validate retries are bounded (at most 10 retries of at most 60 seconds)
+        if self.max_retries > 10 {
+            return Err(GeminiConfigError::Validation(
+                "max_retries must be at most 10".to_string(),
+            ));
+        }
+        if self.retry_delay_ms > 60_000 {
+            return Err(GeminiConfigError::Validation(
+                "retry_delay_ms must be at most 60000".to_string(),
+            ));
+        }
+
         // Validate max image size is reasonable (between 1 and 100MB)
         if self.max_image_size_mb == 0 || self.max_image_size_mb > 100 {
-            return Err(GeminiConfigError::Configuration(
+            return Err(GeminiConfigError::Validation(
                 "max_image_size_mb must be between 1 and 100".to_string(),
             ));
         }
 
-        // Validate base URL format
-        if !self.base_url.starts_with("https://") {
-            return Err(GeminiConfigError::Configuration(
+        // Validate base URL format; plain http is only allowed to this machine,
+        // for local proxies and mock servers
+        let is_local = ["http://localhost", "http://127.0.0.1", "http://[::1]"]
+            .iter()
+            .any(|prefix| self.base_url.starts_with(prefix));
+        if !self.base_url.starts_with("https://") && !is_local {
+            return Err(GeminiConfigError::Validation(
                 "base_url must start with https://".to_string(),
             ));
         }
@@ -153,6 +223,11 @@ impl GeminiConfig {
     pub fn generation_url(&self) -> String {
         format!("{}/models/{}:generateContent", self.base_url, self.model)
     }
+
+    /// Get the API URL describing the configured model
+    pub fn model_url(&self) -> String {
+        format!("{}/models/{}", self.base_url, self.model)
+    }
 }
 
 /// Custom error types for Gemini configuration operations
@@ -164,3 +239,67 @@ pub enum GeminiConfigError {
     #[error("Validation error: {0}")]
     Validation(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config() {
+        let config = GeminiConfig::new("test-key".to_string());
+        assert_eq!(config.base_url, DEFAULT_BASE_URL);
+        assert_eq!(config.model, "gemini-2.5-flash");
+        assert_eq!(config.timeout_seconds, 45);
+        assert_eq!(config.max_retries, 3);
+        assert_eq!(config.max_image_size_mb, 20);
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.generation_url(),
+            format!("{DEFAULT_BASE_URL}/models/gemini-2.5-flash:generateContent")
+        );
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_values() {
+        let valid = GeminiConfig::new("test-key".to_string());
+
+        for invalid in [
+            GeminiConfig {
+                api_key: "test key".to_string(),
+                ..valid.clone()
+            },
+            GeminiConfig {
+                model: String::new(),
+                ..valid.clone()
+            },
+            GeminiConfig {
+                model: "gemini 2.5/flash".to_string(),
+                ..valid.clone()
+            },
+            GeminiConfig {
+                timeout_seconds: 0,
+                ..valid.clone()
+            },
+            GeminiConfig {
+                max_retries: 11,
+                ..valid.clone()
+            },
+            GeminiConfig {
+                max_image_size_mb: 0,
+                ..valid.clone()
+            },
+            GeminiConfig {
+                base_url: "http://generativelanguage.googleapis.com/v1beta".to_string(),
+                ..valid.clone()
+            },
+        ] {
+            assert!(invalid.validate().is_err(), "{invalid:?}");
+        }
+
+        let local = GeminiConfig {
+            base_url: "http://127.0.0.1:8080".to_string(),
+            ..valid
+        };
+        assert!(local.validate().is_ok());
+    }
+}
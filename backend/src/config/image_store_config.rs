@@ -0,0 +1,63 @@
+use std::env;
+use std::path::PathBuf;
+
+/// Settings of the store that keeps the original uploaded images
+#[derive(Debug, Clone)]
+pub struct ImageStoreConfig {
+    /// Directory the images are stored in, named by their SHA-256
+    pub dir: PathBuf,
+    /// Longest side of generated thumbnails, in pixels
+    pub thumbnail_edge: u32,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImageStoreConfigError {
+    #[error("Parse error: {0}")]
+    Parse(String),
+
+    #[error("Invalid value: {0}")]
+    Invalid(String),
+}
+
+impl ImageStoreConfig {
+    /// Create ImageStoreConfig from environment variables
+    pub fn from_env() -> Result<Self, ImageStoreConfigError> {
+        let dir = env::var("IMAGE_STORE_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty())
+            .unwrap_or_else(|| "data/images".to_string());
+        let thumbnail_edge = env::var("IMAGE_THUMBNAIL_EDGE")
+            .unwrap_or_else(|_| "320".to_string())
+            .parse()
+            .map_err(|e| {
+                ImageStoreConfigError::Parse(format!("Invalid IMAGE_THUMBNAIL_EDGE: {e}"))
+            })?;
+
+        let config = Self {
+            dir: PathBuf::from(dir),
+            thumbnail_edge,
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Validate configuration values
+    pub fn validate(&self) -> Result<(), ImageStoreConfigError> {
+        if !(16..=2048).contains(&self.thumbnail_edge) {
+            return Err(ImageStoreConfigError::Invalid(
+                "IMAGE_THUMBNAIL_EDGE must be between 16 and 2048".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Display config info (safe for logging)
+    pub fn display_config(&self) -> String {
+        format!(
+            "dir={}, thumbnail_edge={}px",
+            self.dir.display(),
+            self.thumbnail_edge
+        )
+    }
+}
@@ -0,0 +1,102 @@
+use std::env;
+use std::time::Duration;
+
+/// Settings of the durable OCR job queue and its workers
+#[derive(Debug, Clone)]
+pub struct JobQueueConfig {
+    /// Number of worker loops processing queued jobs concurrently
+    pub workers: usize,
+    /// How often idle workers look for jobs left over by a restart
+    pub poll_interval: Duration,
+    /// A processing job whose worker has not renewed its lock for this long is reclaimed
+    pub lease: Duration,
+    /// Attempts after which an interrupted job is given up
+    pub max_attempts: i32,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum JobQueueConfigError {
+    #[error("Parse error: {0}")]
+    Parse(String),
+
+    #[error("Invalid value: {0}")]
+    Invalid(String),
+}
+
+impl JobQueueConfig {
+    /// Create JobQueueConfig from environment variables
+    pub fn from_env() -> Result<Self, JobQueueConfigError> {
+        let workers: usize = parse_env("OCR_JOB_WORKERS", "4")?;
+        let poll_interval_ms: u64 = parse_env("OCR_JOB_POLL_INTERVAL_MS", "2000")?;
+        let lease_seconds: u64 = parse_env("OCR_JOB_LEASE_SECONDS", "60")?;
+        let max_attempts: i32 = parse_env("OCR_JOB_MAX_ATTEMPTS", "3")?;
+
+        let config = Self {
+            workers,
+            poll_interval: Duration::from_millis(poll_interval_ms),
+            lease: Duration::from_secs(lease_seconds),
+            max_attempts,
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Validate configuration values
+    pub fn validate(&self) -> Result<(), JobQueueConfigError> {
+        if self.workers == 0 {
+            return Err(JobQueueConfigError::Invalid(
+                "OCR_JOB_WORKERS must be at least 1".to_string(),
+            ));
+        }
+        if self.lease.as_secs() < 3 {
+            return Err(JobQueueConfigError::Invalid(
+                "OCR_JOB_LEASE_SECONDS must be at least 3".to_string(),
+            ));
+        }
+        if self.max_attempts < 1 {
+            return Err(JobQueueConfigError::Invalid(
+                "OCR_JOB_MAX_ATTEMPTS must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Interval at which a worker renews the lock of the job it is processing
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.lease / 3
+    }
+
+    /// Display config info (safe for logging)
+    pub fn display_config(&self) -> String {
+        format!(
+            "workers={}, poll_interval={}ms, lease={}s, max_attempts={}",
+            self.workers,
+            self.poll_interval.as_millis(),
+            self.lease.as_secs(),
+            self.max_attempts
+        )
+    }
+}
+
+impl Default for JobQueueConfig {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            poll_interval: Duration::from_millis(2000),
+            lease: Duration::from_secs(60),
+            max_attempts: 3,
+        }
+    }
+}
+
+fn parse_env<T>(name: &str, default: &str) -> Result<T, JobQueueConfigError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    let value = env::var(name).unwrap_or_else(|_| default.to_string());
+    value
+        .parse()
+        .map_err(|e| JobQueueConfigError::Parse(format!("Invalid {} '{}': {}", name, value, e)))
+}
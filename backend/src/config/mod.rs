@@ -1,14 +1,24 @@
 pub mod database;
+pub mod extraction_config;
 pub mod gemini_config;
+pub mod image_store_config;
+pub mod job_queue_config;
 pub mod server_config;
 pub mod upload_config;
+pub mod watch_folder_config;
+pub mod webhook_config;
 
+use crate::utils::database::{PoolInfo, test_database_connectivity_detailed};
 pub use database::{DatabaseConfig, DatabaseError};
+pub use extraction_config::{ExtractionConfig, OpenAiConfig, TesseractConfig};
+pub use gemini_config::{GeminiConfig, GeminiConfigError};
+pub use image_store_config::ImageStoreConfig;
+pub use job_queue_config::JobQueueConfig;
+pub use server_config::{ServerConfig, ServerConfigError};
 use sqlx::PgPool;
 pub use upload_config::UploadConfig;
-// pub use gemini_config::{GeminiConfig, GeminiConfigError};
-use crate::utils::database::{PoolInfo, test_database_connectivity_detailed};
-pub use server_config::{ServerConfig, ServerConfigError};
+pub use watch_folder_config::WatchFolderConfig;
+pub use webhook_config::WebhookConfig;
 
 /// Wrapper around SQLx PgPool for database connection management.
 #[derive(Debug, Clone)]
@@ -38,6 +48,15 @@ impl ConnectionPool {
         Ok(connection_pool)
     }
 
+    /// Wrap a pool that is already set up, such as the database of a `sqlx::test`
+    #[cfg(test)]
+    pub(crate) fn from_pool(pool: PgPool) -> Self {
+        Self {
+            pool,
+            config: DatabaseConfig::new(String::new()),
+        }
+    }
+
     /// Create ConnectionPool from environment variables with full initialization
     pub async fn from_env() -> Result<Self, DatabaseError> {
         let config = DatabaseConfig::from_env().map_err(|e| {
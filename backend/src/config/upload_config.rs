@@ -1,10 +1,50 @@
 use dotenvy::dotenv;
-use std::env;
+use std::{collections::HashMap, env};
+
+use crate::{
+    services::extraction_provider::ExtractionProviderKind,
+    utils::image_utils::{ResizeConfig, ResizeTarget},
+};
 
 #[derive(Debug, Clone)]
 pub struct UploadConfig {
     pub max_file_size_bytes: usize,
     pub max_image_count: usize,
+    /// Combined size of all images of one upload
+    pub max_total_upload_bytes: usize,
+    /// Number of images of one upload that are processed at the same time
+    pub max_concurrent_images: usize,
+    /// Number of pages of one PDF; each page is a separate extraction
+    pub max_pdf_pages: usize,
+    /// Number of files of one upload, counting every file of its ZIP archives
+    pub max_archive_entries: usize,
+    /// Combined uncompressed size of the files of one upload, archives expanded
+    pub max_archive_uncompressed_bytes: usize,
+    /// Combined size of the ZIP archives of one upload, as sent
+    pub max_archive_bytes: usize,
+    /// Bits in which perceptual hashes may differ for images to count as duplicates
+    pub duplicate_max_hash_distance: u32,
+    /// Preprocessing applied to every image before extraction
+    pub image_pipeline: ResizeConfig,
+    /// Resize targets of extraction providers that differ from the pipeline's
+    pub provider_resize_targets: HashMap<ExtractionProviderKind, ResizeTarget>,
+}
+
+/// Read a resize target from `{prefix}_TARGET_LONG_EDGE` or `{prefix}_TARGET_MEGAPIXELS`
+fn resize_target_from_env(
+    prefix: &str,
+) -> Result<Option<ResizeTarget>, Box<dyn std::error::Error>> {
+    let long_edge = env::var(format!("{prefix}_TARGET_LONG_EDGE")).ok();
+    let megapixels = env::var(format!("{prefix}_TARGET_MEGAPIXELS")).ok();
+    match (long_edge, megapixels) {
+        (Some(_), Some(_)) => Err(format!(
+            "{prefix}_TARGET_LONG_EDGE and {prefix}_TARGET_MEGAPIXELS cannot both be set"
+        )
+        .into()),
+        (Some(long_edge), None) => Ok(Some(ResizeTarget::LongEdge(long_edge.parse()?))),
+        (None, Some(megapixels)) => Ok(Some(ResizeTarget::Megapixels(megapixels.parse()?))),
+        (None, None) => Ok(None),
+    }
 }
 
 impl UploadConfig {
@@ -19,9 +59,101 @@ impl UploadConfig {
             .unwrap_or_else(|_| "10".to_string())
             .parse()?;
 
+        let max_total_upload_bytes = env::var("MAX_TOTAL_UPLOAD_BYTES")
+            .unwrap_or_else(|_| "20971520".to_string())
+            .parse()?;
+
+        let max_concurrent_images: usize = env::var("MAX_CONCURRENT_IMAGES")
+            .unwrap_or_else(|_| "3".to_string())
+            .parse()?;
+        if max_concurrent_images == 0 {
+            return Err("MAX_CONCURRENT_IMAGES must be at least 1".into());
+        }
+
+        let max_pdf_pages = env::var("MAX_PDF_PAGES")
+            .unwrap_or_else(|_| "20".to_string())
+            .parse()?;
+
+        let max_archive_entries = env::var("MAX_ARCHIVE_ENTRIES")
+            .unwrap_or_else(|_| "500".to_string())
+            .parse()?;
+
+        let max_archive_uncompressed_bytes = env::var("MAX_ARCHIVE_UNCOMPRESSED_BYTES")
+            .unwrap_or_else(|_| "524288000".to_string())
+            .parse()?;
+
+        let max_archive_bytes = env::var("MAX_ARCHIVE_BYTES")
+            .unwrap_or_else(|_| "104857600".to_string())
+            .parse()?;
+
+        let duplicate_max_hash_distance: u32 = env::var("DUPLICATE_MAX_HASH_DISTANCE")
+            .unwrap_or_else(|_| "6".to_string())
+            .parse()?;
+        if duplicate_max_hash_distance > 64 {
+            return Err("DUPLICATE_MAX_HASH_DISTANCE must be at most 64".into());
+        }
+
+        let defaults = ResizeConfig::default();
+        let flag = |name: &str, default: bool| -> Result<bool, Box<dyn std::error::Error>> {
+            Ok(env::var(name).map_or(Ok(default), |value| value.parse())?)
+        };
+        let image_pipeline = ResizeConfig {
+            apply_exif_orientation: flag(
+                "IMAGE_EXIF_ORIENTATION",
+                defaults.apply_exif_orientation,
+            )?,
+            crop_to_document: flag("IMAGE_CROP_TO_DOCUMENT", defaults.crop_to_document)?,
+            deskew: flag("IMAGE_DESKEW", defaults.deskew)?,
+            max_deskew_degrees: env::var("IMAGE_MAX_DESKEW_DEGREES")
+                .map_or(Ok(defaults.max_deskew_degrees), |value| value.parse())?,
+            normalize_contrast: flag("IMAGE_NORMALIZE_CONTRAST", defaults.normalize_contrast)?,
+            grayscale: flag("IMAGE_GRAYSCALE", defaults.grayscale)?,
+            target: resize_target_from_env("IMAGE")?.unwrap_or(defaults.target),
+            min_long_edge: env::var("IMAGE_MIN_LONG_EDGE")
+                .map_or(Ok(defaults.min_long_edge), |value| value.parse())?,
+            ..defaults
+        };
+
+        let mut provider_resize_targets = HashMap::new();
+        for provider in ExtractionProviderKind::ALL {
+            let prefix = format!("{}_IMAGE", provider.as_str().to_uppercase());
+            if let Some(target) = resize_target_from_env(&prefix)? {
+                provider_resize_targets.insert(*provider, target);
+            }
+        }
+
         Ok(UploadConfig {
             max_file_size_bytes: max_file_size,
             max_image_count,
+            max_total_upload_bytes,
+            max_concurrent_images,
+            max_pdf_pages,
+            max_archive_entries,
+            max_archive_uncompressed_bytes,
+            max_archive_bytes,
+            duplicate_max_hash_distance,
+            image_pipeline,
+            provider_resize_targets,
         })
     }
+
+    /// Preprocessing pipeline for images sent to an extraction provider
+    pub fn image_pipeline_for(&self, provider: ExtractionProviderKind) -> ResizeConfig {
+        ResizeConfig {
+            target: self
+                .provider_resize_targets
+                .get(&provider)
+                .copied()
+                .unwrap_or(self.image_pipeline.target),
+            ..self.image_pipeline.clone()
+        }
+    }
+
+    /// Largest request body accepted by the upload endpoints
+    ///
+    /// Room for the images of an upload plus its archives or email exports.
+    pub fn max_request_body_bytes(&self) -> usize {
+        self.max_total_upload_bytes
+            .saturating_add(self.max_archive_bytes)
+    }
 }
@@ -0,0 +1,87 @@
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Subfolder of the watched directory that receives successfully processed files
+pub const PROCESSED_SUBDIR: &str = "processed";
+/// Subfolder of the watched directory that receives rejected files and their error reports
+pub const FAILED_SUBDIR: &str = "failed";
+
+/// Settings of the watched-folder ingestion mode
+#[derive(Debug, Clone)]
+pub struct WatchFolderConfig {
+    /// Directory the scanner drops images into
+    pub dir: PathBuf,
+    /// How often the directory is scanned for new files
+    pub poll_interval: Duration,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WatchFolderConfigError {
+    #[error("Parse error: {0}")]
+    Parse(String),
+
+    #[error("Invalid value: {0}")]
+    Invalid(String),
+}
+
+impl WatchFolderConfig {
+    /// Create WatchFolderConfig from environment variables
+    ///
+    /// Returns `None` when `WATCH_FOLDER_DIR` is not set, which disables the mode.
+    pub fn from_env() -> Result<Option<Self>, WatchFolderConfigError> {
+        let dir = match env::var("WATCH_FOLDER_DIR") {
+            Ok(dir) if !dir.trim().is_empty() => PathBuf::from(dir),
+            _ => return Ok(None),
+        };
+        let poll_interval_ms: u64 = env::var("WATCH_FOLDER_POLL_INTERVAL_MS")
+            .unwrap_or_else(|_| "5000".to_string())
+            .parse()
+            .map_err(|e| {
+                WatchFolderConfigError::Parse(format!("Invalid WATCH_FOLDER_POLL_INTERVAL_MS: {e}"))
+            })?;
+
+        let config = Self {
+            dir,
+            poll_interval: Duration::from_millis(poll_interval_ms),
+        };
+        config.validate()?;
+
+        Ok(Some(config))
+    }
+
+    /// Validate configuration values
+    pub fn validate(&self) -> Result<(), WatchFolderConfigError> {
+        if !self.dir.is_dir() {
+            return Err(WatchFolderConfigError::Invalid(format!(
+                "WATCH_FOLDER_DIR {} is not a directory",
+                self.dir.display()
+            )));
+        }
+        if self.poll_interval < Duration::from_millis(100) {
+            return Err(WatchFolderConfigError::Invalid(
+                "WATCH_FOLDER_POLL_INTERVAL_MS must be at least 100".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Where successfully processed files are moved
+    pub fn processed_dir(&self) -> PathBuf {
+        self.dir.join(PROCESSED_SUBDIR)
+    }
+
+    /// Where rejected files and their error reports are moved
+    pub fn failed_dir(&self) -> PathBuf {
+        self.dir.join(FAILED_SUBDIR)
+    }
+
+    /// Display config info (safe for logging)
+    pub fn display_config(&self) -> String {
+        format!(
+            "dir={}, poll_interval={}ms",
+            self.dir.display(),
+            self.poll_interval.as_millis()
+        )
+    }
+}
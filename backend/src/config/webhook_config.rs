@@ -0,0 +1,132 @@
+use std::env;
+use std::time::Duration;
+
+/// Settings of webhook delivery
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// Delivery attempts after which a delivery is marked failed
+    pub max_attempts: i32,
+    /// Delay before the first retry; doubled after every failed attempt
+    pub initial_backoff: Duration,
+    /// Upper bound of the retry delay
+    pub max_backoff: Duration,
+    /// Timeout of a single delivery request
+    pub timeout: Duration,
+    /// How often the dispatcher looks for deliveries that are due
+    pub poll_interval: Duration,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookConfigError {
+    #[error("Parse error: {0}")]
+    Parse(String),
+
+    #[error("Invalid value: {0}")]
+    Invalid(String),
+}
+
+impl WebhookConfig {
+    /// Create WebhookConfig from environment variables
+    pub fn from_env() -> Result<Self, WebhookConfigError> {
+        let max_attempts: i32 = parse_env("WEBHOOK_MAX_ATTEMPTS", "6")?;
+        let initial_backoff_seconds: u64 = parse_env("WEBHOOK_INITIAL_BACKOFF_SECONDS", "10")?;
+        let max_backoff_seconds: u64 = parse_env("WEBHOOK_MAX_BACKOFF_SECONDS", "3600")?;
+        let timeout_seconds: u64 = parse_env("WEBHOOK_TIMEOUT_SECONDS", "10")?;
+        let poll_interval_ms: u64 = parse_env("WEBHOOK_POLL_INTERVAL_MS", "5000")?;
+
+        let config = Self {
+            max_attempts,
+            initial_backoff: Duration::from_secs(initial_backoff_seconds),
+            max_backoff: Duration::from_secs(max_backoff_seconds),
+            timeout: Duration::from_secs(timeout_seconds),
+            poll_interval: Duration::from_millis(poll_interval_ms),
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Validate configuration values
+    pub fn validate(&self) -> Result<(), WebhookConfigError> {
+        if self.max_attempts < 1 {
+            return Err(WebhookConfigError::Invalid(
+                "WEBHOOK_MAX_ATTEMPTS must be at least 1".to_string(),
+            ));
+        }
+        if self.timeout.is_zero() {
+            return Err(WebhookConfigError::Invalid(
+                "WEBHOOK_TIMEOUT_SECONDS must be at least 1".to_string(),
+            ));
+        }
+        if self.max_backoff < self.initial_backoff {
+            return Err(WebhookConfigError::Invalid(
+                "WEBHOOK_MAX_BACKOFF_SECONDS must not be below WEBHOOK_INITIAL_BACKOFF_SECONDS"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Delay before retrying a delivery that has failed `attempts` times
+    pub fn backoff(&self, attempts: i32) -> Duration {
+        let doublings = attempts.saturating_sub(1).clamp(0, 30) as u32;
+        self.initial_backoff
+            .saturating_mul(1 << doublings)
+            .min(self.max_backoff)
+    }
+
+    /// Display config info (safe for logging)
+    pub fn display_config(&self) -> String {
+        format!(
+            "max_attempts={}, initial_backoff={}s, max_backoff={}s, timeout={}s, poll_interval={}ms",
+            self.max_attempts,
+            self.initial_backoff.as_secs(),
+            self.max_backoff.as_secs(),
+            self.timeout.as_secs(),
+            self.poll_interval.as_millis()
+        )
+    }
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 6,
+            initial_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(3600),
+            timeout: Duration::from_secs(10),
+            poll_interval: Duration::from_millis(5000),
+        }
+    }
+}
+
+fn parse_env<T>(name: &str, default: &str) -> Result<T, WebhookConfigError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    let value = env::var(name).unwrap_or_else(|_| default.to_string());
+    value
+        .parse()
+        .map_err(|e| WebhookConfigError::Parse(format!("Invalid {} '{}': {}", name, value, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let config = WebhookConfig {
+            initial_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(60),
+            ..WebhookConfig::default()
+        };
+
+        assert_eq!(config.backoff(1), Duration::from_secs(10));
+        assert_eq!(config.backoff(2), Duration::from_secs(20));
+        assert_eq!(config.backoff(3), Duration::from_secs(40));
+        assert_eq!(config.backoff(4), Duration::from_secs(60));
+        assert_eq!(config.backoff(100), Duration::from_secs(60));
+    }
+}
@@ -9,11 +9,41 @@ pub enum UploadError {
     #[error("Image count {count} exceeds limit {limit}")]
     ImageCountExceeded { count: usize, limit: usize },
 
+    #[error("Total upload size {size} exceeds limit {limit}")]
+    TotalSizeExceeded { size: usize, limit: usize },
+
     #[error("Invalid image format: {0}")]
     InvalidImageFormat(String),
 
+    #[error("Invalid PDF: {0}")]
+    InvalidPdf(String),
+
+    #[error("PDF has {count} pages, limit is {limit}")]
+    PdfPageCountExceeded { count: usize, limit: usize },
+
+    #[error("Invalid e-invoice XML: {0}")]
+    InvalidEInvoice(String),
+
+    #[error("Invalid archive: {0}")]
+    InvalidArchive(String),
+
+    #[error("Upload has {count} files with its archives expanded, limit is {limit}")]
+    ArchiveEntryCountExceeded { count: usize, limit: usize },
+
+    #[error("Upload uncompressed size {size} exceeds limit {limit}")]
+    ArchiveSizeExceeded { size: usize, limit: usize },
+
+    #[error("Archive size {size} exceeds limit {limit}")]
+    CompressedArchiveSizeExceeded { size: usize, limit: usize },
+
+    #[error("Invalid email export: {0}")]
+    InvalidEmail(String),
+
     #[error("Multipart parsing failed: {0}")]
     MultipartError(String),
+
+    #[error("Failed to store upload: {0}")]
+    StorageError(String),
 }
 
 impl IntoResponse for UploadError {
@@ -22,13 +52,35 @@ impl IntoResponse for UploadError {
             UploadError::FileSizeExceeded { .. } => {
                 (StatusCode::PAYLOAD_TOO_LARGE, self.to_string())
             }
+            UploadError::TotalSizeExceeded { .. } => {
+                (StatusCode::PAYLOAD_TOO_LARGE, self.to_string())
+            }
             UploadError::ImageCountExceeded { .. } => {
                 (StatusCode::UNPROCESSABLE_ENTITY, self.to_string())
             }
+            UploadError::ArchiveSizeExceeded { .. } => {
+                (StatusCode::PAYLOAD_TOO_LARGE, self.to_string())
+            }
+            UploadError::CompressedArchiveSizeExceeded { .. } => {
+                (StatusCode::PAYLOAD_TOO_LARGE, self.to_string())
+            }
+            UploadError::ArchiveEntryCountExceeded { .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, self.to_string())
+            }
+            UploadError::InvalidPdf(_) => (StatusCode::UNSUPPORTED_MEDIA_TYPE, self.to_string()),
+            UploadError::PdfPageCountExceeded { .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, self.to_string())
+            }
+            UploadError::InvalidEInvoice(_) => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, self.to_string())
+            }
             UploadError::InvalidImageFormat(_) => {
                 (StatusCode::UNSUPPORTED_MEDIA_TYPE, self.to_string())
             }
+            UploadError::InvalidArchive(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            UploadError::InvalidEmail(_) => (StatusCode::BAD_REQUEST, self.to_string()),
             UploadError::MultipartError(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            UploadError::StorageError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
         };
 
         axum::Json(json!({
@@ -10,21 +10,32 @@ use axum::{
     Router,
     extract::DefaultBodyLimit,
     middleware,
-    routing::{get, post},
+    routing::{delete, get, post},
 };
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::broadcast;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
 use tracing::{error, info, warn};
 
 use api::{
-    create_bill, delete_bill, error_handling_middleware, export_bills, get_all_bills, get_bill_by_id,
-    get_bills_count, get_health, get_health_detail, not_found_handler, search_bills,
-    timeout_middleware, update_bill, upload_images_sse,
+    cancel_ocr_job, commit_bill_drafts, create_bill, create_ocr_job, create_webhook, delete_bill,
+    delete_webhook, discard_bill_drafts, error_handling_middleware, export_bills, get_all_bills,
+    get_bill_by_id, get_bill_drafts, get_bill_image, get_bill_image_thumbnail, get_bill_provenance,
+    get_bills_count, get_health, get_health_detail, get_ocr_job, get_ocr_job_events,
+    get_webhook_deliveries, get_webhooks, import_einvoice_xml, not_found_handler,
+    run_ingest_email_command, search_bills, spawn_folder_watcher, spawn_job_workers,
+    spawn_webhook_dispatcher, timeout_middleware, update_bill, upload_email_sse, upload_images_sse,
+};
+use config::{
+    ConnectionPool, DatabaseConfig, ExtractionConfig, ImageStoreConfig, JobQueueConfig,
+    ServerConfig, UploadConfig, WatchFolderConfig, WebhookConfig,
+};
+use services::{
+    extraction_provider::build_provider, image_store::ImageStore, ocr_job_queue::OcrJobQueue,
+    session_registry::SessionRegistry, webhook_service::WebhookService,
 };
-use config::{ConnectionPool, DatabaseConfig, ServerConfig, UploadConfig};
 use state::AppState;
 
 #[tokio::main]
@@ -61,8 +72,11 @@ async fn main() {
     let upload_config = match UploadConfig::from_env() {
         Ok(config) => {
             info!(
-                "Upload configuration loaded: max_file_size_bytes={}, max_image_count={}",
-                config.max_file_size_bytes, config.max_image_count
+                "Upload configuration loaded: max_file_size_bytes={}, max_image_count={}, max_total_upload_bytes={}, max_concurrent_images={}",
+                config.max_file_size_bytes,
+                config.max_image_count,
+                config.max_total_upload_bytes,
+                config.max_concurrent_images
             );
             Arc::new(config)
         }
@@ -72,17 +86,142 @@ async fn main() {
         }
     };
 
-    // Create event broadcaster for SSE
-    let (event_broadcaster, _) = broadcast::channel(1000);
-    info!("Event broadcaster initialized with buffer size: 1000");
+    // Create registry of per-upload SSE sessions
+    let sessions = SessionRegistry::new();
+    info!("Processing session registry initialized");
+
+    // Initialize the durable OCR job queue
+    let job_queue = match JobQueueConfig::from_env() {
+        Ok(config) => {
+            info!(
+                "Job queue configuration loaded: {}",
+                config.display_config()
+            );
+            OcrJobQueue::new(pool.pool().clone(), config)
+        }
+        Err(e) => {
+            error!("Failed to load job queue configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    match job_queue.unfinished_count().await {
+        Ok(0) => {}
+        Ok(count) => info!("Resuming {} unfinished OCR job(s) from the queue", count),
+        Err(e) => {
+            error!(
+                "OCR job queue is not available (are migrations applied?): {}",
+                e
+            );
+            std::process::exit(1);
+        }
+    }
+
+    // Initialize webhook delivery
+    let webhooks = match WebhookConfig::from_env()
+        .map_err(|e| e.to_string())
+        .and_then(|config| {
+            WebhookService::new(pool.pool().clone(), config).map_err(|e| e.to_string())
+        }) {
+        Ok(webhooks) => webhooks,
+        Err(e) => {
+            error!("Failed to initialize webhook delivery: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Open the store of original uploaded images
+    let image_store = match ImageStoreConfig::from_env() {
+        Ok(config) => match ImageStore::open(&config).await {
+            Ok(store) => {
+                info!("Image store opened: {}", config.display_config());
+                store
+            }
+            Err(e) => {
+                error!("Failed to open image store {}: {}", config.dir.display(), e);
+                std::process::exit(1);
+            }
+        },
+        Err(e) => {
+            error!("Failed to load image store configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Create the extraction provider shared by all processing tasks
+    let extraction = match ExtractionConfig::from_env() {
+        Ok(config) => match build_provider(&config) {
+            Ok(provider) => {
+                info!(
+                    "Extraction provider initialized: {}",
+                    config.display_config()
+                );
+                provider
+            }
+            Err(e) => {
+                error!(
+                    "Failed to initialize extraction provider {}: {}",
+                    config.provider, e
+                );
+                std::process::exit(1);
+            }
+        },
+        Err(e) => {
+            error!("Failed to load extraction configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // Fail fast on a rejected API key or unknown model instead of on the first upload
+    if let Err(e) = extraction.verify().await {
+        error!(
+            "Extraction provider {} rejected the configuration: {}",
+            extraction.kind(),
+            e
+        );
+        std::process::exit(1);
+    }
 
     // Create unified application state
     let app_state = AppState {
         pool: pool.clone(),
         upload_config: upload_config.clone(),
-        event_broadcaster,
+        sessions,
+        job_queue,
+        webhooks: webhooks.clone(),
+        image_store,
+        extraction,
     };
 
+    // `backend ingest-email <files...>` processes email exports and exits
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("ingest-email") {
+        let paths: Vec<PathBuf> = args[1..].iter().map(PathBuf::from).collect();
+        if paths.is_empty() {
+            error!("Usage: backend ingest-email <file.eml|archive.mbox>...");
+            std::process::exit(2);
+        }
+        let succeeded = run_ingest_email_command(&paths, app_state).await;
+        std::process::exit(if succeeded { 0 } else { 1 });
+    }
+
+    // Process queued uploads, including those interrupted by a previous run
+    spawn_job_workers(app_state.clone());
+
+    // Post webhook deliveries, including retries left over from a previous run
+    spawn_webhook_dispatcher(webhooks);
+
+    // Ingest images dropped into the watched folder, if one is configured
+    match WatchFolderConfig::from_env() {
+        Ok(Some(config)) => spawn_folder_watcher(config, app_state.clone()),
+        Ok(None) => {}
+        Err(e) => {
+            error!("Failed to load watched-folder configuration: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // Uploads may carry a ZIP archive, which can exceed the general request limit
+    let upload_body_limit = upload_config.max_request_body_bytes();
+
     // Create router with unified state
     let app = Router::new()
         // Health endpoints
@@ -93,12 +232,43 @@ async fn main() {
         .route("/api/bills/search", get(search_bills))
         .route("/api/bills/count", get(get_bills_count))
         .route("/api/bills/export", get(export_bills))
+        .route("/api/bills/import/xml", post(import_einvoice_xml))
         .route(
             "/api/bills/{id}",
             get(get_bill_by_id).put(update_bill).delete(delete_bill),
         )
+        .route("/api/bills/{id}/provenance", get(get_bill_provenance))
+        .route("/api/bills/{id}/image", get(get_bill_image))
+        .route(
+            "/api/bills/{id}/image/thumbnail",
+            get(get_bill_image_thumbnail),
+        )
         // OCR endpoints
-        .route("/api/ocr", post(upload_images_sse))
+        .route(
+            "/api/ocr",
+            post(upload_images_sse).layer(DefaultBodyLimit::max(upload_body_limit)),
+        )
+        .route(
+            "/api/ocr/email",
+            post(upload_email_sse).layer(DefaultBodyLimit::max(upload_body_limit)),
+        )
+        .route(
+            "/api/ocr/jobs",
+            post(create_ocr_job).layer(DefaultBodyLimit::max(upload_body_limit)),
+        )
+        .route("/api/ocr/jobs/{id}", get(get_ocr_job))
+        .route("/api/ocr/jobs/{id}/events", get(get_ocr_job_events))
+        .route("/api/ocr/jobs/{id}/cancel", post(cancel_ocr_job))
+        .route("/api/ocr/jobs/{id}/drafts", get(get_bill_drafts))
+        .route("/api/ocr/jobs/{id}/drafts/commit", post(commit_bill_drafts))
+        .route(
+            "/api/ocr/jobs/{id}/drafts/discard",
+            post(discard_bill_drafts),
+        )
+        // Webhook endpoints
+        .route("/api/webhooks", get(get_webhooks).post(create_webhook))
+        .route("/api/webhooks/{id}", delete(delete_webhook))
+        .route("/api/webhooks/{id}/deliveries", get(get_webhook_deliveries))
         .fallback_service(ServeDir::new("../frontend/out").append_index_html_on_directories(true))
         .layer(DefaultBodyLimit::max(50 * 1024 * 1024)) // 50MB total request limit
         .layer(
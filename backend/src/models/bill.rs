@@ -18,6 +18,8 @@ pub struct Bill {
     pub total_amount: Option<rust_decimal::Decimal>,
     pub vat_rate: Option<rust_decimal::Decimal>,
     pub vat_amount: Option<rust_decimal::Decimal>,
+    /// Image store keys of the original images the bill was extracted from, one per page
+    pub source_images: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
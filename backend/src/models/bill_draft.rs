@@ -0,0 +1,77 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::models::CreateBill;
+
+/// An extracted bill staged for review before it is saved to `bills`
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct BillDraft {
+    pub id: i32,
+    pub session_id: String,
+    pub file_index: i32,
+    pub created_at: DateTime<Utc>,
+    pub form_no: Option<String>,
+    pub serial_no: Option<String>,
+    pub invoice_no: Option<String>,
+    pub issued_date: Option<NaiveDate>,
+    pub seller_name: Option<String>,
+    pub seller_tax_code: Option<String>,
+    pub item_name: Option<String>,
+    pub unit: Option<String>,
+    pub quantity: Option<rust_decimal::Decimal>,
+    pub unit_price: Option<rust_decimal::Decimal>,
+    pub total_amount: Option<rust_decimal::Decimal>,
+    pub vat_rate: Option<rust_decimal::Decimal>,
+    pub vat_amount: Option<rust_decimal::Decimal>,
+    /// Image store keys of the original images, carried over to the bill
+    pub source_images: Vec<String>,
+}
+
+impl BillDraft {
+    /// The bill this draft would create
+    pub fn to_create_bill(&self) -> CreateBill {
+        CreateBill {
+            form_no: self.form_no.clone(),
+            serial_no: self.serial_no.clone(),
+            invoice_no: self.invoice_no.clone(),
+            issued_date: self.issued_date,
+            seller_name: self.seller_name.clone(),
+            seller_tax_code: self.seller_tax_code.clone(),
+            item_name: self.item_name.clone(),
+            unit: self.unit.clone(),
+            quantity: self.quantity,
+            unit_price: self.unit_price,
+            total_amount: self.total_amount,
+            vat_rate: self.vat_rate,
+            vat_amount: self.vat_amount,
+        }
+    }
+}
+
+/// A draft selected for commit, optionally replaced by the reviewer's edits
+#[derive(Debug, Clone, Deserialize)]
+pub struct DraftSelection {
+    pub id: i32,
+    /// Edited bill to save instead of the extracted values
+    pub bill: Option<CreateBill>,
+}
+
+/// Request body of `POST /api/ocr/jobs/{id}/drafts/commit`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommitDraftsRequest {
+    pub drafts: Vec<DraftSelection>,
+}
+
+/// Request body of `POST /api/ocr/jobs/{id}/drafts/discard`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DiscardDraftsRequest {
+    /// Drafts to discard; all drafts of the session when absent
+    pub ids: Option<Vec<i32>>,
+}
+
+/// Result of discarding drafts
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscardDraftsResponse {
+    pub discarded: u64,
+}
@@ -0,0 +1,18 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Where a bill came from, for bills ingested from an external source
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct BillProvenance {
+    pub bill_id: i32,
+    /// Kind of source; currently always `email`
+    pub source_type: String,
+    pub email_subject: Option<String>,
+    pub email_sender: Option<String>,
+    pub email_date: Option<DateTime<Utc>>,
+    pub email_message_id: Option<String>,
+    /// Name of the attachment the bill was extracted from
+    pub attachment_name: Option<String>,
+    pub recorded_at: DateTime<Utc>,
+}
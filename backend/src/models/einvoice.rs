@@ -0,0 +1,41 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// What an e-invoice import issue concerns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EInvoiceIssueKind {
+    /// A required element is missing or a value has the wrong format
+    Schema,
+    /// The signature block (`DSCKS`) is missing or incomplete
+    Signature,
+    /// Line items do not add up to the invoice totals
+    Totals,
+}
+
+/// A problem found in an e-invoice XML file that did not stop its import
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EInvoiceIssue {
+    pub kind: EInvoiceIssueKind,
+    /// Path of the element concerned, such as `TTChung/SHDon`
+    pub element: String,
+    pub message: String,
+}
+
+/// Result of one file uploaded to `POST /api/bills/import/xml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EInvoiceImportResult {
+    pub file_name: Option<String>,
+    pub invoice_no: Option<String>,
+    pub serial_no: Option<String>,
+    pub seller_tax_code: Option<String>,
+    pub buyer_name: Option<String>,
+    pub currency: Option<String>,
+    /// Invoice total before VAT as stated in the file
+    pub total_before_tax: Option<Decimal>,
+    pub total_tax: Option<Decimal>,
+    pub bill_ids: Vec<i32>,
+    pub issues: Vec<EInvoiceIssue>,
+    /// Why the file could not be imported; no bills are saved then
+    pub error_message: Option<String>,
+}
@@ -4,30 +4,59 @@
 //! for Vietnamese bill/invoice OCR processing.
 
 use serde::Serialize;
+use serde_json::{Value, json};
 
 /// Request payload for Gemini AI API
 ///
-/// Contains the image data and prompt for structured bill data extraction.
+/// Contains the prompt and the invoice content for structured bill data extraction.
 #[derive(Debug, Serialize)]
 pub struct GeminiRequest {
-    /// Base64 encoded image data
-    pub image_data: String,
-
     /// Structured output request prompt for Vietnamese bill extraction
     pub prompt: String,
+
+    /// Invoice content sent after the prompt
+    pub parts: Vec<GeminiPart>,
+
+    /// SHA-256 of the uploaded file each part was prepared from, by part
+    /// position, when known
+    ///
+    /// Not sent to the API; cassettes are keyed on it so a recording
+    /// survives changes to the image preprocessing.
+    #[serde(skip)]
+    pub source_hashes: Vec<Option<String>>,
+}
+
+/// A content part of a Gemini request, serialized in the API's `parts` format
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum GeminiPart {
+    /// Plain text, such as the text layer of a PDF invoice
+    Text(String),
+    /// Base64 encoded file content
+    InlineData { mime_type: String, data: String },
 }
 
 impl GeminiRequest {
     /// Create a new Gemini request for bill extraction
     ///
     /// # Arguments
-    /// * `image_data` - Base64 encoded image content
     /// * `prompt` - Structured prompt for bill data extraction
+    /// * `parts` - Invoice content
     ///
     /// # Returns
     /// A new GeminiRequest instance
-    pub fn new(image_data: String, prompt: String) -> Self {
-        Self { image_data, prompt }
+    pub fn new(prompt: String, parts: Vec<GeminiPart>) -> Self {
+        Self {
+            prompt,
+            parts,
+            source_hashes: Vec::new(),
+        }
+    }
+
+    /// Record the SHA-256 of the uploaded file behind each part
+    pub fn with_source_hashes(mut self, source_hashes: Vec<Option<String>>) -> Self {
+        self.source_hashes = source_hashes;
+        self
     }
 
     /// Create a default prompt for Vietnamese bill extraction
@@ -59,14 +88,192 @@ Extract text exactly as shown in the image. Use null for any field not clearly v
             .to_string()
     }
 
+    /// Create a prompt for extracting bill data from the text of an invoice
+    pub fn default_text_extraction_prompt() -> String {
+        r#"Extract structured data from the following text, taken from the text layer of a Vietnamese invoice/bill PDF.
+Use the text exactly as given. Use null for any field not present in the text."#
+            .to_string()
+    }
+
     /// Create a GeminiRequest for bill extraction with default prompt
     ///
     /// # Arguments
-    /// * `image_data` - Base64 encoded image content
+    /// * `image_data` - Base64 encoded JPEG image content
     ///
     /// # Returns
     /// A GeminiRequest configured for Vietnamese bill extraction
     pub fn for_bill_extraction(image_data: String) -> Self {
-        Self::new(image_data, Self::default_bill_extraction_prompt())
+        Self::new(
+            Self::default_bill_extraction_prompt(),
+            vec![GeminiPart::InlineData {
+                mime_type: "image/jpeg".to_string(),
+                data: image_data,
+            }],
+        )
+    }
+
+    /// Create a prompt for extracting bill data from the pages of one invoice
+    pub fn default_document_extraction_prompt() -> String {
+        format!(
+            "The following images are consecutive pages of a single Vietnamese invoice/bill, in order. \
+Read them together as one document: the header (form, series, number, date, seller) usually appears on \
+the first page only and applies to every line item on the following pages. Do not repeat line items \
+that are carried over between pages.\n\n{}",
+            Self::default_bill_extraction_prompt()
+        )
+    }
+
+    /// Create a GeminiRequest for bill extraction from the pages of one invoice
+    ///
+    /// # Arguments
+    /// * `pages` - Base64 encoded JPEG content of each page, in order
+    ///
+    /// # Returns
+    /// A GeminiRequest with one `inlineData` part per page
+    pub fn for_document_extraction(pages: Vec<String>) -> Self {
+        Self::new(
+            Self::default_document_extraction_prompt(),
+            pages
+                .into_iter()
+                .map(|data| GeminiPart::InlineData {
+                    mime_type: "image/jpeg".to_string(),
+                    data,
+                })
+                .collect(),
+        )
+    }
+
+    /// Create a GeminiRequest for bill extraction from the text of an invoice
+    ///
+    /// # Arguments
+    /// * `text` - Invoice text, such as a PDF page's text layer
+    ///
+    /// # Returns
+    /// A GeminiRequest configured for Vietnamese bill extraction
+    pub fn for_text_extraction(text: String) -> Self {
+        Self::new(
+            Self::default_text_extraction_prompt(),
+            vec![GeminiPart::Text(text)],
+        )
+    }
+
+    /// JSON schema of the bill candidates an extraction returns
+    ///
+    /// Gemini gets it as `responseSchema`; other providers send it as their
+    /// structured-output schema, so every provider returns the same fields.
+    pub fn bill_response_schema() -> Value {
+        json!({
+            "type": "array",
+            "description": "Danh sách các mục hóa đơn được trích xuất",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "form_no": {
+                        "type": "string",
+                        "description": "Mẫu số hóa đơn (ví dụ: 01-GTKT, 02-GTTT)"
+                    },
+                    "serial_no": {
+                        "type": "string",
+                        "description": "Ký hiệu hóa đơn (ví dụ: AA/24E, BB/25F)"
+                    },
+                    "invoice_no": {
+                        "type": "string",
+                        "description": "Số hóa đơn"
+                    },
+                    "issued_date": {
+                        "type": "string",
+                        "format": "date",
+                        "description": "Ngày lập hóa đơn (YYYY-MM-DD)"
+                    },
+                    "seller_name": {
+                        "type": "string",
+                        "description": "Tên người bán/công ty"
+                    },
+                    "seller_tax_code": {
+                        "type": "string",
+                        "description": "Mã số thuế của người bán"
+                    },
+                    "item_name": {
+                        "type": "string",
+                        "description": "Tên hàng hóa/dịch vụ"
+                    },
+                    "unit": {
+                        "type": "string",
+                        "description": "Đơn vị tính (ví dụ: cái, kg, giờ, m2)"
+                    },
+                    "quantity": {
+                        "type": "number",
+                        "description": "Số lượng hàng hóa/dịch vụ"
+                    },
+                    "unit_price": {
+                        "type": "number",
+                        "description": "Đơn giá (VND)"
+                    },
+                    "total_amount": {
+                        "type": "number",
+                        "description": "Thành tiền trước thuế (VND)"
+                    },
+                    "vat_rate": {
+                        "type": "number",
+                        "description": "Thuế suất VAT (%) - ví dụ: 0, 5, 8, 10"
+                    },
+                    "vat_amount": {
+                        "type": "number",
+                        "description": "Tiền thuế VAT (VND)"
+                    }
+                },
+                "required": [
+                    "form_no",
+                    "serial_no",
+                    "invoice_no",
+                    "issued_date",
+                    "seller_name",
+                    "seller_tax_code",
+                    "item_name",
+                    "unit",
+                    "quantity",
+                    "unit_price",
+                    "total_amount",
+                    "vat_rate",
+                    "vat_amount"
+                ]
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parts_serialize_in_api_format() {
+        let image = serde_json::to_value(GeminiPart::InlineData {
+            mime_type: "image/jpeg".to_string(),
+            data: "aGVsbG8=".to_string(),
+        })
+        .unwrap();
+        assert_eq!(
+            image,
+            serde_json::json!({"inlineData": {"mimeType": "image/jpeg", "data": "aGVsbG8="}})
+        );
+
+        let text = serde_json::to_value(GeminiPart::Text("Số hóa đơn: 42".to_string())).unwrap();
+        assert_eq!(text, serde_json::json!({"text": "Số hóa đơn: 42"}));
+    }
+
+    #[test]
+    fn test_document_request_has_a_part_per_page() {
+        let request = GeminiRequest::for_document_extraction(vec![
+            "cGFnZTE=".to_string(),
+            "cGFnZTI=".to_string(),
+        ]);
+
+        assert_eq!(request.parts.len(), 2);
+        assert!(matches!(
+            &request.parts[1],
+            GeminiPart::InlineData { data, .. } if data == "cGFnZTI="
+        ));
+        assert!(request.prompt.contains("consecutive pages"));
     }
 }
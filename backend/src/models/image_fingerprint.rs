@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// Hashes of an uploaded image, used to recognise re-uploads
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageFingerprint {
+    /// Hex encoded SHA-256 of the file bytes
+    pub sha256: String,
+    /// 64-bit difference hash of the picture
+    pub perceptual_hash: u64,
+}
+
+/// Bills and drafts already extracted from the same or a near-identical image
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DuplicateMatch {
+    /// Whether one of the images had exactly the same bytes
+    pub exact: bool,
+    pub bill_ids: Vec<i32>,
+    /// Staged drafts extracted from the earlier images
+    pub draft_ids: Vec<i32>,
+}
@@ -22,3 +22,27 @@ pub enum ValidationStatus {
     Valid,
     Invalid(String),
 }
+
+/// A step of the image preprocessing pipeline run before extraction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreprocessingStep {
+    ExifOrientation,
+    CropToDocument,
+    Resize,
+    Deskew,
+    NormalizeContrast,
+    Grayscale,
+}
+
+/// What one preprocessing step did to an image and how long it took
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreprocessingStepReport {
+    pub step: PreprocessingStep,
+    /// Whether the step changed the image; enabled steps may find nothing to do
+    pub applied: bool,
+    pub duration_ms: u64,
+    /// What was done, such as the rotation angle or crop box
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
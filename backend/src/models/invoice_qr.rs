@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+/// A field whose value read by Gemini differs from the invoice's QR code
+///
+/// The QR value is the one kept for the bill.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QrDiscrepancy {
+    /// Name of the `GeminiResponse` field, such as `invoice_no`
+    pub field: String,
+    pub ocr_value: String,
+    pub qr_value: String,
+}
@@ -1,22 +1,40 @@
 pub mod bill;
+pub mod bill_draft;
+pub mod bill_provenance;
+pub mod einvoice;
 pub mod export;
 pub mod gemini_request;
 pub mod gemini_response;
+pub mod image_fingerprint;
 pub mod image_info;
+pub mod invoice_qr;
 pub mod ocr_error;
+pub mod ocr_job;
 pub mod sse_events;
 pub mod validation_result;
+pub mod webhook;
 
 pub use bill::{Bill, CreateBill};
+pub use bill_draft::{
+    BillDraft, CommitDraftsRequest, DiscardDraftsRequest, DiscardDraftsResponse, DraftSelection,
+};
+pub use bill_provenance::BillProvenance;
+pub use einvoice::{EInvoiceImportResult, EInvoiceIssue, EInvoiceIssueKind};
 pub use export::{ExportError, ExportFormat, ExportParams, ExportResponse};
-pub use gemini_request::GeminiRequest;
+pub use gemini_request::{GeminiPart, GeminiRequest};
 pub use gemini_response::GeminiResponse;
-pub use image_info::{ImageFileInfo, ValidationStatus};
+pub use image_fingerprint::{DuplicateMatch, ImageFingerprint};
+pub use image_info::{ImageFileInfo, PreprocessingStep, PreprocessingStepReport, ValidationStatus};
+pub use invoice_qr::QrDiscrepancy;
 pub use ocr_error::{
     ErrorType as OcrErrorType, ProcessingError as OcrProcessingError, ProcessingErrorResponse,
 };
+pub use ocr_job::{OcrJob, OcrJobCreated};
 pub use sse_events::{
-    ProcessingErrorType, ProcessingEvent, ProcessingSession, SSEEventEnvelope, SessionStatus,
-    ValidationErrorCode,
+    PdfPageContent, ProcessingErrorType, ProcessingEvent, ProcessingSession, SSEEventEnvelope,
+    SessionStatus, ValidationErrorCode,
 };
 pub use validation_result::{ValidationData, ValidationResult};
+pub use webhook::{
+    CreateWebhook, DeliveryStatus, Webhook, WebhookDelivery, WebhookDeliveryAttempt, WebhookPayload,
+};
@@ -0,0 +1,493 @@
+//! OCR job status models
+//!
+//! An OCR job is a processing session viewed through the job API. Its status
+//! and per-file results are derived from the session's event log, so the job
+//! view never disagrees with what SSE subscribers have seen.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    EInvoiceIssue, GeminiResponse, PdfPageContent, ProcessingEvent, ProcessingSession,
+    QrDiscrepancy, SSEEventEnvelope, SessionStatus,
+};
+
+/// Processing state of a single file within a job
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FileProcessingStatus {
+    Received,
+    Validating,
+    Extracting,
+    Completed,
+    Failed,
+}
+
+/// Result of a single uploaded file within a job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OcrJobFileResult {
+    pub file_index: usize,
+    pub file_name: Option<String>,
+    pub size_bytes: usize,
+    pub status: FileProcessingStatus,
+    pub extracted_data: Vec<GeminiResponse>,
+    pub bill_ids: Vec<i32>,
+    /// Drafts staged for review in draft mode
+    pub draft_ids: Vec<i32>,
+    pub error_message: Option<String>,
+    /// Per-page results when the file is a PDF
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pages: Vec<OcrJobPageResult>,
+    /// Problems found in an e-invoice XML file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub invoice_issues: Vec<EInvoiceIssue>,
+    /// File indexes of all pages when the file is a page of a multi-page invoice
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub document_pages: Vec<usize>,
+    /// Fields where the invoice QR code overrode Gemini's reading
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub qr_discrepancies: Vec<QrDiscrepancy>,
+    /// Bills already extracted from the same image
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub duplicate_of: Vec<i32>,
+    /// Drafts already staged from the same image
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub duplicate_of_drafts: Vec<i32>,
+}
+
+/// Result of a single page of a PDF within a job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OcrJobPageResult {
+    pub page_number: usize,
+    pub content: PdfPageContent,
+    pub bill_ids: Vec<i32>,
+    pub error_message: Option<String>,
+}
+
+impl OcrJobFileResult {
+    pub fn new(file_index: usize) -> Self {
+        Self {
+            file_index,
+            file_name: None,
+            size_bytes: 0,
+            status: FileProcessingStatus::Received,
+            extracted_data: Vec::new(),
+            bill_ids: Vec::new(),
+            draft_ids: Vec::new(),
+            error_message: None,
+            pages: Vec::new(),
+            invoice_issues: Vec::new(),
+            document_pages: Vec::new(),
+            qr_discrepancies: Vec::new(),
+            duplicate_of: Vec::new(),
+            duplicate_of_drafts: Vec::new(),
+        }
+    }
+
+    fn page_mut(&mut self, page_number: usize) -> Option<&mut OcrJobPageResult> {
+        self.pages
+            .iter_mut()
+            .find(|page| page.page_number == page_number)
+    }
+}
+
+/// Status of an OCR job returned by `GET /api/ocr/jobs/{id}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OcrJob {
+    pub job_id: String,
+    pub status: SessionStatus,
+    pub start_time: DateTime<Utc>,
+    pub total_files: usize,
+    pub processed_files: usize,
+    /// Id of the most recent event; pass it as `Last-Event-ID` to resume
+    pub last_event_id: Option<String>,
+    pub error_message: Option<String>,
+    pub files: Vec<OcrJobFileResult>,
+}
+
+/// Response of `POST /api/ocr/jobs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OcrJobCreated {
+    pub job_id: String,
+    pub total_files: usize,
+    pub status_url: String,
+    pub events_url: String,
+}
+
+impl OcrJobCreated {
+    pub fn new(job_id: String, total_files: usize) -> Self {
+        Self {
+            status_url: format!("/api/ocr/jobs/{job_id}"),
+            events_url: format!("/api/ocr/jobs/{job_id}/events"),
+            job_id,
+            total_files,
+        }
+    }
+}
+
+impl OcrJob {
+    /// Build the job view of a session by replaying its event log
+    pub fn from_events(session: &ProcessingSession, events: &[SSEEventEnvelope]) -> Self {
+        let mut job = Self {
+            job_id: session.session_id.clone(),
+            status: session.status.clone(),
+            start_time: session.start_time,
+            total_files: session.total_files,
+            processed_files: session.processed_files,
+            last_event_id: events.last().and_then(|envelope| envelope.event_id.clone()),
+            error_message: None,
+            files: Vec::new(),
+        };
+
+        for envelope in events {
+            job.apply(&envelope.data);
+        }
+
+        job
+    }
+
+    /// Result of a file, if any event mentioned it
+    pub fn file(&self, file_index: usize) -> Option<&OcrJobFileResult> {
+        self.files.iter().find(|file| file.file_index == file_index)
+    }
+
+    fn file_mut(&mut self, file_index: usize) -> &mut OcrJobFileResult {
+        if let Some(position) = self.files.iter().position(|f| f.file_index == file_index) {
+            return &mut self.files[position];
+        }
+        self.files.push(OcrJobFileResult::new(file_index));
+        self.files.last_mut().unwrap()
+    }
+
+    /// Update the view with the next event of the session
+    pub fn apply(&mut self, event: &ProcessingEvent) {
+        match event {
+            ProcessingEvent::ImageReceived {
+                file_index,
+                file_name,
+                size_bytes,
+                ..
+            } => {
+                let file = self.file_mut(*file_index);
+                file.file_name = file_name.clone();
+                file.size_bytes = *size_bytes;
+            }
+            ProcessingEvent::ImageValidationStart { file_index, .. } => {
+                self.file_mut(*file_index).status = FileProcessingStatus::Validating;
+            }
+            ProcessingEvent::ImageValidationError {
+                file_index,
+                error_message,
+                ..
+            }
+            | ProcessingEvent::GeminiProcessingError {
+                file_index,
+                page_number: None,
+                error_message,
+                ..
+            } => {
+                let file = self.file_mut(*file_index);
+                file.status = FileProcessingStatus::Failed;
+                file.error_message = Some(error_message.clone());
+            }
+            // A failed page does not fail the other pages of its PDF
+            ProcessingEvent::GeminiProcessingError {
+                file_index,
+                page_number: Some(page_number),
+                error_message,
+                ..
+            } => {
+                if let Some(page) = self.file_mut(*file_index).page_mut(*page_number) {
+                    page.error_message = Some(error_message.clone());
+                }
+            }
+            ProcessingEvent::PdfPageExtracted {
+                file_index,
+                page_number,
+                content,
+                ..
+            } => {
+                self.file_mut(*file_index).pages.push(OcrJobPageResult {
+                    page_number: *page_number,
+                    content: *content,
+                    bill_ids: Vec::new(),
+                    error_message: None,
+                });
+            }
+            ProcessingEvent::EInvoiceImported {
+                file_index,
+                issues,
+                drafts,
+                error_message,
+                ..
+            } => {
+                let file = self.file_mut(*file_index);
+                file.invoice_issues = issues.clone();
+                file.draft_ids = drafts.iter().map(|draft| draft.id).collect();
+                if let Some(error_message) = error_message {
+                    file.status = FileProcessingStatus::Failed;
+                    file.error_message = Some(error_message.clone());
+                }
+            }
+            ProcessingEvent::DocumentPagesGrouped {
+                page_file_indexes, ..
+            } => {
+                for file_index in page_file_indexes {
+                    self.file_mut(*file_index).document_pages = page_file_indexes.clone();
+                }
+            }
+            ProcessingEvent::DuplicateDetected {
+                file_index,
+                bill_ids,
+                draft_ids,
+                ..
+            } => {
+                let file = self.file_mut(*file_index);
+                file.duplicate_of = bill_ids.clone();
+                file.duplicate_of_drafts = draft_ids.clone();
+            }
+            ProcessingEvent::GeminiProcessingStart { file_index, .. } => {
+                self.file_mut(*file_index).status = FileProcessingStatus::Extracting;
+            }
+            ProcessingEvent::GeminiProcessingSuccess {
+                file_index,
+                page_number,
+                extracted_data,
+                drafts,
+                ..
+            } => {
+                let file = self.file_mut(*file_index);
+                let draft_ids = drafts.iter().map(|draft| draft.id);
+                if page_number.is_some() {
+                    file.extracted_data.extend(extracted_data.iter().cloned());
+                    file.draft_ids.extend(draft_ids);
+                } else {
+                    file.extracted_data = extracted_data.clone();
+                    file.draft_ids = draft_ids.collect();
+                }
+            }
+            ProcessingEvent::QrDiscrepancyDetected {
+                file_index,
+                discrepancies,
+                ..
+            } => {
+                self.file_mut(*file_index)
+                    .qr_discrepancies
+                    .extend(discrepancies.iter().cloned());
+            }
+            ProcessingEvent::BillDataSaved {
+                file_index,
+                page_number,
+                bill_id,
+                ..
+            } => {
+                let file = self.file_mut(*file_index);
+                file.status = FileProcessingStatus::Completed;
+                file.bill_ids.push(*bill_id);
+                if let Some(page) = page_number.and_then(|number| file.page_mut(number)) {
+                    page.bill_ids.push(*bill_id);
+                }
+            }
+            ProcessingEvent::ProcessingComplete { .. } => {
+                for file in &mut self.files {
+                    if file.status != FileProcessingStatus::Failed {
+                        file.status = FileProcessingStatus::Completed;
+                    }
+                }
+            }
+            ProcessingEvent::ProcessingError { error_message, .. } => {
+                self.error_message = Some(error_message.clone());
+            }
+            ProcessingEvent::ProcessingCancelled { reason, .. } => {
+                self.error_message = Some(format!("Processing cancelled: {reason:?}"));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_from_events() {
+        let mut session = ProcessingSession::new("job-1".to_string());
+        session.total_files = 2;
+        let now = Utc::now();
+
+        let events: Vec<SSEEventEnvelope> = vec![
+            ProcessingEvent::ImageReceived {
+                file_index: 0,
+                file_name: Some("a.jpg".to_string()),
+                size_bytes: 10,
+                timestamp: now,
+            },
+            ProcessingEvent::ImageReceived {
+                file_index: 1,
+                file_name: Some("b.jpg".to_string()),
+                size_bytes: 20,
+                timestamp: now,
+            },
+            ProcessingEvent::GeminiProcessingStart {
+                file_index: 0,
+                file_name: Some("a.jpg".to_string()),
+                page_number: None,
+                timestamp: now,
+            },
+            ProcessingEvent::BillDataSaved {
+                file_index: 0,
+                page_number: None,
+                bill_id: 42,
+                timestamp: now,
+            },
+            ProcessingEvent::ImageValidationError {
+                file_index: 1,
+                file_name: Some("b.jpg".to_string()),
+                error_message: "Invalid image format: Not an image".to_string(),
+                error_code: crate::models::ValidationErrorCode::CorruptedFile,
+                timestamp: now,
+            },
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, event)| SSEEventEnvelope::new(i as u64 + 1, event, None))
+        .collect();
+
+        let job = OcrJob::from_events(&session, &events);
+
+        assert_eq!(job.job_id, "job-1");
+        assert_eq!(job.last_event_id.as_deref(), Some("5"));
+        assert_eq!(job.files.len(), 2);
+        assert_eq!(job.files[0].status, FileProcessingStatus::Completed);
+        assert_eq!(job.files[0].bill_ids, vec![42]);
+        assert_eq!(job.files[1].status, FileProcessingStatus::Failed);
+        assert_eq!(job.files[1].size_bytes, 20);
+        assert!(job.files[1].error_message.is_some());
+    }
+
+    #[test]
+    fn test_job_from_draft_events() {
+        let session = ProcessingSession::new("job-2".to_string());
+        let now = Utc::now();
+        let draft = crate::models::BillDraft {
+            id: 7,
+            session_id: "job-2".to_string(),
+            file_index: 0,
+            created_at: now,
+            form_no: Some("01GTKT0/001".to_string()),
+            serial_no: None,
+            invoice_no: Some("0000123".to_string()),
+            issued_date: None,
+            seller_name: None,
+            seller_tax_code: None,
+            item_name: None,
+            unit: None,
+            quantity: None,
+            unit_price: None,
+            total_amount: None,
+            vat_rate: None,
+            vat_amount: None,
+            source_images: Vec::new(),
+        };
+
+        let events = vec![SSEEventEnvelope::new(
+            1,
+            ProcessingEvent::GeminiProcessingSuccess {
+                file_index: 0,
+                page_number: None,
+                extracted_data: Vec::new(),
+                drafts: vec![draft.clone()],
+                timestamp: now,
+            },
+            None,
+        )];
+
+        let job = OcrJob::from_events(&session, &events);
+
+        assert_eq!(job.files[0].draft_ids, vec![7]);
+        assert!(job.files[0].bill_ids.is_empty());
+        assert_eq!(
+            draft.to_create_bill().invoice_no.as_deref(),
+            Some("0000123")
+        );
+    }
+
+    #[test]
+    fn test_job_from_document_events() {
+        let session = ProcessingSession::new("job-4".to_string());
+        let now = Utc::now();
+
+        let events: Vec<SSEEventEnvelope> = vec![
+            ProcessingEvent::DocumentPagesGrouped {
+                file_index: 1,
+                document: Some("inv-123".to_string()),
+                page_file_indexes: vec![1, 3],
+                timestamp: now,
+            },
+            ProcessingEvent::BillDataSaved {
+                file_index: 1,
+                page_number: None,
+                bill_id: 21,
+                timestamp: now,
+            },
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, event)| SSEEventEnvelope::new(i as u64 + 1, event, None))
+        .collect();
+
+        let job = OcrJob::from_events(&session, &events);
+
+        assert_eq!(job.files.len(), 2);
+        assert_eq!(job.files[0].file_index, 1);
+        assert_eq!(job.files[0].document_pages, vec![1, 3]);
+        assert_eq!(job.files[0].bill_ids, vec![21]);
+        assert_eq!(job.files[1].document_pages, vec![1, 3]);
+        assert!(job.files[1].bill_ids.is_empty());
+    }
+
+    #[test]
+    fn test_job_from_pdf_page_events() {
+        let session = ProcessingSession::new("job-3".to_string());
+        let now = Utc::now();
+        let page = |page_number, content| ProcessingEvent::PdfPageExtracted {
+            file_index: 0,
+            page_number,
+            total_pages: 2,
+            content,
+            timestamp: now,
+        };
+
+        let events: Vec<SSEEventEnvelope> = vec![
+            page(1, PdfPageContent::Text),
+            ProcessingEvent::BillDataSaved {
+                file_index: 0,
+                page_number: Some(1),
+                bill_id: 11,
+                timestamp: now,
+            },
+            page(2, PdfPageContent::Image),
+            ProcessingEvent::GeminiProcessingError {
+                file_index: 0,
+                page_number: Some(2),
+                error_message: "Gemini API request timeout after 30 seconds".to_string(),
+                timestamp: now,
+            },
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, event)| SSEEventEnvelope::new(i as u64 + 1, event, None))
+        .collect();
+
+        let job = OcrJob::from_events(&session, &events);
+        let file = &job.files[0];
+
+        assert_eq!(file.status, FileProcessingStatus::Completed);
+        assert_eq!(file.bill_ids, vec![11]);
+        assert!(file.error_message.is_none());
+        assert_eq!(file.pages.len(), 2);
+        assert_eq!(file.pages[0].bill_ids, vec![11]);
+        assert_eq!(file.pages[1].content, PdfPageContent::Image);
+        assert!(file.pages[1].error_message.is_some());
+    }
+}
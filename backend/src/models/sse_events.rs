@@ -1,4 +1,6 @@
-use crate::models::{GeminiResponse, ImageFileInfo};
+use crate::models::{
+    BillDraft, EInvoiceIssue, GeminiResponse, ImageFileInfo, PreprocessingStepReport, QrDiscrepancy,
+};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -6,7 +8,9 @@ use serde::{Deserialize, Serialize};
 #[serde(tag = "type", content = "data")]
 pub enum ProcessingEvent {
     UploadStarted {
-        total_files: usize,
+        /// `None` while a streamed upload is still arriving; the final count
+        /// is reported by `AllImagesValidated` and `ProcessingComplete`
+        total_files: Option<usize>,
         session_id: String,
         timestamp: DateTime<Utc>,
     },
@@ -52,28 +56,174 @@ pub enum ProcessingEvent {
         error_type: ProcessingErrorType,
         timestamp: DateTime<Utc>,
     },
+    ProcessingCancelled {
+        session_id: String,
+        reason: ProcessingErrorType,
+        total_files: usize,
+        processed_files: usize,
+        saved_bill_ids: Vec<i32>,
+        timestamp: DateTime<Utc>,
+    },
+    /// A page of a PDF was read; its extraction events carry its `page_number`
+    PdfPageExtracted {
+        file_index: usize,
+        /// 1-based page number
+        page_number: usize,
+        total_pages: usize,
+        content: PdfPageContent,
+        timestamp: DateTime<Utc>,
+    },
+    /// An e-invoice XML file was read; its lines are saved without Gemini
+    EInvoiceImported {
+        file_index: usize,
+        file_name: Option<String>,
+        serial_no: Option<String>,
+        invoice_no: Option<String>,
+        /// Number of bills the invoice's lines map to
+        line_count: usize,
+        /// Schema, signature and totals problems that did not stop the import
+        issues: Vec<EInvoiceIssue>,
+        /// Bills staged for review when the upload runs in draft mode
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        drafts: Vec<BillDraft>,
+        /// Set when the bills could not be staged
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error_message: Option<String>,
+        timestamp: DateTime<Utc>,
+    },
+    /// Several images were grouped into one multi-page invoice; its
+    /// extraction events carry the `file_index` of the first page
+    DocumentPagesGrouped {
+        file_index: usize,
+        /// Label sent with the images, absent for automatic grouping
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        document: Option<String>,
+        /// File indexes of the pages, in page order
+        page_file_indexes: Vec<usize>,
+        timestamp: DateTime<Utc>,
+    },
+    /// An image went through the preprocessing pipeline before extraction
+    ImagePreprocessed {
+        file_index: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        page_number: Option<usize>,
+        original_width: u32,
+        original_height: u32,
+        width: u32,
+        height: u32,
+        /// Every enabled step, in the order it ran, with its duration
+        steps: Vec<PreprocessingStepReport>,
+        timestamp: DateTime<Utc>,
+    },
+    /// The image was already uploaded; it is skipped when it has the same
+    /// bytes, or looks the same and names the same invoice, unless the upload
+    /// forces processing
+    DuplicateDetected {
+        file_index: usize,
+        /// Whether an earlier image had exactly the same bytes, rather than
+        /// only looking the same
+        exact: bool,
+        /// Bills extracted from the earlier images
+        bill_ids: Vec<i32>,
+        /// Drafts staged from the earlier images and not committed yet
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        draft_ids: Vec<i32>,
+        /// Whether the image is extracted anyway
+        forced: bool,
+        timestamp: DateTime<Utc>,
+    },
     GeminiProcessingStart {
         file_index: usize,
         file_name: Option<String>,
+        /// Page of a PDF, absent for images
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        page_number: Option<usize>,
         timestamp: DateTime<Utc>,
     },
     GeminiProcessingSuccess {
         file_index: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        page_number: Option<usize>,
         extracted_data: Vec<GeminiResponse>,
+        /// Bills staged for review when the upload runs in draft mode
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        drafts: Vec<BillDraft>,
+        timestamp: DateTime<Utc>,
+    },
+    /// The invoice QR code disagrees with what Gemini read; the QR values
+    /// replace Gemini's in the extracted data and saved bills
+    QrDiscrepancyDetected {
+        file_index: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        page_number: Option<usize>,
+        discrepancies: Vec<QrDiscrepancy>,
         timestamp: DateTime<Utc>,
     },
     GeminiProcessingError {
         file_index: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        page_number: Option<usize>,
         error_message: String,
         timestamp: DateTime<Utc>,
     },
     BillDataSaved {
         file_index: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        page_number: Option<usize>,
         bill_id: i32,
         timestamp: DateTime<Utc>,
     },
 }
 
+impl ProcessingEvent {
+    /// SSE event name used for this event
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            ProcessingEvent::UploadStarted { .. } => "upload_started",
+            ProcessingEvent::ImageReceived { .. } => "image_received",
+            ProcessingEvent::ImageValidationStart { .. } => "image_validation_start",
+            ProcessingEvent::ImageValidationSuccess { .. } => "image_validation_success",
+            ProcessingEvent::ImageValidationError { .. } => "image_validation_error",
+            ProcessingEvent::AllImagesValidated { .. } => "all_images_validated",
+            ProcessingEvent::ProcessingComplete { .. } => "processing_complete",
+            ProcessingEvent::ProcessingError { .. } => "processing_error",
+            ProcessingEvent::ProcessingCancelled { .. } => "processing_cancelled",
+            ProcessingEvent::PdfPageExtracted { .. } => "pdf_page_extracted",
+            ProcessingEvent::EInvoiceImported { .. } => "einvoice_imported",
+            ProcessingEvent::DocumentPagesGrouped { .. } => "document_pages_grouped",
+            ProcessingEvent::ImagePreprocessed { .. } => "image_preprocessed",
+            ProcessingEvent::DuplicateDetected { .. } => "duplicate_detected",
+            ProcessingEvent::GeminiProcessingStart { .. } => "gemini_processing_start",
+            ProcessingEvent::GeminiProcessingSuccess { .. } => "gemini_processing_success",
+            ProcessingEvent::QrDiscrepancyDetected { .. } => "qr_discrepancy_detected",
+            ProcessingEvent::GeminiProcessingError { .. } => "gemini_processing_error",
+            ProcessingEvent::BillDataSaved { .. } => "bill_data_saved",
+        }
+    }
+
+    /// Whether this event ends a processing session
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            ProcessingEvent::ProcessingComplete { .. }
+                | ProcessingEvent::ProcessingError { .. }
+                | ProcessingEvent::ProcessingCancelled { .. }
+        )
+    }
+}
+
+/// What a PDF page contributed to extraction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PdfPageContent {
+    /// The page's text layer was sent for extraction
+    Text,
+    /// The page's embedded image was sent for extraction
+    Image,
+    /// Nothing usable was found; the page is skipped
+    Empty,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ValidationErrorCode {
     FileSizeExceeded { actual: usize, limit: usize },
@@ -81,6 +231,7 @@ pub enum ValidationErrorCode {
     CorruptedFile,
     EmptyFile,
     CountLimitExceeded { count: usize, limit: usize },
+    TotalSizeExceeded { actual: usize, limit: usize },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -89,6 +240,8 @@ pub enum ProcessingErrorType {
     SystemTimeout,
     InternalServerError,
     ClientDisconnected,
+    CancelledByUser,
+    UploadLimitExceeded,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -99,6 +252,23 @@ pub struct SSEEventEnvelope {
     pub retry: Option<u32>,
 }
 
+impl SSEEventEnvelope {
+    /// Wrap an event with its sequence number within the session
+    pub fn new(sequence: u64, data: ProcessingEvent, retry: Option<u32>) -> Self {
+        Self {
+            event_type: data.event_type().to_string(),
+            event_id: Some(sequence.to_string()),
+            data,
+            retry,
+        }
+    }
+
+    /// Sequence number of this event within its session, if numbered
+    pub fn sequence(&self) -> Option<u64> {
+        self.event_id.as_deref()?.parse().ok()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ProcessingSession {
     pub session_id: String,
@@ -109,6 +279,20 @@ pub struct ProcessingSession {
     pub client_connected: bool,
 }
 
+impl ProcessingSession {
+    /// Create a new session in the `Processing` state
+    pub fn new(session_id: String) -> Self {
+        Self {
+            session_id,
+            start_time: Utc::now(),
+            total_files: 0,
+            processed_files: 0,
+            status: SessionStatus::Processing,
+            client_connected: false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SessionStatus {
     Processing,
@@ -0,0 +1,189 @@
+//! Webhook models
+//!
+//! Webhooks are notified when an OCR session finishes. Each notification is
+//! recorded as a delivery so that failed attempts can be retried, and each
+//! attempt is recorded so that they can be inspected.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::models::{ProcessingEvent, ProcessingSession, SessionStatus};
+
+/// A registered webhook endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    /// Key of the payload signature; only returned when the webhook is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Request body of `POST /api/webhooks`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateWebhook {
+    pub url: String,
+    /// Signing secret; a random one is generated when absent
+    pub secret: Option<String>,
+}
+
+/// Delivery status of a webhook notification, stored in `webhook_deliveries.status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+/// One notification of a webhook and the attempts to post it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub webhook_id: i32,
+    pub session_id: String,
+    pub event_type: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    /// Outcome of every finished attempt, oldest first
+    pub attempt_log: Vec<WebhookDeliveryAttempt>,
+}
+
+/// Outcome of one attempt to post a delivery, stored in `webhook_delivery_attempts`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDeliveryAttempt {
+    pub attempt: i32,
+    /// `delivered` or `failed`
+    pub status: String,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub attempted_at: DateTime<Utc>,
+}
+
+/// JSON body posted to webhooks when a session finishes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    /// `processing_complete`, `processing_error` or `processing_cancelled`
+    pub event_type: String,
+    pub session_id: String,
+    pub status: SessionStatus,
+    pub total_files: usize,
+    /// Only known when processing completed
+    pub successful_files: Option<usize>,
+    /// Bills saved before the session finished, including by an attempt
+    /// interrupted by a restart
+    pub saved_bill_ids: Vec<i32>,
+    pub error_message: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl WebhookPayload {
+    /// Build the payload for a session's terminal event
+    ///
+    /// Returns `None` for events that do not end a session.
+    pub fn from_terminal_event(
+        session: &ProcessingSession,
+        event: &ProcessingEvent,
+        saved_bill_ids: Vec<i32>,
+    ) -> Option<Self> {
+        let (successful_files, error_message, timestamp) = match event {
+            ProcessingEvent::ProcessingComplete {
+                successful_files,
+                timestamp,
+                ..
+            } => (Some(*successful_files), None, *timestamp),
+            ProcessingEvent::ProcessingError {
+                error_message,
+                timestamp,
+                ..
+            } => (None, Some(error_message.clone()), *timestamp),
+            ProcessingEvent::ProcessingCancelled { timestamp, .. } => (None, None, *timestamp),
+            _ => return None,
+        };
+
+        Some(Self {
+            event_type: event.event_type().to_string(),
+            session_id: session.session_id.clone(),
+            status: session.status.clone(),
+            total_files: session.total_files,
+            successful_files,
+            saved_bill_ids,
+            error_message,
+            timestamp,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ProcessingErrorType;
+
+    #[test]
+    fn test_payload_from_terminal_events() {
+        let mut session = ProcessingSession::new("session-1".to_string());
+        session.total_files = 2;
+        session.status = SessionStatus::Completed;
+
+        let complete = ProcessingEvent::ProcessingComplete {
+            session_id: "session-1".to_string(),
+            total_files: 2,
+            successful_files: 1,
+            duration_ms: 10,
+            timestamp: Utc::now(),
+        };
+        let payload = WebhookPayload::from_terminal_event(&session, &complete, vec![3, 4]).unwrap();
+        assert_eq!(payload.event_type, "processing_complete");
+        assert_eq!(payload.successful_files, Some(1));
+        assert_eq!(payload.saved_bill_ids, vec![3, 4]);
+
+        session.status = SessionStatus::Failed;
+        let error = ProcessingEvent::ProcessingError {
+            session_id: "session-1".to_string(),
+            error_message: "boom".to_string(),
+            error_type: ProcessingErrorType::InternalServerError,
+            timestamp: Utc::now(),
+        };
+        let payload = WebhookPayload::from_terminal_event(&session, &error, Vec::new()).unwrap();
+        assert_eq!(payload.event_type, "processing_error");
+        assert_eq!(payload.error_message.as_deref(), Some("boom"));
+
+        let cancelled = ProcessingEvent::ProcessingCancelled {
+            session_id: "session-1".to_string(),
+            reason: ProcessingErrorType::CancelledByUser,
+            total_files: 2,
+            processed_files: 1,
+            saved_bill_ids: Vec::new(),
+            timestamp: Utc::now(),
+        };
+        session.status = SessionStatus::Cancelled;
+        let payload = WebhookPayload::from_terminal_event(&session, &cancelled, vec![3]).unwrap();
+        assert_eq!(payload.event_type, "processing_cancelled");
+        assert_eq!(payload.successful_files, None);
+        assert_eq!(payload.saved_bill_ids, vec![3]);
+
+        let saved = ProcessingEvent::BillDataSaved {
+            file_index: 0,
+            page_number: None,
+            bill_id: 3,
+            timestamp: Utc::now(),
+        };
+        assert!(WebhookPayload::from_terminal_event(&session, &saved, Vec::new()).is_none());
+    }
+}
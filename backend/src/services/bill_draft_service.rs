@@ -0,0 +1,193 @@
+use crate::api::ApiError;
+use crate::models::{Bill, BillDraft, CreateBill, DraftSelection};
+use crate::services::bill_extractor::BillDataExtractor;
+use crate::services::bill_service::BillService;
+use sqlx::PgPool;
+
+/// Staging area for bills extracted in draft mode
+///
+/// Drafts belong to the processing session that extracted them and are only
+/// written to `bills` when a reviewer commits them.
+pub struct BillDraftService {
+    pool: PgPool,
+}
+
+impl BillDraftService {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Stage an extracted bill as a draft of a session
+    /// Uses compile-time query validation with sqlx::query_as!
+    pub async fn stage_draft(
+        &self,
+        session_id: &str,
+        file_index: usize,
+        bill: &CreateBill,
+        source_images: &[String],
+    ) -> Result<BillDraft, ApiError> {
+        let draft = sqlx::query_as!(
+            BillDraft,
+            r#"
+            INSERT INTO bill_drafts (
+                session_id, file_index,
+                form_no, serial_no, invoice_no, issued_date,
+                seller_name, seller_tax_code, item_name, unit,
+                quantity, unit_price, total_amount, vat_rate, vat_amount,
+                source_images
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+            RETURNING id, session_id, file_index, created_at,
+                      form_no, serial_no, invoice_no, issued_date,
+                      seller_name, seller_tax_code, item_name, unit,
+                      quantity, unit_price, total_amount, vat_rate, vat_amount,
+                      source_images
+            "#,
+            session_id,
+            file_index as i32,
+            bill.form_no,
+            bill.serial_no,
+            bill.invoice_no,
+            bill.issued_date,
+            bill.seller_name,
+            bill.seller_tax_code,
+            bill.item_name,
+            bill.unit,
+            bill.quantity,
+            bill.unit_price,
+            bill.total_amount,
+            bill.vat_rate,
+            bill.vat_amount,
+            source_images
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| ApiError::InternalServerError(format!("Database error: {e}")))?;
+
+        Ok(draft)
+    }
+
+    /// Get the drafts staged by a session
+    pub async fn get_drafts(&self, session_id: &str) -> Result<Vec<BillDraft>, ApiError> {
+        let drafts = sqlx::query_as!(
+            BillDraft,
+            r#"
+            SELECT id, session_id, file_index, created_at,
+                   form_no, serial_no, invoice_no, issued_date,
+                   seller_name, seller_tax_code, item_name, unit,
+                   quantity, unit_price, total_amount, vat_rate, vat_amount,
+                   source_images
+            FROM bill_drafts
+            WHERE session_id = $1
+            ORDER BY file_index ASC, id ASC
+            "#,
+            session_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| ApiError::InternalServerError(format!("Database error: {e}")))?;
+
+        Ok(drafts)
+    }
+
+    /// Save the selected drafts of a session to `bills`
+    ///
+    /// Edited bills replace the extracted values and are validated like
+    /// extracted ones. All selections are committed in one transaction:
+    /// either every selected draft becomes a bill or none does.
+    pub async fn commit_drafts(
+        &self,
+        session_id: &str,
+        selections: &[DraftSelection],
+    ) -> Result<Vec<Bill>, ApiError> {
+        let db_error =
+            |e: sqlx::Error| ApiError::InternalServerError(format!("Database error: {e}"));
+        let extractor = BillDataExtractor::new();
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        let mut bills = Vec::with_capacity(selections.len());
+
+        for selection in selections {
+            let draft = sqlx::query_as!(
+                BillDraft,
+                r#"
+                SELECT id, session_id, file_index, created_at,
+                       form_no, serial_no, invoice_no, issued_date,
+                       seller_name, seller_tax_code, item_name, unit,
+                       quantity, unit_price, total_amount, vat_rate, vat_amount,
+                       source_images
+                FROM bill_drafts
+                WHERE id = $1 AND session_id = $2
+                FOR UPDATE
+                "#,
+                selection.id,
+                session_id
+            )
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(db_error)?
+            .ok_or_else(|| {
+                ApiError::NotFound(format!(
+                    "Draft {} not found in session {}",
+                    selection.id, session_id
+                ))
+            })?;
+
+            let bill = selection
+                .bill
+                .clone()
+                .unwrap_or_else(|| draft.to_create_bill());
+            extractor.validate_extracted_data(&bill).map_err(|e| {
+                ApiError::BadRequest(format!("Draft {} is not a valid bill: {e}", draft.id))
+            })?;
+
+            let bill = BillService::insert_bill(&mut *tx, &bill, &draft.source_images).await?;
+
+            // Keep the fingerprint of the draft's image for duplicate detection
+            sqlx::query!(
+                "UPDATE image_fingerprints SET bill_id = $1, draft_id = NULL WHERE draft_id = $2",
+                bill.id,
+                draft.id
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+
+            sqlx::query!("DELETE FROM bill_drafts WHERE id = $1", draft.id)
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error)?;
+
+            bills.push(bill);
+        }
+
+        tx.commit().await.map_err(db_error)?;
+        Ok(bills)
+    }
+
+    /// Discard drafts of a session, or all of them when `ids` is `None`
+    pub async fn discard_drafts(
+        &self,
+        session_id: &str,
+        ids: Option<&[i32]>,
+    ) -> Result<u64, ApiError> {
+        let result = match ids {
+            Some(ids) => {
+                sqlx::query!(
+                    "DELETE FROM bill_drafts WHERE session_id = $1 AND id = ANY($2)",
+                    session_id,
+                    ids
+                )
+                .execute(&self.pool)
+                .await
+            }
+            None => {
+                sqlx::query!("DELETE FROM bill_drafts WHERE session_id = $1", session_id)
+                    .execute(&self.pool)
+                    .await
+            }
+        }
+        .map_err(|e| ApiError::InternalServerError(format!("Database error: {e}")))?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -94,7 +94,10 @@ impl BillDataExtractor {
     /// - DD-MM-YYYY
     /// - DD.MM.YYYY
     /// - ISO format YYYY-MM-DD
-    fn parse_vietnamese_date(&self, date_str: &str) -> Result<NaiveDate, ExtractionError> {
+    pub(crate) fn parse_vietnamese_date(
+        &self,
+        date_str: &str,
+    ) -> Result<NaiveDate, ExtractionError> {
         let cleaned = date_str.trim();
 
         // Try different Vietnamese date formats
@@ -125,7 +128,10 @@ impl BillDataExtractor {
     /// - Removes thousands separators (. or ,)
     /// - Handles decimal places with , or .
     /// - Removes currency symbols and spaces
-    fn parse_vietnamese_amount(&self, amount_str: &str) -> Result<Decimal, ExtractionError> {
+    pub(crate) fn parse_vietnamese_amount(
+        &self,
+        amount_str: &str,
+    ) -> Result<Decimal, ExtractionError> {
         let cleaned = amount_str
             .trim()
             .replace("₫", "") // Remove Vietnamese dong symbol
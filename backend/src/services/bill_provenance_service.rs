@@ -0,0 +1,99 @@
+use crate::api::ApiError;
+use crate::models::{Bill, BillProvenance, CreateBill};
+use crate::services::bill_service::BillService;
+use crate::services::email_ingestion::EmailSource;
+use sqlx::{PgExecutor, PgPool};
+
+/// Records where ingested bills came from
+pub struct BillProvenanceService {
+    pool: PgPool,
+}
+
+impl BillProvenanceService {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Create a bill extracted from an email attachment
+    ///
+    /// The bill and its provenance are written in one transaction, so the
+    /// bill is never visible without it.
+    pub async fn create_bill_from_email(
+        &self,
+        create_bill: &CreateBill,
+        source_images: &[String],
+        source: &EmailSource,
+        attachment_name: Option<&str>,
+    ) -> Result<Bill, ApiError> {
+        let db_error =
+            |e: sqlx::Error| ApiError::InternalServerError(format!("Database error: {e}"));
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        let bill = BillService::insert_bill(&mut *tx, create_bill, source_images).await?;
+        Self::record_email_provenance(&mut *tx, bill.id, source, attachment_name).await?;
+        tx.commit().await.map_err(db_error)?;
+
+        Ok(bill)
+    }
+
+    /// Record through `executor` that a bill was extracted from an email attachment
+    pub async fn record_email_provenance<'e, E>(
+        executor: E,
+        bill_id: i32,
+        source: &EmailSource,
+        attachment_name: Option<&str>,
+    ) -> Result<BillProvenance, ApiError>
+    where
+        E: PgExecutor<'e>,
+    {
+        let provenance = sqlx::query_as!(
+            BillProvenance,
+            r#"
+            INSERT INTO bill_provenance (
+                bill_id, source_type, email_subject, email_sender,
+                email_date, email_message_id, attachment_name
+            )
+            VALUES ($1, 'email', $2, $3, $4, $5, $6)
+            ON CONFLICT (bill_id) DO UPDATE SET
+                source_type = EXCLUDED.source_type,
+                email_subject = EXCLUDED.email_subject,
+                email_sender = EXCLUDED.email_sender,
+                email_date = EXCLUDED.email_date,
+                email_message_id = EXCLUDED.email_message_id,
+                attachment_name = EXCLUDED.attachment_name,
+                recorded_at = NOW()
+            RETURNING bill_id, source_type, email_subject, email_sender,
+                      email_date, email_message_id, attachment_name, recorded_at
+            "#,
+            bill_id,
+            source.subject,
+            source.sender,
+            source.sent_at,
+            source.message_id,
+            attachment_name
+        )
+        .fetch_one(executor)
+        .await
+        .map_err(|e| ApiError::InternalServerError(format!("Database error: {e}")))?;
+
+        Ok(provenance)
+    }
+
+    /// Get the provenance of a bill, if it was ingested from an external source
+    pub async fn get_provenance(&self, bill_id: i32) -> Result<Option<BillProvenance>, ApiError> {
+        let provenance = sqlx::query_as!(
+            BillProvenance,
+            r#"
+            SELECT bill_id, source_type, email_subject, email_sender,
+                   email_date, email_message_id, attachment_name, recorded_at
+            FROM bill_provenance
+            WHERE bill_id = $1
+            "#,
+            bill_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| ApiError::InternalServerError(format!("Database error: {e}")))?;
+
+        Ok(provenance)
+    }
+}
@@ -1,6 +1,6 @@
 use crate::api::ApiError;
 use crate::models::{Bill, CreateBill};
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 
 pub struct BillService {
     pool: PgPool,
@@ -19,7 +19,8 @@ impl BillService {
             r#"
             SELECT id, form_no, serial_no, invoice_no, issued_date,
                    seller_name, seller_tax_code, item_name, unit,
-                   quantity, unit_price, total_amount, vat_rate, vat_amount
+                   quantity, unit_price, total_amount, vat_rate, vat_amount,
+                   source_images
             FROM bills
             ORDER BY id ASC
             "#
@@ -39,7 +40,8 @@ impl BillService {
             r#"
             SELECT id, form_no, serial_no, invoice_no, issued_date,
                    seller_name, seller_tax_code, item_name, unit,
-                   quantity, unit_price, total_amount, vat_rate, vat_amount
+                   quantity, unit_price, total_amount, vat_rate, vat_amount,
+                   source_images
             FROM bills
             WHERE id = $1
             "#,
@@ -55,18 +57,42 @@ impl BillService {
     /// Create a new bill
     /// Uses compile-time query validation with sqlx::query!
     pub async fn create_bill(&self, create_bill: CreateBill) -> Result<Bill, ApiError> {
+        self.create_bill_with_sources(create_bill, &[]).await
+    }
+
+    /// Create a new bill extracted from images kept in the image store
+    pub async fn create_bill_with_sources(
+        &self,
+        create_bill: CreateBill,
+        source_images: &[String],
+    ) -> Result<Bill, ApiError> {
+        Self::insert_bill(&self.pool, &create_bill, source_images).await
+    }
+
+    /// Insert a bill through `executor`, such as a transaction that writes
+    /// related rows along with it
+    pub async fn insert_bill<'e, E>(
+        executor: E,
+        create_bill: &CreateBill,
+        source_images: &[String],
+    ) -> Result<Bill, ApiError>
+    where
+        E: PgExecutor<'e>,
+    {
         let bill = sqlx::query_as!(
             Bill,
             r#"
             INSERT INTO bills (
                 form_no, serial_no, invoice_no, issued_date,
                 seller_name, seller_tax_code, item_name, unit,
-                quantity, unit_price, total_amount, vat_rate, vat_amount
+                quantity, unit_price, total_amount, vat_rate, vat_amount,
+                source_images
             )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
             RETURNING id, form_no, serial_no, invoice_no, issued_date,
                       seller_name, seller_tax_code, item_name, unit,
-                      quantity, unit_price, total_amount, vat_rate, vat_amount
+                      quantity, unit_price, total_amount, vat_rate, vat_amount,
+                      source_images
             "#,
             create_bill.form_no,
             create_bill.serial_no,
@@ -80,9 +106,10 @@ impl BillService {
             create_bill.unit_price,
             create_bill.total_amount,
             create_bill.vat_rate,
-            create_bill.vat_amount
+            create_bill.vat_amount,
+            source_images
         )
-        .fetch_one(&self.pool)
+        .fetch_one(executor)
         .await
         .map_err(|e| ApiError::InternalServerError(format!("Database error: {e}")))?;
 
@@ -106,7 +133,8 @@ impl BillService {
             WHERE id = $1
             RETURNING id, form_no, serial_no, invoice_no, issued_date,
                       seller_name, seller_tax_code, item_name, unit,
-                      quantity, unit_price, total_amount, vat_rate, vat_amount
+                      quantity, unit_price, total_amount, vat_rate, vat_amount,
+                      source_images
             "#,
             id,
             update_bill.form_no,
@@ -149,7 +177,8 @@ impl BillService {
             r#"
             SELECT id, form_no, serial_no, invoice_no, issued_date,
                    seller_name, seller_tax_code, item_name, unit,
-                   quantity, unit_price, total_amount, vat_rate, vat_amount
+                   quantity, unit_price, total_amount, vat_rate, vat_amount,
+                   source_images
             FROM bills
             WHERE invoice_no ILIKE $1
             ORDER BY issued_date DESC
@@ -187,7 +216,8 @@ impl BillService {
             r#"
             SELECT id, form_no, serial_no, invoice_no, issued_date,
                    seller_name, seller_tax_code, item_name, unit,
-                   quantity, unit_price, total_amount, vat_rate, vat_amount
+                   quantity, unit_price, total_amount, vat_rate, vat_amount,
+                   source_images
             FROM bills
             ORDER BY id ASC
             LIMIT $1 OFFSET $2
@@ -0,0 +1,230 @@
+//! Multi-page invoice documents
+//!
+//! Long invoices are photographed as two or three images. Images are grouped
+//! into one document either explicitly, with a `document` label sent before
+//! their multipart fields, or automatically, when their invoice QR codes or
+//! the serial and number extracted from them name the same invoice. A
+//! labelled document is extracted with a single Gemini request; automatically
+//! grouped pages are extracted one by one. Either way the bills of a document
+//! share one invoice header.
+
+use crate::{models::GeminiResponse, services::invoice_qr::InvoiceQrData};
+
+/// Identity of an invoice: its seller, serial and number
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InvoiceKey {
+    pub seller_tax_code: Option<String>,
+    pub serial_no: String,
+    pub invoice_no: String,
+}
+
+impl InvoiceKey {
+    /// Key of an invoice, if both its serial and number are known
+    pub fn new(
+        seller_tax_code: Option<&str>,
+        serial_no: Option<&str>,
+        invoice_no: Option<&str>,
+    ) -> Option<Self> {
+        let serial_no = serial_no?.trim();
+        // Numbers are printed with and without leading zeros
+        let invoice_no = invoice_no?.trim().trim_start_matches('0');
+        if serial_no.is_empty() || invoice_no.is_empty() {
+            return None;
+        }
+        Some(Self {
+            seller_tax_code: seller_tax_code
+                .map(str::trim)
+                .filter(|code| !code.is_empty())
+                .map(str::to_string),
+            serial_no: serial_no.to_uppercase(),
+            invoice_no: invoice_no.to_string(),
+        })
+    }
+
+    /// Key of the invoice a QR code belongs to, if it names the serial and number
+    pub fn from_qr(qr: &InvoiceQrData) -> Option<Self> {
+        Self::new(
+            qr.seller_tax_code.as_deref(),
+            qr.serial_no.as_deref(),
+            qr.invoice_no.as_deref(),
+        )
+    }
+
+    /// Key of the invoice a bill was extracted from, if it has the serial and number
+    pub fn from_response(response: &GeminiResponse) -> Option<Self> {
+        Self::new(
+            response.seller_tax_code.as_deref(),
+            response.serial_no.as_deref(),
+            response.invoice_no.as_deref(),
+        )
+    }
+
+    /// Whether both keys name the same invoice; the seller is only compared
+    /// when both keys know it
+    pub fn same_invoice(&self, other: &InvoiceKey) -> bool {
+        self.serial_no == other.serial_no
+            && self.invoice_no == other.invoice_no
+            && match (&self.seller_tax_code, &other.seller_tax_code) {
+                (Some(seller), Some(other_seller)) => seller == other_seller,
+                _ => true,
+            }
+    }
+}
+
+/// Group images by the invoice they belong to
+///
+/// Returns the positions of the images of each group, in upload order and
+/// ordered by their first image. Keys are matched with
+/// [`InvoiceKey::same_invoice`], against the key of each group's first image.
+/// Images without a key form a group of their own.
+pub fn group_by_invoice_key(keys: &[Option<InvoiceKey>]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_keys: Vec<(&InvoiceKey, usize)> = Vec::new();
+
+    for (position, key) in keys.iter().enumerate() {
+        let group = key.as_ref().and_then(|key| {
+            group_keys
+                .iter()
+                .find(|(group_key, _)| group_key.same_invoice(key))
+                .map(|(_, group)| *group)
+        });
+        match group {
+            Some(group) => groups[group].push(position),
+            None => {
+                if let Some(key) = key {
+                    group_keys.push((key, groups.len()));
+                }
+                groups.push(vec![position]);
+            }
+        }
+    }
+
+    groups
+}
+
+/// Give every bill of a document the same invoice header
+///
+/// Gemini may only fill the header on the lines of the first page, or read
+/// it slightly differently per page. Each header field takes the value most
+/// candidates agree on (the earliest one on a tie), and candidates missing
+/// it are filled in.
+pub fn unify_invoice_header(responses: &mut [GeminiResponse]) {
+    let fields: [fn(&mut GeminiResponse) -> &mut Option<String>; 6] = [
+        |response| &mut response.form_no,
+        |response| &mut response.serial_no,
+        |response| &mut response.invoice_no,
+        |response| &mut response.issued_date,
+        |response| &mut response.seller_name,
+        |response| &mut response.seller_tax_code,
+    ];
+
+    for field in fields {
+        let values: Vec<String> = responses
+            .iter_mut()
+            .filter_map(|response| field(response).clone())
+            .collect();
+        let Some(value) = most_common(&values) else {
+            continue;
+        };
+        for response in responses.iter_mut() {
+            *field(response) = Some(value.clone());
+        }
+    }
+}
+
+fn most_common(values: &[String]) -> Option<&String> {
+    let mut counts: Vec<(&String, usize)> = Vec::new();
+    for value in values {
+        match counts.iter_mut().find(|(seen, _)| *seen == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+    // `max_by_key` keeps the last maximum; iterate in reverse to prefer the earliest
+    counts
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(value, _)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(serial_no: &str, invoice_no: &str) -> Option<InvoiceKey> {
+        Some(InvoiceKey {
+            seller_tax_code: Some("0312345678".to_string()),
+            serial_no: serial_no.to_string(),
+            invoice_no: invoice_no.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_key_ignores_leading_zeros_and_needs_number() {
+        let qr = InvoiceQrData {
+            serial_no: Some("C25TAA".to_string()),
+            invoice_no: Some("00000123".to_string()),
+            ..InvoiceQrData::default()
+        };
+        assert_eq!(InvoiceKey::from_qr(&qr).unwrap().invoice_no, "123");
+
+        let without_number = InvoiceQrData {
+            invoice_no: None,
+            ..qr
+        };
+        assert!(InvoiceKey::from_qr(&without_number).is_none());
+    }
+
+    #[test]
+    fn test_same_invoice_ignores_unknown_seller_and_case() {
+        let saved = InvoiceKey::new(Some("0312345678"), Some("c25taa"), Some("0000123")).unwrap();
+        let read = InvoiceKey::new(None, Some("C25TAA"), Some("123")).unwrap();
+        assert!(saved.same_invoice(&read));
+
+        let other_seller =
+            InvoiceKey::new(Some("0109876543"), Some("C25TAA"), Some("123")).unwrap();
+        assert!(!saved.same_invoice(&other_seller));
+        let next_invoice = InvoiceKey::new(None, Some("C25TAA"), Some("124")).unwrap();
+        assert!(!saved.same_invoice(&next_invoice));
+        assert!(InvoiceKey::new(None, Some("C25TAA"), Some("000")).is_none());
+    }
+
+    #[test]
+    fn test_groups_images_of_the_same_invoice() {
+        let keys = [
+            key("C25TAA", "123"),
+            None,
+            key("C25TAA", "124"),
+            key("C25TAA", "123"),
+            None,
+        ];
+
+        assert_eq!(
+            group_by_invoice_key(&keys),
+            vec![vec![0, 3], vec![1], vec![2], vec![4]]
+        );
+    }
+
+    #[test]
+    fn test_unify_header_takes_majority_and_fills_gaps() {
+        let line = |invoice_no: Option<&str>, seller_name: Option<&str>| GeminiResponse {
+            invoice_no: invoice_no.map(str::to_string),
+            seller_name: seller_name.map(str::to_string),
+            ..GeminiResponse::new()
+        };
+        let mut responses = vec![
+            line(Some("123"), Some("Công ty A")),
+            line(Some("128"), None),
+            line(Some("123"), Some("Cong ty A")),
+        ];
+
+        unify_invoice_header(&mut responses);
+
+        for response in &responses {
+            assert_eq!(response.invoice_no.as_deref(), Some("123"));
+            assert_eq!(response.seller_name.as_deref(), Some("Công ty A"));
+            assert_eq!(response.form_no, None);
+        }
+    }
+}
@@ -0,0 +1,667 @@
+//! Import of Vietnamese e-invoice XML files
+//!
+//! E-invoices issued under Decree 123/2020 and Circular 78/2021 come with an
+//! XML file in the format published by the General Department of Taxation:
+//! an `HDon` element whose `DLHDon` holds the general information
+//! (`TTChung`), the seller (`NBan`), the buyer (`NMua`), the goods and
+//! service lines (`DSHHDVu/HHDVu`) and the totals (`TToan`), followed by the
+//! signatures (`DSCKS`). The invoice may also be wrapped in a `TDiep`
+//! transmission message.
+//!
+//! The XML is exact, so every line becomes a bill without a Gemini request.
+//! Missing or malformed fields, an incomplete signature block and lines that
+//! do not add up to the invoice totals are reported as issues; only a file
+//! without the invoice structure is rejected. Signatures are checked for
+//! completeness, not verified cryptographically.
+
+use std::str::FromStr;
+
+use base64::Engine;
+use chrono::NaiveDate;
+use roxmltree::{Document, Node};
+use rust_decimal::Decimal;
+use tracing::debug;
+
+use crate::models::{CreateBill, EInvoiceIssue, EInvoiceIssueKind};
+
+/// Content type reported for validated e-invoice XML files
+pub const XML_CONTENT_TYPE: &str = "application/xml";
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Line totals may differ from the invoice totals by this much due to rounding
+const TOTALS_TOLERANCE: Decimal = Decimal::ONE;
+
+#[derive(Debug, thiserror::Error)]
+pub enum EInvoiceError {
+    #[error("Invalid XML: {0}")]
+    InvalidXml(String),
+
+    #[error("Not an e-invoice: no HDon element found")]
+    NotAnInvoice,
+
+    #[error("E-invoice is missing required element {0}")]
+    MissingElement(&'static str),
+
+    #[error("E-invoice has no goods or service lines")]
+    NoLineItems,
+}
+
+/// Seller (`NBan`) or buyer (`NMua`) of an invoice
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EInvoiceParty {
+    pub name: Option<String>,
+    pub tax_code: Option<String>,
+    pub address: Option<String>,
+}
+
+/// Nature of a line (`TChat`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Goods,
+    Promotion,
+    /// Trade discount, deducted from the invoice total
+    Discount,
+    /// Descriptive text without amounts
+    Note,
+}
+
+/// One `HHDVu` line of an invoice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EInvoiceLine {
+    pub kind: LineKind,
+    pub name: Option<String>,
+    pub unit: Option<String>,
+    pub quantity: Option<Decimal>,
+    pub unit_price: Option<Decimal>,
+    /// Amount before VAT (`ThTien`)
+    pub amount: Option<Decimal>,
+    /// VAT rate in percent; `None` for exempt lines (`KCT`, `KKKNT`)
+    pub vat_rate: Option<Decimal>,
+    pub vat_amount: Option<Decimal>,
+}
+
+/// An e-invoice read from its XML file
+#[derive(Debug, Clone)]
+pub struct EInvoice {
+    /// Invoice form (`KHMSHDon`)
+    pub form_no: Option<String>,
+    /// Invoice series (`KHHDon`)
+    pub serial_no: Option<String>,
+    pub invoice_no: Option<String>,
+    pub issued_date: Option<NaiveDate>,
+    pub currency: Option<String>,
+    pub seller: EInvoiceParty,
+    pub buyer: EInvoiceParty,
+    pub lines: Vec<EInvoiceLine>,
+    /// Total before VAT (`TToan/TgTCThue`)
+    pub total_before_tax: Option<Decimal>,
+    /// Total VAT (`TToan/TgTThue`)
+    pub total_tax: Option<Decimal>,
+    pub issues: Vec<EInvoiceIssue>,
+}
+
+impl EInvoice {
+    /// One bill per goods, promotion and discount line
+    ///
+    /// Note lines are skipped, and discount amounts are negated so the bills
+    /// of an invoice add up to its total.
+    pub fn to_bills(&self) -> Vec<CreateBill> {
+        self.lines
+            .iter()
+            .filter(|line| line.kind != LineKind::Note)
+            .map(|line| {
+                let sign = if line.kind == LineKind::Discount {
+                    -Decimal::ONE
+                } else {
+                    Decimal::ONE
+                };
+                CreateBill {
+                    form_no: self.form_no.clone(),
+                    serial_no: self.serial_no.clone(),
+                    invoice_no: self.invoice_no.clone(),
+                    issued_date: self.issued_date,
+                    seller_name: self.seller.name.clone(),
+                    seller_tax_code: self.seller.tax_code.clone(),
+                    item_name: line.name.clone(),
+                    unit: line.unit.clone(),
+                    quantity: line.quantity,
+                    unit_price: line.unit_price,
+                    total_amount: line.amount.map(|amount| amount * sign),
+                    vat_rate: line.vat_rate,
+                    vat_amount: line.vat_amount.map(|amount| amount * sign),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Whether the data looks like an XML document rather than an image or PDF
+pub fn is_xml(data: &[u8]) -> bool {
+    let data = data.strip_prefix(UTF8_BOM).unwrap_or(data);
+    let start = data
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .map_or(&[][..], |position| &data[position..]);
+
+    start.starts_with(b"<?xml") || start.starts_with(b"<HDon") || start.starts_with(b"<TDiep")
+}
+
+/// Parse an e-invoice XML file
+///
+/// Fails only if the file is not well-formed XML or lacks the invoice
+/// structure (`DLHDon`, `TTChung`, `NBan` and at least one line). All other
+/// problems are reported in [`EInvoice::issues`].
+pub fn parse_einvoice(data: &[u8]) -> Result<EInvoice, EInvoiceError> {
+    let data = data.strip_prefix(UTF8_BOM).unwrap_or(data);
+    let text = std::str::from_utf8(data)
+        .map_err(|e| EInvoiceError::InvalidXml(format!("not UTF-8: {e}")))?;
+    let document = Document::parse(text).map_err(|e| EInvoiceError::InvalidXml(e.to_string()))?;
+
+    let mut invoices = document
+        .descendants()
+        .filter(|node| is_element(node, "HDon"));
+    let invoice = invoices.next().ok_or(EInvoiceError::NotAnInvoice)?;
+    let other_invoices = invoices.count();
+
+    let mut reader = InvoiceReader::default();
+    if other_invoices > 0 {
+        reader.issue(
+            EInvoiceIssueKind::Schema,
+            "HDon",
+            format!(
+                "File holds {} invoices; only the first was imported",
+                other_invoices + 1
+            ),
+        );
+    }
+
+    let data_node = child(invoice, "DLHDon").ok_or(EInvoiceError::MissingElement("DLHDon"))?;
+    let general = child(data_node, "TTChung").ok_or(EInvoiceError::MissingElement("TTChung"))?;
+    let content = child(data_node, "NDHDon").ok_or(EInvoiceError::MissingElement("NDHDon"))?;
+    let seller = child(content, "NBan").ok_or(EInvoiceError::MissingElement("NBan"))?;
+
+    let form_no = reader.required_text(general, "TTChung", "KHMSHDon");
+    let serial_no = reader.required_text(general, "TTChung", "KHHDon");
+    let invoice_no = reader.required_text(general, "TTChung", "SHDon");
+    let issued_date = reader
+        .required_text(general, "TTChung", "NLap")
+        .and_then(|date| {
+            NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .map_err(|_| {
+                    reader.issue(
+                        EInvoiceIssueKind::Schema,
+                        "TTChung/NLap",
+                        format!("Issue date {date:?} is not in YYYY-MM-DD format"),
+                    )
+                })
+                .ok()
+        });
+    let currency = child_text(general, "DVTTe");
+
+    let seller = EInvoiceParty {
+        name: reader.required_text(seller, "NBan", "Ten"),
+        tax_code: reader.required_text(seller, "NBan", "MST"),
+        address: child_text(seller, "DChi"),
+    };
+    let buyer = match child(content, "NMua") {
+        Some(buyer) => EInvoiceParty {
+            // Individual buyers may only have the name of the person buying
+            name: child_text(buyer, "Ten").or_else(|| child_text(buyer, "HVTNMHang")),
+            tax_code: child_text(buyer, "MST"),
+            address: child_text(buyer, "DChi"),
+        },
+        None => {
+            reader.issue(EInvoiceIssueKind::Schema, "NMua", "Buyer is missing");
+            EInvoiceParty::default()
+        }
+    };
+
+    let round_to = if currency.as_deref().is_none_or(|currency| currency == "VND") {
+        0
+    } else {
+        2
+    };
+    let lines: Vec<EInvoiceLine> = child(content, "DSHHDVu")
+        .into_iter()
+        .flat_map(|list| list.children().filter(|node| is_element(node, "HHDVu")))
+        .enumerate()
+        .map(|(index, line)| reader.line(line, index + 1, round_to))
+        .collect();
+    if lines.iter().all(|line| line.kind == LineKind::Note) {
+        return Err(EInvoiceError::NoLineItems);
+    }
+
+    let totals = child(content, "TToan");
+    if totals.is_none() {
+        reader.issue(EInvoiceIssueKind::Schema, "TToan", "Totals are missing");
+    }
+    let total_before_tax = totals.and_then(|totals| reader.decimal(totals, "TToan", "TgTCThue"));
+    let total_tax = totals.and_then(|totals| reader.decimal(totals, "TToan", "TgTThue"));
+    reader.check_totals(&lines, total_before_tax);
+
+    reader.check_signatures(invoice, data_node.attribute("Id"));
+
+    debug!(
+        "Parsed e-invoice {:?}/{:?} with {} line(s) and {} issue(s)",
+        serial_no,
+        invoice_no,
+        lines.len(),
+        reader.issues.len()
+    );
+
+    Ok(EInvoice {
+        form_no,
+        serial_no,
+        invoice_no,
+        issued_date,
+        currency,
+        seller,
+        buyer,
+        lines,
+        total_before_tax,
+        total_tax,
+        issues: reader.issues,
+    })
+}
+
+/// Collects the issues found while reading an invoice
+#[derive(Default)]
+struct InvoiceReader {
+    issues: Vec<EInvoiceIssue>,
+}
+
+impl InvoiceReader {
+    fn issue(&mut self, kind: EInvoiceIssueKind, element: &str, message: impl Into<String>) {
+        self.issues.push(EInvoiceIssue {
+            kind,
+            element: element.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn required_text(&mut self, node: Node<'_, '_>, path: &str, name: &str) -> Option<String> {
+        let text = child_text(node, name);
+        if text.is_none() {
+            self.issue(
+                EInvoiceIssueKind::Schema,
+                &format!("{path}/{name}"),
+                "Required element is missing or empty",
+            );
+        }
+        text
+    }
+
+    fn decimal(&mut self, node: Node<'_, '_>, path: &str, name: &str) -> Option<Decimal> {
+        let text = child_text(node, name)?;
+        match Decimal::from_str(&text) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.issue(
+                    EInvoiceIssueKind::Schema,
+                    &format!("{path}/{name}"),
+                    format!("{text:?} is not a number"),
+                );
+                None
+            }
+        }
+    }
+
+    fn line(&mut self, line: Node<'_, '_>, number: usize, round_to: u32) -> EInvoiceLine {
+        let path = format!("HHDVu[{number}]");
+        let kind = match child_text(line, "TChat").as_deref() {
+            Some("2") => LineKind::Promotion,
+            Some("3") => LineKind::Discount,
+            Some("4") => LineKind::Note,
+            Some("1") | None => LineKind::Goods,
+            Some(other) => {
+                self.issue(
+                    EInvoiceIssueKind::Schema,
+                    &format!("{path}/TChat"),
+                    format!("Unknown line type {other:?}, treated as goods"),
+                );
+                LineKind::Goods
+            }
+        };
+
+        let name = child_text(line, "THHDVu");
+        if kind == LineKind::Note {
+            return EInvoiceLine {
+                kind,
+                name,
+                unit: None,
+                quantity: None,
+                unit_price: None,
+                amount: None,
+                vat_rate: None,
+                vat_amount: None,
+            };
+        }
+        if name.is_none() {
+            self.issue(
+                EInvoiceIssueKind::Schema,
+                &format!("{path}/THHDVu"),
+                "Required element is missing or empty",
+            );
+        }
+
+        let amount = self.decimal(line, &path, "ThTien");
+        let vat_rate = child_text(line, "TSuat").and_then(|rate| {
+            parse_vat_rate(&rate).unwrap_or_else(|| {
+                self.issue(
+                    EInvoiceIssueKind::Schema,
+                    &format!("{path}/TSuat"),
+                    format!("Unknown VAT rate {rate:?}"),
+                );
+                None
+            })
+        });
+        // Few issuers give the VAT of each line; derive it from the rate otherwise
+        let vat_amount = self
+            .decimal(line, &path, "TThue")
+            .or_else(|| Some((amount? * vat_rate? / Decimal::ONE_HUNDRED).round_dp(round_to)));
+
+        EInvoiceLine {
+            kind,
+            name,
+            unit: child_text(line, "DVTinh"),
+            quantity: self.decimal(line, &path, "SLuong"),
+            unit_price: self.decimal(line, &path, "DGia"),
+            amount,
+            vat_rate,
+            vat_amount,
+        }
+    }
+
+    fn check_totals(&mut self, lines: &[EInvoiceLine], total_before_tax: Option<Decimal>) {
+        let Some(total_before_tax) = total_before_tax else {
+            return;
+        };
+
+        let line_total: Decimal = lines
+            .iter()
+            .filter_map(|line| match line.kind {
+                LineKind::Goods | LineKind::Promotion => line.amount,
+                LineKind::Discount => line.amount.map(|amount| -amount),
+                LineKind::Note => None,
+            })
+            .sum();
+        if (line_total - total_before_tax).abs() > TOTALS_TOLERANCE {
+            self.issue(
+                EInvoiceIssueKind::Totals,
+                "TToan/TgTCThue",
+                format!(
+                    "Lines add up to {line_total}, invoice total before VAT is {total_before_tax}"
+                ),
+            );
+        }
+    }
+
+    /// Check that the seller's signature is present, complete and covers the invoice data
+    fn check_signatures(&mut self, invoice: Node<'_, '_>, data_id: Option<&str>) {
+        let Some(signatures) = child(invoice, "DSCKS") else {
+            self.issue(
+                EInvoiceIssueKind::Signature,
+                "DSCKS",
+                "Signature block is missing",
+            );
+            return;
+        };
+        let Some(signature) =
+            child(signatures, "NBan").and_then(|seller| child(seller, "Signature"))
+        else {
+            self.issue(
+                EInvoiceIssueKind::Signature,
+                "DSCKS/NBan",
+                "Seller signature is missing",
+            );
+            return;
+        };
+
+        if child_text(signature, "SignatureValue").is_none() {
+            self.issue(
+                EInvoiceIssueKind::Signature,
+                "DSCKS/NBan/Signature/SignatureValue",
+                "Signature value is missing or empty",
+            );
+        }
+
+        let certificate = signature
+            .descendants()
+            .find(|node| is_element(node, "X509Certificate"))
+            .and_then(|node| node.text());
+        match certificate {
+            None => self.issue(
+                EInvoiceIssueKind::Signature,
+                "DSCKS/NBan/Signature/KeyInfo",
+                "Signing certificate is missing",
+            ),
+            Some(certificate) => {
+                let encoded: String = certificate.split_whitespace().collect();
+                if base64::engine::general_purpose::STANDARD
+                    .decode(&encoded)
+                    .is_err()
+                {
+                    self.issue(
+                        EInvoiceIssueKind::Signature,
+                        "DSCKS/NBan/Signature/KeyInfo",
+                        "Signing certificate is not valid base64",
+                    );
+                }
+            }
+        }
+
+        let Some(signed_info) = child(signature, "SignedInfo") else {
+            self.issue(
+                EInvoiceIssueKind::Signature,
+                "DSCKS/NBan/Signature/SignedInfo",
+                "Signed info is missing",
+            );
+            return;
+        };
+        let Some(data_id) = data_id else {
+            self.issue(
+                EInvoiceIssueKind::Signature,
+                "DLHDon",
+                "Invoice data has no Id for the signature to reference",
+            );
+            return;
+        };
+        let expected_uri = format!("#{data_id}");
+        let covers_data = signed_info
+            .children()
+            .filter(|node| is_element(node, "Reference"))
+            .any(|reference| reference.attribute("URI") == Some(expected_uri.as_str()));
+        if !covers_data {
+            self.issue(
+                EInvoiceIssueKind::Signature,
+                "DSCKS/NBan/Signature/SignedInfo",
+                format!("Signature does not reference the invoice data {expected_uri}"),
+            );
+        }
+    }
+}
+
+/// Parse a `TSuat` value: `10%`, `KHAC:3.5%`, or `KCT`/`KKKNT` for exempt lines
+///
+/// Returns `None` if the value is not a known rate.
+fn parse_vat_rate(rate: &str) -> Option<Option<Decimal>> {
+    match rate {
+        "KCT" | "KKKNT" => Some(None),
+        rate => {
+            let rate = rate.strip_prefix("KHAC:").unwrap_or(rate);
+            let rate = rate.strip_suffix('%').unwrap_or(rate).trim();
+            Decimal::from_str(rate).ok().map(Some)
+        }
+    }
+}
+
+/// Elements are matched by local name; the invoice data has no namespace,
+/// the signature uses the XML-DSig one
+fn is_element(node: &Node<'_, '_>, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| is_element(child, name))
+}
+
+fn child_text(node: Node<'_, '_>, name: &str) -> Option<String> {
+    child(node, name)
+        .and_then(|child| child.text())
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNATURE: &str = r##"<DSCKS><NBan><Signature xmlns="http://www.w3.org/2000/09/xmldsig#">
+        <SignedInfo><Reference URI="#data"><DigestValue>abc=</DigestValue></Reference></SignedInfo>
+        <SignatureValue>c2lnbmF0dXJl</SignatureValue>
+        <KeyInfo><X509Data><X509Certificate>Y2VydA==</X509Certificate></X509Data></KeyInfo>
+    </Signature></NBan></DSCKS>"##;
+
+    fn invoice_xml(lines: &str, total_before_tax: &str, signatures: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<HDon>
+  <DLHDon Id="data">
+    <TTChung>
+      <PBan>2.0.1</PBan>
+      <KHMSHDon>1</KHMSHDon>
+      <KHHDon>C25TAA</KHHDon>
+      <SHDon>123</SHDon>
+      <NLap>2025-10-14</NLap>
+      <DVTTe>VND</DVTTe>
+    </TTChung>
+    <NDHDon>
+      <NBan><Ten>Công ty TNHH Nhà Cung Cấp</Ten><MST>0312345678</MST><DChi>Hà Nội</DChi></NBan>
+      <NMua><Ten>Công ty Cổ phần Khách Hàng</Ten><MST>0109876543</MST></NMua>
+      <DSHHDVu>{lines}</DSHHDVu>
+      <TToan><TgTCThue>{total_before_tax}</TgTCThue><TgTThue>100000</TgTThue></TToan>
+    </NDHDon>
+  </DLHDon>
+  {signatures}
+</HDon>"#
+        )
+    }
+
+    const LINES: &str = r#"
+        <HHDVu><TChat>1</TChat><STT>1</STT><THHDVu>Giấy in A4</THHDVu><DVTinh>Ram</DVTinh>
+          <SLuong>10</SLuong><DGia>100000</DGia><ThTien>1000000</ThTien><TSuat>10%</TSuat></HHDVu>
+        <HHDVu><TChat>4</TChat><THHDVu>Giao hàng tận nơi</THHDVu></HHDVu>
+        <HHDVu><TChat>3</TChat><STT>2</STT><THHDVu>Chiết khấu</THHDVu>
+          <ThTien>50000</ThTien><TSuat>KCT</TSuat></HHDVu>"#;
+
+    #[test]
+    fn test_parse_maps_lines_to_bills() {
+        let xml = invoice_xml(LINES, "950000", SIGNATURE);
+        assert!(is_xml(xml.as_bytes()));
+
+        let invoice = parse_einvoice(xml.as_bytes()).unwrap();
+        assert_eq!(invoice.issues, Vec::new());
+        assert_eq!(invoice.buyer.tax_code.as_deref(), Some("0109876543"));
+
+        let bills = invoice.to_bills();
+        assert_eq!(bills.len(), 2);
+        let paper = &bills[0];
+        assert_eq!(paper.form_no.as_deref(), Some("1"));
+        assert_eq!(paper.serial_no.as_deref(), Some("C25TAA"));
+        assert_eq!(paper.invoice_no.as_deref(), Some("123"));
+        assert_eq!(paper.issued_date, NaiveDate::from_ymd_opt(2025, 10, 14));
+        assert_eq!(paper.seller_tax_code.as_deref(), Some("0312345678"));
+        assert_eq!(paper.item_name.as_deref(), Some("Giấy in A4"));
+        assert_eq!(paper.quantity, Some(Decimal::from(10)));
+        assert_eq!(paper.total_amount, Some(Decimal::from(1_000_000)));
+        assert_eq!(paper.vat_rate, Some(Decimal::from(10)));
+        assert_eq!(paper.vat_amount, Some(Decimal::from(100_000)));
+
+        let discount = &bills[1];
+        assert_eq!(discount.total_amount, Some(Decimal::from(-50_000)));
+        assert_eq!(discount.vat_rate, None);
+    }
+
+    #[test]
+    fn test_reports_schema_and_totals_issues() {
+        let lines = r#"<HHDVu><THHDVu>Mực in</THHDVu><ThTien>1.000.000</ThTien><TSuat>10</TSuat></HHDVu>
+            <HHDVu><THHDVu>Bút</THHDVu><ThTien>20000</ThTien></HHDVu>"#;
+        let xml = invoice_xml(lines, "500000", SIGNATURE).replace("<SHDon>123</SHDon>", "");
+
+        let invoice = parse_einvoice(xml.as_bytes()).unwrap();
+        let elements: Vec<_> = invoice
+            .issues
+            .iter()
+            .map(|issue| (issue.kind, issue.element.as_str()))
+            .collect();
+        assert_eq!(
+            elements,
+            [
+                (EInvoiceIssueKind::Schema, "TTChung/SHDon"),
+                (EInvoiceIssueKind::Schema, "HHDVu[1]/ThTien"),
+                (EInvoiceIssueKind::Totals, "TToan/TgTCThue"),
+            ]
+        );
+        assert_eq!(invoice.lines[0].vat_rate, Some(Decimal::from(10)));
+    }
+
+    #[test]
+    fn test_reports_signature_problems() {
+        let xml = invoice_xml(LINES, "950000", "");
+        let invoice = parse_einvoice(xml.as_bytes()).unwrap();
+        assert_eq!(invoice.issues.len(), 1);
+        assert_eq!(invoice.issues[0].kind, EInvoiceIssueKind::Signature);
+        assert_eq!(invoice.issues[0].element, "DSCKS");
+
+        let signature = SIGNATURE
+            .replace("#data", "#other")
+            .replace("c2lnbmF0dXJl", "");
+        let xml = invoice_xml(LINES, "950000", &signature);
+        let invoice = parse_einvoice(xml.as_bytes()).unwrap();
+        let messages: Vec<_> = invoice.issues.iter().map(|issue| &issue.element).collect();
+        assert_eq!(
+            messages,
+            [
+                "DSCKS/NBan/Signature/SignatureValue",
+                "DSCKS/NBan/Signature/SignedInfo",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reads_invoice_inside_transmission_message() {
+        let xml = invoice_xml(LINES, "950000", SIGNATURE);
+        let xml = xml.replace(r#"<?xml version="1.0" encoding="UTF-8"?>"#, "");
+        let message = format!(
+            "\u{feff}<TDiep><TTChung><MLTDiep>200</MLTDiep></TTChung><DLieu>{xml}</DLieu></TDiep>"
+        );
+        assert!(is_xml(message.as_bytes()));
+
+        let invoice = parse_einvoice(message.as_bytes()).unwrap();
+        assert_eq!(invoice.invoice_no.as_deref(), Some("123"));
+    }
+
+    #[test]
+    fn test_rejects_files_without_invoice_structure() {
+        assert!(matches!(
+            parse_einvoice(b"<HDon><DLHDon>"),
+            Err(EInvoiceError::InvalidXml(_))
+        ));
+        assert!(matches!(
+            parse_einvoice(b"<?xml version=\"1.0\"?><svg/>"),
+            Err(EInvoiceError::NotAnInvoice)
+        ));
+        assert!(matches!(
+            parse_einvoice(b"<HDon><DLHDon><NDHDon/></DLHDon></HDon>"),
+            Err(EInvoiceError::MissingElement("TTChung"))
+        ));
+        let no_lines = invoice_xml("", "0", SIGNATURE);
+        assert!(matches!(
+            parse_einvoice(no_lines.as_bytes()),
+            Err(EInvoiceError::NoLineItems)
+        ));
+        assert!(!is_xml(b"\x89PNG\r\n\x1a\n"));
+    }
+}
@@ -0,0 +1,241 @@
+//! Attachment extraction from email exports
+//!
+//! Accepts a single RFC 822 message (`.eml`) or an mbox archive and returns
+//! the image and PDF attachments of every message together with the
+//! message's subject, sender and date. Attached messages (forwarded emails)
+//! are searched as well, with their own provenance.
+
+use std::io::Cursor;
+
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use mail_parser::{Message, MessageParser, MimeHeaders, mailbox::mbox::MessageIterator};
+use tracing::debug;
+
+use crate::services::einvoice_xml::{is_xml, parse_einvoice};
+
+/// How deep attached messages are searched for attachments
+const MAX_NESTED_MESSAGE_DEPTH: usize = 3;
+
+/// Errors returned while reading an email export
+#[derive(Debug, thiserror::Error)]
+pub enum EmailIngestionError {
+    #[error("Not a valid email message: {0}")]
+    InvalidMessage(String),
+
+    #[error("Failed to read mbox archive: {0}")]
+    Mbox(#[from] std::io::Error),
+}
+
+/// The message an attachment came from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EmailSource {
+    pub subject: Option<String>,
+    /// Sender as `Name <address>`, or just the address
+    pub sender: Option<String>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub message_id: Option<String>,
+}
+
+/// An image, PDF or e-invoice XML file attached to an email
+#[derive(Debug, Clone)]
+pub struct EmailAttachment {
+    pub file_name: String,
+    pub data: Bytes,
+    pub source: EmailSource,
+}
+
+/// Whether the export is an mbox archive rather than a single message
+pub fn is_mbox(data: &[u8]) -> bool {
+    data.starts_with(b"From ")
+}
+
+/// Extract the image, PDF and e-invoice XML attachments of an `.eml` message or mbox archive
+///
+/// Attachments of other types (signatures, documents, ...) are skipped.
+pub fn extract_attachments(data: &[u8]) -> Result<Vec<EmailAttachment>, EmailIngestionError> {
+    let parser = MessageParser::default();
+    let mut attachments = Vec::new();
+
+    if is_mbox(data) {
+        for (index, entry) in MessageIterator::new(Cursor::new(data)).enumerate() {
+            let entry = entry?;
+            let message = parser.parse(entry.contents()).ok_or_else(|| {
+                EmailIngestionError::InvalidMessage(format!(
+                    "message {} of the mbox archive",
+                    index + 1
+                ))
+            })?;
+            collect_attachments(&message, 0, &mut attachments);
+        }
+    } else {
+        let message = parser
+            .parse(data)
+            .filter(|message| !message.headers().is_empty())
+            .ok_or_else(|| {
+                EmailIngestionError::InvalidMessage("no message headers found".to_string())
+            })?;
+        collect_attachments(&message, 0, &mut attachments);
+    }
+
+    debug!(
+        "Extracted {} attachment(s) from email export",
+        attachments.len()
+    );
+    Ok(attachments)
+}
+
+fn collect_attachments(
+    message: &Message<'_>,
+    depth: usize,
+    attachments: &mut Vec<EmailAttachment>,
+) {
+    let source = email_source(message);
+
+    for part in message.attachments() {
+        if let Some(nested) = part.message() {
+            if depth < MAX_NESTED_MESSAGE_DEPTH {
+                collect_attachments(nested, depth + 1, attachments);
+            }
+            continue;
+        }
+
+        let data = part.contents();
+        if !is_supported_attachment(data) {
+            debug!(
+                "Skipping attachment {:?} of unsupported type",
+                part.attachment_name()
+            );
+            continue;
+        }
+
+        let file_name = part
+            .attachment_name()
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("attachment-{}", attachments.len() + 1));
+        attachments.push(EmailAttachment {
+            file_name,
+            data: Bytes::copy_from_slice(data),
+            source: source.clone(),
+        });
+    }
+}
+
+/// Whether an attachment is an image, a PDF or an e-invoice XML file, judged by its content
+fn is_supported_attachment(data: &[u8]) -> bool {
+    if is_xml(data) {
+        return parse_einvoice(data).is_ok();
+    }
+    infer::get(data).is_some_and(|kind| {
+        kind.matcher_type() == infer::MatcherType::Image || kind.mime_type() == "application/pdf"
+    })
+}
+
+fn email_source(message: &Message<'_>) -> EmailSource {
+    let sender = message
+        .from()
+        .and_then(|from| from.first())
+        .and_then(|addr| match (addr.name(), addr.address()) {
+            (Some(name), Some(address)) => Some(format!("{name} <{address}>")),
+            (None, Some(address)) => Some(address.to_string()),
+            (Some(name), None) => Some(name.to_string()),
+            (None, None) => None,
+        });
+    let sent_at = message
+        .date()
+        .and_then(|date| DateTime::from_timestamp(date.to_timestamp(), 0));
+
+    EmailSource {
+        subject: message.subject().map(str::to_string),
+        sender,
+        sent_at,
+        message_id: message.message_id().map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    fn eml(subject: &str, attachment: &[u8], attachment_name: &str) -> String {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(attachment);
+        format!(
+            "From: Nha Cung Cap <billing@supplier.vn>\r\n\
+             To: accounts@example.com\r\n\
+             Subject: {subject}\r\n\
+             Date: Tue, 14 Oct 2025 09:30:00 +0700\r\n\
+             Message-ID: <{subject}@supplier.vn>\r\n\
+             MIME-Version: 1.0\r\n\
+             Content-Type: multipart/mixed; boundary=\"b1\"\r\n\
+             \r\n\
+             --b1\r\n\
+             Content-Type: text/plain\r\n\
+             \r\n\
+             Please find the invoice attached.\r\n\
+             --b1\r\n\
+             Content-Type: application/octet-stream; name=\"{attachment_name}\"\r\n\
+             Content-Disposition: attachment; filename=\"{attachment_name}\"\r\n\
+             Content-Transfer-Encoding: base64\r\n\
+             \r\n\
+             {encoded}\r\n\
+             --b1--\r\n"
+        )
+    }
+
+    #[test]
+    fn test_extract_attachments_from_eml() {
+        let message = eml("Invoice-42", PNG_SIGNATURE, "invoice.png");
+
+        let attachments = extract_attachments(message.as_bytes()).unwrap();
+
+        assert_eq!(attachments.len(), 1);
+        let attachment = &attachments[0];
+        assert_eq!(attachment.file_name, "invoice.png");
+        assert_eq!(attachment.data.as_ref(), PNG_SIGNATURE);
+        assert_eq!(attachment.source.subject.as_deref(), Some("Invoice-42"));
+        assert_eq!(
+            attachment.source.sender.as_deref(),
+            Some("Nha Cung Cap <billing@supplier.vn>")
+        );
+        assert_eq!(
+            attachment.source.sent_at,
+            DateTime::parse_from_rfc3339("2025-10-14T02:30:00Z")
+                .ok()
+                .map(|date| date.with_timezone(&Utc))
+        );
+    }
+
+    #[test]
+    fn test_extract_attachments_skips_unsupported_types() {
+        let message = eml("Notes", b"just some text", "notes.txt");
+
+        let attachments = extract_attachments(message.as_bytes()).unwrap();
+
+        assert!(attachments.is_empty());
+    }
+
+    #[test]
+    fn test_extract_attachments_from_mbox() {
+        let mbox = format!(
+            "From billing@supplier.vn Tue Oct 14 09:30:00 2025\r\n{}\r\nFrom billing@supplier.vn Wed Oct 15 09:30:00 2025\r\n{}",
+            eml("Invoice-1", PNG_SIGNATURE, "one.png"),
+            eml("Invoice-2", b"%PDF-1.7\n%binary", "two.pdf")
+        );
+        assert!(is_mbox(mbox.as_bytes()));
+
+        let attachments = extract_attachments(mbox.as_bytes()).unwrap();
+
+        assert_eq!(attachments.len(), 2);
+        assert_eq!(attachments[0].source.subject.as_deref(), Some("Invoice-1"));
+        assert_eq!(attachments[1].file_name, "two.pdf");
+        assert_eq!(attachments[1].source.subject.as_deref(), Some("Invoice-2"));
+    }
+
+    #[test]
+    fn test_extract_attachments_rejects_non_email() {
+        assert!(extract_attachments(b"\x89PNG\r\n\x1a\n").is_err());
+    }
+}
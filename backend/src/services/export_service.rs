@@ -304,7 +304,8 @@ impl ExportService {
                 unit_price,
                 total_amount,
                 vat_rate,
-                vat_amount
+                vat_amount,
+                source_images
             FROM bills
             ORDER BY id ASC
             "#
@@ -0,0 +1,328 @@
+//! Extraction providers
+//!
+//! An extraction provider turns invoice images or text into bill candidates.
+//! The OCR pipeline only talks to the [`ExtractionProvider`] trait, so the
+//! backend that reads the invoices is chosen by configuration.
+
+use futures_util::future::BoxFuture;
+use std::{fmt, str::FromStr, sync::Arc};
+
+use crate::{
+    config::ExtractionConfig,
+    models::GeminiResponse,
+    services::{
+        gemini_service::{GeminiError, GeminiService},
+        openai_compatible_service::OpenAiCompatibleService,
+        tesseract_service::TesseractService,
+    },
+};
+
+/// Error of an extraction, independent of the provider that produced it
+#[derive(Debug, thiserror::Error)]
+pub enum ExtractionError {
+    #[error("Rate limit exceeded. Retry after: {retry_after:?} seconds")]
+    RateLimited { retry_after: Option<u64> },
+
+    #[error("Authentication failed")]
+    AuthenticationFailed,
+
+    #[error("Request timeout after {seconds} seconds")]
+    Timeout { seconds: u64 },
+
+    #[error("API error {status}: {message}")]
+    Api { status: u16, message: String },
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+
+    #[error("Provider unavailable: {0}")]
+    Unavailable(String),
+}
+
+impl From<GeminiError> for ExtractionError {
+    fn from(err: GeminiError) -> Self {
+        match err {
+            GeminiError::RateLimitExceeded { retry_after } => Self::RateLimited { retry_after },
+            GeminiError::AuthenticationFailed => Self::AuthenticationFailed,
+            GeminiError::Timeout { seconds } => Self::Timeout { seconds },
+            GeminiError::ApiError { status, message } => Self::Api { status, message },
+            GeminiError::ImageEncodingError(message) => Self::InvalidInput(message),
+            GeminiError::JsonError(e) => Self::InvalidResponse(e.to_string()),
+            GeminiError::InvalidResponseFormat(message) => Self::InvalidResponse(message),
+            GeminiError::RequestFailed(e) => Self::Unavailable(e.to_string()),
+            GeminiError::NetworkError(message) => Self::Unavailable(message),
+            e @ GeminiError::CassetteNotFound { .. } => Self::Unavailable(e.to_string()),
+            GeminiError::CassetteError(message) => Self::Unavailable(message),
+            GeminiError::Configuration(e) => Self::Unavailable(e.to_string()),
+        }
+    }
+}
+
+/// An invoice image handed to a provider
+#[derive(Debug, Clone, Copy)]
+pub struct ExtractionImage<'a> {
+    /// Image bytes to read, already preprocessed for the provider
+    pub data: &'a [u8],
+    /// SHA-256 of the uploaded file the image was prepared from, if known
+    pub source_sha256: Option<&'a str>,
+}
+
+impl<'a> ExtractionImage<'a> {
+    /// Image bytes without a known source
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            source_sha256: None,
+        }
+    }
+}
+
+/// Bill candidates read from one image, document or text
+pub type ExtractionResult = Result<Vec<GeminiResponse>, ExtractionError>;
+
+/// Backend that extracts bill candidates from invoices
+///
+/// Every method returns one `GeminiResponse` per invoice line found.
+pub trait ExtractionProvider: Send + Sync {
+    /// Kind of the provider, which also selects its image resize target
+    fn kind(&self) -> ExtractionProviderKind;
+
+    /// Extract the bills of one invoice image
+    fn extract_image<'a>(&'a self, image: ExtractionImage<'a>) -> BoxFuture<'a, ExtractionResult>;
+
+    /// Extract the bills of one invoice photographed as several pages
+    fn extract_pages<'a>(
+        &'a self,
+        pages: &'a [ExtractionImage<'a>],
+    ) -> BoxFuture<'a, ExtractionResult>;
+
+    /// Extract the bills of an invoice's text
+    fn extract_text<'a>(&'a self, text: &'a str) -> BoxFuture<'a, ExtractionResult>;
+
+    /// Check the provider's credentials and model at startup
+    fn verify(&self) -> BoxFuture<'_, Result<(), ExtractionError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Extraction provider shared by all processing tasks
+pub type SharedExtractionProvider = Arc<dyn ExtractionProvider>;
+
+/// Available extraction providers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExtractionProviderKind {
+    Gemini,
+    /// OpenAI-compatible chat completions endpoint of a self-hosted model
+    OpenAi,
+    /// Local Tesseract OCR with a rule-based invoice parser, needing no network
+    Tesseract,
+}
+
+impl ExtractionProviderKind {
+    pub const ALL: &'static [Self] = &[Self::Gemini, Self::OpenAi, Self::Tesseract];
+
+    /// Name used in configuration, e.g. `EXTRACTION_PROVIDER=gemini`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Gemini => "gemini",
+            Self::OpenAi => "openai",
+            Self::Tesseract => "tesseract",
+        }
+    }
+
+    /// Name shown in processing events
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Gemini => "Gemini API",
+            Self::OpenAi => "OpenAI-compatible API",
+            Self::Tesseract => "Tesseract OCR",
+        }
+    }
+}
+
+impl fmt::Display for ExtractionProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ExtractionProviderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.as_str() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(|kind| kind.as_str()).collect();
+                format!(
+                    "Unknown extraction provider '{}', expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Create the extraction provider selected by the configuration
+pub fn build_provider(
+    config: &ExtractionConfig,
+) -> Result<SharedExtractionProvider, ExtractionError> {
+    match config.provider {
+        ExtractionProviderKind::Gemini => {
+            let gemini = config.gemini.clone().ok_or_else(|| {
+                ExtractionError::Unavailable("Gemini API is not configured".to_string())
+            })?;
+            let service = GeminiService::new(gemini)?;
+            match config.gemini_cassette.clone() {
+                Some(cassette) => Ok(Arc::new(service.with_cassette(cassette))),
+                None => Ok(Arc::new(service)),
+            }
+        }
+        ExtractionProviderKind::OpenAi => {
+            let openai = config.openai.clone().ok_or_else(|| {
+                ExtractionError::Unavailable(
+                    "OpenAI-compatible endpoint is not configured".to_string(),
+                )
+            })?;
+            Ok(Arc::new(OpenAiCompatibleService::new(openai)?))
+        }
+        ExtractionProviderKind::Tesseract => {
+            let tesseract = config.tesseract.clone().ok_or_else(|| {
+                ExtractionError::Unavailable("Tesseract OCR is not configured".to_string())
+            })?;
+            let service = TesseractService::new(tesseract);
+            service.check_installation()?;
+            Ok(Arc::new(service))
+        }
+    }
+}
+
+impl ExtractionProvider for GeminiService {
+    fn kind(&self) -> ExtractionProviderKind {
+        ExtractionProviderKind::Gemini
+    }
+
+    fn extract_image<'a>(&'a self, image: ExtractionImage<'a>) -> BoxFuture<'a, ExtractionResult> {
+        Box::pin(async move { Ok(self.extract_bill_data(image).await?) })
+    }
+
+    fn extract_pages<'a>(
+        &'a self,
+        pages: &'a [ExtractionImage<'a>],
+    ) -> BoxFuture<'a, ExtractionResult> {
+        Box::pin(async move { Ok(self.extract_bill_data_from_pages(pages).await?) })
+    }
+
+    fn extract_text<'a>(&'a self, text: &'a str) -> BoxFuture<'a, ExtractionResult> {
+        Box::pin(async move { Ok(self.extract_bill_data_from_text(text).await?) })
+    }
+
+    fn verify(&self) -> BoxFuture<'_, Result<(), ExtractionError>> {
+        Box::pin(async move {
+            // Replayed responses need no API key
+            if self.is_replaying() {
+                return Ok(());
+            }
+            Ok(GeminiService::verify(self).await?)
+        })
+    }
+}
+
+impl ExtractionProvider for OpenAiCompatibleService {
+    fn kind(&self) -> ExtractionProviderKind {
+        ExtractionProviderKind::OpenAi
+    }
+
+    fn extract_image<'a>(&'a self, image: ExtractionImage<'a>) -> BoxFuture<'a, ExtractionResult> {
+        Box::pin(self.extract_bill_data(image.data))
+    }
+
+    fn extract_pages<'a>(
+        &'a self,
+        pages: &'a [ExtractionImage<'a>],
+    ) -> BoxFuture<'a, ExtractionResult> {
+        Box::pin(async move {
+            let pages: Vec<&[u8]> = pages.iter().map(|page| page.data).collect();
+            self.extract_bill_data_from_pages(&pages).await
+        })
+    }
+
+    fn extract_text<'a>(&'a self, text: &'a str) -> BoxFuture<'a, ExtractionResult> {
+        Box::pin(self.extract_bill_data_from_text(text))
+    }
+}
+
+impl ExtractionProvider for TesseractService {
+    fn kind(&self) -> ExtractionProviderKind {
+        ExtractionProviderKind::Tesseract
+    }
+
+    fn extract_image<'a>(&'a self, image: ExtractionImage<'a>) -> BoxFuture<'a, ExtractionResult> {
+        Box::pin(self.extract_bill_data(image.data))
+    }
+
+    fn extract_pages<'a>(
+        &'a self,
+        pages: &'a [ExtractionImage<'a>],
+    ) -> BoxFuture<'a, ExtractionResult> {
+        Box::pin(async move {
+            let pages: Vec<&[u8]> = pages.iter().map(|page| page.data).collect();
+            self.extract_bill_data_from_pages(&pages).await
+        })
+    }
+
+    fn extract_text<'a>(&'a self, text: &'a str) -> BoxFuture<'a, ExtractionResult> {
+        Box::pin(self.extract_bill_data_from_text(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_kind_from_str() {
+        assert_eq!(
+            "gemini".parse::<ExtractionProviderKind>(),
+            Ok(ExtractionProviderKind::Gemini)
+        );
+        assert_eq!(
+            " Gemini ".parse::<ExtractionProviderKind>(),
+            Ok(ExtractionProviderKind::Gemini)
+        );
+        assert_eq!(
+            "openai".parse::<ExtractionProviderKind>(),
+            Ok(ExtractionProviderKind::OpenAi)
+        );
+        assert!("unknown".parse::<ExtractionProviderKind>().is_err());
+    }
+
+    #[test]
+    fn test_gemini_error_conversion() {
+        assert!(matches!(
+            ExtractionError::from(GeminiError::RateLimitExceeded {
+                retry_after: Some(5)
+            }),
+            ExtractionError::RateLimited {
+                retry_after: Some(5)
+            }
+        ));
+        assert!(matches!(
+            ExtractionError::from(GeminiError::ApiError {
+                status: 500,
+                message: "boom".to_string()
+            }),
+            ExtractionError::Api { status: 500, .. }
+        ));
+        assert!(matches!(
+            ExtractionError::from(GeminiError::InvalidResponseFormat("x".to_string())),
+            ExtractionError::InvalidResponse(_)
+        ));
+    }
+}
@@ -0,0 +1,229 @@
+//! Record-and-replay cassettes of Gemini requests
+//!
+//! In record mode every response Gemini sends is saved, raw, to a fixture
+//! file named by the fingerprint of its request. In replay mode those files
+//! answer the requests instead of the API, so tests and demos of the OCR flow
+//! run offline and give the same result every time.
+//!
+//! The fingerprint covers the prompt, the response schema and, for each
+//! part, the SHA-256 of the uploaded file it was prepared from (or of the
+//! part itself when the source is unknown, as for text). Keying images on
+//! their source keeps a recording valid when the preprocessing that produces
+//! the bytes sent to Gemini changes; changing the prompt or schema still
+//! means re-recording.
+
+use base64::Engine;
+use chrono::Utc;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::{fmt, io, path::PathBuf, str::FromStr};
+
+use crate::{
+    models::{GeminiPart, GeminiRequest},
+    utils::image_hash::sha256_hex,
+};
+
+/// Whether requests are recorded to or replayed from the cassette directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Call the API and save each response
+    Record,
+    /// Answer requests from the saved responses, without calling the API
+    Replay,
+}
+
+impl fmt::Display for CassetteMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Record => "record",
+            Self::Replay => "replay",
+        })
+    }
+}
+
+impl FromStr for CassetteMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "record" => Ok(Self::Record),
+            "replay" => Ok(Self::Replay),
+            other => Err(format!(
+                "Unknown cassette mode '{}', expected record or replay",
+                other
+            )),
+        }
+    }
+}
+
+/// Directory of recorded Gemini responses
+#[derive(Debug, Clone)]
+pub struct GeminiCassette {
+    pub mode: CassetteMode,
+    pub dir: PathBuf,
+}
+
+impl GeminiCassette {
+    pub fn new(mode: CassetteMode, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            mode,
+            dir: dir.into(),
+        }
+    }
+
+    /// Fingerprint of a request: SHA-256 over the prompt, a hash of every
+    /// part and the response schema
+    pub fn fingerprint(request: &GeminiRequest, schema: &Value) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(request.prompt.as_bytes());
+        for part_hash in part_hashes(request) {
+            hasher.update(b"\n");
+            hasher.update(part_hash.as_bytes());
+        }
+        hasher.update(b"\n");
+        hasher.update(schema.to_string().as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    fn path(&self, fingerprint: &str) -> PathBuf {
+        self.dir.join(format!("{fingerprint}.json"))
+    }
+
+    /// Raw response recorded for a fingerprint, if any
+    pub async fn load(&self, fingerprint: &str) -> io::Result<Option<Value>> {
+        match tokio::fs::read(self.path(fingerprint)).await {
+            Ok(data) => {
+                let mut entry: Value = serde_json::from_slice(&data)?;
+                Ok(Some(entry["response"].take()))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Save the raw response of a request
+    ///
+    /// Besides the response the file lists the prompt and part hashes, so a
+    /// reader can tell which request it answers.
+    pub async fn save(
+        &self,
+        fingerprint: &str,
+        request: &GeminiRequest,
+        response: &Value,
+    ) -> io::Result<()> {
+        let entry = json!({
+            "fingerprint": fingerprint,
+            "recorded_at": Utc::now(),
+            "prompt": request.prompt,
+            "parts": part_hashes(request),
+            "response": response,
+        });
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(self.path(fingerprint), serde_json::to_vec_pretty(&entry)?).await
+    }
+}
+
+/// `source:<sha256>` of the uploaded file behind each part when known,
+/// otherwise `image:<sha256>` of the decoded bytes of an image and
+/// `text:<sha256>` of a text
+fn part_hashes(request: &GeminiRequest) -> Vec<String> {
+    let sources = request
+        .source_hashes
+        .iter()
+        .map(Option::as_deref)
+        .chain(std::iter::repeat(None));
+    request
+        .parts
+        .iter()
+        .zip(sources)
+        .map(|(part, source)| match (source, part) {
+            (Some(source), _) => format!("source:{source}"),
+            (None, GeminiPart::Text(text)) => format!("text:{}", sha256_hex(text.as_bytes())),
+            (None, GeminiPart::InlineData { data, .. }) => {
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .unwrap_or_else(|_| data.as_bytes().to_vec());
+                format!("image:{}", sha256_hex(&bytes))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_covers_image_prompt_and_schema() {
+        let schema = GeminiRequest::bill_response_schema();
+        let request = GeminiRequest::for_bill_extraction("aGVsbG8=".to_string());
+        let fingerprint = GeminiCassette::fingerprint(&request, &schema);
+
+        assert_eq!(
+            fingerprint,
+            GeminiCassette::fingerprint(
+                &GeminiRequest::for_bill_extraction("aGVsbG8=".to_string()),
+                &schema
+            )
+        );
+        let other_image = GeminiRequest::for_bill_extraction("d29ybGQ=".to_string());
+        assert_ne!(
+            fingerprint,
+            GeminiCassette::fingerprint(&other_image, &schema)
+        );
+        let other_prompt = GeminiRequest::new("Other prompt".to_string(), request.parts.clone());
+        assert_ne!(
+            fingerprint,
+            GeminiCassette::fingerprint(&other_prompt, &schema)
+        );
+        assert_ne!(
+            fingerprint,
+            GeminiCassette::fingerprint(&request, &json!({ "type": "array" }))
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_uses_source_hash_over_sent_bytes() {
+        let schema = GeminiRequest::bill_response_schema();
+        let source = || vec![Some("a".repeat(64))];
+        let request =
+            GeminiRequest::for_bill_extraction("aGVsbG8=".to_string()).with_source_hashes(source());
+
+        // Preprocessing the same upload differently keeps the fingerprint
+        let reencoded =
+            GeminiRequest::for_bill_extraction("d29ybGQ=".to_string()).with_source_hashes(source());
+        assert_eq!(
+            GeminiCassette::fingerprint(&request, &schema),
+            GeminiCassette::fingerprint(&reencoded, &schema)
+        );
+        let other_source = GeminiRequest::for_bill_extraction("aGVsbG8=".to_string())
+            .with_source_hashes(vec![Some("b".repeat(64))]);
+        assert_ne!(
+            GeminiCassette::fingerprint(&request, &schema),
+            GeminiCassette::fingerprint(&other_source, &schema)
+        );
+        assert_eq!(
+            part_hashes(&request),
+            vec![format!("source:{}", "a".repeat(64))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cassette = GeminiCassette::new(CassetteMode::Record, dir.path().join("cassettes"));
+        let request = GeminiRequest::for_text_extraction("Số: 42".to_string());
+        let response = json!({ "candidates": [{ "content": { "parts": [{ "text": "[]" }] } }] });
+
+        assert!(cassette.load("missing").await.unwrap().is_none());
+        cassette.save("abc", &request, &response).await.unwrap();
+        assert_eq!(cassette.load("abc").await.unwrap(), Some(response));
+    }
+
+    #[test]
+    fn test_mode_from_str() {
+        assert_eq!("Record".parse(), Ok(CassetteMode::Record));
+        assert_eq!("replay".parse(), Ok(CassetteMode::Replay));
+        assert!("off".parse::<CassetteMode>().is_err());
+    }
+}
@@ -10,8 +10,10 @@ use std::time::{Duration, Instant};
 use tokio::time::{sleep, timeout};
 use tracing::{debug, error, info, instrument, warn};
 
-use crate::models::{GeminiRequest, GeminiResponse};
-use crate::utils::env::get_gemini_api_key;
+use crate::config::{GeminiConfig, GeminiConfigError};
+use crate::models::{GeminiPart, GeminiRequest, GeminiResponse};
+use crate::services::extraction_provider::ExtractionImage;
+use crate::services::gemini_cassette::{CassetteMode, GeminiCassette};
 
 /// Error types for Gemini API operations
 #[derive(Debug, thiserror::Error)]
@@ -42,33 +44,15 @@ pub enum GeminiError {
 
     #[error("Network error: {0}")]
     NetworkError(String),
-}
 
-/// Gemini AI API service configuration
-#[derive(Debug, Clone)]
-pub struct GeminiConfig {
-    /// API base URL
-    pub base_url: String,
-    /// Request timeout in seconds
-    pub timeout_seconds: u64,
-    /// Maximum retry attempts for rate limiting
-    pub max_retries: u32,
-    /// Base delay between retries in milliseconds
-    pub retry_delay_ms: u64,
-    /// Model name to use for API calls
-    pub model: String,
-}
+    #[error("No cassette recorded for request {fingerprint}")]
+    CassetteNotFound { fingerprint: String },
 
-impl Default for GeminiConfig {
-    fn default() -> Self {
-        Self {
-            base_url: "https://generativelanguage.googleapis.com/v1beta".to_string(),
-            timeout_seconds: 30,
-            max_retries: 3,
-            retry_delay_ms: 1000,
-            model: "gemini-2.5-flash".to_string(), // Support response schema from v1beta
-        }
-    }
+    #[error("Cassette error: {0}")]
+    CassetteError(String),
+
+    #[error("Invalid configuration: {0}")]
+    Configuration(#[from] GeminiConfigError),
 }
 
 /// Service for interacting with Gemini AI API
@@ -79,22 +63,25 @@ pub struct GeminiService {
     client: Client,
     api_key: String,
     config: GeminiConfig,
+    cassette: Option<GeminiCassette>,
 }
 
 impl GeminiService {
     /// Create a new GeminiService instance
     ///
+    /// Creates the HTTP client once; the service is meant to be shared by
+    /// every extraction.
+    ///
     /// # Arguments
-    /// * `config` - Optional configuration for the service. Uses default if None.
+    /// * `config` - Validated configuration for the service
     ///
     /// # Returns
-    /// Result containing the GeminiService or an error if API key is missing
-    pub fn new(config: Option<GeminiConfig>) -> Result<Self, GeminiError> {
-        let api_key = get_gemini_api_key();
-        let config = config.unwrap_or_default();
+    /// Result containing the GeminiService or an error if the configuration is invalid
+    pub fn new(config: GeminiConfig) -> Result<Self, GeminiError> {
+        config.validate()?;
 
         let client = Client::builder()
-            .timeout(Duration::from_secs(config.timeout_seconds))
+            .timeout(config.timeout_duration())
             .build()
             .map_err(|e| {
                 GeminiError::NetworkError(format!("Failed to create HTTP client: {}", e))
@@ -102,28 +89,39 @@ impl GeminiService {
 
         Ok(Self {
             client,
-            api_key,
+            api_key: config.api_key.clone(),
             config,
+            cassette: None,
         })
     }
 
-    /// Create a new GeminiService with default configuration
-    pub fn with_default_config() -> Result<Self, GeminiError> {
-        Self::new(None)
+    /// Record responses to, or replay them from, a cassette
+    pub fn with_cassette(mut self, cassette: GeminiCassette) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    /// Whether requests are answered from a cassette instead of the API
+    pub fn is_replaying(&self) -> bool {
+        self.cassette
+            .as_ref()
+            .is_some_and(|cassette| cassette.mode == CassetteMode::Replay)
     }
 
     /// Extract bill data from image bytes
     ///
     /// # Arguments
-    /// * `image_data` - Raw image bytes (JPEG, PNG, etc.)
+    /// * `image` - Raw image bytes (JPEG, PNG, etc.) and the hash of the
+    ///   upload they came from
     ///
     /// # Returns
     /// Result containing extracted GeminiResponse or error
-    #[instrument(skip(self, image_data), fields(image_size = image_data.len()))]
+    #[instrument(skip(self, image), fields(image_size = image.data.len()))]
     pub async fn extract_bill_data(
         &self,
-        image_data: &[u8],
+        image: ExtractionImage<'_>,
     ) -> Result<Vec<GeminiResponse>, GeminiError> {
+        let image_data = image.data;
         let start_time = Instant::now();
         info!(
             "Starting Gemini bill data extraction for image of {} bytes",
@@ -139,7 +137,8 @@ impl GeminiService {
         );
 
         // Create the request with default Vietnamese bill extraction prompt
-        let request = GeminiRequest::for_bill_extraction(encoded_image);
+        let request = GeminiRequest::for_bill_extraction(encoded_image)
+            .with_source_hashes(vec![image.source_sha256.map(str::to_string)]);
         debug!("Created GeminiRequest with Vietnamese bill extraction prompt");
 
         // Send request to Gemini API with retry logic
@@ -172,6 +171,47 @@ impl GeminiService {
         }
     }
 
+    /// Extract bill data from the pages of one multi-page invoice
+    ///
+    /// All pages are sent in a single request, one `inlineData` part each, so
+    /// Gemini reads them as one document.
+    ///
+    /// # Arguments
+    /// * `pages` - Raw image bytes of each page, in order, with the hash of
+    ///   the upload each came from
+    ///
+    /// # Returns
+    /// Result containing extracted GeminiResponse or error
+    #[instrument(skip(self, pages), fields(page_count = pages.len()))]
+    pub async fn extract_bill_data_from_pages(
+        &self,
+        pages: &[ExtractionImage<'_>],
+    ) -> Result<Vec<GeminiResponse>, GeminiError> {
+        info!(
+            "Starting Gemini bill data extraction for a document of {} page(s)",
+            pages.len()
+        );
+
+        let encoded_pages = pages
+            .iter()
+            .map(|page| self.encode_image(page.data))
+            .collect::<Result<Vec<_>, _>>()?;
+        let request = GeminiRequest::for_document_extraction(encoded_pages).with_source_hashes(
+            pages
+                .iter()
+                .map(|page| page.source_sha256.map(str::to_string))
+                .collect(),
+        );
+        let responses = self.send_request_with_retry(&request).await?;
+        info!(
+            "Successfully extracted {} bill candidate(s) from {} page(s)",
+            responses.len(),
+            pages.len()
+        );
+
+        Ok(responses)
+    }
+
     /// Extract bill data with custom prompt
     ///
     /// # Arguments
@@ -186,10 +226,45 @@ impl GeminiService {
         custom_prompt: String,
     ) -> Result<Vec<GeminiResponse>, GeminiError> {
         let encoded_image = self.encode_image(image_data)?;
-        let request = GeminiRequest::new(encoded_image, custom_prompt);
+        let request = GeminiRequest::new(
+            custom_prompt,
+            vec![GeminiPart::InlineData {
+                mime_type: "image/jpeg".to_string(),
+                data: encoded_image,
+            }],
+        );
         self.send_request_with_retry(&request).await
     }
 
+    /// Extract bill data from the text of an invoice
+    ///
+    /// Used for PDF pages with a text layer, which need no OCR.
+    ///
+    /// # Arguments
+    /// * `text` - Invoice text
+    ///
+    /// # Returns
+    /// Result containing extracted GeminiResponse or error
+    #[instrument(skip(self, text), fields(text_length = text.len()))]
+    pub async fn extract_bill_data_from_text(
+        &self,
+        text: &str,
+    ) -> Result<Vec<GeminiResponse>, GeminiError> {
+        info!(
+            "Starting Gemini bill data extraction for {} characters of text",
+            text.chars().count()
+        );
+
+        let request = GeminiRequest::for_text_extraction(text.to_string());
+        let responses = self.send_request_with_retry(&request).await?;
+        info!(
+            "Successfully extracted {} bill candidate(s) from text",
+            responses.len()
+        );
+
+        Ok(responses)
+    }
+
     /// Send request to Gemini API with retry logic for rate limiting
     #[instrument(skip(self, request))]
     async fn send_request_with_retry(
@@ -253,106 +328,39 @@ impl GeminiService {
         request: &GeminiRequest,
     ) -> Result<Vec<GeminiResponse>, GeminiError> {
         let start_time = Instant::now();
-        let url = format!(
-            "{}/models/{}:generateContent",
-            self.config.base_url, self.config.model
-        );
+        let schema = GeminiRequest::bill_response_schema();
+        if let Some(cassette) = self
+            .cassette
+            .as_ref()
+            .filter(|cassette| cassette.mode == CassetteMode::Replay)
+        {
+            let fingerprint = GeminiCassette::fingerprint(request, &schema);
+            let response_json = cassette
+                .load(&fingerprint)
+                .await
+                .map_err(|e| GeminiError::CassetteError(e.to_string()))?
+                .ok_or_else(|| GeminiError::CassetteNotFound {
+                    fingerprint: fingerprint.clone(),
+                })?;
+            debug!("Replaying Gemini response {} from cassette", fingerprint);
+            return self.parse_gemini_response(response_json).await;
+        }
+
+        let url = self.config.generation_url();
         debug!("Sending request to Gemini API: {}", url);
 
         // Build the request payload according to Gemini API format with response schema
+        let mut parts = vec![json!({ "text": request.prompt })];
+        for part in &request.parts {
+            parts.push(serde_json::to_value(part)?);
+        }
         let payload = json!({
             "contents": [{
-                "parts": [
-                    {
-                        "text": request.prompt
-                    },
-                    {
-                        "inlineData": {
-                            "mimeType": "image/jpeg", // Assume JPEG for now
-                            "data": request.image_data
-                        }
-                    }
-                ]
+                "parts": parts
             }],
             "generationConfig": {
                 "responseMimeType": "application/json",
-                "responseSchema": {
-                    "type": "array",
-                    "description": "Danh sách các mục hóa đơn được trích xuất",
-                    "items": {
-                        "type": "object",
-                        "properties": {
-                            "form_no": {
-                                "type": "string",
-                                "description": "Mẫu số hóa đơn (ví dụ: 01-GTKT, 02-GTTT)"
-                            },
-                            "serial_no": {
-                                "type": "string",
-                                "description": "Ký hiệu hóa đơn (ví dụ: AA/24E, BB/25F)"
-                            },
-                            "invoice_no": {
-                                "type": "string",
-                                "description": "Số hóa đơn"
-                            },
-                            "issued_date": {
-                                "type": "string",
-                                "format": "date",
-                                "description": "Ngày lập hóa đơn (YYYY-MM-DD)"
-                            },
-                            "seller_name": {
-                                "type": "string",
-                                "description": "Tên người bán/công ty"
-                            },
-                            "seller_tax_code": {
-                                "type": "string",
-                                "description": "Mã số thuế của người bán"
-                            },
-                            "item_name": {
-                                "type": "string",
-                                "description": "Tên hàng hóa/dịch vụ"
-                            },
-                            "unit": {
-                                "type": "string",
-                                "description": "Đơn vị tính (ví dụ: cái, kg, giờ, m2)"
-                            },
-                            "quantity": {
-                                "type": "number",
-                                "description": "Số lượng hàng hóa/dịch vụ"
-                            },
-                            "unit_price": {
-                                "type": "number",
-                                "description": "Đơn giá (VND)"
-                            },
-                            "total_amount": {
-                                "type": "number",
-                                "description": "Thành tiền trước thuế (VND)"
-                            },
-                            "vat_rate": {
-                                "type": "number",
-                                "description": "Thuế suất VAT (%) - ví dụ: 0, 5, 8, 10"
-                            },
-                            "vat_amount": {
-                                "type": "number",
-                                "description": "Tiền thuế VAT (VND)"
-                            }
-                        },
-                        "required": [
-                            "form_no",
-                            "serial_no",
-                            "invoice_no",
-                            "issued_date",
-                            "seller_name",
-                            "seller_tax_code",
-                            "item_name",
-                            "unit",
-                            "quantity",
-                            "unit_price",
-                            "total_amount",
-                            "vat_rate",
-                            "vat_amount"
-                        ]
-                    }
-                }
+                "responseSchema": schema
             },
             "safetySettings": [
                 {
@@ -380,7 +388,7 @@ impl GeminiService {
             self.config.timeout_seconds
         );
         let response = timeout(
-            Duration::from_secs(self.config.timeout_seconds),
+            self.config.timeout_duration(),
             self.client
                 .post(&url)
                 .header("Content-Type", "application/json")
@@ -453,6 +461,18 @@ impl GeminiService {
         let api_duration = start_time.elapsed();
         debug!("Gemini API call completed in {:?}", api_duration);
 
+        if let Some(cassette) = self
+            .cassette
+            .as_ref()
+            .filter(|cassette| cassette.mode == CassetteMode::Record)
+        {
+            let fingerprint = GeminiCassette::fingerprint(request, &schema);
+            match cassette.save(&fingerprint, request, &response_json).await {
+                Ok(()) => debug!("Recorded Gemini response {} to cassette", fingerprint),
+                Err(e) => warn!("Failed to record Gemini response {}: {}", fingerprint, e),
+            }
+        }
+
         match self.parse_gemini_response(response_json).await {
             Ok(parsed_response) => {
                 info!("Successfully parsed Gemini response in {:?}", api_duration);
@@ -582,6 +602,14 @@ impl GeminiService {
             ));
         }
 
+        if image_data.len() as u64 > self.config.max_image_size_bytes() {
+            return Err(GeminiError::ImageEncodingError(format!(
+                "Image is {} bytes, larger than the {}MB limit",
+                image_data.len(),
+                self.config.max_image_size_mb
+            )));
+        }
+
         // Validate image format (basic check)
         if !self.is_valid_image_format(image_data) {
             return Err(GeminiError::ImageEncodingError(
@@ -611,6 +639,47 @@ impl GeminiService {
         false
     }
 
+    /// Check that the API key is accepted and the configured model exists
+    ///
+    /// Fetches the model's metadata, which costs no quota. Only a rejected key
+    /// or an unknown model fails; a network failure or any other status (such
+    /// as a transient 429 or 503) is only logged, so the server still starts
+    /// while the API is unreachable.
+    pub async fn verify(&self) -> Result<(), GeminiError> {
+        let response = match self
+            .client
+            .get(self.config.model_url())
+            .query(&[("key", &self.api_key)])
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Could not reach Gemini API to verify configuration: {}", e);
+                return Ok(());
+            }
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let error_text = response.text().await.unwrap_or_default();
+        if status == 401 || status == 403 || error_text.contains("API_KEY_INVALID") {
+            return Err(GeminiError::AuthenticationFailed);
+        }
+        if status == 404 {
+            return Err(GeminiError::Configuration(GeminiConfigError::Validation(
+                format!("model '{}' does not exist", self.config.model),
+            )));
+        }
+        warn!(
+            "Gemini API returned {} while verifying configuration: {}",
+            status, error_text
+        );
+        Ok(())
+    }
+
     /// Test the API connection with a simple request
     pub async fn test_connection(&self) -> Result<(), GeminiError> {
         // Create a minimal test image (1x1 PNG)
@@ -623,7 +692,13 @@ impl GeminiService {
         ];
 
         let encoded = self.encode_image(&test_image)?;
-        let test_request = GeminiRequest::new(encoded, "Describe this image briefly.".to_string());
+        let test_request = GeminiRequest::new(
+            "Describe this image briefly.".to_string(),
+            vec![GeminiPart::InlineData {
+                mime_type: "image/png".to_string(),
+                data: encoded,
+            }],
+        );
 
         match self.send_gemini_request(&test_request).await {
             Ok(_) => Ok(()),
@@ -653,7 +728,7 @@ mod tests {
 
     #[test]
     fn test_image_format_validation() {
-        let service = GeminiService::with_default_config().unwrap();
+        let service = GeminiService::new(GeminiConfig::new("test-key".to_string())).unwrap();
 
         // Test JPEG format
         let jpeg_data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A, 0x46];
@@ -673,7 +748,7 @@ mod tests {
 
     #[test]
     fn test_clean_json_response() {
-        let service = GeminiService::with_default_config().unwrap();
+        let service = GeminiService::new(GeminiConfig::new("test-key".to_string())).unwrap();
 
         // Test with markdown code blocks
         let markdown_json = "```json\n{\"test\": \"value\"}\n```";
@@ -706,7 +781,7 @@ mod tests {
 
     #[test]
     fn test_encode_image() {
-        let service = GeminiService::with_default_config().unwrap();
+        let service = GeminiService::new(GeminiConfig::new("test-key".to_string())).unwrap();
 
         // Test valid JPEG
         let jpeg_data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A, 0x46];
@@ -721,26 +796,171 @@ mod tests {
     }
 
     #[test]
-    fn test_default_config() {
-        let config = GeminiConfig::default();
-        assert_eq!(
-            config.base_url,
-            "https://generativelanguage.googleapis.com/v1beta"
+    fn test_encode_image_enforces_size_limit() {
+        let service = GeminiService::new(GeminiConfig {
+            max_image_size_mb: 1,
+            ..GeminiConfig::new("test-key".to_string())
+        })
+        .unwrap();
+
+        let mut jpeg_data = vec![0xFF, 0xD8, 0xFF, 0xE0];
+        jpeg_data.resize(1024 * 1024 + 1, 0);
+        assert!(matches!(
+            service.encode_image(&jpeg_data),
+            Err(GeminiError::ImageEncodingError(_))
+        ));
+    }
+
+    #[test]
+    fn test_new_rejects_invalid_config() {
+        let config = GeminiConfig {
+            model: "gemini pro".to_string(),
+            ..GeminiConfig::new("test-key".to_string())
+        };
+        assert!(matches!(
+            GeminiService::new(config),
+            Err(GeminiError::Configuration(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_verify_reports_invalid_key_and_model() {
+        use wiremock::{
+            Mock, MockServer, ResponseTemplate,
+            matchers::{method, path, query_param},
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/models/gemini-2.5-flash"))
+            .and(query_param("key", "test-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "name": "models/gemini-2.5-flash"
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/models/gemini-2.5-flash"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "error": { "status": "INVALID_ARGUMENT", "details": [{ "reason": "API_KEY_INVALID" }] }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/models/gemini-0"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/models/gemini-busy"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        let service = |api_key: &str, model: &str| {
+            GeminiService::new(GeminiConfig {
+                model: model.to_string(),
+                base_url: server.uri(),
+                ..GeminiConfig::new(api_key.to_string())
+            })
+            .unwrap()
+        };
+
+        assert!(
+            service("test-key", "gemini-2.5-flash")
+                .verify()
+                .await
+                .is_ok()
         );
-        assert_eq!(config.timeout_seconds, 30);
-        assert_eq!(config.max_retries, 3);
-        assert_eq!(config.model, "gemini-2.5-flash");
+        assert!(matches!(
+            service("wrong-key", "gemini-2.5-flash").verify().await,
+            Err(GeminiError::AuthenticationFailed)
+        ));
+        assert!(matches!(
+            service("test-key", "gemini-0").verify().await,
+            Err(GeminiError::Configuration(_))
+        ));
+        // A transient outage must not keep the server from starting
+        assert!(service("test-key", "gemini-busy").verify().await.is_ok());
     }
 
     #[tokio::test]
-    async fn test_service_creation() {
-        // This test requires GEMINI_API_KEY environment variable
-        // Skip if not available in test environment
-        if std::env::var("GEMINI_API_KEY").is_err() {
-            return;
-        }
+    async fn test_records_and_replays_cassette() {
+        use crate::services::gemini_cassette::{CassetteMode, GeminiCassette};
+        use crate::utils::image_hash::sha256_hex;
+        use wiremock::{
+            Mock, MockServer, ResponseTemplate,
+            matchers::{method, path},
+        };
 
-        let service = GeminiService::with_default_config();
-        assert!(service.is_ok());
+        let image = std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../test_images/bill_1.jpg"
+        ))
+        .unwrap();
+        let bills = json!([{
+            "form_no": "01GTKT0/001", "serial_no": "AA/24E", "invoice_no": "0000123",
+            "issued_date": "2024-03-15", "seller_name": "Công ty ABC",
+            "seller_tax_code": "0312345678", "item_name": "Giấy A4", "unit": "ram",
+            "quantity": 2, "unit_price": 65000, "total_amount": 130000,
+            "vat_rate": 10, "vat_amount": 13000
+        }]);
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/models/gemini-2.5-flash:generateContent"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "candidates": [{ "content": { "parts": [{ "text": bills.to_string() }] } }]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+
+        let recorder = GeminiService::new(GeminiConfig {
+            base_url: server.uri(),
+            ..GeminiConfig::new("test-key".to_string())
+        })
+        .unwrap()
+        .with_cassette(GeminiCassette::new(CassetteMode::Record, dir.path()));
+        let source_sha256 = sha256_hex(&image);
+        let recorded = recorder
+            .extract_bill_data(ExtractionImage {
+                data: &image,
+                source_sha256: Some(&source_sha256),
+            })
+            .await
+            .unwrap();
+
+        // Nothing listens on the replaying service's base URL
+        let replayer = GeminiService::new(GeminiConfig {
+            base_url: "http://127.0.0.1:9".to_string(),
+            ..GeminiConfig::default()
+        })
+        .unwrap()
+        .with_cassette(GeminiCassette::new(CassetteMode::Replay, dir.path()));
+        // A different preprocessing of the same upload replays the recording
+        let mut reencoded = Vec::new();
+        image::load_from_memory(&image)
+            .unwrap()
+            .write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(
+                &mut reencoded,
+                50,
+            ))
+            .unwrap();
+        assert_ne!(reencoded, image);
+        let replayed = replayer
+            .extract_bill_data(ExtractionImage {
+                data: &reencoded,
+                source_sha256: Some(&source_sha256),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(replayed[0].invoice_no, recorded[0].invoice_no);
+        assert_eq!(replayed[0].total_amount, Some(130000.0));
+        assert!(matches!(
+            replayer.extract_bill_data_from_text("Số: 42").await,
+            Err(GeminiError::CassetteNotFound { .. })
+        ));
     }
-}
\ No newline at end of file
+}
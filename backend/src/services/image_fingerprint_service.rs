@@ -0,0 +1,251 @@
+use crate::api::ApiError;
+use crate::models::{DuplicateMatch, ImageFingerprint};
+use crate::services::document_grouping::InvoiceKey;
+use crate::utils::image_hash::{band_neighbours, perceptual_hash, sha256_hex};
+use sqlx::PgPool;
+
+/// Hash an uploaded image
+///
+/// Fails when the bytes cannot be decoded as an image.
+pub fn fingerprint_image(data: &[u8]) -> Result<ImageFingerprint, image::ImageError> {
+    let image = image::load_from_memory(data)?;
+    Ok(ImageFingerprint {
+        sha256: sha256_hex(data),
+        perceptual_hash: perceptual_hash(&image.to_luma8()),
+    })
+}
+
+/// Stores the fingerprints of the images bills were extracted from
+///
+/// A draft's fingerprint moves to its bill when the draft is committed.
+pub struct ImageFingerprintService {
+    pool: PgPool,
+}
+
+impl ImageFingerprintService {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Find bills and staged drafts extracted from the same image, or from one
+    /// whose perceptual hash differs in at most `max_distance` bits
+    ///
+    /// Near duplicates are narrowed down with the indexed 16-bit bands of the
+    /// stored hashes before their distance is computed; see
+    /// [`band_neighbours`]. The candidates grow quickly with the distance: a
+    /// `max_distance` of 12 or more looks up hundreds of values per band, and
+    /// the lookup comes close to comparing every stored hash.
+    pub async fn find_duplicates(
+        &self,
+        fingerprint: &ImageFingerprint,
+        max_distance: u32,
+    ) -> Result<Option<DuplicateMatch>, ApiError> {
+        let [band_0, band_1, band_2, band_3] =
+            band_neighbours(fingerprint.perceptual_hash, max_distance)
+                .map(|values| values.into_iter().map(i32::from).collect::<Vec<_>>());
+        let rows = sqlx::query!(
+            r#"
+            SELECT bill_id, draft_id, sha256 = $1 AS "exact!"
+            FROM image_fingerprints
+            WHERE sha256 = $1
+               OR ((hash_band_0 = ANY($3)
+                    OR hash_band_1 = ANY($4)
+                    OR hash_band_2 = ANY($5)
+                    OR hash_band_3 = ANY($6))
+                   AND bit_count((perceptual_hash # $2)::bit(64)) <= $7)
+            "#,
+            fingerprint.sha256,
+            fingerprint.perceptual_hash as i64,
+            &band_0,
+            &band_1,
+            &band_2,
+            &band_3,
+            i64::from(max_distance)
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| ApiError::InternalServerError(format!("Database error: {e}")))?;
+
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        let exact = rows.iter().any(|row| row.exact);
+        let mut bill_ids: Vec<i32> = rows.iter().filter_map(|row| row.bill_id).collect();
+        bill_ids.sort_unstable();
+        bill_ids.dedup();
+        let mut draft_ids: Vec<i32> = rows.iter().filter_map(|row| row.draft_id).collect();
+        draft_ids.sort_unstable();
+        draft_ids.dedup();
+        Ok(Some(DuplicateMatch {
+            exact,
+            bill_ids,
+            draft_ids,
+        }))
+    }
+
+    /// Invoice keys of bills and drafts that have a serial and number
+    ///
+    /// Tells a re-upload of a saved invoice from a new invoice printed on the
+    /// same layout, whose images hash alike.
+    pub async fn invoice_keys(
+        &self,
+        bill_ids: &[i32],
+        draft_ids: &[i32],
+    ) -> Result<Vec<InvoiceKey>, ApiError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT seller_tax_code, serial_no, invoice_no
+            FROM bills
+            WHERE id = ANY($1)
+            UNION ALL
+            SELECT seller_tax_code, serial_no, invoice_no
+            FROM bill_drafts
+            WHERE id = ANY($2)
+            "#,
+            bill_ids,
+            draft_ids
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| ApiError::InternalServerError(format!("Database error: {e}")))?;
+
+        Ok(rows
+            .iter()
+            .filter_map(|row| {
+                InvoiceKey::new(
+                    row.seller_tax_code.as_deref(),
+                    row.serial_no.as_deref(),
+                    row.invoice_no.as_deref(),
+                )
+            })
+            .collect())
+    }
+
+    /// Record the image a saved bill was extracted from
+    pub async fn record_for_bill(
+        &self,
+        fingerprint: &ImageFingerprint,
+        bill_id: i32,
+    ) -> Result<(), ApiError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO image_fingerprints (sha256, perceptual_hash, bill_id)
+            VALUES ($1, $2, $3)
+            "#,
+            fingerprint.sha256,
+            fingerprint.perceptual_hash as i64,
+            bill_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| ApiError::InternalServerError(format!("Database error: {e}")))?;
+
+        Ok(())
+    }
+
+    /// Record the image a staged draft was extracted from
+    pub async fn record_for_draft(
+        &self,
+        fingerprint: &ImageFingerprint,
+        draft_id: i32,
+    ) -> Result<(), ApiError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO image_fingerprints (sha256, perceptual_hash, draft_id)
+            VALUES ($1, $2, $3)
+            "#,
+            fingerprint.sha256,
+            fingerprint.perceptual_hash as i64,
+            draft_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| ApiError::InternalServerError(format!("Database error: {e}")))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(sha256: &str, perceptual_hash: u64) -> ImageFingerprint {
+        ImageFingerprint {
+            sha256: sha256.to_string(),
+            perceptual_hash,
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_drafts_and_near_duplicates_are_found(pool: PgPool) {
+        let bill_id = sqlx::query_scalar!(
+            "INSERT INTO bills (serial_no, invoice_no) VALUES ('C26TAA', '1') RETURNING id"
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        sqlx::query!("INSERT INTO ocr_jobs (id) VALUES ('job-1')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let draft_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO bill_drafts (session_id, file_index, serial_no, invoice_no)
+            VALUES ('job-1', 0, 'C26TAA', '2')
+            RETURNING id
+            "#
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        let service = ImageFingerprintService::new(pool);
+        let hash = 0x0123_4567_89ab_cdef;
+        service
+            .record_for_bill(&fingerprint("bill", hash), bill_id)
+            .await
+            .unwrap();
+        service
+            .record_for_draft(
+                &fingerprint("draft", hash ^ 0xffff_0000_0000_0000),
+                draft_id,
+            )
+            .await
+            .unwrap();
+
+        // A staged draft counts as an earlier upload of its image
+        let duplicate = service
+            .find_duplicates(&fingerprint("draft", 0), 0)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(duplicate.exact);
+        assert!(duplicate.bill_ids.is_empty());
+        assert_eq!(duplicate.draft_ids, vec![draft_id]);
+
+        // Six bits away from the bill, spread over all four bands
+        let near = hash ^ 0b11 ^ (0b11 << 16) ^ (1 << 32) ^ (1 << 48);
+        let duplicate = service
+            .find_duplicates(&fingerprint("near", near), 6)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!duplicate.exact);
+        assert_eq!(duplicate.bill_ids, vec![bill_id]);
+        assert!(duplicate.draft_ids.is_empty());
+        assert!(
+            service
+                .find_duplicates(&fingerprint("near", near), 5)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let keys = service
+            .invoice_keys(&duplicate.bill_ids, &[draft_id])
+            .await
+            .unwrap();
+        assert_eq!(keys.len(), 2);
+    }
+}
@@ -0,0 +1,217 @@
+//! Content-addressed store of the original uploaded images
+//!
+//! Every image is kept once, under the hex SHA-256 of its bytes, so the same
+//! photo uploaded twice takes no extra space. Bills record the keys of the
+//! images they were extracted from. Thumbnails are generated on first request
+//! and cached next to the originals. Uploaded PDFs are kept too, but have no
+//! thumbnail.
+
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use image::{DynamicImage, ImageDecoder, ImageReader, metadata::Orientation};
+use tracing::debug;
+
+use crate::{
+    config::ImageStoreConfig, services::pdf_extraction::is_pdf, utils::image_hash::sha256_hex,
+};
+
+/// Subdirectory of the store holding generated thumbnails
+const THUMBNAIL_SUBDIR: &str = "thumbnails";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImageStoreError {
+    #[error("Image store I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid image key: {0}")]
+    InvalidKey(String),
+
+    #[error("Failed to create thumbnail: {0}")]
+    Thumbnail(#[from] image::ImageError),
+
+    #[error("{0} is a PDF, which has no thumbnail")]
+    NotAnImage(String),
+}
+
+/// Filesystem store of original images, keyed by content hash
+#[derive(Debug, Clone)]
+pub struct ImageStore {
+    dir: PathBuf,
+    thumbnail_edge: u32,
+}
+
+impl ImageStore {
+    /// Open the store, creating its directory if needed
+    pub async fn open(config: &ImageStoreConfig) -> Result<Self, ImageStoreError> {
+        tokio::fs::create_dir_all(&config.dir).await?;
+        Ok(Self {
+            dir: config.dir.clone(),
+            thumbnail_edge: config.thumbnail_edge,
+        })
+    }
+
+    /// Whether `key` is a hex SHA-256, the only form of key the store hands out
+    pub fn is_valid_key(key: &str) -> bool {
+        key.len() == 64 && key.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    }
+
+    /// Store an image and return its key; storing the same bytes again is a no-op
+    pub async fn put(&self, data: &[u8]) -> Result<String, ImageStoreError> {
+        let key = sha256_hex(data);
+        let path = self.original_path(&key);
+        if !tokio::fs::try_exists(&path).await? {
+            write_atomically(&path, data).await?;
+            debug!("Stored image {} ({} bytes)", key, data.len());
+        }
+        Ok(key)
+    }
+
+    /// The original image stored under `key`, if any
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, ImageStoreError> {
+        self.check_key(key)?;
+        read_if_exists(&self.original_path(key)).await
+    }
+
+    /// A JPEG thumbnail of the image stored under `key`, if any
+    ///
+    /// Fails with [`ImageStoreError::NotAnImage`] when a PDF is stored there.
+    pub async fn thumbnail(&self, key: &str) -> Result<Option<Vec<u8>>, ImageStoreError> {
+        self.check_key(key)?;
+        let path = self.thumbnail_path(key);
+        if let Some(thumbnail) = read_if_exists(&path).await? {
+            return Ok(Some(thumbnail));
+        }
+        let Some(original) = self.get(key).await? else {
+            return Ok(None);
+        };
+        if is_pdf(&original) {
+            return Err(ImageStoreError::NotAnImage(key.to_string()));
+        }
+
+        let edge = self.thumbnail_edge;
+        let thumbnail = tokio::task::spawn_blocking(move || make_thumbnail(&original, edge))
+            .await
+            .map_err(std::io::Error::other)??;
+        write_atomically(&path, &thumbnail).await?;
+        Ok(Some(thumbnail))
+    }
+
+    fn check_key(&self, key: &str) -> Result<(), ImageStoreError> {
+        if Self::is_valid_key(key) {
+            Ok(())
+        } else {
+            Err(ImageStoreError::InvalidKey(key.to_string()))
+        }
+    }
+
+    /// Originals are spread over subdirectories named by the first two hex digits
+    fn original_path(&self, key: &str) -> PathBuf {
+        self.dir.join(&key[..2]).join(key)
+    }
+
+    fn thumbnail_path(&self, key: &str) -> PathBuf {
+        self.dir
+            .join(THUMBNAIL_SUBDIR)
+            .join(self.thumbnail_edge.to_string())
+            .join(&key[..2])
+            .join(format!("{key}.jpg"))
+    }
+}
+
+/// Scale an image down to fit `edge` x `edge`, upright, as JPEG
+pub fn make_thumbnail(data: &[u8], edge: u32) -> Result<Vec<u8>, image::ImageError> {
+    let mut decoder = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    let thumbnail = DynamicImage::ImageRgb8(image.thumbnail(edge, edge).to_rgb8());
+    let mut buffer = Cursor::new(Vec::new());
+    thumbnail.write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(
+        &mut buffer,
+        85,
+    ))?;
+    Ok(buffer.into_inner())
+}
+
+async fn read_if_exists(path: &Path) -> Result<Option<Vec<u8>>, ImageStoreError> {
+    match tokio::fs::read(path).await {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write through a temporary file so readers never see a partial image
+async fn write_atomically(path: &Path, data: &[u8]) -> Result<(), ImageStoreError> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let temp_path = path.with_extension(format!("tmp-{}", uuid::Uuid::new_v4()));
+    tokio::fs::write(&temp_path, data).await?;
+    tokio::fs::rename(&temp_path, path).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = image::RgbImage::from_pixel(width, height, image::Rgb([200, 30, 30]));
+        let mut buffer = Cursor::new(Vec::new());
+        image
+            .write_to(&mut buffer, image::ImageFormat::Png)
+            .unwrap();
+        buffer.into_inner()
+    }
+
+    async fn open_store(dir: &Path) -> ImageStore {
+        ImageStore::open(&ImageStoreConfig {
+            dir: dir.to_path_buf(),
+            thumbnail_edge: 64,
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_put_is_content_addressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open_store(dir.path()).await;
+        let data = png(40, 20);
+
+        let key = store.put(&data).await.unwrap();
+        assert_eq!(store.put(&data).await.unwrap(), key);
+        assert_eq!(key, sha256_hex(&data));
+        assert_eq!(store.get(&key).await.unwrap(), Some(data));
+
+        let missing = "0".repeat(64);
+        assert_eq!(store.get(&missing).await.unwrap(), None);
+        assert!(matches!(
+            store.get("../etc/passwd").await,
+            Err(ImageStoreError::InvalidKey(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_thumbnail_fits_edge_and_is_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open_store(dir.path()).await;
+        let key = store.put(&png(400, 200)).await.unwrap();
+
+        let thumbnail = store.thumbnail(&key).await.unwrap().unwrap();
+        let decoded = image::load_from_memory(&thumbnail).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (64, 32));
+        assert!(store.thumbnail_path(&key).exists());
+
+        let pdf = store.put(b"%PDF-1.7\n%%EOF\n").await.unwrap();
+        assert!(matches!(
+            store.thumbnail(&pdf).await,
+            Err(ImageStoreError::NotAnImage(_))
+        ));
+    }
+}
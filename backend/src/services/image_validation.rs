@@ -1,6 +1,47 @@
 use crate::errors::UploadError;
+use crate::services::einvoice_xml::{XML_CONTENT_TYPE, is_xml, parse_einvoice};
+use crate::services::pdf_extraction::{is_pdf, page_count};
 use tracing::{debug, error, warn};
 
+/// Content type reported for validated PDF documents
+pub const PDF_CONTENT_TYPE: &str = "application/pdf";
+
+/// Validate an uploaded file as an image, a PDF document or an e-invoice XML file,
+/// returning its content type
+///
+/// PDFs must be readable, not password protected, and have at most `max_pdf_pages` pages.
+/// XML files must have the e-invoice structure.
+pub async fn validate_upload_format(
+    data: &[u8],
+    max_pdf_pages: usize,
+) -> Result<String, UploadError> {
+    if is_xml(data) {
+        parse_einvoice(data).map_err(|e| {
+            warn!("E-invoice XML validation failed: {}", e);
+            UploadError::InvalidEInvoice(e.to_string())
+        })?;
+        return Ok(XML_CONTENT_TYPE.to_string());
+    }
+    if !is_pdf(data) {
+        return validate_image_format(data).await;
+    }
+
+    let pages = page_count(data).map_err(|e| {
+        warn!("PDF validation failed: {}", e);
+        UploadError::InvalidPdf(e.to_string())
+    })?;
+    if pages > max_pdf_pages {
+        warn!("PDF has {} pages, limit is {}", pages, max_pdf_pages);
+        return Err(UploadError::PdfPageCountExceeded {
+            count: pages,
+            limit: max_pdf_pages,
+        });
+    }
+
+    debug!("PDF successfully parsed: {} page(s)", pages);
+    Ok(PDF_CONTENT_TYPE.to_string())
+}
+
 pub async fn validate_image_format(data: &[u8]) -> Result<String, UploadError> {
     debug!("Starting image format validation for {} bytes", data.len());
 
@@ -0,0 +1,492 @@
+//! Invoice QR code cross-check
+//!
+//! Vietnamese e-invoices print a QR code carrying the seller's tax code, the
+//! invoice serial and number, the issue date and the amount due. The code is
+//! decoded from the original upload, before resizing, and compared with what
+//! Gemini read from the same image: where they disagree the QR value is kept
+//! and the difference is reported as a `QrDiscrepancy`.
+//!
+//! Issuers lay the code out differently, so the content is parsed
+//! tolerantly: `key=value` pairs (`mst=...&khhdon=...`, also inside a lookup
+//! URL) or plain values separated by `|`, `;` or new lines, recognised by
+//! their shape.
+
+use axum::body::Bytes;
+use chrono::NaiveDate;
+use image::GrayImage;
+use rust_decimal::Decimal;
+use tracing::{debug, info, warn};
+
+use crate::{
+    models::{GeminiResponse, QrDiscrepancy},
+    services::bill_extractor::BillDataExtractor,
+};
+
+/// Difference in the amount due ignored as rounding
+const TOTAL_TOLERANCE: Decimal = Decimal::ONE;
+
+/// Prefix of EMVCo payment codes (VietQR), which are not invoice codes
+const PAYMENT_QR_PREFIX: &str = "000201";
+
+/// Invoice fields read from a QR code
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InvoiceQrData {
+    pub seller_tax_code: Option<String>,
+    pub serial_no: Option<String>,
+    pub invoice_no: Option<String>,
+    pub issued_date: Option<NaiveDate>,
+    /// Amount due, VAT included
+    pub total_amount: Option<Decimal>,
+}
+
+impl InvoiceQrData {
+    fn field_count(&self) -> usize {
+        [
+            self.seller_tax_code.is_some(),
+            self.serial_no.is_some(),
+            self.invoice_no.is_some(),
+            self.issued_date.is_some(),
+            self.total_amount.is_some(),
+        ]
+        .into_iter()
+        .filter(|present| *present)
+        .count()
+    }
+}
+
+/// Decode the invoice QR code of an uploaded image, if it has one
+///
+/// Decoding runs on a blocking thread; images that cannot be decoded or
+/// carry no invoice code yield `None`.
+pub async fn read_invoice_qr(data: Bytes, file_index: usize) -> Option<InvoiceQrData> {
+    let result = tokio::task::spawn_blocking(move || {
+        let image = image::load_from_memory(&data).ok()?.to_luma8();
+        decode_qr_codes(image)
+            .iter()
+            .find_map(|text| parse_invoice_qr(text))
+    })
+    .await;
+
+    match result {
+        Ok(qr) => {
+            if let Some(qr) = &qr {
+                info!(
+                    "Read invoice QR code for file index {}: {:?}",
+                    file_index, qr
+                );
+            }
+            qr
+        }
+        Err(e) => {
+            warn!(
+                "QR decoding task failed for file index {}: {}",
+                file_index, e
+            );
+            None
+        }
+    }
+}
+
+/// Decode the QR codes found in an image, skipping those that cannot be read
+///
+/// This is CPU-bound; call it from a blocking task.
+fn decode_qr_codes(image: GrayImage) -> Vec<String> {
+    let mut image = rqrr::PreparedImage::prepare(image);
+    image
+        .detect_grids()
+        .into_iter()
+        .filter_map(|grid| match grid.decode() {
+            Ok((_, content)) => Some(content),
+            Err(e) => {
+                debug!("Skipping unreadable QR code: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Parse the content of an invoice QR code
+///
+/// Returns `None` for payment codes and for content in which fewer than two
+/// invoice fields can be recognised.
+pub fn parse_invoice_qr(text: &str) -> Option<InvoiceQrData> {
+    let text = text.trim();
+    if text.starts_with(PAYMENT_QR_PREFIX) {
+        return None;
+    }
+    // Lookup URLs carry the fields in their query string
+    let text = match text.split_once('?') {
+        Some((base, query)) if base.starts_with("http") => query,
+        _ => text,
+    };
+
+    let extractor = BillDataExtractor::new();
+    let mut qr = InvoiceQrData::default();
+    let tokens = text
+        .split(['|', ';', '&', '\n', '\r', '\t'])
+        .map(str::trim)
+        .filter(|token| !token.is_empty());
+
+    for token in tokens {
+        if let Some((key, value)) = token.split_once('=') {
+            read_keyed_value(&mut qr, key, value.trim(), &extractor);
+        } else {
+            read_positional_value(&mut qr, token, &extractor);
+        }
+    }
+
+    debug!("Parsed invoice QR content {:?} as {:?}", text, qr);
+    (qr.field_count() >= 2).then_some(qr)
+}
+
+fn read_keyed_value(qr: &mut InvoiceQrData, key: &str, value: &str, extractor: &BillDataExtractor) {
+    let key: String = key
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match key.as_str() {
+        "mst" | "mstnban" | "taxcode" | "sellertaxcode" => {
+            qr.seller_tax_code = is_tax_code(value).then(|| value.to_string());
+        }
+        "khhdon" | "kyhieu" | "serial" | "serialno" => qr.serial_no = parse_serial(value),
+        "shdon" | "sohoadon" | "invoiceno" | "so" => {
+            qr.invoice_no = is_invoice_no(value).then(|| value.to_string());
+        }
+        "nlap" | "ngaylap" | "date" | "issueddate" => qr.issued_date = parse_date(value, extractor),
+        "tgtttbso" | "tongtien" | "total" | "amount" => {
+            qr.total_amount = extractor.parse_vietnamese_amount(value).ok();
+        }
+        _ => {}
+    }
+}
+
+/// Recognise an unlabelled value by its shape
+///
+/// Values are expected in the usual order: tax code, serial, number, date,
+/// amount. The first run of at most 8 digits is the invoice number and the
+/// last number the amount due.
+fn read_positional_value(qr: &mut InvoiceQrData, token: &str, extractor: &BillDataExtractor) {
+    if qr.seller_tax_code.is_none() && is_tax_code(token) {
+        qr.seller_tax_code = Some(token.to_string());
+    } else if let Some(serial) = qr
+        .serial_no
+        .is_none()
+        .then(|| parse_serial(token))
+        .flatten()
+    {
+        qr.serial_no = Some(serial);
+    } else if let Some(date) = qr
+        .issued_date
+        .is_none()
+        .then(|| parse_date(token, extractor))
+        .flatten()
+    {
+        qr.issued_date = Some(date);
+    } else if qr.invoice_no.is_none() && is_invoice_no(token) {
+        qr.invoice_no = Some(token.to_string());
+    } else if token
+        .chars()
+        .all(|c| c.is_ascii_digit() || c == '.' || c == ',')
+    {
+        let amount = extractor.parse_vietnamese_amount(token).ok();
+        qr.total_amount = amount.or(qr.total_amount);
+    }
+}
+
+/// 10-digit tax code, or 13 with a branch suffix (`0312345678-001`)
+fn is_tax_code(value: &str) -> bool {
+    let (main, branch) = value.split_once('-').unwrap_or((value, ""));
+    main.len() == 10
+        && main.chars().all(|c| c.is_ascii_digit())
+        && (branch.is_empty() || (branch.len() == 3 && branch.chars().all(|c| c.is_ascii_digit())))
+}
+
+fn is_invoice_no(value: &str) -> bool {
+    (1..=8).contains(&value.len()) && value.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Serial such as `C25TAA`, dropping the form number some issuers put in front (`1C25TAA`)
+fn parse_serial(value: &str) -> Option<String> {
+    let value = value.to_ascii_uppercase();
+    let serial = match value.as_bytes() {
+        [form, rest @ ..] if rest.len() == 6 && form.is_ascii_digit() => &value[1..],
+        _ => value.as_str(),
+    };
+    let bytes = serial.as_bytes();
+    let valid = bytes.len() == 6
+        && matches!(bytes[0], b'C' | b'K')
+        && bytes[1..3].iter().all(u8::is_ascii_digit)
+        && bytes[3..].iter().all(u8::is_ascii_alphabetic);
+    valid.then(|| serial.to_string())
+}
+
+fn parse_date(value: &str, extractor: &BillDataExtractor) -> Option<NaiveDate> {
+    // Timestamps such as 2025-10-14T09:30:00 keep only their date
+    let date = value.split(['T', ' ']).next().unwrap_or(value);
+    if !date.contains(['/', '-', '.']) {
+        return None;
+    }
+    extractor.parse_vietnamese_date(date).ok()
+}
+
+/// Cross-check Gemini's candidates against the invoice QR code
+///
+/// Header fields that differ are replaced with the QR value in every
+/// candidate, and missing ones are filled in. The amount due is compared
+/// with the sum of the candidates' totals and VAT; it is corrected only when
+/// there is a single candidate, since the difference cannot be attributed to
+/// one line otherwise. Returns one discrepancy per field that differed.
+pub fn reconcile(responses: &mut [GeminiResponse], qr: &InvoiceQrData) -> Vec<QrDiscrepancy> {
+    let extractor = BillDataExtractor::new();
+    let mut discrepancies = Vec::new();
+
+    if let Some(qr_value) = &qr.seller_tax_code {
+        reconcile_field(
+            responses,
+            "seller_tax_code",
+            qr_value,
+            |response| &mut response.seller_tax_code,
+            |ocr, qr| ocr.replace([' ', '.'], "") == qr,
+            &mut discrepancies,
+        );
+    }
+    if let Some(qr_value) = &qr.serial_no {
+        reconcile_field(
+            responses,
+            "serial_no",
+            qr_value,
+            |response| &mut response.serial_no,
+            |ocr, qr| parse_serial(ocr.trim()).is_some_and(|serial| serial == qr),
+            &mut discrepancies,
+        );
+    }
+    if let Some(qr_value) = &qr.invoice_no {
+        reconcile_field(
+            responses,
+            "invoice_no",
+            qr_value,
+            |response| &mut response.invoice_no,
+            |ocr, qr| ocr.trim().trim_start_matches('0') == qr.trim_start_matches('0'),
+            &mut discrepancies,
+        );
+    }
+
+    if let Some(qr_date) = qr.issued_date {
+        let qr_value = qr_date.format("%d/%m/%Y").to_string();
+        reconcile_field(
+            responses,
+            "issued_date",
+            &qr_value,
+            |response| &mut response.issued_date,
+            |ocr, _| extractor.parse_vietnamese_date(ocr).ok() == Some(qr_date),
+            &mut discrepancies,
+        );
+    }
+
+    if let Some(qr_total) = qr.total_amount {
+        let to_decimal = |amount: Option<f64>| {
+            amount
+                .and_then(|amount| Decimal::try_from(amount).ok())
+                .unwrap_or_default()
+        };
+        let ocr_total: Decimal = responses
+            .iter()
+            .map(|response| to_decimal(response.total_amount) + to_decimal(response.vat_amount))
+            .sum();
+        if !responses.is_empty() && (ocr_total - qr_total).abs() > TOTAL_TOLERANCE {
+            discrepancies.push(QrDiscrepancy {
+                field: "total_amount".to_string(),
+                ocr_value: ocr_total.normalize().to_string(),
+                qr_value: qr_total.normalize().to_string(),
+            });
+            if let [response] = responses {
+                let corrected = qr_total - to_decimal(response.vat_amount);
+                response.total_amount = corrected.try_into().ok();
+            }
+        }
+    }
+
+    discrepancies
+}
+
+/// Replace a header field that differs from the QR code in every candidate
+fn reconcile_field(
+    responses: &mut [GeminiResponse],
+    field: &str,
+    qr_value: &str,
+    accessor: fn(&mut GeminiResponse) -> &mut Option<String>,
+    same: impl Fn(&str, &str) -> bool,
+    discrepancies: &mut Vec<QrDiscrepancy>,
+) {
+    let mut reported = false;
+    for response in responses.iter_mut() {
+        let value = accessor(response);
+        match value.as_deref() {
+            Some(ocr_value) if same(ocr_value, qr_value) => continue,
+            Some(ocr_value) if !reported => {
+                discrepancies.push(QrDiscrepancy {
+                    field: field.to_string(),
+                    ocr_value: ocr_value.to_string(),
+                    qr_value: qr_value.to_string(),
+                });
+                reported = true;
+            }
+            _ => {}
+        }
+        *value = Some(qr_value.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+    use qrcode::QrCode;
+
+    fn response(invoice_no: &str, total: f64, vat: f64) -> GeminiResponse {
+        GeminiResponse {
+            serial_no: Some("1C25TAA".to_string()),
+            invoice_no: Some(invoice_no.to_string()),
+            issued_date: Some("14/10/2025".to_string()),
+            seller_tax_code: Some("0312345678".to_string()),
+            total_amount: Some(total),
+            vat_amount: Some(vat),
+            ..GeminiResponse::new()
+        }
+    }
+
+    #[test]
+    fn test_parse_delimited_content() {
+        let qr = parse_invoice_qr("0312345678|1C25TAA|00000123|2025-10-14|1100000").unwrap();
+
+        assert_eq!(qr.seller_tax_code.as_deref(), Some("0312345678"));
+        assert_eq!(qr.serial_no.as_deref(), Some("C25TAA"));
+        assert_eq!(qr.invoice_no.as_deref(), Some("00000123"));
+        assert_eq!(qr.issued_date, NaiveDate::from_ymd_opt(2025, 10, 14));
+        assert_eq!(qr.total_amount, Some(Decimal::new(1_100_000, 0)));
+    }
+
+    #[test]
+    fn test_parse_keyed_content_and_lookup_url() {
+        let qr = parse_invoice_qr(
+            "https://tracuu.example.vn/?mst=0312345678-001&khhdon=K25TBB&shdon=45&nlap=14/10/2025&tgtttbso=2200000.50",
+        )
+        .unwrap();
+
+        assert_eq!(qr.seller_tax_code.as_deref(), Some("0312345678-001"));
+        assert_eq!(qr.serial_no.as_deref(), Some("K25TBB"));
+        assert_eq!(qr.invoice_no.as_deref(), Some("45"));
+        assert_eq!(qr.issued_date, NaiveDate::from_ymd_opt(2025, 10, 14));
+        assert_eq!(qr.total_amount, Some(Decimal::new(220_000_050, 2)));
+    }
+
+    #[test]
+    fn test_rejects_payment_and_unrelated_codes() {
+        assert!(parse_invoice_qr("00020101021238570010A000000727").is_none());
+        assert!(parse_invoice_qr("https://example.vn/about").is_none());
+        assert!(parse_invoice_qr("hello world").is_none());
+    }
+
+    #[test]
+    fn test_reconcile_prefers_qr_values() {
+        let qr = parse_invoice_qr("0312345678|C25TAA|124|15/10/2025|1100000").unwrap();
+        let mut responses = vec![response("0000123", 1_000_000.0, 80_000.0)];
+
+        let discrepancies = reconcile(&mut responses, &qr);
+
+        let fields: Vec<&str> = discrepancies.iter().map(|d| d.field.as_str()).collect();
+        assert_eq!(fields, ["invoice_no", "issued_date", "total_amount"]);
+        assert_eq!(discrepancies[2].ocr_value, "1080000");
+        assert_eq!(responses[0].invoice_no.as_deref(), Some("124"));
+        assert_eq!(responses[0].issued_date.as_deref(), Some("15/10/2025"));
+        assert_eq!(responses[0].total_amount, Some(1_020_000.0));
+        // Equivalent spellings are not discrepancies and are left as read
+        assert_eq!(responses[0].serial_no.as_deref(), Some("1C25TAA"));
+    }
+
+    #[test]
+    fn test_reconcile_reports_total_without_correcting_several_lines() {
+        let qr = parse_invoice_qr("mst=0312345678;shdon=123;tgtttbso=2000000").unwrap();
+        let mut responses = vec![
+            response("123", 1_000_000.0, 100_000.0),
+            GeminiResponse {
+                seller_tax_code: None,
+                ..response("123", 500_000.0, 50_000.0)
+            },
+        ];
+
+        let discrepancies = reconcile(&mut responses, &qr);
+
+        assert_eq!(discrepancies.len(), 1);
+        assert_eq!(discrepancies[0].field, "total_amount");
+        assert_eq!(responses[0].total_amount, Some(1_000_000.0));
+        assert_eq!(responses[1].seller_tax_code.as_deref(), Some("0312345678"));
+    }
+
+    /// A photographed receipt: the code sits on a page that is rotated and
+    /// sheared, unevenly lit, blurred and saved as a lossy JPEG
+    fn photographed_receipt(text: &str) -> Vec<u8> {
+        let symbol = QrCode::new(text)
+            .unwrap()
+            .render::<Luma<u8>>()
+            .module_dimensions(6, 6)
+            .build();
+        let mut page = GrayImage::from_pixel(900, 1200, Luma([240]));
+        for y in (80..600).step_by(40) {
+            for x in 60..840 {
+                page.put_pixel(x, y, Luma([30]));
+            }
+        }
+        image::imageops::overlay(&mut page, &symbol, 520, 760);
+
+        let (angle, shear) = (0.2_f32, 0.08_f32);
+        let (sin, cos) = angle.sin_cos();
+        let (cx, cy) = (450.0, 600.0);
+        let photo = GrayImage::from_fn(1000, 1300, |x, y| {
+            // Map each photo pixel back onto the page
+            let (dx, dy) = (x as f32 - 500.0, y as f32 - 650.0);
+            let px = cos * dx + sin * dy + shear * dy + cx;
+            let py = -sin * dx + cos * dy + cy;
+            let ink = if px < 0.0 || py < 0.0 || px >= 899.0 || py >= 1199.0 {
+                90.0
+            } else {
+                page.get_pixel(px as u32, py as u32)[0] as f32
+            };
+            // Darker towards the bottom-right corner
+            let light = 1.0 - (x + y) as f32 / 2300.0 * 0.35;
+            Luma([(ink * light) as u8])
+        });
+        let photo = image::imageops::blur(&photo, 1.2);
+
+        let mut jpeg = std::io::Cursor::new(Vec::new());
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 60)
+            .encode_image(&photo)
+            .unwrap();
+        jpeg.into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_reads_invoice_qr_from_photographed_receipt() {
+        let text = "0312345678|1C25TAA|00000123|2025-10-14|1100000";
+        let qr = read_invoice_qr(Bytes::from(photographed_receipt(text)), 0)
+            .await
+            .unwrap();
+
+        assert_eq!(qr.seller_tax_code.as_deref(), Some("0312345678"));
+        assert_eq!(qr.invoice_no.as_deref(), Some("00000123"));
+        assert_eq!(qr.total_amount, Some(Decimal::from(1_100_000)));
+    }
+
+    #[tokio::test]
+    async fn test_image_without_qr_code() {
+        let page = GrayImage::from_fn(300, 200, |x, y| Luma([((x * 7 + y * 3) % 256) as u8]));
+        let mut png = std::io::Cursor::new(Vec::new());
+        page.write_to(&mut png, image::ImageFormat::Png).unwrap();
+
+        assert_eq!(
+            read_invoice_qr(Bytes::from(png.into_inner()), 0).await,
+            None
+        );
+    }
+}
@@ -1,6 +1,24 @@
+pub mod bill_draft_service;
 pub mod bill_extractor;
+pub mod bill_provenance_service;
 pub mod bill_service;
+pub mod document_grouping;
+pub mod einvoice_xml;
+pub mod email_ingestion;
 pub mod export_service;
+pub mod extraction_provider;
+pub mod gemini_cassette;
 pub mod gemini_service;
 pub mod health;
+pub mod image_fingerprint_service;
+pub mod image_store;
 pub mod image_validation;
+pub mod invoice_qr;
+pub mod ocr_job_queue;
+pub mod openai_compatible_service;
+pub mod pdf_extraction;
+pub mod session_registry;
+pub mod tesseract_service;
+pub mod vietnamese_invoice_parser;
+pub mod webhook_service;
+pub mod zip_archive;
@@ -0,0 +1,655 @@
+//! Durable OCR job queue
+//!
+//! Every upload is stored in the `ocr_jobs` / `ocr_job_images` tables before
+//! any processing starts. Worker loops claim queued jobs with
+//! `SELECT ... FOR UPDATE SKIP LOCKED`, so several workers never process the
+//! same job twice. The sessions that report a job's progress live in the
+//! memory of the instance, so the queue is served by a single backend
+//! instance.
+//!
+//! A claimed job holds a lease that its worker renews while processing. When
+//! the backend stops mid-job the lease runs out and the job is claimed again;
+//! images that were already processed are skipped, so the resumed run picks
+//! up where the interrupted one stopped. Streamed uploads are registered
+//! already claimed and receive their images one by one while they arrive.
+//!
+//! A finished job drops its images but keeps what processing made of each
+//! file, so its status outlives the in-memory session.
+
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use tempfile::NamedTempFile;
+use tokio::sync::Notify;
+use tracing::{debug, info};
+
+use crate::{config::JobQueueConfig, services::email_ingestion::EmailSource};
+
+/// Errors returned by job queue operations
+#[derive(Debug, thiserror::Error)]
+pub enum JobQueueError {
+    #[error("Job queue database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Failed to read spooled file: {0}")]
+    Spool(#[from] std::io::Error),
+}
+
+/// Lifecycle status of a queued job, stored in `ocr_jobs.status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueuedJobStatus {
+    Queued,
+    Processing,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl QueuedJobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueuedJobStatus::Queued => "queued",
+            QueuedJobStatus::Processing => "processing",
+            QueuedJobStatus::Completed => "completed",
+            QueuedJobStatus::Failed => "failed",
+            QueuedJobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Status read back from `ocr_jobs.status`, whose CHECK constraint only
+    /// allows the values of [`Self::as_str`]
+    fn from_db(value: &str) -> Self {
+        match value {
+            "queued" => QueuedJobStatus::Queued,
+            "processing" => QueuedJobStatus::Processing,
+            "completed" => QueuedJobStatus::Completed,
+            "cancelled" => QueuedJobStatus::Cancelled,
+            _ => QueuedJobStatus::Failed,
+        }
+    }
+}
+
+/// Processing status of a single stored image, stored in `ocr_job_images.status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueuedFileStatus {
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+impl QueuedFileStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueuedFileStatus::Pending => "pending",
+            QueuedFileStatus::Succeeded => "succeeded",
+            QueuedFileStatus::Failed => "failed",
+        }
+    }
+
+    /// Status read back from `ocr_job_images.status`
+    fn from_db(value: &str) -> Self {
+        match value {
+            "succeeded" => QueuedFileStatus::Succeeded,
+            "failed" => QueuedFileStatus::Failed,
+            _ => QueuedFileStatus::Pending,
+        }
+    }
+}
+
+/// Per-upload processing settings, stored with the job so resumed runs keep them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcessingOptions {
+    /// Stage extracted bills in `bill_drafts` instead of saving them to `bills`
+    pub draft_mode: bool,
+    /// Save images of the same invoice, by QR code or extracted serial and number, as one document
+    pub group_pages: bool,
+    /// Extract images even when they duplicate an image bills were already extracted from
+    pub force_duplicates: bool,
+}
+
+/// A job claimed by a worker
+#[derive(Debug, Clone)]
+pub struct ClaimedJob {
+    pub job_id: String,
+    /// Number of times the job has been claimed, including this one
+    pub attempts: i32,
+    pub options: ProcessingOptions,
+}
+
+impl ClaimedJob {
+    /// Whether an earlier attempt was interrupted before the job finished
+    pub fn is_resumed(&self) -> bool {
+        self.attempts > 1
+    }
+}
+
+/// An image of a job that still has to be processed
+#[derive(Debug, Clone)]
+pub struct QueuedFile {
+    pub file_index: usize,
+    pub file_name: Option<String>,
+    /// Label of the multi-page document this image is a page of
+    pub document: Option<String>,
+    pub data: FileData,
+    /// Email the file was attached to, recorded as the provenance of its bills
+    ///
+    /// Not stored in the queue: ingested emails are processed outside it.
+    pub email: Option<EmailSource>,
+}
+
+/// Contents of a queued file
+///
+/// A large field of a streamed upload stays in the temporary file it was
+/// spooled to, and is only read into memory when it is processed.
+#[derive(Debug, Clone)]
+pub enum FileData {
+    Memory(Bytes),
+    /// Removed from disk once the last clone is dropped
+    Spooled {
+        file: Arc<NamedTempFile>,
+        size: usize,
+    },
+}
+
+impl FileData {
+    pub fn len(&self) -> usize {
+        match self {
+            FileData::Memory(data) => data.len(),
+            FileData::Spooled { size, .. } => *size,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Read the contents into memory
+    pub async fn load(&self) -> std::io::Result<Bytes> {
+        match self {
+            FileData::Memory(data) => Ok(data.clone()),
+            FileData::Spooled { file, .. } => tokio::fs::read(file.path()).await.map(Bytes::from),
+        }
+    }
+}
+
+impl From<Bytes> for FileData {
+    fn from(data: Bytes) -> Self {
+        FileData::Memory(data)
+    }
+}
+
+/// The images of a job that are left to process, plus what earlier attempts did
+#[derive(Debug, Clone)]
+pub struct JobFiles {
+    pub pending: Vec<QueuedFile>,
+    pub total_files: usize,
+    /// Images finished by earlier attempts
+    pub processed_files: usize,
+    /// Images that passed validation in earlier attempts
+    pub successful_files: usize,
+}
+
+/// What processing made of a stored file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileResult {
+    pub status: QueuedFileStatus,
+    pub bill_ids: Vec<i32>,
+    /// Drafts staged for review in draft mode
+    pub draft_ids: Vec<i32>,
+    pub error_message: Option<String>,
+}
+
+/// A file of a job as kept in the queue, without its image
+#[derive(Debug, Clone)]
+pub struct StoredFile {
+    pub file_index: usize,
+    pub file_name: Option<String>,
+    pub size_bytes: usize,
+    pub result: FileResult,
+}
+
+/// A job and its files as kept in the queue
+#[derive(Debug, Clone)]
+pub struct StoredJob {
+    pub job_id: String,
+    pub status: QueuedJobStatus,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub files: Vec<StoredFile>,
+}
+
+/// Postgres-backed queue of OCR jobs
+#[derive(Clone)]
+pub struct OcrJobQueue {
+    pool: PgPool,
+    config: Arc<JobQueueConfig>,
+    /// Wakes an idle worker as soon as a job is enqueued by this instance
+    job_available: Arc<Notify>,
+}
+
+impl OcrJobQueue {
+    pub fn new(pool: PgPool, config: JobQueueConfig) -> Self {
+        Self {
+            pool,
+            config: Arc::new(config),
+            job_available: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn config(&self) -> &JobQueueConfig {
+        &self.config
+    }
+
+    /// Store an upload as a queued job and wake a worker
+    pub async fn enqueue(
+        &self,
+        job_id: &str,
+        files: &[QueuedFile],
+        options: ProcessingOptions,
+    ) -> Result<(), JobQueueError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO ocr_jobs (id, status, draft_mode, group_pages, force_duplicates)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            job_id,
+            QueuedJobStatus::Queued.as_str(),
+            options.draft_mode,
+            options.group_pages,
+            options.force_duplicates
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        for file in files {
+            let data = file.data.load().await?;
+            sqlx::query!(
+                r#"
+                INSERT INTO ocr_job_images (
+                    job_id, file_index, file_name, document, image_data, size_bytes
+                )
+                VALUES ($1, $2, $3, $4, $5, $6)
+                "#,
+                job_id,
+                file.file_index as i32,
+                file.file_name.as_deref(),
+                file.document.as_deref(),
+                data.as_ref(),
+                data.len() as i32
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        debug!("Enqueued OCR job {} with {} file(s)", job_id, files.len());
+
+        self.job_available.notify_one();
+        Ok(())
+    }
+
+    /// Register a job that this instance processes while its upload is still arriving
+    ///
+    /// The job starts out claimed; its images are added with [`Self::add_file`]
+    /// as they arrive. If the backend stops before the job finishes, its lease
+    /// expires and a worker resumes it with the images received so far.
+    pub async fn begin(
+        &self,
+        job_id: &str,
+        options: ProcessingOptions,
+    ) -> Result<(), JobQueueError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO ocr_jobs (
+                id, status, attempts, locked_at, draft_mode, group_pages, force_duplicates
+            )
+            VALUES ($1, $2, 1, NOW(), $3, $4, $5)
+            "#,
+            job_id,
+            QueuedJobStatus::Processing.as_str(),
+            options.draft_mode,
+            options.group_pages,
+            options.force_duplicates
+        )
+        .execute(&self.pool)
+        .await?;
+
+        debug!("Began streamed OCR job {}", job_id);
+        Ok(())
+    }
+
+    /// Store one image of a job begun with [`Self::begin`]
+    ///
+    /// A spooled image is read for the insert and stays spooled for processing.
+    pub async fn add_file(&self, job_id: &str, file: &QueuedFile) -> Result<(), JobQueueError> {
+        let data = file.data.load().await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO ocr_job_images (
+                job_id, file_index, file_name, document, image_data, size_bytes
+            )
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            job_id,
+            file.file_index as i32,
+            file.file_name.as_deref(),
+            file.document.as_deref(),
+            data.as_ref(),
+            data.len() as i32
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Claim the oldest queued job, or a processing job whose lease expired
+    pub async fn claim_next(&self) -> Result<Option<ClaimedJob>, JobQueueError> {
+        let lease_seconds = self.config.lease.as_secs_f64();
+
+        let claimed = sqlx::query!(
+            r#"
+            UPDATE ocr_jobs
+            SET status = 'processing',
+                attempts = attempts + 1,
+                locked_at = NOW(),
+                updated_at = NOW()
+            WHERE id = (
+                SELECT id
+                FROM ocr_jobs
+                WHERE status = 'queued'
+                   OR (status = 'processing' AND locked_at < NOW() - make_interval(secs => $1))
+                ORDER BY created_at
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, attempts, draft_mode, group_pages, force_duplicates
+            "#,
+            lease_seconds
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(claimed.map(|row| ClaimedJob {
+            job_id: row.id,
+            attempts: row.attempts,
+            options: ProcessingOptions {
+                draft_mode: row.draft_mode,
+                group_pages: row.group_pages,
+                force_duplicates: row.force_duplicates,
+            },
+        }))
+    }
+
+    /// Load the images of a job that have not been processed yet
+    pub async fn load_files(&self, job_id: &str) -> Result<JobFiles, JobQueueError> {
+        let counts = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS "total!",
+                   COUNT(*) FILTER (WHERE status <> 'pending') AS "processed!",
+                   COUNT(*) FILTER (WHERE status = 'succeeded') AS "successful!"
+            FROM ocr_job_images
+            WHERE job_id = $1
+            "#,
+            job_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let pending = sqlx::query!(
+            r#"
+            SELECT file_index, file_name, document, image_data AS "image_data!"
+            FROM ocr_job_images
+            WHERE job_id = $1 AND status = 'pending'
+            ORDER BY file_index
+            "#,
+            job_id
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| QueuedFile {
+            file_index: row.file_index as usize,
+            file_name: row.file_name,
+            document: row.document,
+            data: Bytes::from(row.image_data).into(),
+            email: None,
+        })
+        .collect();
+
+        Ok(JobFiles {
+            pending,
+            total_files: counts.total as usize,
+            processed_files: counts.processed as usize,
+            successful_files: counts.successful as usize,
+        })
+    }
+
+    /// Load a job and the results of its files, if the job is known
+    pub async fn load_job(&self, job_id: &str) -> Result<Option<StoredJob>, JobQueueError> {
+        let Some(job) = sqlx::query!(
+            r#"
+            SELECT id, status, last_error, created_at
+            FROM ocr_jobs
+            WHERE id = $1
+            "#,
+            job_id
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let files = sqlx::query!(
+            r#"
+            SELECT file_index, file_name, size_bytes, status, bill_ids, draft_ids, error_message
+            FROM ocr_job_images
+            WHERE job_id = $1
+            ORDER BY file_index
+            "#,
+            job_id
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| StoredFile {
+            file_index: row.file_index as usize,
+            file_name: row.file_name,
+            size_bytes: row.size_bytes as usize,
+            result: FileResult {
+                status: QueuedFileStatus::from_db(&row.status),
+                bill_ids: row.bill_ids,
+                draft_ids: row.draft_ids,
+                error_message: row.error_message,
+            },
+        })
+        .collect();
+
+        Ok(Some(StoredJob {
+            job_id: job.id,
+            status: QueuedJobStatus::from_db(&job.status),
+            last_error: job.last_error,
+            created_at: job.created_at,
+            files,
+        }))
+    }
+
+    /// Record what processing made of an image, so a resumed run skips it
+    /// and the job status outlives its session
+    pub async fn mark_file(
+        &self,
+        job_id: &str,
+        file_index: usize,
+        result: &FileResult,
+    ) -> Result<(), JobQueueError> {
+        sqlx::query!(
+            r#"
+            UPDATE ocr_job_images
+            SET status = $3, bill_ids = $4, draft_ids = $5, error_message = $6
+            WHERE job_id = $1 AND file_index = $2
+            "#,
+            job_id,
+            file_index as i32,
+            result.status.as_str(),
+            &result.bill_ids,
+            &result.draft_ids,
+            result.error_message.as_deref()
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Renew the lease of a job that is still being processed
+    pub async fn heartbeat(&self, job_id: &str) -> Result<(), JobQueueError> {
+        sqlx::query!(
+            r#"
+            UPDATE ocr_jobs
+            SET locked_at = NOW(), updated_at = NOW()
+            WHERE id = $1 AND status = 'processing'
+            "#,
+            job_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Move a job to its final status and drop its stored images, keeping
+    /// the results of its files
+    pub async fn finish(
+        &self,
+        job_id: &str,
+        status: QueuedJobStatus,
+        last_error: Option<&str>,
+    ) -> Result<(), JobQueueError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE ocr_jobs
+            SET status = $2,
+                last_error = COALESCE($3, last_error),
+                locked_at = NULL,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+            job_id,
+            status.as_str(),
+            last_error
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE ocr_job_images
+            SET image_data = NULL
+            WHERE job_id = $1
+            "#,
+            job_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        info!("OCR job {} finished as {}", job_id, status.as_str());
+        Ok(())
+    }
+
+    /// Record an error on a job without finishing it
+    pub async fn record_error(&self, job_id: &str, error: &str) -> Result<(), JobQueueError> {
+        sqlx::query!(
+            r#"
+            UPDATE ocr_jobs
+            SET last_error = $2, updated_at = NOW()
+            WHERE id = $1
+            "#,
+            job_id,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Number of jobs that are queued or were interrupted while processing
+    pub async fn unfinished_count(&self) -> Result<i64, JobQueueError> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM ocr_jobs
+            WHERE status IN ('queued', 'processing')
+            "#
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Wait until a job is enqueued by this instance or the poll interval passes
+    pub async fn wait_for_job(&self) {
+        tokio::select! {
+            _ = self.job_available.notified() => {}
+            _ = tokio::time::sleep(self.config.poll_interval) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_strings_match_schema() {
+        // Must stay in sync with the CHECK constraints of the ocr_job_queue migration
+        let job_statuses: Vec<&str> = [
+            QueuedJobStatus::Queued,
+            QueuedJobStatus::Processing,
+            QueuedJobStatus::Completed,
+            QueuedJobStatus::Failed,
+            QueuedJobStatus::Cancelled,
+        ]
+        .iter()
+        .map(QueuedJobStatus::as_str)
+        .collect();
+        assert_eq!(
+            job_statuses,
+            vec!["queued", "processing", "completed", "failed", "cancelled"]
+        );
+
+        let file_statuses: Vec<&str> = [
+            QueuedFileStatus::Pending,
+            QueuedFileStatus::Succeeded,
+            QueuedFileStatus::Failed,
+        ]
+        .iter()
+        .map(QueuedFileStatus::as_str)
+        .collect();
+        assert_eq!(file_statuses, vec!["pending", "succeeded", "failed"]);
+    }
+
+    #[test]
+    fn test_resumed_job() {
+        let first = ClaimedJob {
+            job_id: "job".to_string(),
+            attempts: 1,
+            options: ProcessingOptions::default(),
+        };
+        let retry = ClaimedJob {
+            attempts: 2,
+            ..first.clone()
+        };
+        assert!(!first.is_resumed());
+        assert!(retry.is_resumed());
+    }
+}
@@ -0,0 +1,354 @@
+//! OpenAI-compatible vision endpoint service
+//!
+//! This module extracts bill data through the `/v1/chat/completions` endpoint
+//! exposed by self-hosted model servers such as llama.cpp server, vLLM and
+//! Ollama, so invoice images never leave the premises. It sends the same
+//! prompts and bill schema as the Gemini service.
+
+use base64::Engine;
+use reqwest::{Client, StatusCode};
+use serde_json::{Value, json};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::{
+    config::OpenAiConfig,
+    models::{GeminiPart, GeminiRequest, GeminiResponse},
+    services::extraction_provider::{ExtractionError, ExtractionResult},
+};
+
+/// Service for extracting bill data with an OpenAI-compatible vision model
+pub struct OpenAiCompatibleService {
+    client: Client,
+    config: OpenAiConfig,
+}
+
+impl OpenAiCompatibleService {
+    /// Create a new OpenAiCompatibleService instance
+    pub fn new(config: OpenAiConfig) -> Result<Self, ExtractionError> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .build()
+            .map_err(|e| {
+                ExtractionError::Unavailable(format!("Failed to create HTTP client: {}", e))
+            })?;
+
+        Ok(Self { client, config })
+    }
+
+    /// Extract bill data from image bytes
+    #[instrument(skip(self, image_data), fields(image_size = image_data.len()))]
+    pub async fn extract_bill_data(&self, image_data: &[u8]) -> ExtractionResult {
+        let request = GeminiRequest::for_bill_extraction(encode_image(image_data)?);
+        self.send_request(&request).await
+    }
+
+    /// Extract bill data from the pages of one multi-page invoice, sent in a
+    /// single request
+    #[instrument(skip(self, pages), fields(page_count = pages.len()))]
+    pub async fn extract_bill_data_from_pages(&self, pages: &[&[u8]]) -> ExtractionResult {
+        let encoded_pages = pages
+            .iter()
+            .map(|page| encode_image(page))
+            .collect::<Result<Vec<_>, _>>()?;
+        let request = GeminiRequest::for_document_extraction(encoded_pages);
+        self.send_request(&request).await
+    }
+
+    /// Extract bill data from the text of an invoice
+    #[instrument(skip(self, text), fields(text_length = text.len()))]
+    pub async fn extract_bill_data_from_text(&self, text: &str) -> ExtractionResult {
+        let request = GeminiRequest::for_text_extraction(text.to_string());
+        self.send_request(&request).await
+    }
+
+    /// Build the chat completions payload of a request
+    ///
+    /// The prompt and every part become the content of one user message, and
+    /// the bill schema is requested as the `json_schema` response format.
+    /// Strict servers only accept an object at the top level, so the array of
+    /// bills is wrapped in a `bills` property.
+    fn chat_payload(&self, request: &GeminiRequest) -> Value {
+        let mut content = vec![json!({ "type": "text", "text": request.prompt })];
+        for part in &request.parts {
+            content.push(match part {
+                GeminiPart::Text(text) => json!({ "type": "text", "text": text }),
+                GeminiPart::InlineData { mime_type, data } => json!({
+                    "type": "image_url",
+                    "image_url": { "url": format!("data:{};base64,{}", mime_type, data) }
+                }),
+            });
+        }
+
+        json!({
+            "model": self.config.model,
+            "messages": [{ "role": "user", "content": content }],
+            "temperature": 0,
+            "response_format": {
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "bills",
+                    "schema": {
+                        "type": "object",
+                        "properties": { "bills": GeminiRequest::bill_response_schema() },
+                        "required": ["bills"]
+                    }
+                }
+            }
+        })
+    }
+
+    /// Send a request to the chat completions endpoint
+    async fn send_request(&self, request: &GeminiRequest) -> ExtractionResult {
+        let start_time = Instant::now();
+        let url = format!("{}/chat/completions", self.config.base_url);
+        debug!("Sending request to OpenAI-compatible endpoint: {}", url);
+
+        let mut http_request = self.client.post(&url).json(&self.chat_payload(request));
+        if let Some(api_key) = &self.config.api_key {
+            http_request = http_request.bearer_auth(api_key);
+        }
+
+        let response = http_request.send().await.map_err(|e| {
+            if e.is_timeout() {
+                ExtractionError::Timeout {
+                    seconds: self.config.timeout_seconds,
+                }
+            } else {
+                error!("HTTP request to OpenAI-compatible endpoint failed: {}", e);
+                ExtractionError::Unavailable(e.to_string())
+            }
+        })?;
+
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get("retry-after")
+                .and_then(|h| h.to_str().ok())
+                .and_then(|s| s.parse().ok());
+            warn!(
+                "OpenAI-compatible endpoint rate limit exceeded (429). Retry after: {:?}",
+                retry_after
+            );
+            return Err(ExtractionError::RateLimited { retry_after });
+        }
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            error!(
+                "OpenAI-compatible endpoint authentication failed ({}). Check OPENAI_API_KEY",
+                status
+            );
+            return Err(ExtractionError::AuthenticationFailed);
+        }
+        if !status.is_success() {
+            let message = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            error!(
+                "OpenAI-compatible endpoint returned error status {}: {}",
+                status, message
+            );
+            return Err(ExtractionError::Api {
+                status: status.as_u16(),
+                message,
+            });
+        }
+
+        let body: Value = response
+            .json()
+            .await
+            .map_err(|e| ExtractionError::InvalidResponse(e.to_string()))?;
+        let responses = parse_chat_response(&body)?;
+        info!(
+            "Extracted {} bill candidate(s) from OpenAI-compatible endpoint in {:?}",
+            responses.len(),
+            start_time.elapsed()
+        );
+
+        Ok(responses)
+    }
+}
+
+/// Encode image bytes for a `data:` URL
+fn encode_image(image_data: &[u8]) -> Result<String, ExtractionError> {
+    if image_data.is_empty() {
+        return Err(ExtractionError::InvalidInput(
+            "Image data is empty".to_string(),
+        ));
+    }
+    Ok(base64::engine::general_purpose::STANDARD.encode(image_data))
+}
+
+/// Read the bill candidates from the first choice of a chat completion
+///
+/// Accepts the `{"bills": [...]}` object that was asked for, and also a bare
+/// array or a single bill, which servers without schema support may return.
+fn parse_chat_response(body: &Value) -> ExtractionResult {
+    let content = body["choices"][0]["message"]["content"]
+        .as_str()
+        .ok_or_else(|| {
+            ExtractionError::InvalidResponse("Missing choices[0].message.content".to_string())
+        })?;
+    let content = content
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+
+    let value: Value = serde_json::from_str(content).map_err(|e| {
+        ExtractionError::InvalidResponse(format!(
+            "Failed to parse structured JSON response: {}. Response: {}",
+            e, content
+        ))
+    })?;
+    let bills = match value {
+        Value::Object(mut object) if object.contains_key("bills") => object.remove("bills"),
+        other => Some(other),
+    }
+    .unwrap_or_default();
+    let responses = match bills {
+        Value::Array(_) => serde_json::from_value::<Vec<GeminiResponse>>(bills),
+        single => serde_json::from_value::<GeminiResponse>(single).map(|bill| vec![bill]),
+    }
+    .map_err(|e| ExtractionError::InvalidResponse(e.to_string()))?;
+
+    if responses.is_empty() {
+        return Err(ExtractionError::InvalidResponse(
+            "Parsed array is empty".to_string(),
+        ));
+    }
+    Ok(responses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{header, method, path},
+    };
+
+    const JPEG: &[u8] = &[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A, 0x46];
+
+    fn service(base_url: String) -> OpenAiCompatibleService {
+        OpenAiCompatibleService::new(OpenAiConfig {
+            base_url,
+            model: "qwen2.5-vl".to_string(),
+            api_key: Some("secret".to_string()),
+            timeout_seconds: 5,
+        })
+        .unwrap()
+    }
+
+    /// A bill with every field of the schema, as a server following it returns
+    fn bill(invoice_no: &str) -> Value {
+        json!({
+            "form_no": "01GTKT0/001", "serial_no": "AA/24E", "invoice_no": invoice_no,
+            "issued_date": "2024-03-15", "seller_name": "Công ty ABC",
+            "seller_tax_code": "0312345678", "item_name": "Giấy A4", "unit": "ram",
+            "quantity": 2, "unit_price": 65000, "total_amount": 130000,
+            "vat_rate": 10, "vat_amount": 13000
+        })
+    }
+
+    fn completion(content: &str) -> Value {
+        json!({ "choices": [{ "message": { "role": "assistant", "content": content } }] })
+    }
+
+    #[test]
+    fn test_chat_payload_sends_images_and_schema() {
+        let service = service("http://localhost:8080/v1".to_string());
+        let request = GeminiRequest::for_bill_extraction("aGVsbG8=".to_string());
+        let payload = service.chat_payload(&request);
+
+        assert_eq!(payload["model"], "qwen2.5-vl");
+        let content = payload["messages"][0]["content"].as_array().unwrap();
+        assert_eq!(content[0]["type"], "text");
+        assert_eq!(
+            content[1]["image_url"]["url"],
+            "data:image/jpeg;base64,aGVsbG8="
+        );
+        assert_eq!(
+            payload["response_format"]["json_schema"]["schema"]["properties"]["bills"],
+            GeminiRequest::bill_response_schema()
+        );
+    }
+
+    #[test]
+    fn test_parse_chat_response_shapes() {
+        let wrapped = completion(&json!({ "bills": [bill("42"), bill("43")] }).to_string());
+        assert_eq!(parse_chat_response(&wrapped).unwrap().len(), 2);
+
+        let bare = completion(&format!("```json\n{}\n```", json!([bill("42")])));
+        assert_eq!(
+            parse_chat_response(&bare).unwrap()[0].invoice_no.as_deref(),
+            Some("42")
+        );
+
+        let single = completion(&bill("42").to_string());
+        assert_eq!(parse_chat_response(&single).unwrap().len(), 1);
+
+        assert!(parse_chat_response(&completion(r#"{"bills": []}"#)).is_err());
+        assert!(parse_chat_response(&json!({ "choices": [] })).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_extracts_bills_from_mock_server() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(header("authorization", "Bearer secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(completion(
+                &json!({ "bills": [bill("0000123")] }).to_string(),
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let responses = service(format!("{}/v1", server.uri()))
+            .extract_bill_data(JPEG)
+            .await
+            .unwrap();
+
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].invoice_no.as_deref(), Some("0000123"));
+        assert_eq!(responses[0].vat_rate, Some(10.0));
+    }
+
+    #[tokio::test]
+    async fn test_maps_error_statuses() {
+        let server = MockServer::start().await;
+        Mock::given(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "7"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(401))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("model not loaded"))
+            .mount(&server)
+            .await;
+        let service = service(format!("{}/v1", server.uri()));
+
+        assert!(matches!(
+            service.extract_bill_data_from_text("Số: 42").await,
+            Err(ExtractionError::RateLimited {
+                retry_after: Some(7)
+            })
+        ));
+        assert!(matches!(
+            service.extract_bill_data_from_text("Số: 42").await,
+            Err(ExtractionError::AuthenticationFailed)
+        ));
+        assert!(matches!(
+            service.extract_bill_data_from_text("Số: 42").await,
+            Err(ExtractionError::Api { status: 500, message }) if message == "model not loaded"
+        ));
+    }
+}
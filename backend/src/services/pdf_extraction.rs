@@ -0,0 +1,356 @@
+//! Page extraction from PDF invoices
+//!
+//! Every page of a PDF is turned into the input for one extraction request:
+//! its text when the page has a text layer (digitally issued invoices), or
+//! otherwise the largest image embedded in it (scanned invoices). Embedded
+//! JPEGs are passed through unchanged; uncompressed and Flate-compressed
+//! grayscale or RGB images are re-encoded as PNG.
+
+use std::io::Cursor;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage};
+use lopdf::{Document, ObjectId, xobject::PdfImage};
+use tracing::{debug, warn};
+
+use crate::models::PdfPageContent;
+
+/// Pages whose text layer has fewer alphanumeric characters are treated as scans
+const MIN_TEXT_LAYER_CHARS: usize = 20;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PdfError {
+    #[error("Invalid PDF: {0}")]
+    Invalid(String),
+
+    #[error("PDF is password protected")]
+    Encrypted,
+
+    #[error("PDF has no pages")]
+    NoPages,
+}
+
+impl From<lopdf::Error> for PdfError {
+    fn from(err: lopdf::Error) -> Self {
+        PdfError::Invalid(err.to_string())
+    }
+}
+
+/// What was extracted from one page
+#[derive(Debug, Clone)]
+pub enum PageContent {
+    /// The page's text layer
+    Text(String),
+    /// The largest image embedded in the page, as JPEG or PNG
+    Image(Vec<u8>),
+    /// Neither text nor a supported image was found
+    Empty,
+}
+
+impl PageContent {
+    pub fn kind(&self) -> PdfPageContent {
+        match self {
+            PageContent::Text(_) => PdfPageContent::Text,
+            PageContent::Image(_) => PdfPageContent::Image,
+            PageContent::Empty => PdfPageContent::Empty,
+        }
+    }
+}
+
+/// One page of a PDF
+#[derive(Debug, Clone)]
+pub struct PdfPage {
+    /// 1-based page number
+    pub page_number: usize,
+    pub content: PageContent,
+}
+
+/// Whether the data is a PDF document, judged by its magic bytes
+pub fn is_pdf(data: &[u8]) -> bool {
+    infer::get(data).is_some_and(|kind| kind.mime_type() == "application/pdf")
+}
+
+/// Number of pages of a PDF, failing if it cannot be parsed or opened
+pub fn page_count(data: &[u8]) -> Result<usize, PdfError> {
+    let document = load_document(data)?;
+    match document.get_pages().len() {
+        0 => Err(PdfError::NoPages),
+        count => Ok(count),
+    }
+}
+
+/// Extract the text layer or the main image of every page
+///
+/// This is CPU-bound; call it from a blocking task.
+pub fn extract_pages(data: &[u8]) -> Result<Vec<PdfPage>, PdfError> {
+    let document = load_document(data)?;
+    let pages = document.get_pages();
+    if pages.is_empty() {
+        return Err(PdfError::NoPages);
+    }
+
+    let texts = extract_page_texts(data);
+
+    Ok(pages
+        .into_iter()
+        .enumerate()
+        .map(|(index, (page_number, page_id))| {
+            let text = texts.get(index).map(|text| text.trim()).unwrap_or_default();
+            let content = if has_text_layer(text) {
+                PageContent::Text(text.to_string())
+            } else {
+                match largest_page_image(&document, page_id) {
+                    Some(image) => PageContent::Image(image),
+                    None => PageContent::Empty,
+                }
+            };
+            debug!("PDF page {}: {:?}", page_number, content.kind());
+
+            PdfPage {
+                page_number: page_number as usize,
+                content,
+            }
+        })
+        .collect())
+}
+
+fn load_document(data: &[u8]) -> Result<Document, PdfError> {
+    let mut document = Document::load_mem(data)?;
+    // Many invoices are "encrypted" with an empty user password only to set permissions
+    if document.is_encrypted() && document.decrypt("").is_err() {
+        return Err(PdfError::Encrypted);
+    }
+    Ok(document)
+}
+
+/// Text of every page, or nothing if the text layer cannot be read
+///
+/// Text extraction panics on some malformed fonts; such documents are
+/// handled like scans.
+fn extract_page_texts(data: &[u8]) -> Vec<String> {
+    match catch_unwind(AssertUnwindSafe(|| {
+        pdf_extract::extract_text_from_mem_by_pages(data)
+    })) {
+        Ok(Ok(texts)) => texts,
+        Ok(Err(e)) => {
+            warn!("Failed to read PDF text layer: {}", e);
+            Vec::new()
+        }
+        Err(_) => {
+            warn!("PDF text extraction panicked; using page images only");
+            Vec::new()
+        }
+    }
+}
+
+fn has_text_layer(text: &str) -> bool {
+    text.chars().filter(|c| c.is_alphanumeric()).count() >= MIN_TEXT_LAYER_CHARS
+}
+
+/// The largest embedded image of a page that can be decoded
+fn largest_page_image(document: &Document, page_id: ObjectId) -> Option<Vec<u8>> {
+    let images = document.get_page_images(page_id).ok()?;
+
+    images
+        .iter()
+        .filter_map(|image| {
+            let area = image.width.saturating_mul(image.height);
+            decode_image(document, image).map(|data| (area, data))
+        })
+        .max_by_key(|(area, _)| *area)
+        .map(|(_, data)| data)
+}
+
+/// Encoded image bytes of an image XObject, if its encoding is supported
+fn decode_image(document: &Document, image: &PdfImage<'_>) -> Option<Vec<u8>> {
+    let filters = image.filters.as_deref().unwrap_or_default();
+
+    match filters {
+        [filter] if filter == "DCTDecode" => Some(image.content.to_vec()),
+        [] => encode_raw_pixels(image, image.content.to_vec()),
+        [filter] if filter == "FlateDecode" => {
+            let pixels = document
+                .get_object(image.id)
+                .and_then(|object| object.as_stream())
+                .and_then(|stream| stream.decompressed_content())
+                .ok()?;
+            encode_raw_pixels(image, pixels)
+        }
+        _ => {
+            debug!("Skipping PDF image with unsupported filters {:?}", filters);
+            None
+        }
+    }
+}
+
+/// Encode the raw samples of an 8-bit RGB or grayscale, or 1-bit grayscale image as PNG
+fn encode_raw_pixels(image: &PdfImage<'_>, pixels: Vec<u8>) -> Option<Vec<u8>> {
+    let width = u32::try_from(image.width).ok()?;
+    let height = u32::try_from(image.height).ok()?;
+
+    let decoded = match (image.color_space.as_deref(), image.bits_per_component) {
+        (Some("DeviceRGB"), Some(8)) => {
+            DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, pixels)?)
+        }
+        (Some("DeviceGray"), Some(8)) => {
+            DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, pixels)?)
+        }
+        (Some("DeviceGray") | None, Some(1)) => {
+            DynamicImage::ImageLuma8(expand_bitonal(width, height, &pixels)?)
+        }
+        (color_space, bits) => {
+            debug!(
+                "Skipping PDF image with color space {:?} and {:?} bits per component",
+                color_space, bits
+            );
+            return None;
+        }
+    };
+
+    let mut encoded = Cursor::new(Vec::new());
+    decoded.write_to(&mut encoded, ImageFormat::Png).ok()?;
+    Some(encoded.into_inner())
+}
+
+/// Expand 1-bit samples (rows padded to whole bytes, 1 = white) to 8-bit grayscale
+fn expand_bitonal(width: u32, height: u32, packed: &[u8]) -> Option<GrayImage> {
+    let row_bytes = (width as usize).div_ceil(8);
+    if packed.len() < row_bytes * height as usize {
+        return None;
+    }
+
+    Some(GrayImage::from_fn(width, height, |x, y| {
+        let byte = packed[y as usize * row_bytes + x as usize / 8];
+        let bit = (byte >> (7 - x % 8)) & 1;
+        image::Luma([if bit == 1 { 255 } else { 0 }])
+    }))
+}
+
+/// Build a single-page PDF with optional text and an optional embedded image
+#[cfg(test)]
+pub(crate) fn pdf_with(text: Option<&str>, image: Option<lopdf::Stream>) -> Vec<u8> {
+    use lopdf::{Object, Stream, dictionary};
+
+    let mut doc = Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+    });
+
+    let mut resources = dictionary! {
+        "Font" => dictionary! { "F1" => font_id },
+    };
+    let mut content = String::new();
+    if let Some(image) = image {
+        let image_id = doc.add_object(image);
+        resources.set("XObject", dictionary! { "Im1" => image_id });
+        content.push_str("q 200 0 0 100 0 0 cm /Im1 Do Q\n");
+    }
+    if let Some(text) = text {
+        content.push_str(&format!("BT /F1 12 Tf 20 700 Td ({text}) Tj ET\n"));
+    }
+
+    let content_id = doc.add_object(Stream::new(dictionary! {}, content.into_bytes()));
+    let resources_id = doc.add_object(resources);
+    let page_id = doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "Contents" => content_id,
+        "Resources" => resources_id,
+        "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+    });
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+
+    let mut data = Vec::new();
+    doc.save_to(&mut data).unwrap();
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{Stream, dictionary};
+
+    fn gray_image_stream() -> Stream {
+        Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => 2,
+                "Height" => 2,
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 8,
+            },
+            vec![0, 255, 255, 0],
+        )
+    }
+
+    #[test]
+    fn test_text_layer_is_preferred() {
+        let data = pdf_with(
+            Some("HOA DON GIA TRI GIA TANG So 0001234 Ngay 14 thang 10 nam 2025"),
+            Some(gray_image_stream()),
+        );
+        assert!(is_pdf(&data));
+        assert_eq!(page_count(&data).unwrap(), 1);
+
+        let pages = extract_pages(&data).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].page_number, 1);
+        match &pages[0].content {
+            PageContent::Text(text) => assert!(text.contains("0001234")),
+            other => panic!("expected text, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_scanned_page_yields_embedded_image() {
+        let data = pdf_with(None, Some(gray_image_stream()));
+
+        let pages = extract_pages(&data).unwrap();
+        let PageContent::Image(png) = &pages[0].content else {
+            panic!("expected an image, got {:?}", pages[0].content);
+        };
+        let decoded = image::load_from_memory(png).unwrap().to_luma8();
+        assert_eq!(decoded.dimensions(), (2, 2));
+        assert_eq!(decoded.get_pixel(1, 0).0, [255]);
+    }
+
+    #[test]
+    fn test_page_without_content_is_empty() {
+        let data = pdf_with(None, None);
+
+        let pages = extract_pages(&data).unwrap();
+        assert_eq!(pages[0].content.kind(), PdfPageContent::Empty);
+    }
+
+    #[test]
+    fn test_rejects_invalid_pdf() {
+        assert!(page_count(b"%PDF-1.7\nnot really a pdf").is_err());
+        assert!(!is_pdf(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    #[test]
+    fn test_expand_bitonal() {
+        // 3 pixels wide: rows padded to one byte each
+        let image = expand_bitonal(3, 2, &[0b1010_0000, 0b0100_0000]).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [255]);
+        assert_eq!(image.get_pixel(1, 0).0, [0]);
+        assert_eq!(image.get_pixel(1, 1).0, [255]);
+        assert!(expand_bitonal(16, 2, &[0, 0]).is_none());
+    }
+}
@@ -0,0 +1,660 @@
+//! OCR processing session registry
+//!
+//! Keeps one event channel per upload session so that SSE subscribers only
+//! receive the events produced by their own upload, and tracks the
+//! `ProcessingSession` bookkeeping for every session.
+//!
+//! Every published event is numbered and kept in the session's event log so
+//! that a client which lost its connection can replay everything after its
+//! `Last-Event-ID`. Finished sessions stay queryable for
+//! [`FINISHED_SESSION_RETENTION`] and are then pruned.
+//!
+//! The registry lives in the memory of one backend instance. A job resumed
+//! after a restart starts a new event log, numbered from
+//! [`RESUMED_EVENT_ID_STRIDE`] times its attempt, so that an id handed out
+//! before the restart is recognised as stale and answered with a full replay.
+//!
+//! A session can be cancelled explicitly, and sessions bound to a single SSE
+//! response are treated as cancelled once their last subscriber is gone. The
+//! processing task polls [`SessionHandle::cancellation_reason`] between files.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::broadcast;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::models::{
+    OcrJob, ProcessingErrorType, ProcessingEvent, ProcessingSession, SSEEventEnvelope,
+    SessionStatus, ocr_job::OcrJobFileResult,
+};
+
+/// Buffer size of each per-session event channel
+const SESSION_CHANNEL_CAPACITY: usize = 256;
+
+/// Reconnection delay advertised to SSE clients (milliseconds)
+pub const SSE_RETRY_MS: u32 = 3000;
+
+/// How long a finished session and its event log are kept for replay
+pub const FINISHED_SESSION_RETENTION: Duration = Duration::hours(1);
+
+/// Event ids of a job resumed after a restart start at its attempt times this,
+/// well above the number of events one attempt publishes
+pub const RESUMED_EVENT_ID_STRIDE: u64 = 1_000_000;
+
+/// Errors returned by session operations
+#[derive(Debug, thiserror::Error)]
+pub enum SessionError {
+    #[error("Processing session {0} not found")]
+    NotFound(String),
+
+    #[error("Processing session {0} has already finished")]
+    AlreadyFinished(String),
+}
+
+/// Registry entry holding the session state, its event log and channel
+struct SessionEntry {
+    session: ProcessingSession,
+    sender: broadcast::Sender<SSEEventEnvelope>,
+    events: Vec<SSEEventEnvelope>,
+    /// Id of the event before the first one of this log
+    first_event_id: u64,
+    finished_at: Option<DateTime<Utc>>,
+    /// Cancel the session once it has no subscriber left
+    cancel_on_disconnect: bool,
+    cancel_requested: bool,
+    saved_bill_ids: Vec<i32>,
+    /// Job view of the events so far, kept up to date as they are published
+    job: OcrJob,
+}
+
+impl SessionEntry {
+    fn is_processing(&self) -> bool {
+        matches!(self.session.status, SessionStatus::Processing)
+    }
+
+    /// Id of the last event published, or the id before the first one
+    fn last_event_id(&self) -> u64 {
+        self.first_event_id + self.events.len() as u64
+    }
+
+    /// Whether an event id was handed out by this event log
+    fn issued(&self, event_id: u64) -> bool {
+        event_id > self.first_event_id && event_id <= self.last_event_id()
+    }
+
+    fn snapshot(&self) -> ProcessingSession {
+        let mut session = self.session.clone();
+        session.client_connected = self.sender.receiver_count() > 0;
+        session
+    }
+}
+
+/// Events to replay for a resumed subscription, plus the live receiver
+///
+/// `receiver` is `None` once the session has finished: everything it will
+/// ever produce is already part of `replay`.
+pub struct SessionSubscription {
+    pub replay: Vec<SSEEventEnvelope>,
+    pub receiver: Option<broadcast::Receiver<SSEEventEnvelope>>,
+}
+
+impl SessionSubscription {
+    /// Subscription of a session that was subscribed to before its first event
+    pub fn live(receiver: broadcast::Receiver<SSEEventEnvelope>) -> Self {
+        Self {
+            replay: Vec::new(),
+            receiver: Some(receiver),
+        }
+    }
+}
+
+/// Registry of processing sessions keyed by `session_id`
+#[derive(Clone, Default)]
+pub struct SessionRegistry {
+    sessions: Arc<RwLock<HashMap<String, SessionEntry>>>,
+}
+
+impl SessionRegistry {
+    /// Create an empty session registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new session and return its handle plus a first subscriber
+    ///
+    /// The receiver is created before any event can be published, so the
+    /// caller never misses the start of its own session. With
+    /// `cancel_on_disconnect` the session is cancelled as soon as no
+    /// subscriber is left, which is what a streaming upload wants; detached
+    /// jobs keep running without listeners.
+    pub fn create_session(
+        &self,
+        cancel_on_disconnect: bool,
+    ) -> (SessionHandle, broadcast::Receiver<SSEEventEnvelope>) {
+        let session_id = Uuid::new_v4().to_string();
+        let (sender, receiver) = broadcast::channel(SESSION_CHANNEL_CAPACITY);
+        self.insert(&session_id, sender, cancel_on_disconnect, 0);
+
+        let handle = SessionHandle {
+            session_id,
+            registry: self.clone(),
+        };
+
+        (handle, receiver)
+    }
+
+    /// Get a handle to a processing session, registering it if it is unknown
+    ///
+    /// Used by the job queue workers: a job enqueued by this instance already
+    /// has its session (and maybe its SSE client), while a job resumed after
+    /// a restart gets a fresh, detached session under the same id whose event
+    /// ids follow from `attempt`.
+    pub fn resume_session(&self, session_id: &str, attempt: u32) -> SessionHandle {
+        let attached = self
+            .sessions
+            .read()
+            .unwrap()
+            .get(session_id)
+            .is_some_and(SessionEntry::is_processing);
+
+        if !attached {
+            let (sender, _) = broadcast::channel(SESSION_CHANNEL_CAPACITY);
+            let first_event_id = u64::from(attempt) * RESUMED_EVENT_ID_STRIDE;
+            self.insert(session_id, sender, false, first_event_id);
+        }
+
+        SessionHandle {
+            session_id: session_id.to_string(),
+            registry: self.clone(),
+        }
+    }
+
+    /// Subscribe to a session, replaying every event after `last_event_id`
+    ///
+    /// An id this session's event log did not hand out, such as one from
+    /// before a restart, replays the whole log. The replay snapshot and the
+    /// live receiver are taken under the same lock, so no event is lost or
+    /// duplicated between the two.
+    pub fn subscribe_from(
+        &self,
+        session_id: &str,
+        last_event_id: Option<u64>,
+    ) -> Option<SessionSubscription> {
+        let sessions = self.sessions.read().unwrap();
+        let entry = sessions.get(session_id)?;
+
+        let last_event_id = last_event_id.filter(|&event_id| {
+            let issued = entry.issued(event_id);
+            if !issued {
+                debug!(
+                    "Replaying all events of session {} for stale event id {}",
+                    session_id, event_id
+                );
+            }
+            issued
+        });
+        let replay = entry
+            .events
+            .iter()
+            .filter(|envelope| envelope.sequence() > last_event_id)
+            .cloned()
+            .collect();
+        let receiver = entry.is_processing().then(|| entry.sender.subscribe());
+
+        Some(SessionSubscription { replay, receiver })
+    }
+
+    /// Subscribe to the live events of a session that is still processing
+    pub fn subscribe(&self, session_id: &str) -> Option<broadcast::Receiver<SSEEventEnvelope>> {
+        self.sessions
+            .read()
+            .unwrap()
+            .get(session_id)
+            .filter(|entry| entry.is_processing())
+            .map(|entry| entry.sender.subscribe())
+    }
+
+    /// Logged events of a session after `last_sequence`
+    ///
+    /// Lets a subscriber that fell behind the live channel catch up: the
+    /// event log keeps every event of the session.
+    pub fn events_after(
+        &self,
+        session_id: &str,
+        last_sequence: Option<u64>,
+    ) -> Option<Vec<SSEEventEnvelope>> {
+        self.sessions.read().unwrap().get(session_id).map(|entry| {
+            entry
+                .events
+                .iter()
+                .filter(|envelope| envelope.sequence() > last_sequence)
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Get a snapshot of a registered session
+    pub fn get_session(&self, session_id: &str) -> Option<ProcessingSession> {
+        self.sessions
+            .read()
+            .unwrap()
+            .get(session_id)
+            .map(SessionEntry::snapshot)
+    }
+
+    /// Get a snapshot of a registered session together with its event log
+    pub fn get_session_with_events(
+        &self,
+        session_id: &str,
+    ) -> Option<(ProcessingSession, Vec<SSEEventEnvelope>)> {
+        self.sessions
+            .read()
+            .unwrap()
+            .get(session_id)
+            .map(|entry| (entry.snapshot(), entry.events.clone()))
+    }
+
+    /// Request cancellation of a session that is still processing
+    ///
+    /// The processing task stops before its next file and ends the session
+    /// with a `ProcessingCancelled` event.
+    pub fn cancel(&self, session_id: &str) -> Result<(), SessionError> {
+        let mut sessions = self.sessions.write().unwrap();
+        let entry = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| SessionError::NotFound(session_id.to_string()))?;
+
+        if !entry.is_processing() {
+            return Err(SessionError::AlreadyFinished(session_id.to_string()));
+        }
+
+        entry.cancel_requested = true;
+        debug!(
+            "Cancellation requested for processing session {}",
+            session_id
+        );
+        Ok(())
+    }
+
+    /// Number of sessions that are still processing
+    pub fn active_count(&self) -> usize {
+        self.sessions
+            .read()
+            .unwrap()
+            .values()
+            .filter(|entry| entry.is_processing())
+            .count()
+    }
+
+    fn insert(
+        &self,
+        session_id: &str,
+        sender: broadcast::Sender<SSEEventEnvelope>,
+        cancel_on_disconnect: bool,
+        first_event_id: u64,
+    ) {
+        let mut sessions = self.sessions.write().unwrap();
+        let retention_cutoff = Utc::now() - FINISHED_SESSION_RETENTION;
+        sessions.retain(|_, entry| {
+            entry.is_processing()
+                || entry.sender.receiver_count() > 0
+                || entry
+                    .finished_at
+                    .is_some_and(|finished_at| finished_at > retention_cutoff)
+        });
+        sessions.insert(
+            session_id.to_string(),
+            SessionEntry {
+                session: ProcessingSession::new(session_id.to_string()),
+                sender,
+                events: Vec::new(),
+                first_event_id,
+                finished_at: None,
+                cancel_on_disconnect,
+                cancel_requested: false,
+                saved_bill_ids: Vec::new(),
+                job: OcrJob::from_events(&ProcessingSession::new(session_id.to_string()), &[]),
+            },
+        );
+        drop(sessions);
+        debug!("Registered processing session {}", session_id);
+    }
+
+    fn update<F: FnOnce(&mut ProcessingSession)>(&self, session_id: &str, f: F) {
+        if let Some(entry) = self.sessions.write().unwrap().get_mut(session_id) {
+            f(&mut entry.session);
+        }
+    }
+}
+
+/// Handle used by the processing task to publish events for one session
+#[derive(Clone)]
+pub struct SessionHandle {
+    session_id: String,
+    registry: SessionRegistry,
+}
+
+impl SessionHandle {
+    /// Identifier of the session this handle publishes to
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Publish an event to the subscribers of this session
+    ///
+    /// The event is numbered, appended to the session's event log and then
+    /// broadcast. Terminal events (`ProcessingComplete`, `ProcessingError`,
+    /// `ProcessingCancelled`) also move the session out of the `Processing`
+    /// status.
+    pub fn send(&self, event: ProcessingEvent) {
+        let mut sessions = self.registry.sessions.write().unwrap();
+        let Some(entry) = sessions.get_mut(&self.session_id) else {
+            debug!(
+                "Dropping event for unknown session {}: {}",
+                self.session_id,
+                event.event_type()
+            );
+            return;
+        };
+
+        match &event {
+            ProcessingEvent::UploadStarted {
+                total_files: Some(total_files),
+                ..
+            } => {
+                entry.session.total_files = *total_files;
+            }
+            ProcessingEvent::ImageReceived { file_index, .. } => {
+                // Streamed uploads only learn their size file by file
+                entry.session.total_files = entry.session.total_files.max(file_index + 1);
+            }
+            ProcessingEvent::ProcessingComplete { total_files, .. } => {
+                entry.session.total_files = *total_files;
+                entry.session.status = SessionStatus::Completed;
+                entry.finished_at = Some(Utc::now());
+            }
+            ProcessingEvent::ProcessingError { .. } => {
+                entry.session.status = SessionStatus::Failed;
+                entry.finished_at = Some(Utc::now());
+            }
+            ProcessingEvent::ProcessingCancelled { .. } => {
+                entry.session.status = SessionStatus::Cancelled;
+                entry.finished_at = Some(Utc::now());
+            }
+            ProcessingEvent::BillDataSaved { bill_id, .. } => {
+                entry.saved_bill_ids.push(*bill_id);
+            }
+            _ => {}
+        }
+
+        entry.job.apply(&event);
+        let envelope = SSEEventEnvelope::new(entry.last_event_id() + 1, event, Some(SSE_RETRY_MS));
+        entry.events.push(envelope.clone());
+
+        // Sending only fails when nobody is subscribed, which is not an error here
+        let _ = entry.sender.send(envelope);
+    }
+
+    /// Why this session should stop processing, if it should
+    ///
+    /// Returns `CancelledByUser` after an explicit cancel request and
+    /// `ClientDisconnected` when a stream-bound session lost its last
+    /// subscriber.
+    pub fn cancellation_reason(&self) -> Option<ProcessingErrorType> {
+        let sessions = self.registry.sessions.read().unwrap();
+        let entry = sessions.get(&self.session_id)?;
+
+        if entry.cancel_requested {
+            Some(ProcessingErrorType::CancelledByUser)
+        } else if entry.cancel_on_disconnect && entry.sender.receiver_count() == 0 {
+            Some(ProcessingErrorType::ClientDisconnected)
+        } else {
+            None
+        }
+    }
+
+    /// Ids of the bills saved so far in this session
+    pub fn saved_bill_ids(&self) -> Vec<i32> {
+        self.registry
+            .sessions
+            .read()
+            .unwrap()
+            .get(&self.session_id)
+            .map(|entry| entry.saved_bill_ids.clone())
+            .unwrap_or_default()
+    }
+
+    /// Result of a file of this session so far, as the job view shows it
+    pub fn file_result(&self, file_index: usize) -> Option<OcrJobFileResult> {
+        self.registry
+            .sessions
+            .read()
+            .unwrap()
+            .get(&self.session_id)
+            .and_then(|entry| entry.job.file(file_index).cloned())
+    }
+
+    /// Record that one more file of this session has been processed
+    pub fn mark_file_processed(&self) {
+        self.mark_files_processed(1);
+    }
+
+    /// Record that `count` more files of this session have been processed
+    pub fn mark_files_processed(&self, count: usize) {
+        self.registry
+            .update(&self.session_id, |s| s.processed_files += count);
+    }
+
+    /// Current status of this session
+    pub fn status(&self) -> Option<SessionStatus> {
+        self.registry
+            .get_session(&self.session_id)
+            .map(|session| session.status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upload_started(session_id: &str, total_files: usize) -> ProcessingEvent {
+        ProcessingEvent::UploadStarted {
+            total_files: Some(total_files),
+            session_id: session_id.to_string(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn processing_complete(session_id: &str) -> ProcessingEvent {
+        ProcessingEvent::ProcessingComplete {
+            session_id: session_id.to_string(),
+            total_files: 1,
+            successful_files: 1,
+            duration_ms: 0,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_sessions_do_not_share_events() {
+        let registry = SessionRegistry::new();
+        let (first, mut first_rx) = registry.create_session(true);
+        let (second, mut second_rx) = registry.create_session(true);
+
+        first.send(upload_started(first.session_id(), 1));
+        second.send(upload_started(second.session_id(), 2));
+
+        match first_rx.try_recv().unwrap().data {
+            ProcessingEvent::UploadStarted { session_id, .. } => {
+                assert_eq!(session_id, first.session_id())
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(first_rx.try_recv().is_err());
+
+        match second_rx.try_recv().unwrap().data {
+            ProcessingEvent::UploadStarted { session_id, .. } => {
+                assert_eq!(session_id, second.session_id())
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(second_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_session_bookkeeping() {
+        let registry = SessionRegistry::new();
+        let (handle, _rx) = registry.create_session(true);
+
+        handle.send(upload_started(handle.session_id(), 3));
+        handle.mark_file_processed();
+
+        let session = registry.get_session(handle.session_id()).unwrap();
+        assert_eq!(session.total_files, 3);
+        assert_eq!(session.processed_files, 1);
+        assert!(session.client_connected);
+        assert!(matches!(session.status, SessionStatus::Processing));
+
+        handle.send(processing_complete(handle.session_id()));
+
+        let session = registry.get_session(handle.session_id()).unwrap();
+        assert!(matches!(session.status, SessionStatus::Completed));
+        assert!(registry.subscribe(handle.session_id()).is_none());
+        assert_eq!(registry.active_count(), 0);
+    }
+
+    #[test]
+    fn test_events_are_numbered_and_replayed() {
+        let registry = SessionRegistry::new();
+        let (handle, mut rx) = registry.create_session(true);
+
+        handle.send(upload_started(handle.session_id(), 1));
+        assert_eq!(rx.try_recv().unwrap().event_id.as_deref(), Some("1"));
+
+        // A reconnecting client replays what it missed and keeps listening
+        let subscription = registry
+            .subscribe_from(handle.session_id(), Some(0))
+            .unwrap();
+        assert_eq!(subscription.replay.len(), 1);
+        let mut live = subscription.receiver.unwrap();
+
+        handle.send(processing_complete(handle.session_id()));
+        let envelope = live.try_recv().unwrap();
+        assert_eq!(envelope.sequence(), Some(2));
+        assert_eq!(envelope.retry, Some(SSE_RETRY_MS));
+
+        // After completion the full log is replayed and there is no live part
+        let subscription = registry
+            .subscribe_from(handle.session_id(), Some(1))
+            .unwrap();
+        assert_eq!(subscription.replay.len(), 1);
+        assert!(subscription.replay[0].data.is_terminal());
+        assert!(subscription.receiver.is_none());
+
+        assert!(registry.subscribe_from("unknown", None).is_none());
+    }
+
+    #[test]
+    fn test_cancel_request() {
+        let registry = SessionRegistry::new();
+        let (handle, _rx) = registry.create_session(false);
+        assert!(handle.cancellation_reason().is_none());
+
+        handle.send(ProcessingEvent::BillDataSaved {
+            file_index: 0,
+            page_number: None,
+            bill_id: 7,
+            timestamp: Utc::now(),
+        });
+        registry.cancel(handle.session_id()).unwrap();
+        assert!(matches!(
+            handle.cancellation_reason(),
+            Some(ProcessingErrorType::CancelledByUser)
+        ));
+        assert_eq!(handle.saved_bill_ids(), vec![7]);
+
+        handle.send(ProcessingEvent::ProcessingCancelled {
+            session_id: handle.session_id().to_string(),
+            reason: ProcessingErrorType::CancelledByUser,
+            total_files: 2,
+            processed_files: 1,
+            saved_bill_ids: handle.saved_bill_ids(),
+            timestamp: Utc::now(),
+        });
+        let session = registry.get_session(handle.session_id()).unwrap();
+        assert!(matches!(session.status, SessionStatus::Cancelled));
+        assert!(matches!(
+            registry.cancel(handle.session_id()),
+            Err(SessionError::AlreadyFinished(_))
+        ));
+        assert!(matches!(
+            registry.cancel("unknown"),
+            Err(SessionError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_disconnect_detection() {
+        let registry = SessionRegistry::new();
+
+        // Stream-bound sessions are cancelled once the last subscriber leaves
+        let (streaming, rx) = registry.create_session(true);
+        assert!(streaming.cancellation_reason().is_none());
+        drop(rx);
+        assert!(matches!(
+            streaming.cancellation_reason(),
+            Some(ProcessingErrorType::ClientDisconnected)
+        ));
+
+        // Detached jobs keep running without listeners
+        let (detached, rx) = registry.create_session(false);
+        drop(rx);
+        assert!(detached.cancellation_reason().is_none());
+    }
+
+    #[test]
+    fn test_resume_session() {
+        let registry = SessionRegistry::new();
+        let (handle, mut rx) = registry.create_session(true);
+
+        // A worker picking up the job publishes to the session the client listens on
+        let resumed = registry.resume_session(handle.session_id(), 2);
+        resumed.send(upload_started(handle.session_id(), 1));
+        assert!(rx.try_recv().is_ok());
+
+        // A job resumed after a restart gets a detached session under its id
+        let orphan = registry.resume_session("job-from-before-restart", 2);
+        assert!(matches!(orphan.status(), Some(SessionStatus::Processing)));
+        assert!(orphan.cancellation_reason().is_none());
+    }
+
+    #[test]
+    fn test_stale_event_id_replays_everything() {
+        let registry = SessionRegistry::new();
+
+        // The first attempt handed out ids 1 and 2 before the backend stopped
+        let resumed = registry.resume_session("job-from-before-restart", 2);
+        resumed.send(upload_started(resumed.session_id(), 1));
+        resumed.send(processing_complete(resumed.session_id()));
+
+        let subscription = registry
+            .subscribe_from(resumed.session_id(), Some(1))
+            .unwrap();
+        assert_eq!(subscription.replay.len(), 2);
+        assert_eq!(
+            subscription.replay[0].sequence(),
+            Some(2 * RESUMED_EVENT_ID_STRIDE + 1)
+        );
+
+        let subscription = registry
+            .subscribe_from(resumed.session_id(), Some(2 * RESUMED_EVENT_ID_STRIDE + 1))
+            .unwrap();
+        assert_eq!(subscription.replay.len(), 1);
+        assert!(subscription.replay[0].data.is_terminal());
+    }
+}
@@ -0,0 +1,209 @@
+//! Tesseract OCR service
+//!
+//! This module extracts bill data without any language model: a local
+//! `tesseract` process reads the text of the preprocessed invoice image and
+//! the rule-based Vietnamese invoice parser turns it into bill candidates.
+//! It works on air-gapped sites and when the Gemini quota runs out.
+
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::{io::AsyncWriteExt, process::Command, time::timeout};
+use tracing::{debug, info, instrument, warn};
+
+use crate::{
+    config::TesseractConfig,
+    services::{
+        extraction_provider::{ExtractionError, ExtractionResult},
+        vietnamese_invoice_parser::parse_invoice_text,
+    },
+};
+
+/// Service for extracting bill data with the local Tesseract OCR engine
+pub struct TesseractService {
+    config: TesseractConfig,
+}
+
+impl TesseractService {
+    /// Create a new TesseractService instance
+    pub fn new(config: TesseractConfig) -> Self {
+        Self { config }
+    }
+
+    /// Check that the executable runs and has the configured language data
+    pub fn check_installation(&self) -> Result<(), ExtractionError> {
+        let output = std::process::Command::new(&self.config.command)
+            .arg("--list-langs")
+            .output()
+            .map_err(|e| {
+                ExtractionError::Unavailable(format!(
+                    "Failed to run '{}': {}",
+                    self.config.command, e
+                ))
+            })?;
+        let installed = String::from_utf8_lossy(&output.stdout);
+        let installed: Vec<&str> = installed.lines().map(str::trim).collect();
+        for language in self.config.language.split('+') {
+            if !installed.contains(&language) {
+                return Err(ExtractionError::Unavailable(format!(
+                    "Tesseract language data '{}' is not installed",
+                    language
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Extract bill data from image bytes
+    #[instrument(skip(self, image_data), fields(image_size = image_data.len()))]
+    pub async fn extract_bill_data(&self, image_data: &[u8]) -> ExtractionResult {
+        let text = self.recognize(image_data).await?;
+        candidates_from_text(&text)
+    }
+
+    /// Extract bill data from the pages of one multi-page invoice
+    ///
+    /// The pages are recognised one after the other and parsed as one text,
+    /// so the header on the first page applies to the lines on later pages.
+    #[instrument(skip(self, pages), fields(page_count = pages.len()))]
+    pub async fn extract_bill_data_from_pages(&self, pages: &[&[u8]]) -> ExtractionResult {
+        let mut texts = Vec::with_capacity(pages.len());
+        for page in pages {
+            texts.push(self.recognize(page).await?);
+        }
+        candidates_from_text(&texts.join("\n"))
+    }
+
+    /// Extract bill data from the text of an invoice, which needs no OCR
+    pub async fn extract_bill_data_from_text(&self, text: &str) -> ExtractionResult {
+        candidates_from_text(text)
+    }
+
+    /// Run `tesseract` on an image, passed through stdin, and return its text
+    async fn recognize(&self, image_data: &[u8]) -> Result<String, ExtractionError> {
+        if image_data.is_empty() {
+            return Err(ExtractionError::InvalidInput(
+                "Image data is empty".to_string(),
+            ));
+        }
+
+        let start_time = Instant::now();
+        let mut child = Command::new(&self.config.command)
+            .args(["stdin", "stdout", "-l", &self.config.language, "--psm"])
+            .arg(self.config.page_segmentation_mode.to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| {
+                ExtractionError::Unavailable(format!(
+                    "Failed to run '{}': {}",
+                    self.config.command, e
+                ))
+            })?;
+
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| ExtractionError::Unavailable("Tesseract stdin is closed".to_string()))?;
+        let image = image_data.to_vec();
+        let writer = tokio::spawn(async move {
+            // Dropping stdin after the write tells tesseract the image is complete
+            stdin.write_all(&image).await
+        });
+
+        let output = timeout(
+            Duration::from_secs(self.config.timeout_seconds),
+            child.wait_with_output(),
+        )
+        .await
+        .map_err(|_| ExtractionError::Timeout {
+            seconds: self.config.timeout_seconds,
+        })?
+        .map_err(|e| ExtractionError::Unavailable(format!("Tesseract failed: {}", e)))?;
+        if let Ok(Err(e)) = writer.await {
+            warn!("Failed to write image to tesseract: {}", e);
+        }
+
+        if !output.status.success() {
+            return Err(ExtractionError::InvalidInput(format!(
+                "Tesseract exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        let text = String::from_utf8_lossy(&output.stdout).into_owned();
+        info!(
+            "Tesseract recognised {} characters in {:?}",
+            text.chars().count(),
+            start_time.elapsed()
+        );
+        debug!("Tesseract output: {}", text);
+        Ok(text)
+    }
+}
+
+/// Parse invoice text into bill candidates, failing when nothing was recognised
+fn candidates_from_text(text: &str) -> ExtractionResult {
+    let responses = parse_invoice_text(text);
+    if responses.is_empty() {
+        return Err(ExtractionError::InvalidResponse(
+            "No invoice fields recognised in the text".to_string(),
+        ));
+    }
+    Ok(responses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(command: &str) -> TesseractService {
+        TesseractService::new(TesseractConfig {
+            command: command.to_string(),
+            language: "vie".to_string(),
+            page_segmentation_mode: 6,
+            timeout_seconds: 5,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_extracts_bills_from_text_without_ocr() {
+        let text = "Mẫu số: 01GTKT0/001\nKý hiệu: AA/24E\nSố: 0000123\n\
+Ngày 15 tháng 03 năm 2024\nMã số thuế: 0312345678\n\
+Cộng tiền hàng: 650.000\nThuế suất GTGT: 10%\nTiền thuế GTGT: 65.000";
+        let responses = service("tesseract")
+            .extract_bill_data_from_text(text)
+            .await
+            .unwrap();
+
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].invoice_no.as_deref(), Some("0000123"));
+        assert_eq!(responses[0].total_amount, Some(650_000.0));
+    }
+
+    #[tokio::test]
+    async fn test_unrecognised_text_is_an_invalid_response() {
+        assert!(matches!(
+            service("tesseract")
+                .extract_bill_data_from_text("Lorem ipsum")
+                .await,
+            Err(ExtractionError::InvalidResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_missing_executable_is_unavailable() {
+        let service = service("/nonexistent/tesseract");
+
+        assert!(matches!(
+            service.check_installation(),
+            Err(ExtractionError::Unavailable(_))
+        ));
+        assert!(matches!(
+            service.extract_bill_data(&[0xFF, 0xD8, 0xFF]).await,
+            Err(ExtractionError::Unavailable(_))
+        ));
+    }
+}
@@ -0,0 +1,297 @@
+//! Rule-based Vietnamese invoice parser
+//!
+//! Finds the labelled fields of a Vietnamese VAT invoice (Mẫu số, Ký hiệu,
+//! Số, Ngày … tháng … năm, MST, Thành tiền, Thuế suất) in plain text, such as
+//! the output of an OCR engine, without any language model. Labels are
+//! matched with and without diacritics, since OCR often loses them.
+
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+use crate::models::GeminiResponse;
+
+/// Optional English translation in parentheses after a label, e.g. `Số (No.):`
+const TRANSLATION: &str = r"(?:[ \t]*\([^)\n]*\))?";
+
+fn label_regex(label: &str, value: &str) -> Regex {
+    Regex::new(&format!(
+        r"(?i){label}{TRANSLATION}[ \t]*[:：]?[ \t]*{value}"
+    ))
+    .unwrap()
+}
+
+static FORM_NO: LazyLock<Regex> =
+    LazyLock::new(|| label_regex(r"m[ẫầa]u[ \t]*s[ốo]", r"([0-9A-Z][0-9A-Z/\-.]*)"));
+
+static SERIAL_NO: LazyLock<Regex> =
+    LazyLock::new(|| label_regex(r"k[ýy][ \t]*hi[ệe]u", r"([0-9A-Z][0-9A-Z/\-]*)"));
+
+/// `Số:` or `Số hóa đơn:`; the colon is required so `Số lượng` never matches
+static INVOICE_NO: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?i)(?:^|[^\p{{L}}])s[ốo](?:[ \t]*h[óo]a[ \t]*[đd][ơo]n)?{TRANSLATION}[ \t]*[:：][ \t]*(\d+)"
+    ))
+    .unwrap()
+});
+
+static ISSUED_DATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)ng[àa]y\D{0,20}?(\d{1,2})\D{0,20}?th[áa]ng\D{0,20}?(\d{1,2})\D{0,20}?n[ăa]m\D{0,20}?(\d{4})",
+    )
+    .unwrap()
+});
+
+static TAX_CODE: LazyLock<Regex> = LazyLock::new(|| {
+    label_regex(
+        r"(?:\bMST\b|m[ãa][ \t]*s[ốo][ \t]*thu[ếe])",
+        r"(\d[\d \-]{8,16}\d)",
+    )
+});
+
+static SELLER_NAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?im)(?:[đd][ơo]n[ \t]*v[ịi][ \t]*b[áa]n(?:[ \t]*h[àa]ng)?|t[êe]n[ \t]*ng[ưu][ờo]i[ \t]*b[áa]n|ng[ưu][ờo]i[ \t]*b[áa]n(?:[ \t]*h[àa]ng)?){TRANSLATION}[ \t]*[:：][ \t]*(.+)$"
+    ))
+    .unwrap()
+});
+
+static VAT_RATE: LazyLock<Regex> = LazyLock::new(|| {
+    label_regex(
+        r"thu[ếe][ \t]*su[ấa]t(?:[ \t]*GTGT)?",
+        r"(\d{1,2}(?:[.,]\d+)?)[ \t]*%",
+    )
+});
+
+/// Amount before VAT of the whole invoice
+static TOTAL_AMOUNT: LazyLock<Regex> = LazyLock::new(|| {
+    label_regex(
+        r"(?:c[ộo]ng[ \t]*ti[ềe]n[ \t]*h[àa]ng|t[ổo]ng[ \t]*ti[ềe]n[ \t]*h[àa]ng|th[àa]nh[ \t]*ti[ềe]n(?:[ \t]*tr[ưu][ớo]c[ \t]*thu[ếe])?)",
+        r"(\d[\d.,]*\d|\d)",
+    )
+});
+
+static VAT_AMOUNT: LazyLock<Regex> =
+    LazyLock::new(|| label_regex(r"ti[ềe]n[ \t]*thu[ếe](?:[ \t]*GTGT)?", r"(\d[\d.,]*\d|\d)"));
+
+/// A row of the goods table: STT, name, unit, quantity, unit price, amount
+static LINE_ITEM: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?m)^[ \t|]*(\d{1,3})[ \t|.]+(\S.*?)[ \t|]+(\p{L}[\p{L}\d/]{0,9})[ \t|]+(\d[\d.,]*)[ \t|]+(\d[\d.,]*)[ \t|]+(\d[\d.,]*)[ \t|]*$",
+    )
+    .unwrap()
+});
+
+static GROUPED_THOUSANDS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\d{1,3}(?:([.,])\d{3})(?:[.,]\d{3})*$").unwrap());
+
+/// A goods or service line read from the invoice's table
+#[derive(Debug, Clone, PartialEq)]
+struct LineItem {
+    name: String,
+    unit: String,
+    quantity: f64,
+    unit_price: f64,
+    amount: f64,
+}
+
+/// Parse the text of a Vietnamese invoice into bill candidates
+///
+/// Every line of the goods table becomes one candidate carrying the invoice
+/// header; without a readable table the invoice totals form a single
+/// candidate. Returns no candidates when nothing was recognised.
+pub fn parse_invoice_text(text: &str) -> Vec<GeminiResponse> {
+    let mut header = GeminiResponse::new();
+    header.form_no = capture(&FORM_NO, text);
+    header.serial_no = capture(&SERIAL_NO, text);
+    header.invoice_no = find_invoice_no(text);
+    header.issued_date = ISSUED_DATE.captures(text).and_then(|c| issued_date(&c));
+    header.seller_tax_code = capture(&TAX_CODE, text).and_then(|code| normalize_tax_code(&code));
+    header.seller_name = capture(&SELLER_NAME, text);
+    header.vat_rate = capture(&VAT_RATE, text).and_then(|rate| parse_amount(&rate));
+
+    let total_amount = capture(&TOTAL_AMOUNT, text).and_then(|amount| parse_amount(&amount));
+    let vat_amount = capture(&VAT_AMOUNT, text).and_then(|amount| parse_amount(&amount));
+    let items = line_items(text);
+
+    if items.is_empty() {
+        let mut bill = header;
+        bill.total_amount = total_amount;
+        bill.vat_amount = vat_amount;
+        return if bill.has_essential_data() {
+            vec![bill]
+        } else {
+            Vec::new()
+        };
+    }
+
+    let single_item = items.len() == 1;
+    items
+        .into_iter()
+        .map(|item| {
+            let mut bill = header.clone();
+            bill.vat_amount = match (single_item, vat_amount, header.vat_rate) {
+                (true, Some(vat_amount), _) => Some(vat_amount),
+                (_, _, Some(rate)) => Some((item.amount * rate / 100.0).round()),
+                _ => None,
+            };
+            bill.item_name = Some(item.name);
+            bill.unit = Some(item.unit);
+            bill.quantity = Some(item.quantity);
+            bill.unit_price = Some(item.unit_price);
+            bill.total_amount = Some(item.amount);
+            bill
+        })
+        .collect()
+}
+
+fn capture(regex: &Regex, text: &str) -> Option<String> {
+    regex
+        .captures(text)
+        .map(|c| c[1].trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Find `Số: …`, skipping the `số` of `Mẫu số`
+fn find_invoice_no(text: &str) -> Option<String> {
+    INVOICE_NO.captures_iter(text).find_map(|c| {
+        let label_start = c.get(0)?.start();
+        let before = text[..label_start].to_lowercase();
+        let before = before.trim_end();
+        if before.ends_with("mẫu") || before.ends_with("mau") || before.ends_with("mầu") {
+            return None;
+        }
+        Some(c[1].to_string())
+    })
+}
+
+fn issued_date(captures: &Captures) -> Option<String> {
+    let day: u32 = captures[1].parse().ok()?;
+    let month: u32 = captures[2].parse().ok()?;
+    let year: i32 = captures[3].parse().ok()?;
+    chrono::NaiveDate::from_ymd_opt(year, month, day)
+        .map(|date| date.format("%Y-%m-%d").to_string())
+}
+
+/// Keep a tax code of 10 digits, or 10 digits and a 3-digit branch suffix
+fn normalize_tax_code(code: &str) -> Option<String> {
+    let digits: String = code.chars().filter(char::is_ascii_digit).collect();
+    match digits.len() {
+        10 => Some(digits),
+        13 => Some(format!("{}-{}", &digits[..10], &digits[10..])),
+        _ => None,
+    }
+}
+
+/// Parse a number as printed on Vietnamese invoices, where `.` groups
+/// thousands and `,` marks decimals (`1.250.000`, `1,5`)
+fn parse_amount(value: &str) -> Option<f64> {
+    let value = value.trim().trim_end_matches(['.', ',']);
+    if GROUPED_THOUSANDS.is_match(value) {
+        return value.replace(['.', ','], "").parse().ok();
+    }
+    if value.contains('.') && value.contains(',') {
+        return value.replace('.', "").replace(',', ".").parse().ok();
+    }
+    value.replace(',', ".").parse().ok()
+}
+
+/// Rows of the goods table whose quantity times unit price matches the amount
+fn line_items(text: &str) -> Vec<LineItem> {
+    LINE_ITEM
+        .captures_iter(text)
+        .filter_map(|c| {
+            let item = LineItem {
+                name: c[2].trim().to_string(),
+                unit: c[3].to_string(),
+                quantity: parse_amount(&c[4])?,
+                unit_price: parse_amount(&c[5])?,
+                amount: parse_amount(&c[6])?,
+            };
+            let expected = item.quantity * item.unit_price;
+            ((expected - item.amount).abs() <= item.amount.max(1.0) * 0.01).then_some(item)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVOICE: &str = "\
+HÓA ĐƠN GIÁ TRỊ GIA TĂNG
+(VAT INVOICE)
+Mẫu số (Form): 01GTKT0/001
+Ký hiệu (Serial): AA/24E
+Số (No.): 0000123
+Ngày (Date) 15 tháng (Month) 03 năm (Year) 2024
+Đơn vị bán hàng (Seller): CÔNG TY TNHH THƯƠNG MẠI ABC
+Mã số thuế (Tax code): 0312 345 678
+Họ tên người mua hàng: Nguyễn Văn B
+MST: 0109876543
+STT | Tên hàng hóa, dịch vụ | Đơn vị tính | Số lượng | Đơn giá | Thành tiền
+1 | Giấy in A4 Double A | Ram | 10 | 65.000 | 650.000
+2 | Mực in HP 85A | Hộp | 2 | 1.250.000 | 2.500.000
+Cộng tiền hàng (Total amount): 3.150.000
+Thuế suất GTGT (VAT rate): 10%
+Tiền thuế GTGT (VAT amount): 315.000
+Tổng cộng tiền thanh toán: 3.465.000
+";
+
+    #[test]
+    fn test_parses_header_and_line_items() {
+        let bills = parse_invoice_text(INVOICE);
+
+        assert_eq!(bills.len(), 2);
+        let first = &bills[0];
+        assert_eq!(first.form_no.as_deref(), Some("01GTKT0/001"));
+        assert_eq!(first.serial_no.as_deref(), Some("AA/24E"));
+        assert_eq!(first.invoice_no.as_deref(), Some("0000123"));
+        assert_eq!(first.issued_date.as_deref(), Some("2024-03-15"));
+        assert_eq!(
+            first.seller_name.as_deref(),
+            Some("CÔNG TY TNHH THƯƠNG MẠI ABC")
+        );
+        assert_eq!(first.seller_tax_code.as_deref(), Some("0312345678"));
+        assert_eq!(first.item_name.as_deref(), Some("Giấy in A4 Double A"));
+        assert_eq!(first.unit.as_deref(), Some("Ram"));
+        assert_eq!(first.quantity, Some(10.0));
+        assert_eq!(first.unit_price, Some(65_000.0));
+        assert_eq!(first.total_amount, Some(650_000.0));
+        assert_eq!(first.vat_rate, Some(10.0));
+        assert_eq!(first.vat_amount, Some(65_000.0));
+        assert_eq!(bills[1].total_amount, Some(2_500_000.0));
+        assert_eq!(bills[1].vat_amount, Some(250_000.0));
+    }
+
+    #[test]
+    fn test_falls_back_to_invoice_totals_without_diacritics() {
+        let text = "Mau so: 1C24TAA\nKy hieu: C24TAA\nSo: 42\n\
+Ngay 5 thang 1 nam 2024\nMST: 0312345678-001\n\
+Thanh tien: 1.000.000\nThue suat: 8%\nTien thue: 80.000";
+        let bills = parse_invoice_text(text);
+
+        assert_eq!(bills.len(), 1);
+        assert_eq!(bills[0].form_no.as_deref(), Some("1C24TAA"));
+        assert_eq!(bills[0].invoice_no.as_deref(), Some("42"));
+        assert_eq!(bills[0].issued_date.as_deref(), Some("2024-01-05"));
+        assert_eq!(bills[0].seller_tax_code.as_deref(), Some("0312345678-001"));
+        assert_eq!(bills[0].total_amount, Some(1_000_000.0));
+        assert_eq!(bills[0].vat_rate, Some(8.0));
+        assert_eq!(bills[0].vat_amount, Some(80_000.0));
+        assert_eq!(bills[0].item_name, None);
+    }
+
+    #[test]
+    fn test_unrecognised_text_yields_no_candidates() {
+        assert!(parse_invoice_text("Lorem ipsum dolor sit amet").is_empty());
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("1.250.000"), Some(1_250_000.0));
+        assert_eq!(parse_amount("65.000"), Some(65_000.0));
+        assert_eq!(parse_amount("1,5"), Some(1.5));
+        assert_eq!(parse_amount("1.234,56"), Some(1234.56));
+        assert_eq!(parse_amount("10"), Some(10.0));
+    }
+}
@@ -0,0 +1,583 @@
+//! Webhook registration and delivery
+//!
+//! When a session finishes, one delivery row per active webhook is written to
+//! `webhook_deliveries` with the exact JSON body to post. A dispatcher loop
+//! claims due deliveries with `SELECT ... FOR UPDATE SKIP LOCKED` and posts
+//! them with an HMAC-SHA256 signature. Failed attempts are retried with
+//! exponential backoff until `WEBHOOK_MAX_ATTEMPTS` is reached. Every attempt
+//! is recorded in `webhook_delivery_attempts`, which forms the delivery log
+//! together with the delivery rows.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use sha2::Sha256;
+use sqlx::PgPool;
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+
+use crate::config::WebhookConfig;
+use crate::models::{
+    CreateWebhook, DeliveryStatus, Webhook, WebhookDelivery, WebhookDeliveryAttempt, WebhookPayload,
+};
+
+/// Header carrying the `sha256=<hex>` signature of `<timestamp>.<body>`
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+/// Header carrying the Unix timestamp included in the signature
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+/// Header carrying the event type of the payload
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+/// Header carrying the delivery id; retries of one delivery share it
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+/// Errors returned by webhook operations
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("Webhook database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Invalid webhook URL: {0}")]
+    InvalidUrl(String),
+
+    #[error("Failed to serialize webhook payload: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Failed to create HTTP client: {0}")]
+    Client(String),
+}
+
+/// A delivery claimed by the dispatcher, with the target it is posted to
+#[derive(Debug, Clone)]
+pub struct ClaimedDelivery {
+    pub id: i32,
+    pub event_type: String,
+    pub payload: String,
+    /// Number of attempts including this one
+    pub attempts: i32,
+    pub url: String,
+    pub secret: String,
+}
+
+/// Outcome of posting a delivery once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryAttempt {
+    /// HTTP status of the response, if one was received
+    pub response_status: Option<u16>,
+    /// Why the attempt failed; `None` for a 2xx response
+    pub error: Option<String>,
+}
+
+impl DeliveryAttempt {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Webhook registry and delivery queue
+#[derive(Clone)]
+pub struct WebhookService {
+    pool: PgPool,
+    client: Client,
+    config: Arc<WebhookConfig>,
+    /// Wakes the dispatcher as soon as a delivery is enqueued by this instance
+    delivery_available: Arc<Notify>,
+}
+
+impl WebhookService {
+    pub fn new(pool: PgPool, config: WebhookConfig) -> Result<Self, WebhookError> {
+        let client = Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .map_err(|e| WebhookError::Client(e.to_string()))?;
+
+        Ok(Self {
+            pool,
+            client,
+            config: Arc::new(config),
+            delivery_available: Arc::new(Notify::new()),
+        })
+    }
+
+    pub fn config(&self) -> &WebhookConfig {
+        &self.config
+    }
+
+    /// Register a webhook; the returned webhook includes its secret
+    pub async fn create_webhook(&self, request: CreateWebhook) -> Result<Webhook, WebhookError> {
+        let url = reqwest::Url::parse(&request.url)
+            .map_err(|e| WebhookError::InvalidUrl(format!("{}: {}", request.url, e)))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(WebhookError::InvalidUrl(format!(
+                "{}: only http and https URLs are supported",
+                request.url
+            )));
+        }
+        let secret = request
+            .secret
+            .filter(|secret| !secret.is_empty())
+            .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string());
+
+        let webhook = sqlx::query_as!(
+            Webhook,
+            r#"
+            INSERT INTO webhooks (url, secret)
+            VALUES ($1, $2)
+            RETURNING id, url, secret AS "secret?", active, created_at
+            "#,
+            url.as_str(),
+            secret
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        info!("Registered webhook {} for {}", webhook.id, webhook.url);
+        Ok(webhook)
+    }
+
+    /// List registered webhooks without their secrets
+    pub async fn list_webhooks(&self) -> Result<Vec<Webhook>, WebhookError> {
+        let webhooks = sqlx::query_as!(
+            Webhook,
+            r#"
+            SELECT id, url, NULL::TEXT AS "secret?", active, created_at
+            FROM webhooks
+            ORDER BY id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    /// Remove a webhook together with its delivery log
+    ///
+    /// Returns `false` if the webhook does not exist.
+    pub async fn delete_webhook(&self, id: i32) -> Result<bool, WebhookError> {
+        let result = sqlx::query!("DELETE FROM webhooks WHERE id = $1", id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Most recent deliveries of a webhook with their attempts, newest first
+    pub async fn list_deliveries(
+        &self,
+        webhook_id: i32,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, webhook_id, session_id, event_type, payload, status, attempts,
+                   next_attempt_at, created_at, delivered_at
+            FROM webhook_deliveries
+            WHERE webhook_id = $1
+            ORDER BY created_at DESC, id DESC
+            LIMIT $2
+            "#,
+            webhook_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let delivery_ids: Vec<i32> = rows.iter().map(|row| row.id).collect();
+        let attempt_rows = sqlx::query!(
+            r#"
+            SELECT delivery_id, attempt, status, response_status, error, attempted_at
+            FROM webhook_delivery_attempts
+            WHERE delivery_id = ANY($1)
+            ORDER BY delivery_id, attempt, id
+            "#,
+            &delivery_ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut attempts: HashMap<i32, Vec<WebhookDeliveryAttempt>> = HashMap::new();
+        for row in attempt_rows {
+            attempts
+                .entry(row.delivery_id)
+                .or_default()
+                .push(WebhookDeliveryAttempt {
+                    attempt: row.attempt,
+                    status: row.status,
+                    response_status: row.response_status,
+                    error: row.error,
+                    attempted_at: row.attempted_at,
+                });
+        }
+
+        Ok(rows
+            .into_iter()
+            .map(|row| WebhookDelivery {
+                attempt_log: attempts.remove(&row.id).unwrap_or_default(),
+                id: row.id,
+                webhook_id: row.webhook_id,
+                session_id: row.session_id,
+                event_type: row.event_type,
+                payload: row.payload,
+                status: row.status,
+                attempts: row.attempts,
+                next_attempt_at: row.next_attempt_at,
+                created_at: row.created_at,
+                delivered_at: row.delivered_at,
+            })
+            .collect())
+    }
+
+    /// Queue a delivery of the payload to every active webhook
+    ///
+    /// Returns the number of deliveries queued.
+    pub async fn enqueue(&self, payload: &WebhookPayload) -> Result<u64, WebhookError> {
+        let body = serde_json::to_string(payload)?;
+
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO webhook_deliveries (webhook_id, session_id, event_type, payload)
+            SELECT id, $1, $2, $3
+            FROM webhooks
+            WHERE active
+            "#,
+            payload.session_id,
+            payload.event_type,
+            body
+        )
+        .execute(&self.pool)
+        .await?;
+
+        let queued = result.rows_affected();
+        if queued > 0 {
+            debug!(
+                "Queued {} webhook delivery(ies) of {} for session {}",
+                queued, payload.event_type, payload.session_id
+            );
+            self.delivery_available.notify_one();
+        }
+        Ok(queued)
+    }
+
+    /// Claim the oldest pending delivery that is due
+    ///
+    /// The claim pushes `next_attempt_at` past the request timeout, so a
+    /// delivery whose dispatcher stopped mid-request is retried later.
+    pub async fn claim_due(&self) -> Result<Option<ClaimedDelivery>, WebhookError> {
+        let lease_seconds = (self.config.timeout * 2).as_secs_f64();
+
+        let claimed = sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries AS d
+            SET attempts = d.attempts + 1,
+                next_attempt_at = NOW() + make_interval(secs => $1)
+            FROM webhooks AS w
+            WHERE w.id = d.webhook_id
+              AND d.id = (
+                  SELECT id
+                  FROM webhook_deliveries
+                  WHERE status = 'pending' AND next_attempt_at <= NOW()
+                  ORDER BY next_attempt_at
+                  LIMIT 1
+                  FOR UPDATE SKIP LOCKED
+              )
+            RETURNING d.id, d.event_type, d.payload, d.attempts, w.url, w.secret
+            "#,
+            lease_seconds
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(claimed.map(|row| ClaimedDelivery {
+            id: row.id,
+            event_type: row.event_type,
+            payload: row.payload,
+            attempts: row.attempts,
+            url: row.url,
+            secret: row.secret,
+        }))
+    }
+
+    /// Post a claimed delivery once
+    pub async fn send(&self, delivery: &ClaimedDelivery) -> DeliveryAttempt {
+        send_delivery(&self.client, delivery).await
+    }
+
+    /// Record an attempt in the delivery log and schedule a retry if one is left
+    pub async fn record_attempt(
+        &self,
+        delivery: &ClaimedDelivery,
+        attempt: &DeliveryAttempt,
+    ) -> Result<DeliveryStatus, WebhookError> {
+        let status = if attempt.is_success() {
+            DeliveryStatus::Delivered
+        } else if delivery.attempts >= self.config.max_attempts {
+            DeliveryStatus::Failed
+        } else {
+            DeliveryStatus::Pending
+        };
+        let retry_in = self.config.backoff(delivery.attempts).as_secs_f64();
+        let attempt_status = if attempt.is_success() {
+            DeliveryStatus::Delivered
+        } else {
+            DeliveryStatus::Failed
+        };
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO webhook_delivery_attempts (
+                delivery_id, attempt, status, response_status, error
+            )
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            delivery.id,
+            delivery.attempts,
+            attempt_status.as_str(),
+            attempt.response_status.map(i32::from),
+            attempt.error.as_deref()
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = $2,
+                next_attempt_at = CASE WHEN $2 = 'pending'
+                                       THEN NOW() + make_interval(secs => $3)
+                                       ELSE next_attempt_at END,
+                delivered_at = CASE WHEN $2 = 'delivered' THEN NOW() ELSE NULL END
+            WHERE id = $1
+            "#,
+            delivery.id,
+            status.as_str(),
+            retry_in
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(status)
+    }
+
+    /// Wait until a delivery is enqueued by this instance or the poll interval passes
+    pub async fn wait_for_delivery(&self) {
+        let _ = tokio::time::timeout(
+            self.config.poll_interval,
+            self.delivery_available.notified(),
+        )
+        .await;
+    }
+}
+
+/// Post a delivery with its signature headers
+///
+/// Any 2xx response counts as delivered.
+pub async fn send_delivery(client: &Client, delivery: &ClaimedDelivery) -> DeliveryAttempt {
+    let timestamp = Utc::now().timestamp();
+    let signature = sign_payload(&delivery.secret, timestamp, &delivery.payload);
+
+    let response = client
+        .post(&delivery.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, signature)
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(EVENT_HEADER, &delivery.event_type)
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .body(delivery.payload.clone())
+        .send()
+        .await;
+
+    match response {
+        Ok(response) if response.status().is_success() => DeliveryAttempt {
+            response_status: Some(response.status().as_u16()),
+            error: None,
+        },
+        Ok(response) => {
+            let status = response.status();
+            warn!(
+                "Webhook delivery {} to {} was rejected with {}",
+                delivery.id, delivery.url, status
+            );
+            DeliveryAttempt {
+                response_status: Some(status.as_u16()),
+                error: Some(format!("Endpoint responded with {status}")),
+            }
+        }
+        Err(e) => {
+            warn!(
+                "Webhook delivery {} to {} failed: {}",
+                delivery.id, delivery.url, e
+            );
+            DeliveryAttempt {
+                response_status: None,
+                error: Some(e.to_string()),
+            }
+        }
+    }
+}
+
+/// Signature of a payload: `sha256=` followed by the hex HMAC-SHA256 of
+/// `<timestamp>.<body>` keyed with the webhook secret
+pub fn sign_payload(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, http::HeaderMap, http::StatusCode, routing::post};
+    use std::sync::Mutex;
+
+    /// Local HTTP stand-in that records requests and answers with `status`
+    async fn spawn_receiver(status: StatusCode) -> (String, Arc<Mutex<Vec<(HeaderMap, String)>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let recorder = received.clone();
+        let app = Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: String| {
+                let recorder = recorder.clone();
+                async move {
+                    recorder.lock().unwrap().push((headers, body));
+                    status
+                }
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (format!("http://{address}/hook"), received)
+    }
+
+    fn delivery(url: String) -> ClaimedDelivery {
+        ClaimedDelivery {
+            id: 1,
+            event_type: "processing_complete".to_string(),
+            payload: r#"{"session_id":"s1","saved_bill_ids":[1,2]}"#.to_string(),
+            attempts: 1,
+            url,
+            secret: "top-secret".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_sign_payload() {
+        let signature = sign_payload("key", 1_700_000_000, "{}");
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert_eq!(signature, sign_payload("key", 1_700_000_000, "{}"));
+        assert_ne!(signature, sign_payload("other", 1_700_000_000, "{}"));
+        assert_ne!(signature, sign_payload("key", 1_700_000_001, "{}"));
+    }
+
+    #[tokio::test]
+    async fn test_send_delivery_signs_payload() {
+        let (url, received) = spawn_receiver(StatusCode::NO_CONTENT).await;
+        let delivery = delivery(url);
+
+        let attempt = send_delivery(&Client::new(), &delivery).await;
+        assert!(attempt.is_success());
+        assert_eq!(attempt.response_status, Some(204));
+
+        let received = received.lock().unwrap();
+        let (headers, body) = &received[0];
+        assert_eq!(body, &delivery.payload);
+        assert_eq!(headers[EVENT_HEADER], "processing_complete");
+        assert_eq!(headers[DELIVERY_HEADER], "1");
+
+        let timestamp: i64 = headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+        assert_eq!(
+            headers[SIGNATURE_HEADER].to_str().unwrap(),
+            sign_payload("top-secret", timestamp, body)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_delivery_reports_rejection() {
+        let (url, _received) = spawn_receiver(StatusCode::INTERNAL_SERVER_ERROR).await;
+
+        let attempt = send_delivery(&Client::new(), &delivery(url)).await;
+        assert!(!attempt.is_success());
+        assert_eq!(attempt.response_status, Some(500));
+    }
+
+    #[tokio::test]
+    async fn test_send_delivery_reports_unreachable_endpoint() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        drop(listener);
+
+        let attempt = send_delivery(&Client::new(), &delivery(url)).await;
+        assert!(!attempt.is_success());
+        assert_eq!(attempt.response_status, None);
+    }
+
+    #[sqlx::test]
+    async fn test_every_attempt_is_kept_in_the_delivery_log(pool: PgPool) {
+        let (url, _received) = spawn_receiver(StatusCode::SERVICE_UNAVAILABLE).await;
+        let webhooks = WebhookService::new(
+            pool,
+            WebhookConfig {
+                max_attempts: 3,
+                initial_backoff: std::time::Duration::ZERO,
+                max_backoff: std::time::Duration::ZERO,
+                timeout: std::time::Duration::from_secs(5),
+                poll_interval: std::time::Duration::from_secs(1),
+            },
+        )
+        .unwrap();
+        let webhook = webhooks
+            .create_webhook(CreateWebhook { url, secret: None })
+            .await
+            .unwrap();
+        let payload = WebhookPayload {
+            event_type: "processing_cancelled".to_string(),
+            session_id: "s1".to_string(),
+            status: crate::models::SessionStatus::Cancelled,
+            total_files: 1,
+            successful_files: None,
+            saved_bill_ids: Vec::new(),
+            error_message: None,
+            timestamp: Utc::now(),
+        };
+        assert_eq!(webhooks.enqueue(&payload).await.unwrap(), 1);
+
+        let first = webhooks.claim_due().await.unwrap().unwrap();
+        let rejected = webhooks.send(&first).await;
+        let status = webhooks.record_attempt(&first, &rejected).await.unwrap();
+        assert_eq!(status, DeliveryStatus::Pending);
+
+        let second = webhooks.claim_due().await.unwrap().unwrap();
+        let delivered = DeliveryAttempt {
+            response_status: Some(200),
+            error: None,
+        };
+        let status = webhooks.record_attempt(&second, &delivered).await.unwrap();
+        assert_eq!(status, DeliveryStatus::Delivered);
+
+        let deliveries = webhooks.list_deliveries(webhook.id, 10).await.unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].status, "delivered");
+        assert_eq!(deliveries[0].attempts, 2);
+        let log = &deliveries[0].attempt_log;
+        assert_eq!(log.len(), 2);
+        assert_eq!((log[0].attempt, log[0].status.as_str()), (1, "failed"));
+        assert_eq!(log[0].response_status, Some(503));
+        assert!(log[0].error.is_some());
+        assert_eq!((log[1].attempt, log[1].status.as_str()), (2, "delivered"));
+        assert_eq!(log[1].response_status, Some(200));
+        assert_eq!(log[1].error, None);
+    }
+}
@@ -0,0 +1,306 @@
+//! Safe expansion of uploaded ZIP archives
+//!
+//! The central directory is checked before anything is extracted: the
+//! archive is rejected if it has too many files, declares more uncompressed
+//! data than allowed or contains an entry whose path would escape the
+//! archive (absolute paths, `..`). Entries are then extracted one at a
+//! time, and the uncompressed size is enforced again while reading in case
+//! the declared sizes are wrong.
+//!
+//! The limits cover a whole upload: files taken by earlier fields and
+//! archives of the same upload are passed in and count toward them.
+
+use std::io::{Read, Seek};
+use std::path::{Component, Path};
+
+use axum::body::Bytes;
+use zip::ZipArchive;
+
+/// Content types browsers and clients send for ZIP files
+const ZIP_CONTENT_TYPES: &[&str] = &[
+    "application/zip",
+    "application/x-zip-compressed",
+    "application/x-zip",
+];
+
+/// Limits applied to the files of an upload, which may hold several archives
+#[derive(Debug, Clone, Copy)]
+pub struct ArchiveLimits {
+    /// Number of files (directories and skipped entries excluded)
+    pub max_entries: usize,
+    /// Combined uncompressed size of the files
+    pub max_uncompressed_bytes: usize,
+    /// Files the upload already holds, from its earlier fields and archives
+    pub used_entries: usize,
+    /// Uncompressed bytes the upload already holds
+    pub used_bytes: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ArchiveError {
+    #[error("Invalid ZIP archive: {0}")]
+    Invalid(String),
+
+    #[error("Archive entry {0:?} has an unsafe path")]
+    UnsafePath(String),
+
+    #[error("Upload has {count} files, limit is {limit}")]
+    TooManyEntries { count: usize, limit: usize },
+
+    #[error("Upload uncompressed size {size} exceeds limit {limit}")]
+    TooLarge { size: usize, limit: usize },
+}
+
+impl From<zip::result::ZipError> for ArchiveError {
+    fn from(err: zip::result::ZipError) -> Self {
+        ArchiveError::Invalid(err.to_string())
+    }
+}
+
+/// A file extracted from an archive
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    /// Path of the file inside the archive
+    pub name: String,
+    pub data: Bytes,
+}
+
+/// Whether an upload field holds a ZIP archive, judged by its content type or file name
+pub fn is_zip_upload(content_type: Option<&str>, file_name: Option<&str>) -> bool {
+    content_type.is_some_and(|content_type| ZIP_CONTENT_TYPES.contains(&content_type))
+        || file_name.is_some_and(|file_name| file_name.to_ascii_lowercase().ends_with(".zip"))
+}
+
+/// Extracts the files of a validated archive one at a time
+pub struct ArchiveReader<R> {
+    archive: ZipArchive<R>,
+    /// Indices of the entries to extract, in archive order
+    entries: Vec<usize>,
+    position: usize,
+    extracted_bytes: usize,
+    limits: ArchiveLimits,
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    /// Open an archive and check its central directory against `limits`
+    pub fn open(reader: R, limits: ArchiveLimits) -> Result<Self, ArchiveError> {
+        let mut archive = ZipArchive::new(reader)?;
+        let mut entries = Vec::new();
+        let mut declared_bytes = limits.used_bytes;
+
+        for index in 0..archive.len() {
+            let file = archive.by_index_raw(index)?;
+            if file.enclosed_name().is_none() {
+                return Err(ArchiveError::UnsafePath(file.name().to_string()));
+            }
+            if file.is_dir() || file.is_symlink() || is_hidden(file.name()) {
+                continue;
+            }
+
+            entries.push(index);
+            if limits.used_entries + entries.len() > limits.max_entries {
+                return Err(ArchiveError::TooManyEntries {
+                    count: limits.used_entries + entries.len(),
+                    limit: limits.max_entries,
+                });
+            }
+
+            declared_bytes = declared_bytes.saturating_add(file.size() as usize);
+            if declared_bytes > limits.max_uncompressed_bytes {
+                return Err(ArchiveError::TooLarge {
+                    size: declared_bytes,
+                    limit: limits.max_uncompressed_bytes,
+                });
+            }
+        }
+
+        Ok(Self {
+            archive,
+            entries,
+            position: 0,
+            extracted_bytes: limits.used_bytes,
+            limits,
+        })
+    }
+
+    /// Number of files that will be extracted
+    pub fn entry_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Extract the next file, or `None` once every file has been extracted
+    pub fn next_entry(&mut self) -> Result<Option<ArchiveEntry>, ArchiveError> {
+        let Some(&index) = self.entries.get(self.position) else {
+            return Ok(None);
+        };
+        self.position += 1;
+
+        let remaining = self
+            .limits
+            .max_uncompressed_bytes
+            .saturating_sub(self.extracted_bytes);
+        let file = self.archive.by_index(index)?;
+        let name = file.name().to_string();
+
+        // Read one byte past the budget to detect entries larger than declared
+        let mut data = Vec::new();
+        file.take(remaining as u64 + 1)
+            .read_to_end(&mut data)
+            .map_err(|e| ArchiveError::Invalid(format!("{name}: {e}")))?;
+        if data.len() > remaining {
+            return Err(ArchiveError::TooLarge {
+                size: self.extracted_bytes + data.len(),
+                limit: self.limits.max_uncompressed_bytes,
+            });
+        }
+        self.extracted_bytes += data.len();
+
+        Ok(Some(ArchiveEntry {
+            name,
+            data: Bytes::from(data),
+        }))
+    }
+}
+
+/// Hidden files and macOS resource forks (`__MACOSX/`, `.DS_Store`) are not scans
+fn is_hidden(name: &str) -> bool {
+    Path::new(name)
+        .components()
+        .any(|component| match component {
+            Component::Normal(part) => {
+                let part = part.to_string_lossy();
+                part.starts_with('.') || part == "__MACOSX"
+            }
+            _ => false,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+
+    const LIMITS: ArchiveLimits = ArchiveLimits {
+        max_entries: 3,
+        max_uncompressed_bytes: 1024,
+        used_entries: 0,
+        used_bytes: 0,
+    };
+
+    fn zip_with(files: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            if name.ends_with('/') {
+                writer
+                    .add_directory(*name, SimpleFileOptions::default())
+                    .unwrap();
+            } else {
+                writer
+                    .start_file(*name, SimpleFileOptions::default())
+                    .unwrap();
+                writer.write_all(data).unwrap();
+            }
+        }
+        let mut cursor = writer.finish().unwrap();
+        cursor.set_position(0);
+        cursor
+    }
+
+    #[test]
+    fn test_extracts_files_in_order_and_skips_hidden_entries() {
+        let archive = zip_with(&[
+            ("october/", b""),
+            ("october/scan-1.jpg", b"one"),
+            ("__MACOSX/october/._scan-1.jpg", b"fork"),
+            (".DS_Store", b"meta"),
+            ("october/scan-2.jpg", b"two"),
+        ]);
+
+        let mut reader = ArchiveReader::open(archive, LIMITS).unwrap();
+        assert_eq!(reader.entry_count(), 2);
+
+        let first = reader.next_entry().unwrap().unwrap();
+        assert_eq!(first.name, "october/scan-1.jpg");
+        assert_eq!(first.data.as_ref(), b"one");
+        let second = reader.next_entry().unwrap().unwrap();
+        assert_eq!(second.name, "october/scan-2.jpg");
+        assert!(reader.next_entry().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rejects_path_traversal() {
+        let archive = zip_with(&[("scan.jpg", b"ok"), ("../../etc/cron.d/job", b"evil")]);
+
+        assert!(matches!(
+            ArchiveReader::open(archive, LIMITS),
+            Err(ArchiveError::UnsafePath(name)) if name == "../../etc/cron.d/job"
+        ));
+    }
+
+    #[test]
+    fn test_enforces_entry_and_size_limits() {
+        let archive = zip_with(&[
+            ("1.jpg", b"a"),
+            ("2.jpg", b"b"),
+            ("3.jpg", b"c"),
+            ("4.jpg", b"d"),
+        ]);
+        assert!(matches!(
+            ArchiveReader::open(archive, LIMITS),
+            Err(ArchiveError::TooManyEntries { count: 4, limit: 3 })
+        ));
+
+        let large = vec![0u8; 800];
+        let archive = zip_with(&[("1.jpg", &large), ("2.jpg", &large)]);
+        assert!(matches!(
+            ArchiveReader::open(archive, LIMITS),
+            Err(ArchiveError::TooLarge {
+                size: 1600,
+                limit: 1024
+            })
+        ));
+    }
+
+    #[test]
+    fn test_limits_count_files_already_in_the_upload() {
+        let limits = ArchiveLimits {
+            used_entries: 2,
+            used_bytes: 600,
+            ..LIMITS
+        };
+
+        let archive = zip_with(&[("1.jpg", b"a"), ("2.jpg", b"b")]);
+        assert!(matches!(
+            ArchiveReader::open(archive, limits),
+            Err(ArchiveError::TooManyEntries { count: 4, limit: 3 })
+        ));
+
+        let archive = zip_with(&[("1.jpg", &[0u8; 500])]);
+        assert!(matches!(
+            ArchiveReader::open(archive, limits),
+            Err(ArchiveError::TooLarge {
+                size: 1100,
+                limit: 1024
+            })
+        ));
+    }
+
+    #[test]
+    fn test_rejects_data_that_is_not_a_zip() {
+        assert!(matches!(
+            ArchiveReader::open(Cursor::new(b"not a zip".to_vec()), LIMITS),
+            Err(ArchiveError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_is_zip_upload() {
+        assert!(is_zip_upload(Some("application/zip"), None));
+        assert!(is_zip_upload(
+            Some("application/octet-stream"),
+            Some("Batch-Oct.ZIP")
+        ));
+        assert!(!is_zip_upload(Some("image/jpeg"), Some("scan.jpg")));
+    }
+}
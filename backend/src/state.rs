@@ -1,17 +1,24 @@
 use axum::extract::FromRef;
 use std::sync::Arc;
-use tokio::sync::broadcast;
 
 use crate::{
     config::{ConnectionPool, UploadConfig},
-    models::ProcessingEvent,
+    services::{
+        extraction_provider::SharedExtractionProvider, image_store::ImageStore,
+        ocr_job_queue::OcrJobQueue, session_registry::SessionRegistry,
+        webhook_service::WebhookService,
+    },
 };
 
 #[derive(Clone)]
 pub struct AppState {
     pub pool: ConnectionPool,
     pub upload_config: Arc<UploadConfig>,
-    pub event_broadcaster: broadcast::Sender<ProcessingEvent>,
+    pub sessions: SessionRegistry,
+    pub job_queue: OcrJobQueue,
+    pub webhooks: WebhookService,
+    pub image_store: ImageStore,
+    pub extraction: SharedExtractionProvider,
 }
 
 impl FromRef<AppState> for ConnectionPool {
@@ -26,8 +33,32 @@ impl FromRef<AppState> for Arc<UploadConfig> {
     }
 }
 
-impl FromRef<AppState> for broadcast::Sender<ProcessingEvent> {
+impl FromRef<AppState> for SessionRegistry {
     fn from_ref(app_state: &AppState) -> Self {
-        app_state.event_broadcaster.clone()
+        app_state.sessions.clone()
+    }
+}
+
+impl FromRef<AppState> for OcrJobQueue {
+    fn from_ref(app_state: &AppState) -> Self {
+        app_state.job_queue.clone()
+    }
+}
+
+impl FromRef<AppState> for ImageStore {
+    fn from_ref(app_state: &AppState) -> Self {
+        app_state.image_store.clone()
+    }
+}
+
+impl FromRef<AppState> for WebhookService {
+    fn from_ref(app_state: &AppState) -> Self {
+        app_state.webhooks.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedExtractionProvider {
+    fn from_ref(app_state: &AppState) -> Self {
+        app_state.extraction.clone()
     }
 }
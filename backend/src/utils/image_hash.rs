@@ -0,0 +1,126 @@
+//! Content and perceptual hashes of uploaded images
+//!
+//! The SHA-256 of the file bytes finds exact re-uploads. The perceptual hash
+//! is a 64-bit difference hash: it survives re-encoding, resizing and small
+//! brightness changes, so the same photo sent again through another app or
+//! at another quality stays within a few bits of the original.
+//!
+//! Stored hashes are also split into 16-bit bands, indexed in the database,
+//! so near duplicates can be looked up without comparing every stored hash.
+
+use image::{GrayImage, imageops::FilterType};
+use sha2::{Digest, Sha256};
+
+/// Hex encoded SHA-256 of the file bytes
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Difference hash of an image
+///
+/// The image is shrunk to 9x8 pixels; each bit tells whether a pixel is
+/// brighter than its right-hand neighbour.
+pub fn perceptual_hash(image: &GrayImage) -> u64 {
+    let small = image::imageops::resize(image, 9, 8, FilterType::Triangle);
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = small.get_pixel(x, y)[0];
+            let right = small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(left > right);
+        }
+    }
+    hash
+}
+
+/// Number of 16-bit bands a perceptual hash is split into
+pub const HASH_BANDS: usize = 4;
+
+/// The 16-bit bands of a perceptual hash, lowest bits first
+pub fn hash_bands(hash: u64) -> [u16; HASH_BANDS] {
+    std::array::from_fn(|band| (hash >> (16 * band)) as u16)
+}
+
+/// For each band of `hash`, the values within `max_distance / 4` bits of it
+///
+/// Two hashes that differ in at most `max_distance` bits have at least one
+/// band that differs in at most `max_distance / 4` bits, so a hash that close
+/// has one of its bands among the returned values. There are 17 values per
+/// band up to a distance of 7, 137 up to 11 and 697 up to 15.
+pub fn band_neighbours(hash: u64, max_distance: u32) -> [Vec<u16>; HASH_BANDS] {
+    let radius = max_distance / HASH_BANDS as u32;
+    let masks: Vec<u16> = (0..=u16::MAX)
+        .filter(|mask| mask.count_ones() <= radius)
+        .collect();
+    hash_bands(hash).map(|band| masks.iter().map(|mask| band ^ mask).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn hamming_distance(a: u64, b: u64) -> u32 {
+        (a ^ b).count_ones()
+    }
+
+    fn gradient(width: u32, height: u32) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, y| {
+            Luma([((x * 7 + y * 3) % 256) as u8 ^ if (x / 40 + y / 30) % 2 == 0 { 0 } else { 90 }])
+        })
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_resized_and_brightened_copy_stays_close() {
+        let original = gradient(400, 300);
+        let resized = image::imageops::resize(&original, 200, 150, FilterType::Lanczos3);
+        let mut brighter = original.clone();
+        for pixel in brighter.pixels_mut() {
+            pixel[0] = pixel[0].saturating_add(10);
+        }
+        let other = GrayImage::from_fn(400, 300, |x, y| Luma([((x * y) % 251) as u8]));
+
+        let hash = perceptual_hash(&original);
+        assert!(hamming_distance(hash, perceptual_hash(&resized)) <= 4);
+        assert!(hamming_distance(hash, perceptual_hash(&brighter)) <= 4);
+        assert!(hamming_distance(hash, perceptual_hash(&other)) > 10);
+    }
+
+    #[test]
+    fn test_close_hashes_share_a_band_neighbour() {
+        let hash = 0x0123_4567_89ab_cdef;
+        assert_eq!(hash_bands(hash), [0xcdef, 0x89ab, 0x4567, 0x0123]);
+        assert_eq!(band_neighbours(hash, 3)[0], vec![0xcdef]);
+        assert_eq!(band_neighbours(hash, 6)[0].len(), 17);
+
+        // Flips spread as evenly as possible over the bands
+        for (max_distance, flipped_bits) in [
+            (3, vec![0, 16, 32]),
+            (6, vec![0, 1, 16, 17, 32, 48]),
+            (7, vec![0, 1, 16, 17, 32, 33, 48]),
+            (11, vec![0, 1, 2, 16, 17, 18, 32, 33, 34, 48, 49]),
+        ] {
+            let other = flipped_bits
+                .iter()
+                .fold(hash, |other, bit| other ^ (1 << bit));
+            assert_eq!(hamming_distance(hash, other), max_distance);
+
+            let neighbours = band_neighbours(hash, max_distance);
+            assert!(
+                hash_bands(other)
+                    .iter()
+                    .zip(&neighbours)
+                    .any(|(band, values)| values.contains(band)),
+                "no band of a hash {max_distance} bits away is a neighbour"
+            );
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! Image preprocessing steps
+//!
+//! Phone photos of invoices arrive tilted, with the table around the paper
+//! in frame, and often dim. These steps straighten, crop and brighten them
+//! before extraction. Each works on the whole image with the `image` crate
+//! only; detection runs on a downscaled grayscale copy.
+
+use image::{GrayImage, Luma, Rgb, RgbImage, imageops::FilterType};
+
+/// Long edge of the grayscale copy used to detect skew and document edges
+const ANALYSIS_EDGE: u32 = 1000;
+
+/// Smallest tilt worth rotating the image for, in degrees
+const MIN_SKEW_DEGREES: f32 = 0.2;
+
+/// Share of the brightness histogram clipped at either end by contrast normalization
+const CONTRAST_CLIP: f64 = 0.01;
+
+/// Bounds of the document found in an image, in pixels of that image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DocumentBounds {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Downscale a grayscale image so its long edge is at most `ANALYSIS_EDGE`
+///
+/// Returns the copy and the factor from its coordinates to the original's.
+fn analysis_copy(gray: &GrayImage) -> (GrayImage, f32) {
+    let (width, height) = gray.dimensions();
+    let long_edge = width.max(height);
+    if long_edge <= ANALYSIS_EDGE {
+        return (gray.clone(), 1.0);
+    }
+    let scale = ANALYSIS_EDGE as f32 / long_edge as f32;
+    let small = image::imageops::resize(
+        gray,
+        ((width as f32 * scale).round() as u32).max(1),
+        ((height as f32 * scale).round() as u32).max(1),
+        FilterType::Triangle,
+    );
+    (small, 1.0 / scale)
+}
+
+fn histogram(gray: &GrayImage) -> [u64; 256] {
+    let mut histogram = [0u64; 256];
+    for Luma([value]) in gray.pixels() {
+        histogram[*value as usize] += 1;
+    }
+    histogram
+}
+
+/// Otsu's threshold, with the mean brightness of the classes below and above it
+fn otsu_threshold(histogram: &[u64; 256]) -> (u8, f64, f64) {
+    let total: u64 = histogram.iter().sum();
+    let weighted_total: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, count)| value as f64 * *count as f64)
+        .sum();
+
+    let mut best = (0u8, 0.0, 0.0);
+    let mut best_variance = -1.0;
+    let mut below = 0u64;
+    let mut weighted_below = 0.0;
+    for (value, count) in histogram.iter().enumerate() {
+        below += count;
+        weighted_below += value as f64 * *count as f64;
+        let above = total - below;
+        if below == 0 || above == 0 {
+            continue;
+        }
+        let mean_below = weighted_below / below as f64;
+        let mean_above = (weighted_total - weighted_below) / above as f64;
+        let variance = below as f64 * above as f64 * (mean_below - mean_above).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best = (value as u8, mean_below, mean_above);
+        }
+    }
+    best
+}
+
+/// Find the light sheet of paper in a photo taken against a darker background
+///
+/// Returns `None` when the image has no such background, such as a scan, or
+/// when the paper would cover less than a fifth of the image.
+pub fn find_document_bounds(gray: &GrayImage) -> Option<DocumentBounds> {
+    let (small, scale) = analysis_copy(gray);
+    let (width, height) = small.dimensions();
+    let (threshold, dark_mean, light_mean) = otsu_threshold(&histogram(&small));
+    if light_mean - dark_mean < 40.0 {
+        return None;
+    }
+
+    let mut column_light = vec![0u32; width as usize];
+    let mut row_light = vec![0u32; height as usize];
+    for (x, y, Luma([value])) in small.enumerate_pixels() {
+        if *value > threshold {
+            column_light[x as usize] += 1;
+            row_light[y as usize] += 1;
+        }
+    }
+
+    let span = |counts: &[u32]| {
+        let max = *counts.iter().max()?;
+        let limit = max.div_ceil(2).max(1);
+        let first = counts.iter().position(|count| *count >= limit)?;
+        let last = counts.iter().rposition(|count| *count >= limit)?;
+        Some((first as u32, last as u32))
+    };
+    let (left, right) = span(&column_light)?;
+    let (top, bottom) = span(&row_light)?;
+
+    let box_area = ((right - left + 1) * (bottom - top + 1)) as f32;
+    let image_area = (width * height) as f32;
+    if box_area < image_area * 0.2 || box_area > image_area * 0.97 {
+        return None;
+    }
+
+    // Keep a thin margin so that text at the paper's edge is not cut
+    let margin_x = width / 100;
+    let margin_y = height / 100;
+    let left = left.saturating_sub(margin_x);
+    let top = top.saturating_sub(margin_y);
+    let right = (right + margin_x).min(width - 1);
+    let bottom = (bottom + margin_y).min(height - 1);
+
+    let (full_width, full_height) = gray.dimensions();
+    let x = ((left as f32 * scale) as u32).min(full_width - 1);
+    let y = ((top as f32 * scale) as u32).min(full_height - 1);
+    let end_x = (((right + 1) as f32 * scale).ceil() as u32).min(full_width);
+    let end_y = (((bottom + 1) as f32 * scale).ceil() as u32).min(full_height);
+    Some(DocumentBounds {
+        x,
+        y,
+        width: end_x - x,
+        height: end_y - y,
+    })
+}
+
+/// Estimate the tilt of the text lines, in degrees within `±max_degrees`
+///
+/// Dark pixels are projected onto the vertical axis at candidate angles; the
+/// angle at which text lines line up gives the most peaked profile. A
+/// positive angle means lines descend to the right.
+pub fn detect_skew(gray: &GrayImage, max_degrees: f32) -> f32 {
+    let (small, _) = analysis_copy(gray);
+    let (width, height) = small.dimensions();
+    let (threshold, dark_mean, light_mean) = otsu_threshold(&histogram(&small));
+    if light_mean - dark_mean < 40.0 {
+        return 0.0;
+    }
+
+    let mut dark: Vec<(f32, f32)> = small
+        .enumerate_pixels()
+        .filter(|(_, _, Luma([value]))| *value <= threshold)
+        .map(|(x, y, _)| (x as f32, y as f32))
+        .collect();
+    let pixel_count = (width * height) as usize;
+    if dark.len() < 100 || dark.len() > pixel_count / 2 {
+        return 0.0;
+    }
+    if dark.len() > 100_000 {
+        let step = dark.len() / 100_000 + 1;
+        dark = dark.into_iter().step_by(step).collect();
+    }
+
+    let diagonal = ((width * width + height * height) as f32).sqrt().ceil() as usize;
+    let mut bins = vec![0u32; diagonal * 2 + 2];
+    let mut score = |degrees: f32| {
+        bins.iter_mut().for_each(|bin| *bin = 0);
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        for (x, y) in &dark {
+            let row = y * cos - x * sin + diagonal as f32;
+            bins[row as usize] += 1;
+        }
+        bins.iter()
+            .map(|count| (*count as f64).powi(2))
+            .sum::<f64>()
+    };
+
+    let mut search = |from: f32, to: f32, step: f32, best: (f32, f64)| {
+        let mut best = best;
+        let steps = ((to - from) / step).round() as i32;
+        for i in 0..=steps {
+            let degrees = from + i as f32 * step;
+            let value = score(degrees);
+            if value > best.1 {
+                best = (degrees, value);
+            }
+        }
+        best
+    };
+    let (coarse, coarse_score) = search(-max_degrees, max_degrees, 0.5, (0.0, -1.0));
+    let (fine, _) = search(
+        (coarse - 0.5).max(-max_degrees),
+        (coarse + 0.5).min(max_degrees),
+        0.1,
+        (coarse, coarse_score),
+    );
+    fine
+}
+
+/// Straighten an image whose text lines are tilted by up to `max_degrees`
+///
+/// Returns the straightened image and the angle it was rotated by, or
+/// `None` when the tilt is too small to matter.
+pub fn deskew(image: &RgbImage, max_degrees: f32) -> Option<(RgbImage, f32)> {
+    let skew = detect_skew(&image::imageops::grayscale(image), max_degrees);
+    if skew.abs() < MIN_SKEW_DEGREES {
+        return None;
+    }
+    Some((rotate(image, -skew), -skew))
+}
+
+/// Rotate an image about its center, growing the canvas to keep the corners
+///
+/// Positive angles turn the content clockwise as displayed. Uncovered areas
+/// are filled with white, like the paper.
+pub fn rotate(image: &RgbImage, degrees: f32) -> RgbImage {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (width, height) = image.dimensions();
+    let (w, h) = (width as f32, height as f32);
+    // The small allowance absorbs rounding in sin/cos of right angles
+    let out_width = (w * cos.abs() + h * sin.abs() - 1e-3).ceil() as u32;
+    let out_height = (w * sin.abs() + h * cos.abs() - 1e-3).ceil() as u32;
+    let (center_x, center_y) = (w / 2.0, h / 2.0);
+    let (out_center_x, out_center_y) = (out_width as f32 / 2.0, out_height as f32 / 2.0);
+
+    RgbImage::from_fn(out_width, out_height, |x, y| {
+        let dx = x as f32 + 0.5 - out_center_x;
+        let dy = y as f32 + 0.5 - out_center_y;
+        // Inverse rotation gives the source position of this pixel
+        let source_x = dx * cos + dy * sin + center_x - 0.5;
+        let source_y = -dx * sin + dy * cos + center_y - 0.5;
+        sample_bilinear(image, source_x, source_y)
+    })
+}
+
+fn sample_bilinear(image: &RgbImage, x: f32, y: f32) -> Rgb<u8> {
+    let (width, height) = image.dimensions();
+    if x < -0.5 || y < -0.5 || x > width as f32 - 0.5 || y > height as f32 - 0.5 {
+        return Rgb([255, 255, 255]);
+    }
+    let x = x.clamp(0.0, (width - 1) as f32);
+    let y = y.clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+    let corners = [
+        (image.get_pixel(x0, y0), (1.0 - fx) * (1.0 - fy)),
+        (image.get_pixel(x1, y0), fx * (1.0 - fy)),
+        (image.get_pixel(x0, y1), (1.0 - fx) * fy),
+        (image.get_pixel(x1, y1), fx * fy),
+    ];
+    let mut pixel = [0u8; 3];
+    for (channel, value) in pixel.iter_mut().enumerate() {
+        let sum: f32 = corners
+            .iter()
+            .map(|(corner, weight)| corner[channel] as f32 * weight)
+            .sum();
+        *value = sum.round().clamp(0.0, 255.0) as u8;
+    }
+    Rgb(pixel)
+}
+
+/// Stretch the brightness range of an image to the full 0-255 range
+///
+/// The darkest and brightest 1% of pixels are clipped. Returns the range
+/// that was stretched, or `None` when the image already uses (nearly) the
+/// full range or is flat.
+pub fn normalize_contrast(image: &mut RgbImage) -> Option<(u8, u8)> {
+    let mut histogram = [0u64; 256];
+    for Rgb([r, g, b]) in image.pixels() {
+        let luma = (299 * *r as u32 + 587 * *g as u32 + 114 * *b as u32) / 1000;
+        histogram[luma as usize] += 1;
+    }
+
+    let total: u64 = histogram.iter().sum();
+    let clip = (total as f64 * CONTRAST_CLIP) as u64;
+    let percentile = |values: &mut dyn Iterator<Item = usize>| {
+        let mut seen = 0;
+        for value in values {
+            seen += histogram[value];
+            if seen > clip {
+                return value as u8;
+            }
+        }
+        0
+    };
+    let low = percentile(&mut (0..256));
+    let high = percentile(&mut (0..256).rev());
+    if high <= low.saturating_add(16) || (low <= 4 && high >= 251) {
+        return None;
+    }
+
+    let range = (high - low) as f32;
+    let mut lookup = [0u8; 256];
+    for (value, mapped) in lookup.iter_mut().enumerate() {
+        let stretched = (value as f32 - low as f32) * 255.0 / range;
+        *mapped = stretched.round().clamp(0.0, 255.0) as u8;
+    }
+    for pixel in image.pixels_mut() {
+        for channel in pixel.0.iter_mut() {
+            *channel = lookup[*channel as usize];
+        }
+    }
+
+    Some((low, high))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A white page with dark text lines, on a plain background
+    fn page_with_lines(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| {
+            let in_margin = x < 30 || x >= width - 30;
+            if y % 24 < 4 && y > 20 && y < height - 20 && !in_margin {
+                Rgb([20, 20, 20])
+            } else {
+                Rgb([250, 250, 250])
+            }
+        })
+    }
+
+    fn gray(image: &RgbImage) -> GrayImage {
+        image::DynamicImage::ImageRgb8(image.clone()).to_luma8()
+    }
+
+    #[test]
+    fn test_detects_and_corrects_skew() {
+        let page = page_with_lines(400, 300);
+        assert!(detect_skew(&gray(&page), 10.0).abs() < 0.2);
+
+        let tilted = rotate(&page, 4.0);
+        let skew = detect_skew(&gray(&tilted), 10.0);
+        assert!((skew - 4.0).abs() < 0.3, "detected {skew}");
+
+        let straightened = rotate(&tilted, -skew);
+        assert!(detect_skew(&gray(&straightened), 10.0).abs() < 0.3);
+    }
+
+    #[test]
+    fn test_rotation_grows_canvas() {
+        let image = RgbImage::from_pixel(200, 100, Rgb([0, 0, 0]));
+        let rotated = rotate(&image, 90.0);
+        assert_eq!(rotated.dimensions(), (100, 200));
+        assert_eq!(rotated.get_pixel(50, 100), &Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_finds_document_on_dark_background() {
+        let page = page_with_lines(240, 200);
+        let mut photo = RgbImage::from_pixel(400, 320, Rgb([70, 60, 50]));
+        image::imageops::overlay(&mut photo, &page, 80, 60);
+
+        let bounds = find_document_bounds(&gray(&photo)).unwrap();
+        assert!(bounds.x.abs_diff(80) <= 6, "{bounds:?}");
+        assert!(bounds.y.abs_diff(60) <= 6, "{bounds:?}");
+        assert!(bounds.width.abs_diff(240) <= 12, "{bounds:?}");
+        assert!(bounds.height.abs_diff(200) <= 12, "{bounds:?}");
+
+        // A scan is all paper and is left alone
+        assert_eq!(find_document_bounds(&gray(&page)), None);
+    }
+
+    #[test]
+    fn test_normalizes_dim_image() {
+        let mut dim = RgbImage::from_fn(100, 100, |x, _| {
+            let value = 60 + (x as u8 % 10) * 9;
+            Rgb([value, value, value])
+        });
+
+        assert_eq!(normalize_contrast(&mut dim), Some((60, 141)));
+        let values: Vec<u8> = dim.pixels().map(|pixel| pixel[0]).collect();
+        assert_eq!(values.iter().min(), Some(&0));
+        assert_eq!(values.iter().max(), Some(&255));
+
+        // Already full range: nothing to do
+        assert_eq!(normalize_contrast(&mut dim), None);
+    }
+}
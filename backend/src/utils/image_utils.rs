@@ -1,13 +1,18 @@
 //! Image processing utilities
 //!
-//! This module provides utilities for image processing, including resizing
-//! images before sending to AI services to optimize performance and costs.
+//! This module provides utilities for image processing, including the
+//! preprocessing pipeline that straightens, crops, brightens and resizes
+//! images before sending to AI services to optimize accuracy, performance
+//! and costs.
 
-use image::imageops::FilterType;
-use std::io::Cursor;
+use image::{DynamicImage, ImageDecoder, ImageReader, imageops::FilterType, metadata::Orientation};
+use std::{io::Cursor, time::Instant};
 use thiserror::Error;
 use tracing::{debug, info, instrument};
 
+use crate::models::{PreprocessingStep, PreprocessingStepReport};
+use crate::utils::image_preprocessing::{deskew, find_document_bounds, normalize_contrast};
+
 /// Errors that can occur during image processing
 #[derive(Debug, Error)]
 pub enum ImageProcessingError {
@@ -20,15 +25,50 @@ pub enum ImageProcessingError {
     #[error("Unsupported image format")]
     UnsupportedFormat,
 
-    #[error("Invalid scale factor: {0}. Must be between 0.1 and 1.0")]
-    InvalidScaleFactor(f32),
+    #[error("Invalid resize target: {0:?}. Must be a positive size")]
+    InvalidResizeTarget(ResizeTarget),
+}
+
+/// Size an image is scaled down to before extraction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResizeTarget {
+    /// Longest side in pixels
+    LongEdge(u32),
+    /// Total pixel count in megapixels
+    Megapixels(f32),
 }
 
-/// Configuration for image resizing
+impl ResizeTarget {
+    fn is_valid(&self) -> bool {
+        match *self {
+            ResizeTarget::LongEdge(pixels) => pixels > 0,
+            ResizeTarget::Megapixels(megapixels) => megapixels.is_finite() && megapixels > 0.0,
+        }
+    }
+
+    /// Scale factor that brings an image of `width` x `height` to the target
+    fn scale_for(&self, width: u32, height: u32) -> f32 {
+        match *self {
+            ResizeTarget::LongEdge(pixels) => pixels as f32 / width.max(height) as f32,
+            ResizeTarget::Megapixels(megapixels) => {
+                (megapixels * 1_000_000.0 / (width as f32 * height as f32)).sqrt()
+            }
+        }
+    }
+}
+
+/// Configuration of the image preprocessing pipeline
+///
+/// Steps run in this order: EXIF orientation, crop to document, resize,
+/// deskew, contrast normalization, grayscale. Each can be turned off except
+/// resizing, which never upscales.
 #[derive(Debug, Clone)]
 pub struct ResizeConfig {
-    /// Scale factor for resizing (0.1 to 1.0)
-    pub scale_factor: f32,
+    /// Size large images are scaled down to
+    pub target: ResizeTarget,
+    /// Shortest long edge kept for text to stay legible; wins over `target`
+    /// and the maximum dimensions
+    pub min_long_edge: u32,
     /// JPEG quality (1-100)
     pub jpeg_quality: u8,
     /// Filter type for resizing
@@ -37,137 +77,254 @@ pub struct ResizeConfig {
     pub max_width: Option<u32>,
     /// Maximum height after resizing (None for no limit)
     pub max_height: Option<u32>,
+    /// Rotate the image upright according to its EXIF orientation tag
+    pub apply_exif_orientation: bool,
+    /// Crop away the background around a photographed sheet of paper
+    pub crop_to_document: bool,
+    /// Straighten text lines tilted by up to `max_deskew_degrees`
+    pub deskew: bool,
+    pub max_deskew_degrees: f32,
+    /// Stretch the brightness range of dim or washed-out images
+    pub normalize_contrast: bool,
+    /// Convert the image to grayscale
+    pub grayscale: bool,
 }
 
 impl Default for ResizeConfig {
     fn default() -> Self {
         Self {
-            scale_factor: 0.4, // 40% of original dimensions (width x height)
+            target: ResizeTarget::LongEdge(2048), // Enough for small print on an A4 page
+            min_long_edge: 1280,
             jpeg_quality: 100, // Maximum quality - no compression artifacts
             filter_type: FilterType::Lanczos3, // High quality resampling filter
-            max_width: None,    // No artificial width limit
-            max_height: None,   // No artificial height limit
+            max_width: None,   // No artificial width limit
+            max_height: None,  // No artificial height limit
+            apply_exif_orientation: true,
+            crop_to_document: true,
+            deskew: true,
+            max_deskew_degrees: 10.0,
+            normalize_contrast: true,
+            grayscale: false,
         }
     }
 }
 
-/// Resize an image to reduce pixel dimensions while preserving image quality
-///
-/// This function reduces only the width and height of the image (pixel count) while
-/// maintaining maximum image quality. No compression artifacts are introduced - only
-/// the pixel dimensions are scaled down by the specified factor.
-///
-/// # Arguments
-/// * `image_data` - The original image data as bytes
-/// * `config` - Configuration for the resizing operation
-///
-/// # Returns
-/// * `Ok(Vec<u8>)` - The resized image as high-quality JPEG bytes
-/// * `Err(ImageProcessingError)` - If the operation fails
-///
-/// # Example
-/// ```rust
-/// use backend::utils::image_utils::{resize_image, ResizeConfig};
+/// An image prepared for extraction, with what each pipeline step did
+#[derive(Debug, Clone)]
+pub struct PreprocessedImage {
+    /// JPEG encoded result
+    pub data: Vec<u8>,
+    /// Width and height of the decoded upload
+    pub original_dimensions: (u32, u32),
+    pub dimensions: (u32, u32),
+    /// Report of every enabled step, in the order they ran
+    pub steps: Vec<PreprocessingStepReport>,
+}
+
+/// Report of a step that started at `started`; it was applied when it has a `detail`
+fn step_report(
+    step: PreprocessingStep,
+    started: Instant,
+    detail: Option<String>,
+) -> PreprocessingStepReport {
+    PreprocessingStepReport {
+        step,
+        applied: detail.is_some(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        detail,
+    }
+}
+
+/// Run the preprocessing pipeline on an uploaded image
 ///
-/// let config = ResizeConfig::default(); // 40% of original dimensions, 100% quality
-/// let resized_data = resize_image(&original_image_bytes, &config)?;
-/// ```
-#[instrument(skip(image_data), fields(original_size = image_data.len()))]
-pub fn resize_image(
+/// The enabled steps of `config` are applied in order and the result is
+/// encoded as JPEG. Steps that find nothing to do (an image without EXIF
+/// rotation, a scan without background to crop) are reported as not applied.
+/// A JPEG that no step changed is returned as is, since re-encoding it would
+/// only grow the file.
+#[instrument(skip(image_data, config), fields(original_size = image_data.len()))]
+pub fn preprocess_image(
     image_data: &[u8],
-    config: &ResizeConfig
-) -> Result<Vec<u8>, ImageProcessingError> {
-    // Validate scale factor
-    if config.scale_factor <= 0.0 || config.scale_factor > 1.0 {
-        return Err(ImageProcessingError::InvalidScaleFactor(config.scale_factor));
+    config: &ResizeConfig,
+) -> Result<PreprocessedImage, ImageProcessingError> {
+    if !config.target.is_valid() {
+        return Err(ImageProcessingError::InvalidResizeTarget(config.target));
     }
 
-    debug!("Starting image resize with scale factor: {}", config.scale_factor);
-
-    // Load the image from bytes
-    let img = image::load_from_memory(image_data)?;
-    let (original_width, original_height) = (img.width(), img.height());
+    // Load the image from bytes, keeping its EXIF orientation
+    let mut decoder = ImageReader::new(Cursor::new(image_data))
+        .with_guessed_format()
+        .map_err(|_| ImageProcessingError::UnsupportedFormat)?
+        .into_decoder()?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    let original_dimensions = (img.width(), img.height());
+    let mut steps = Vec::new();
 
     info!(
-        "Loaded image: {}x{} pixels, format: {:?}",
-        original_width,
-        original_height,
-        img.color()
+        "Loaded image: {}x{} pixels, format: {:?}, orientation: {:?}",
+        original_dimensions.0,
+        original_dimensions.1,
+        img.color(),
+        orientation
     );
 
-    // Calculate new dimensions
-    let mut new_width = (original_width as f32 * config.scale_factor) as u32;
-    let mut new_height = (original_height as f32 * config.scale_factor) as u32;
-
-    // Apply maximum dimension limits if specified
-    if let Some(max_width) = config.max_width {
-        if new_width > max_width {
-            let scale = max_width as f32 / new_width as f32;
-            new_width = max_width;
-            new_height = (new_height as f32 * scale) as u32;
-        }
+    if config.apply_exif_orientation {
+        let started = Instant::now();
+        let detail = (orientation != Orientation::NoTransforms).then(|| {
+            img.apply_orientation(orientation);
+            format!("{orientation:?}")
+        });
+        steps.push(step_report(
+            PreprocessingStep::ExifOrientation,
+            started,
+            detail,
+        ));
     }
 
-    if let Some(max_height) = config.max_height {
-        if new_height > max_height {
-            let scale = max_height as f32 / new_height as f32;
-            new_height = max_height;
-            new_width = (new_width as f32 * scale) as u32;
-        }
-    }
+    let mut rgb = img.to_rgb8();
+    drop(img);
 
-    // Ensure minimum dimensions
-    new_width = new_width.max(1);
-    new_height = new_height.max(1);
+    if config.crop_to_document {
+        let started = Instant::now();
+        let detail = find_document_bounds(&image::imageops::grayscale(&rgb)).map(|bounds| {
+            rgb = image::imageops::crop_imm(&rgb, bounds.x, bounds.y, bounds.width, bounds.height)
+                .to_image();
+            format!(
+                "{}x{} at ({}, {})",
+                bounds.width, bounds.height, bounds.x, bounds.y
+            )
+        });
+        steps.push(step_report(
+            PreprocessingStep::CropToDocument,
+            started,
+            detail,
+        ));
+    }
 
+    let started = Instant::now();
+    let (width, height) = rgb.dimensions();
+    let (new_width, new_height) = scaled_dimensions(width, height, config);
     debug!(
         "Resizing pixel dimensions from {}x{} to {}x{} (pixel reduction: {:.1}%)",
-        original_width,
-        original_height,
+        width,
+        height,
         new_width,
         new_height,
-        (1.0 - (new_width * new_height) as f32 / (original_width * original_height) as f32) * 100.0
+        (1.0 - (new_width * new_height) as f32 / (width * height) as f32) * 100.0
     );
+    let detail = ((new_width, new_height) != (width, height)).then(|| {
+        rgb = image::imageops::resize(&rgb, new_width, new_height, config.filter_type);
+        format!("{width}x{height} -> {new_width}x{new_height}")
+    });
+    steps.push(step_report(PreprocessingStep::Resize, started, detail));
+
+    if config.deskew {
+        let started = Instant::now();
+        let detail = deskew(&rgb, config.max_deskew_degrees).map(|(straightened, degrees)| {
+            rgb = straightened;
+            format!("rotated by {degrees:.1}°")
+        });
+        steps.push(step_report(PreprocessingStep::Deskew, started, detail));
+    }
+
+    if config.normalize_contrast {
+        let started = Instant::now();
+        let detail = normalize_contrast(&mut rgb)
+            .map(|(low, high)| format!("stretched {low}-{high} to 0-255"));
+        steps.push(step_report(
+            PreprocessingStep::NormalizeContrast,
+            started,
+            detail,
+        ));
+    }
+
+    let dimensions = rgb.dimensions();
+    let applied_steps = steps.iter().filter(|step| step.applied).count();
+    if applied_steps == 0 && !config.grayscale && is_jpeg(image_data) {
+        info!(
+            "Image already meets the target, keeping the original {} bytes",
+            image_data.len()
+        );
+        return Ok(PreprocessedImage {
+            data: image_data.to_vec(),
+            original_dimensions,
+            dimensions,
+            steps,
+        });
+    }
 
-    // Resize the image
-    let resized_img = img.resize(new_width, new_height, config.filter_type);
+    let output = if config.grayscale {
+        let started = Instant::now();
+        let gray = DynamicImage::ImageLuma8(image::imageops::grayscale(&rgb));
+        steps.push(step_report(
+            PreprocessingStep::Grayscale,
+            started,
+            Some("converted to grayscale".to_string()),
+        ));
+        gray
+    } else {
+        DynamicImage::ImageRgb8(rgb)
+    };
 
     // Encode with maximum quality to preserve image fidelity
     let mut buffer = Cursor::new(Vec::new());
 
     // Use JPEG with maximum quality to minimize compression artifacts
     // This preserves image quality while only reducing pixel dimensions
-    let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buffer, config.jpeg_quality);
-    resized_img
+    let encoder =
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buffer, config.jpeg_quality);
+    output
         .write_with_encoder(encoder)
         .map_err(|e| ImageProcessingError::EncodeError(e.to_string()))?;
 
     let resized_data = buffer.into_inner();
-    let size_reduction = ((image_data.len() - resized_data.len()) as f32 / image_data.len() as f32) * 100.0;
 
     info!(
-        "Image pixel dimensions resized successfully: {} bytes -> {} bytes (file size reduced by {:.1}%)",
+        "Image preprocessed successfully: {} bytes -> {} bytes (file size reduced by {:.1}%), {} step(s) applied",
         image_data.len(),
         resized_data.len(),
-        size_reduction
+        calculate_size_reduction(image_data.len(), resized_data.len()),
+        steps.iter().filter(|step| step.applied).count()
     );
 
-    Ok(resized_data)
+    Ok(PreprocessedImage {
+        data: resized_data,
+        original_dimensions,
+        dimensions,
+        steps,
+    })
 }
 
-/// Resize an image with default configuration (40% pixel dimensions, 100% quality)
+/// Dimensions after applying the resize target and maximum dimension limits
 ///
-/// This convenience function reduces the image to 40% of its original pixel dimensions
-/// (width x height) while preserving maximum image quality with no compression artifacts.
-///
-/// # Arguments
-/// * `image_data` - The original image data as bytes
-///
-/// # Returns
-/// * `Ok(Vec<u8>)` - The resized image as high-quality JPEG bytes
-/// * `Err(ImageProcessingError)` - If the operation fails
-pub fn resize_image_default(image_data: &[u8]) -> Result<Vec<u8>, ImageProcessingError> {
-    resize_image(image_data, &ResizeConfig::default())
+/// Images are never upscaled, and not shrunk below `min_long_edge`.
+fn scaled_dimensions(width: u32, height: u32, config: &ResizeConfig) -> (u32, u32) {
+    let mut scale = config.target.scale_for(width, height);
+
+    // Apply maximum dimension limits if specified
+    if let Some(max_width) = config.max_width {
+        scale = scale.min(max_width as f32 / width as f32);
+    }
+    if let Some(max_height) = config.max_height {
+        scale = scale.min(max_height as f32 / height as f32);
+    }
+
+    let legible_scale = config.min_long_edge as f32 / width.max(height) as f32;
+    let scale = scale.max(legible_scale).min(1.0);
+    if scale >= 1.0 {
+        return (width, height);
+    }
+
+    // Ensure minimum dimensions
+    (
+        ((width as f32 * scale).round() as u32).max(1),
+        ((height as f32 * scale).round() as u32).max(1),
+    )
+}
+
+fn is_jpeg(data: &[u8]) -> bool {
+    data.starts_with(&[0xFF, 0xD8, 0xFF])
 }
 
 /// Get image dimensions without fully loading the image
@@ -201,12 +358,13 @@ pub fn is_valid_image(image_data: &[u8]) -> bool {
 /// * `new_size` - New file size in bytes
 ///
 /// # Returns
-/// * Percentage reduction as f32 (e.g., 60.5 for 60.5% reduction)
+/// * Percentage reduction as f32 (e.g., 60.5 for 60.5% reduction); negative
+///   when the file grew
 pub fn calculate_size_reduction(original_size: usize, new_size: usize) -> f32 {
     if original_size == 0 {
         return 0.0;
     }
-    ((original_size - new_size) as f32 / original_size as f32) * 100.0
+    (1.0 - new_size as f32 / original_size as f32) * 100.0
 }
 
 #[cfg(test)]
@@ -216,19 +374,94 @@ mod tests {
     #[test]
     fn test_calculate_size_reduction() {
         assert!((calculate_size_reduction(1000, 400) - 60.0).abs() < 0.1);
+        assert!((calculate_size_reduction(1000, 1500) + 50.0).abs() < 0.1);
         assert_eq!(calculate_size_reduction(1000, 1000), 0.0);
         assert_eq!(calculate_size_reduction(0, 0), 0.0);
     }
 
     #[test]
-    fn test_invalid_scale_factor() {
+    fn test_invalid_resize_target() {
         let config = ResizeConfig {
-            scale_factor: 1.5, // Invalid: > 1.0
+            target: ResizeTarget::Megapixels(0.0), // Invalid: not positive
             ..Default::default()
         };
 
         let dummy_data = vec![0u8; 100];
-        let result = resize_image(&dummy_data, &config);
-        assert!(matches!(result, Err(ImageProcessingError::InvalidScaleFactor(_))));
+        let result = preprocess_image(&dummy_data, &config);
+        assert!(matches!(
+            result,
+            Err(ImageProcessingError::InvalidResizeTarget(_))
+        ));
+    }
+
+    #[test]
+    fn test_scaled_dimensions_follow_target() {
+        let config = ResizeConfig::default();
+        // A 48MP photo comes down to the target long edge
+        assert_eq!(scaled_dimensions(8000, 6000, &config), (2048, 1536));
+        // A small receipt is never upscaled
+        assert_eq!(scaled_dimensions(800, 1200, &config), (800, 1200));
+
+        let megapixels = ResizeConfig {
+            target: ResizeTarget::Megapixels(3.0),
+            ..Default::default()
+        };
+        assert_eq!(scaled_dimensions(4000, 3000, &megapixels), (2000, 1500));
+
+        // Text stays legible even when the target or limits ask for less
+        let small = ResizeConfig {
+            target: ResizeTarget::LongEdge(500),
+            max_height: Some(300),
+            ..Default::default()
+        };
+        assert_eq!(scaled_dimensions(4000, 2000, &small), (1280, 640));
+    }
+
+    #[test]
+    fn test_untouched_jpeg_is_not_reencoded() {
+        let image = image::RgbImage::from_pixel(300, 200, image::Rgb([120, 120, 120]));
+        let mut jpeg = Cursor::new(Vec::new());
+        image.write_to(&mut jpeg, image::ImageFormat::Jpeg).unwrap();
+
+        let preprocessed = preprocess_image(jpeg.get_ref(), &ResizeConfig::default()).unwrap();
+
+        assert_eq!(&preprocessed.data, jpeg.get_ref());
+        assert!(preprocessed.steps.iter().all(|step| !step.applied));
+    }
+
+    #[test]
+    fn test_preprocess_reports_enabled_steps() {
+        let image = image::RgbImage::from_pixel(200, 100, image::Rgb([120, 120, 120]));
+        let mut png = Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageFormat::Png).unwrap();
+
+        let config = ResizeConfig {
+            target: ResizeTarget::LongEdge(100),
+            min_long_edge: 0,
+            crop_to_document: false,
+            grayscale: true,
+            ..Default::default()
+        };
+        let preprocessed = preprocess_image(png.get_ref(), &config).unwrap();
+
+        assert_eq!(preprocessed.original_dimensions, (200, 100));
+        assert_eq!(preprocessed.dimensions, (100, 50));
+        let steps: Vec<(PreprocessingStep, bool)> = preprocessed
+            .steps
+            .iter()
+            .map(|report| (report.step, report.applied))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                (PreprocessingStep::ExifOrientation, false),
+                (PreprocessingStep::Resize, true),
+                (PreprocessingStep::Deskew, false),
+                (PreprocessingStep::NormalizeContrast, false),
+                (PreprocessingStep::Grayscale, true),
+            ]
+        );
+        let encoded = image::load_from_memory(&preprocessed.data).unwrap();
+        assert_eq!(encoded.color(), image::ColorType::L8);
     }
-}
\ No newline at end of file
+}
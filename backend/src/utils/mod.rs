@@ -6,4 +6,6 @@
 
 pub mod database;
 pub mod env;
+pub mod image_hash;
+pub mod image_preprocessing;
 pub mod image_utils;
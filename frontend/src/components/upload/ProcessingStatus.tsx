@@ -173,7 +173,7 @@ export function ProcessingStatus({ events, isProcessing, className }: Processing
   };
 
   const completedEvents = events.filter(e =>
-    ['processing_complete', 'processing_error', 'finished'].includes(e.type)
+    ['processing_complete', 'processing_error', 'processing_cancelled', 'finished'].includes(e.type)
   ).length;
 
   const uploadStartedEvent = events.find(e => e.type === 'upload_started');
@@ -80,7 +80,7 @@ function normalizeEventData(data: OcrEventData, totalFiles: number): OcrEventDat
  * Helper function to check if event type indicates completion
  */
 function isCompletionEvent(eventType: string): boolean {
-  return ['processing_complete', 'processing_error', 'finished'].includes(eventType);
+  return ['processing_complete', 'processing_error', 'processing_cancelled', 'finished'].includes(eventType);
 }
 
 /**